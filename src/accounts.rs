@@ -0,0 +1,758 @@
+//! # Accounts
+//!
+//! Optional accounts, so that features keyed to a person have something to
+//! key on. Everything keeps working without one: an account is a name, a way
+//! to prove it, and the chat session (see `sessions.rs`) that follows its
+//! owner from device to device. Readings are owned by that session too.
+//!
+//! - Passwords are hashed with Argon2id (the `argon2` crate's defaults) and
+//!   kept as PHC strings. Unknown names are checked against a dummy hash, so
+//!   how long a login takes doesn't tell whether the name exists
+//! - Passkeys are WebAuthn credentials with ES256 or RS256 keys. Attestation
+//!   is neither asked for nor checked: what matters is that later logins are
+//!   signed with the key registered. Challenges are single use and kept in
+//!   memory for five minutes
+//! - Signing in sets `cat_account` to `<account id>.<expiry>.<HMAC-SHA256>`,
+//!   keyed with `SESSION_SECRET` or a key generated in `DATA_DIR/session.key`.
+//!   It is HttpOnly, SameSite=Lax and, when `PUBLIC_URL` is https, Secure.
+//!   `cat_session` is pointed at the account's chat session alongside it
+//! - An account's chat session is only reached with a valid `cat_account`:
+//!   a `cat_session` naming it is otherwise treated as a new visitor. Every
+//!   sign-in and sign-out moves the session to a fresh id, so a copied
+//!   `cat_session` goes stale
+//! - Signing up can keep the visitor's anonymous conversation and readings,
+//!   moved to a fresh session id rather than adopting one whoever set the
+//!   visitor's cookie might know
+//!
+//! Accounts are kept in `DATA_DIR/accounts.json`.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header::SET_COOKIE, request::Parts, HeaderMap, HeaderValue},
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use ciborium::value::Value as Cbor;
+use once_cell::sync::Lazy;
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcPoint},
+    hash::{hash, MessageDigest},
+    memcmp,
+    nid::Nid,
+    pkey::{PKey, Private, Public},
+    rsa::Rsa,
+    sign::{Signer, Verifier},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::info;
+
+use crate::config::Config;
+use crate::cookies;
+use crate::json_file::JsonFile;
+use crate::sessions::{new_session_id, SESSION_COOKIE};
+use crate::state::AppState;
+
+/// Cookie carrying the signed-in account
+pub const ACCOUNT_COOKIE: &str = "cat_account";
+
+/// How long a sign-in lasts
+pub const SESSION_DAYS: i64 = 30;
+
+/// Shortest password accepted
+pub const MIN_PASSWORD_CHARS: usize = 8;
+
+/// Longest password accepted, so nobody can make the server hash megabytes
+const MAX_PASSWORD_CHARS: usize = 256;
+
+/// How long a passkey ceremony may take
+const CHALLENGE_MINUTES: i64 = 5;
+
+/// COSE algorithm ids of the passkey keys accepted
+const ES256: i64 = -7;
+const RS256: i64 = -257;
+
+/// Authenticator data flags (WebAuthn §6.1)
+const USER_PRESENT: u8 = 0x01;
+const ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// Checked against when a name is unknown, so that takes as long as a wrong password
+static DUMMY_HASH: Lazy<String> =
+    Lazy::new(|| hash_password("the cat knows no one by this name").expect("Hashing a constant must work"));
+
+/// Why an account request failed
+#[derive(Debug, Error)]
+pub enum AccountError {
+    /// The request can't be carried out as given; the message says why
+    #[error("{0}")]
+    Invalid(String),
+    #[error("That name is already taken")]
+    Taken,
+    #[error("Wrong name, password or passkey")]
+    Unauthorized,
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Someone who signed up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    /// Lower case, and unique
+    pub username: String,
+    /// Argon2 PHC string; `None` for accounts that only use passkeys
+    pub password_hash: Option<String>,
+    /// The WebAuthn `user.id`, random so it says nothing about the account
+    pub user_handle: String,
+    #[serde(default)]
+    pub passkeys: Vec<Passkey>,
+    /// The chat session the account signs in to, which also owns its readings
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_login: Option<DateTime<Utc>>,
+}
+
+impl Account {
+    fn new(username: String, password_hash: Option<String>, user_handle: String, session_id: String) -> Self {
+        Self {
+            id: random_id(),
+            username,
+            password_hash,
+            user_handle,
+            passkeys: Vec::new(),
+            session_id,
+            created_at: Utc::now(),
+            last_login: Some(Utc::now()),
+        }
+    }
+}
+
+/// A WebAuthn credential registered to an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Passkey {
+    /// Credential id, base64url
+    pub id: String,
+    /// DER SubjectPublicKeyInfo, base64url
+    pub public_key: String,
+    /// COSE algorithm: ES256 (-7) or RS256 (-257)
+    pub algorithm: i64,
+    /// Signature counter last seen; stays 0 for authenticators without one
+    pub sign_count: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What a passkey challenge was issued for
+#[derive(Debug, Clone)]
+enum Ceremony {
+    /// Creating a passkey for a new account, or for `account_id`
+    Register { username: String, user_handle: String, account_id: Option<String> },
+    Login,
+}
+
+#[derive(Debug)]
+struct Challenge {
+    ceremony: Ceremony,
+    expires: DateTime<Utc>,
+}
+
+/// Every account, the key that signs their cookies and open passkey ceremonies
+#[derive(Clone)]
+pub struct Accounts {
+    accounts: JsonFile<HashMap<String, Account>>,
+    key: Arc<PKey<Private>>,
+    /// `DATA_DIR/session.key`, whether or not `SESSION_SECRET` overrides it
+    key_path: Arc<Path>,
+    challenges: Arc<Mutex<HashMap<String, Challenge>>>,
+    /// WebAuthn relying party id: the host of `PUBLIC_URL`
+    rp_id: Arc<str>,
+    /// The origin browsers report in passkey ceremonies
+    origin: Arc<str>,
+    secure_cookies: bool,
+}
+
+impl Accounts {
+    /// Opens the accounts in `data_dir`, generating the cookie key there if
+    /// `SESSION_SECRET` isn't set and no key exists yet
+    pub fn open(config: &Config, data_dir: &Path) -> Result<Self> {
+        let key_path = data_dir.join("session.key");
+        let secret = match &config.session_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                if key_path.exists() {
+                    let key = std::fs::read_to_string(&key_path).with_context(|| format!("Failed to read {}", key_path.display()))?;
+                    hex::decode(key.trim()).with_context(|| format!("Failed to parse {}", key_path.display()))?
+                } else {
+                    info!("Generating the session cookie key in {}", key_path.display());
+                    let mut key = [0u8; 32];
+                    rand::thread_rng().fill_bytes(&mut key);
+                    std::fs::create_dir_all(data_dir)?;
+                    std::fs::write(&key_path, hex::encode(key)).with_context(|| format!("Failed to write {}", key_path.display()))?;
+                    key.to_vec()
+                }
+            }
+        };
+
+        let url = reqwest::Url::parse(&config.public_url).context("PUBLIC_URL must be a URL")?;
+        let rp_id = url.host_str().context("PUBLIC_URL must have a host")?;
+
+        Ok(Self {
+            accounts: JsonFile::open(data_dir.join("accounts.json"))?,
+            key: Arc::new(PKey::hmac(&secret)?),
+            key_path: key_path.into(),
+            challenges: Arc::new(Mutex::new(HashMap::new())),
+            rp_id: rp_id.into(),
+            origin: url.origin().ascii_serialization().into(),
+            secure_cookies: url.scheme() == "https",
+        })
+    }
+
+    /// The account with id `id`
+    pub async fn get(&self, id: &str) -> Option<Account> {
+        self.accounts.read(|accounts| accounts.get(id).cloned()).await
+    }
+
+    /// The account named `username`, in any case
+    pub async fn find(&self, username: &str) -> Option<Account> {
+        let username = username.trim().to_lowercase();
+        self.accounts.read(|accounts| accounts.values().find(|account| account.username == username).cloned()).await
+    }
+
+    /// Number of accounts
+    pub async fn len(&self) -> usize {
+        self.accounts.read(HashMap::len).await
+    }
+
+    /// Whether nobody has signed up
+    pub async fn is_empty(&self) -> bool {
+        self.accounts.read(HashMap::is_empty).await
+    }
+
+    /// Whether `session_id` is the chat session of some account
+    pub async fn holds_session(&self, session_id: &str) -> bool {
+        self.accounts.read(|accounts| accounts.values().any(|account| account.session_id == session_id)).await
+    }
+
+    /// Points the account `id` at a fresh chat session id, returning the old
+    /// one along with the updated account
+    ///
+    /// The caller moves the conversation and readings over (see
+    /// `routes/account.rs`).
+    pub async fn renew_session(&self, id: &str) -> Option<(String, Account)> {
+        self.accounts
+            .update(|accounts| {
+                let account = accounts.get_mut(id)?;
+                let old = std::mem::replace(&mut account.session_id, new_session_id());
+                Some((old, account.clone()))
+            })
+            .await
+    }
+
+    /// Creates an account with a password
+    pub async fn sign_up(&self, username: &str, password: &str, session_id: String) -> Result<Account, AccountError> {
+        let username = valid_username(username)?;
+        let chars = password.chars().count();
+        if chars < MIN_PASSWORD_CHARS {
+            return Err(AccountError::Invalid(format!("The password needs at least {} characters", MIN_PASSWORD_CHARS)));
+        }
+        if chars > MAX_PASSWORD_CHARS {
+            return Err(AccountError::Invalid(format!("The password can have at most {} characters", MAX_PASSWORD_CHARS)));
+        }
+        if self.find(&username).await.is_some() {
+            return Err(AccountError::Taken);
+        }
+
+        let password = password.to_string();
+        let hash = tokio::task::spawn_blocking(move || hash_password(&password)).await.context("Hashing panicked")??;
+        self.insert(Account::new(username, Some(hash), random_id(), session_id)).await
+    }
+
+    /// Checks a name and password
+    pub async fn log_in(&self, username: &str, password: &str) -> Result<Account, AccountError> {
+        let account = self.find(username).await;
+        let hash = account.as_ref().and_then(|account| account.password_hash.clone());
+        let known = hash.is_some();
+
+        let password = password.chars().take(MAX_PASSWORD_CHARS).collect::<String>();
+        let hash = hash.unwrap_or_else(|| DUMMY_HASH.clone());
+        let matches = tokio::task::spawn_blocking(move || verify_password(&password, &hash)).await.unwrap_or(false);
+
+        match account {
+            Some(account) if known && matches => Ok(self.touch(&account.id).await.unwrap_or(account)),
+            _ => Err(AccountError::Unauthorized),
+        }
+    }
+
+    /// Every account, for backups
+    pub async fn all(&self) -> HashMap<String, Account> {
+        self.accounts.read(HashMap::clone).await
+    }
+
+    /// The generated cookie key in `session.key`, hex, for backups; `None`
+    /// if there is none, as when `SESSION_SECRET` was always set
+    pub fn session_key(&self) -> Result<Option<String>> {
+        if !self.key_path.exists() {
+            return Ok(None);
+        }
+        let key = std::fs::read_to_string(&self.key_path).with_context(|| format!("Failed to read {}", self.key_path.display()))?;
+        Ok(Some(key.trim().to_string()))
+    }
+
+    /// Adds `imported` accounts, replacing those with the same id or name,
+    /// and writes `session_key` to `session.key`; with `replace`, every other
+    /// account is dropped first
+    ///
+    /// The key signs cookies from the next start on (unless `SESSION_SECRET`
+    /// is set), so cookies handed out before the backup keep working.
+    pub async fn import(&self, imported: HashMap<String, Account>, session_key: Option<&str>, replace: bool) -> Result<()> {
+        if let Some(key) = session_key {
+            hex::decode(key).context("The backup's session key is not hex")?;
+            std::fs::write(&self.key_path, key).with_context(|| format!("Failed to write {}", self.key_path.display()))?;
+        }
+
+        self.accounts
+            .update(|accounts| {
+                if replace {
+                    accounts.clear();
+                }
+                accounts.retain(|id, account| {
+                    !imported.values().any(|i| &i.id == id || i.username == account.username)
+                });
+                accounts.extend(imported);
+            })
+            .await;
+        Ok(())
+    }
+
+    /// Deletes the account `id`, returning it
+    pub async fn delete(&self, id: &str) -> Option<Account> {
+        self.accounts.update(|accounts| accounts.remove(id)).await
+    }
+
+    async fn insert(&self, account: Account) -> Result<Account, AccountError> {
+        self.accounts
+            .update(|accounts| {
+                if accounts.values().any(|existing| existing.username == account.username) {
+                    return Err(AccountError::Taken);
+                }
+                accounts.insert(account.id.clone(), account.clone());
+                Ok(account)
+            })
+            .await
+    }
+
+    /// Notes a login
+    async fn touch(&self, id: &str) -> Option<Account> {
+        self.accounts
+            .update(|accounts| {
+                let account = accounts.get_mut(id)?;
+                account.last_login = Some(Utc::now());
+                Some(account.clone())
+            })
+            .await
+    }
+
+    /// The account a request's `cat_account` cookie signs in, if it is valid and current
+    pub async fn from_headers(&self, headers: &HeaderMap) -> Option<Account> {
+        let cookie = cookies::get(headers, ACCOUNT_COOKIE)?;
+        let id = self.verify_cookie(&cookie, Utc::now())?;
+        self.get(&id).await
+    }
+
+    /// Cookies that sign `account` in, and point the chat session at its own
+    pub fn sign_in_cookies(&self, account: &Account) -> HeaderMap {
+        let expires = Utc::now() + Duration::days(SESSION_DAYS);
+        let payload = format!("{}.{}", account.id, expires.timestamp());
+        let signed = match self.mac(&payload) {
+            Ok(mac) => format!("{}.{}", payload, mac),
+            Err(_) => return HeaderMap::new(),
+        };
+        self.cookies(&[(ACCOUNT_COOKIE, &signed), (SESSION_COOKIE, &account.session_id)], SESSION_DAYS * 24 * 60 * 60)
+    }
+
+    /// Cookies that sign out, and drop the account's chat session; the next
+    /// request starts a fresh anonymous one
+    pub fn sign_out_cookies(&self) -> HeaderMap {
+        self.cookies(&[(ACCOUNT_COOKIE, ""), (SESSION_COOKIE, "")], 0)
+    }
+
+    fn cookies(&self, cookies: &[(&str, &str)], max_age: i64) -> HeaderMap {
+        let secure = if self.secure_cookies { "; Secure" } else { "" };
+        let mut headers = HeaderMap::new();
+        for (name, value) in cookies {
+            let cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}", name, value, max_age, secure);
+            if let Ok(value) = HeaderValue::from_str(&cookie) {
+                headers.append(SET_COOKIE, value);
+            }
+        }
+        headers
+    }
+
+    /// The account id in a `cat_account` value, if its signature holds and it hasn't expired
+    fn verify_cookie(&self, cookie: &str, now: DateTime<Utc>) -> Option<String> {
+        let (payload, mac) = cookie.rsplit_once('.')?;
+        let expected = self.mac(payload).ok()?;
+        if mac.len() != expected.len() || !memcmp::eq(mac.as_bytes(), expected.as_bytes()) {
+            return None;
+        }
+        let (id, expires) = payload.split_once('.')?;
+        (expires.parse::<i64>().ok()? > now.timestamp()).then(|| id.to_string())
+    }
+
+    fn mac(&self, payload: &str) -> Result<String> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(payload.as_bytes())?;
+        Ok(URL_SAFE_NO_PAD.encode(signer.sign_to_vec()?))
+    }
+
+    /// Options for `navigator.credentials.create`, base64url-encoded as in
+    /// `PublicKeyCredentialCreationOptionsJSON`
+    ///
+    /// Signed in, the passkey is added to `account`; otherwise it creates a
+    /// new account named `username`.
+    pub async fn registration_options(&self, username: Option<&str>, account: Option<&Account>) -> Result<Value, AccountError> {
+        let (username, user_handle, account_id, exclude) = match account {
+            Some(account) => (
+                account.username.clone(),
+                account.user_handle.clone(),
+                Some(account.id.clone()),
+                account.passkeys.iter().map(|passkey| json!({ "type": "public-key", "id": passkey.id })).collect(),
+            ),
+            None => {
+                let username = valid_username(username.unwrap_or_default())?;
+                if self.find(&username).await.is_some() {
+                    return Err(AccountError::Taken);
+                }
+                (username, random_id(), None, Vec::new())
+            }
+        };
+
+        let challenge = self.challenge(Ceremony::Register { username: username.clone(), user_handle: user_handle.clone(), account_id });
+        Ok(json!({
+            "challenge": challenge,
+            "rp": { "id": &*self.rp_id, "name": "The Enlightened Cat" },
+            "user": { "id": user_handle, "name": username, "displayName": username },
+            "pubKeyCredParams": [
+                { "type": "public-key", "alg": ES256 },
+                { "type": "public-key", "alg": RS256 },
+            ],
+            "timeout": CHALLENGE_MINUTES * 60 * 1000,
+            "attestation": "none",
+            "authenticatorSelection": { "residentKey": "preferred", "userVerification": "preferred" },
+            "excludeCredentials": exclude,
+        }))
+    }
+
+    /// Checks a new passkey (`PublicKeyCredential.toJSON()`) against its
+    /// challenge and stores it, creating the account if it was for a new one
+    ///
+    /// `signed_in` must be the account the options were made for, if any;
+    /// a new account signs in to `session_id`.
+    pub async fn finish_registration(
+        &self,
+        credential: &Value,
+        signed_in: Option<&Account>,
+        session_id: String,
+    ) -> Result<Account, AccountError> {
+        let invalid = |err: anyhow::Error| AccountError::Invalid(format!("The passkey couldn't be registered: {:#}", err));
+
+        let response = &credential["response"];
+        let client_data = base64_field(response, "clientDataJSON").map_err(invalid)?;
+        let challenge = self.check_client_data(&client_data, "webauthn.create").map_err(invalid)?;
+        let Some(Ceremony::Register { username, user_handle, account_id }) = self.take_challenge(&challenge) else {
+            return Err(invalid(anyhow::anyhow!("unknown or expired challenge")));
+        };
+        if account_id.as_deref() != signed_in.map(|account| account.id.as_str()) {
+            return Err(invalid(anyhow::anyhow!("the challenge was for someone else")));
+        }
+
+        let passkey = self.parse_attestation(&base64_field(response, "attestationObject").map_err(invalid)?).map_err(invalid)?;
+        let in_use = self
+            .accounts
+            .read(|accounts| accounts.values().any(|account| account.passkeys.iter().any(|existing| existing.id == passkey.id)))
+            .await;
+        if in_use {
+            return Err(AccountError::Invalid("That passkey is already registered".to_string()));
+        }
+
+        match account_id {
+            Some(id) => self
+                .accounts
+                .update(|accounts| {
+                    let account = accounts.get_mut(&id)?;
+                    account.passkeys.push(passkey);
+                    Some(account.clone())
+                })
+                .await
+                .ok_or(AccountError::Unauthorized),
+            None => {
+                let mut account = Account::new(username, None, user_handle, session_id);
+                account.passkeys.push(passkey);
+                self.insert(account).await
+            }
+        }
+    }
+
+    /// Options for `navigator.credentials.get`; `allowCredentials` is empty,
+    /// so the browser offers whichever passkeys it has for this site
+    pub fn login_options(&self) -> Value {
+        json!({
+            "challenge": self.challenge(Ceremony::Login),
+            "rpId": &*self.rp_id,
+            "timeout": CHALLENGE_MINUTES * 60 * 1000,
+            "userVerification": "preferred",
+            "allowCredentials": [],
+        })
+    }
+
+    /// Checks a passkey assertion (`PublicKeyCredential.toJSON()`) and returns the account it signs in
+    pub async fn finish_login(&self, credential: &Value) -> Result<Account, AccountError> {
+        match self.verify_assertion(credential).await {
+            Ok(account) => Ok(account),
+            Err(err) => {
+                info!("Passkey login refused: {:#}", err);
+                Err(AccountError::Unauthorized)
+            }
+        }
+    }
+
+    async fn verify_assertion(&self, credential: &Value) -> Result<Account> {
+        let response = &credential["response"];
+        let client_data = base64_field(response, "clientDataJSON")?;
+        let challenge = self.check_client_data(&client_data, "webauthn.get")?;
+        if !matches!(self.take_challenge(&challenge), Some(Ceremony::Login)) {
+            bail!("unknown or expired challenge");
+        }
+
+        let credential_id = credential["id"].as_str().context("missing credential id")?;
+        let account = self
+            .accounts
+            .read(|accounts| accounts.values().find(|account| account.passkeys.iter().any(|passkey| passkey.id == credential_id)).cloned())
+            .await
+            .context("unknown passkey")?;
+        let passkey = account.passkeys.iter().find(|passkey| passkey.id == credential_id).context("unknown passkey")?;
+
+        let authenticator_data = base64_field(response, "authenticatorData")?;
+        let data = AuthenticatorData::parse(&authenticator_data)?;
+        self.check_authenticator_data(&data)?;
+
+        let mut signed = authenticator_data.clone();
+        signed.extend(hash(MessageDigest::sha256(), &client_data)?.iter());
+        let key = PKey::public_key_from_der(&URL_SAFE_NO_PAD.decode(&passkey.public_key)?)?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+        verifier.update(&signed)?;
+        if !verifier.verify(&base64_field(response, "signature")?)? {
+            bail!("bad signature");
+        }
+        // A counter that went backwards means two authenticators share the key
+        if (data.sign_count != 0 || passkey.sign_count != 0) && data.sign_count <= passkey.sign_count {
+            bail!("signature counter went from {} to {}", passkey.sign_count, data.sign_count);
+        }
+
+        let (account_id, passkey_id, sign_count) = (account.id.clone(), passkey.id.clone(), data.sign_count);
+        self.accounts
+            .update(|accounts| {
+                let account = accounts.get_mut(&account_id)?;
+                if let Some(passkey) = account.passkeys.iter_mut().find(|passkey| passkey.id == passkey_id) {
+                    passkey.sign_count = sign_count;
+                }
+                account.last_login = Some(Utc::now());
+                Some(account.clone())
+            })
+            .await
+            .context("account deleted meanwhile")
+    }
+
+    /// A new random challenge for `ceremony`, base64url
+    fn challenge(&self, ceremony: Ceremony) -> String {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let challenge = URL_SAFE_NO_PAD.encode(bytes);
+
+        let now = Utc::now();
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, challenge| challenge.expires > now);
+        challenges.insert(challenge.clone(), Challenge { ceremony, expires: now + Duration::minutes(CHALLENGE_MINUTES) });
+        challenge
+    }
+
+    /// The ceremony `challenge` was issued for, if it is still open; it can't be used again
+    fn take_challenge(&self, challenge: &str) -> Option<Ceremony> {
+        let challenge = self.challenges.lock().unwrap().remove(challenge)?;
+        (challenge.expires > Utc::now()).then_some(challenge.ceremony)
+    }
+
+    /// Checks `clientDataJSON` was made by this site for a `kind` ceremony and returns its challenge
+    fn check_client_data(&self, client_data: &[u8], kind: &str) -> Result<String> {
+        let client_data: Value = serde_json::from_slice(client_data).context("clientDataJSON isn't JSON")?;
+        if client_data["type"] != kind {
+            bail!("expected a {} ceremony", kind);
+        }
+        if client_data["origin"] != *self.origin {
+            bail!("made for another origin");
+        }
+        if client_data["crossOrigin"] == true {
+            bail!("made in a cross-origin frame");
+        }
+        Ok(client_data["challenge"].as_str().context("no challenge")?.to_string())
+    }
+
+    fn check_authenticator_data(&self, data: &AuthenticatorData) -> Result<()> {
+        if *hash(MessageDigest::sha256(), self.rp_id.as_bytes())? != data.rp_id_hash[..] {
+            bail!("made for another relying party");
+        }
+        if data.flags & USER_PRESENT == 0 {
+            bail!("the user wasn't present");
+        }
+        Ok(())
+    }
+
+    /// The credential in an attestation object; the attestation statement itself is ignored
+    fn parse_attestation(&self, attestation_object: &[u8]) -> Result<Passkey> {
+        let object: Cbor = ciborium::de::from_reader(attestation_object).context("attestationObject isn't CBOR")?;
+        let auth_data = cbor_entry(&object, &Cbor::Text("authData".to_string()))
+            .and_then(Cbor::as_bytes)
+            .context("attestationObject has no authData")?;
+        let data = AuthenticatorData::parse(auth_data)?;
+        self.check_authenticator_data(&data)?;
+        let (id, cose_key) = data.credential.context("no credential in authData")?;
+        let (public_key, algorithm) = cose_public_key(&cose_key)?;
+
+        Ok(Passkey {
+            id: URL_SAFE_NO_PAD.encode(id),
+            public_key: URL_SAFE_NO_PAD.encode(public_key.public_key_to_der()?),
+            algorithm,
+            sign_count: data.sign_count,
+            created_at: Utc::now(),
+        })
+    }
+}
+
+/// The signed-in account, if the request carries a valid `cat_account` cookie
+///
+/// Use it as an extractor in any handler that serves something personal.
+#[derive(Debug, Clone)]
+pub struct CurrentAccount(pub Option<Account>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for CurrentAccount {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        Ok(Self(state.accounts.from_headers(&parts.headers).await))
+    }
+}
+
+/// Authenticator data (WebAuthn §6.1), as far as it matters here
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    /// Credential id and COSE public key, present when registering
+    credential: Option<(Vec<u8>, Cbor)>,
+}
+
+impl AuthenticatorData {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 37 {
+            bail!("authenticator data too short");
+        }
+        let flags = bytes[32];
+        let sign_count = u32::from_be_bytes(bytes[33..37].try_into()?);
+
+        let credential = if flags & ATTESTED_CREDENTIAL_DATA != 0 {
+            // AAGUID (16 bytes), then the id's length, the id and the key
+            let length = bytes.get(53..55).context("attested credential data too short")?;
+            let length = u16::from_be_bytes([length[0], length[1]]) as usize;
+            let id = bytes.get(55..55 + length).context("credential id too short")?.to_vec();
+            let mut key_bytes = &bytes[55 + length..];
+            let key: Cbor = ciborium::de::from_reader(&mut key_bytes).context("credential public key isn't CBOR")?;
+            Some((id, key))
+        } else {
+            None
+        };
+
+        Ok(Self { rp_id_hash: bytes[..32].to_vec(), flags, sign_count, credential })
+    }
+}
+
+/// The key in a COSE_Key (RFC 9053), with its algorithm
+fn cose_public_key(key: &Cbor) -> Result<(PKey<Public>, i64)> {
+    let field = |label: i64| cbor_entry(key, &Cbor::Integer(label.into()));
+    let int = |label: i64| field(label).and_then(Cbor::as_integer).and_then(|value| i64::try_from(value).ok());
+    let bytes = |label: i64| field(label).and_then(Cbor::as_bytes).context("incomplete key");
+
+    match (int(1), int(3)) {
+        // EC2 on P-256
+        (Some(2), Some(ES256)) if int(-1) == Some(1) => {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+            let mut point = vec![4u8];
+            point.extend(bytes(-2)?);
+            point.extend(bytes(-3)?);
+            let mut ctx = BigNumContext::new()?;
+            let point = EcPoint::from_bytes(&group, &point, &mut ctx)?;
+            Ok((PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)?, ES256))
+        }
+        // RSA
+        (Some(3), Some(RS256)) => {
+            let rsa = Rsa::from_public_components(BigNum::from_slice(bytes(-1)?)?, BigNum::from_slice(bytes(-2)?)?)?;
+            Ok((PKey::from_rsa(rsa)?, RS256))
+        }
+        (kty, alg) => bail!("unsupported key type {:?} with algorithm {:?}", kty, alg),
+    }
+}
+
+fn cbor_entry<'a>(map: &'a Cbor, key: &Cbor) -> Option<&'a Cbor> {
+    map.as_map()?.iter().find(|(k, _)| k == key).map(|(_, value)| value)
+}
+
+fn base64_field(object: &Value, name: &str) -> Result<Vec<u8>> {
+    let value = object[name].as_str().with_context(|| format!("missing {}", name))?;
+    URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).with_context(|| format!("{} isn't base64url", name))
+}
+
+/// A name made of 3 to 32 lower-case letters, digits, `-`, `_` and `.`
+fn valid_username(username: &str) -> Result<String, AccountError> {
+    let username = username.trim().to_lowercase();
+    let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.');
+    if !(3..=32).contains(&username.len()) || !username.chars().all(allowed) {
+        return Err(AccountError::Invalid(
+            "Names are 3 to 32 letters, digits, dashes, underscores or dots".to_string(),
+        ));
+    }
+    Ok(username)
+}
+
+fn hash_password(password: &str) -> Result<String> {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = SaltString::encode_b64(&salt).map_err(|err| anyhow::anyhow!("Bad salt: {}", err))?;
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow::anyhow!("Failed to hash a password: {}", err))?
+        .to_string())
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
@@ -0,0 +1,742 @@
+//! # ActivityPub
+//!
+//! Makes the cat followable from Mastodon and the rest of the fediverse as
+//! `@{ACTIVITYPUB_USERNAME}@{PUBLIC_URL's host}`. The actor, its collections
+//! and the inbox are served by `routes/activitypub.rs`; this module holds the
+//! actor's key, its followers, HTTP signatures and delivery.
+//!
+//! - The actor's RSA key pair is generated on first start and kept in
+//!   `DATA_DIR/activitypub.pem`. Losing it means remote servers no longer
+//!   trust the actor's signatures, so it is worth backing up.
+//! - Followers (and the dates already published) are kept in
+//!   `DATA_DIR/federation.json`. Follows are accepted automatically.
+//! - Once `ACTIVITYPUB_POST_TIME` has passed in `SITE_TIMEZONE`, the day's
+//!   wisdom is published as a `Note`, delivered once per inbox (a server's
+//!   shared inbox when it has one). Failed deliveries are not retried.
+//!
+//! Requests are signed with draft-cavage HTTP Signatures, as Mastodon
+//! expects: `rsa-sha256` over `(request-target)`, `host`, `date` and, for
+//! POSTs, a `SHA-256` `digest` of the body. Incoming activities must be signed
+//! the same way by the actor they claim to come from; keys are fetched from
+//! the signature's `keyId` with a signed GET.
+//!
+//! Those URLs come from strangers, so the cat only ever fetches from and
+//! delivers to `https` URLs whose host resolves to public addresses (not
+//! loopback, private or link-local ones), connects to the address it checked,
+//! and doesn't follow redirects. `ACTIVITYPUB_ALLOW_LOCAL` lifts this for
+//! tests and local development.
+//!
+//! Without `ACTIVITYPUB_USERNAME` there is no actor and nothing is generated.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use openssl::{
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    sha::sha256,
+    sign::{Signer, Verifier},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::json_file::JsonFile;
+use crate::rate_limit::RateLimiter;
+use crate::state::{AppState, DailyWisdom};
+use crate::webhooks::DISPLAY_NAME;
+
+/// Media type of ActivityPub documents
+pub const ACTIVITY_JSON: &str = "application/activity+json";
+/// Addressing an object to this makes it public
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+/// The JSON-LD context of ActivityStreams documents
+pub const ACTIVITYSTREAMS: &str = "https://www.w3.org/ns/activitystreams";
+const SECURITY: &str = "https://w3id.org/security/v1";
+
+/// How far a signed request's `Date` may be from ours
+pub const MAX_CLOCK_SKEW_SECS: i64 = 60 * 60;
+
+/// How often the scheduler checks whether the day's note is due
+const TICK: Duration = Duration::from_secs(30);
+
+/// How long a remote server gets to answer a fetch or delivery
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size of the generated RSA key, as Mastodon uses
+const KEY_BITS: u32 = 2048;
+
+/// How many of one actor's mentions are answered per hour; each costs a model call
+pub const MAX_MENTIONS_PER_HOUR: usize = 10;
+
+/// Mentions asking more than this are not answered
+pub const MAX_MENTION_CHARS: usize = 1000;
+
+/// A remote actor following the cat
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Follower {
+    /// The follower's actor id
+    pub actor: String,
+    /// Where notes are delivered: the server's shared inbox, or the actor's own
+    pub inbox: String,
+    pub since: DateTime<Utc>,
+}
+
+/// Everything the federation keeps in `federation.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FederationData {
+    #[serde(default)]
+    followers: Vec<Follower>,
+    /// Dates whose note was published
+    #[serde(default)]
+    published: Vec<NaiveDate>,
+}
+
+/// The actor's followers, published dates and key, as kept in backups
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FederationBackup {
+    #[serde(default)]
+    pub followers: Vec<Follower>,
+    #[serde(default)]
+    pub published: Vec<NaiveDate>,
+    /// The contents of `activitypub.pem`
+    pub private_key_pem: String,
+}
+
+impl FederationBackup {
+    /// Fails if the key can't be read
+    pub fn validate(&self) -> Result<()> {
+        PKey::private_key_from_pem(self.private_key_pem.as_bytes()).context("The backup's ActivityPub key is invalid")?;
+        Ok(())
+    }
+}
+
+/// The parts of a remote actor document the cat uses
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteActor {
+    pub id: String,
+    pub inbox: String,
+    #[serde(default)]
+    pub endpoints: Option<Endpoints>,
+    #[serde(default)]
+    pub preferred_username: Option<String>,
+    /// The actor's profile page
+    #[serde(default)]
+    pub url: Option<String>,
+    pub public_key: PublicKey,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoints {
+    #[serde(default)]
+    pub shared_inbox: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKey {
+    pub id: String,
+    pub owner: String,
+    pub public_key_pem: String,
+}
+
+impl RemoteActor {
+    /// `@user@host`, for mentioning the actor
+    pub fn handle(&self) -> String {
+        let host = reqwest::Url::parse(&self.id).ok().and_then(|url| url.host_str().map(String::from));
+        format!(
+            "@{}@{}",
+            self.preferred_username.as_deref().unwrap_or("someone"),
+            host.unwrap_or_default()
+        )
+    }
+}
+
+/// The cat's actor: its key pair, followers and delivery
+#[derive(Clone)]
+pub struct Federation {
+    username: String,
+    public_url: String,
+    key: Arc<PKey<Private>>,
+    public_key_pem: Arc<str>,
+    key_path: Arc<Path>,
+    data: JsonFile<FederationData>,
+    /// Whether `http` URLs and local addresses may be reached (`ACTIVITYPUB_ALLOW_LOCAL`)
+    allow_local: bool,
+    /// Mentions answered per remote actor
+    mentions: RateLimiter,
+}
+
+impl Federation {
+    /// Loads or generates the actor's key in `data_dir` and opens the
+    /// followers; `None` unless `ACTIVITYPUB_USERNAME` is set
+    pub fn open(config: &Config, data_dir: &Path) -> Result<Option<Self>> {
+        let Some(username) = config.activitypub_username.clone() else {
+            return Ok(None);
+        };
+
+        let key_path = data_dir.join("activitypub.pem");
+        let key = if key_path.exists() {
+            let pem = std::fs::read(&key_path).with_context(|| format!("Failed to read {}", key_path.display()))?;
+            PKey::private_key_from_pem(&pem).with_context(|| format!("Failed to parse {}", key_path.display()))?
+        } else {
+            info!("Generating the ActivityPub key pair in {}", key_path.display());
+            let key = PKey::from_rsa(Rsa::generate(KEY_BITS)?)?;
+            std::fs::create_dir_all(data_dir)?;
+            std::fs::write(&key_path, key.private_key_to_pem_pkcs8()?)
+                .with_context(|| format!("Failed to write {}", key_path.display()))?;
+            key
+        };
+        let public_key_pem = String::from_utf8(key.public_key_to_pem()?)?;
+
+        Ok(Some(Self {
+            username,
+            public_url: config.public_url.clone(),
+            key: Arc::new(key),
+            public_key_pem: public_key_pem.into(),
+            key_path: key_path.into(),
+            data: JsonFile::open(data_dir.join("federation.json"))?,
+            allow_local: config.activitypub_allow_local,
+            mentions: RateLimiter::new(MAX_MENTIONS_PER_HOUR, Duration::from_secs(60 * 60)),
+        }))
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// The host the cat's handle lives on
+    pub fn domain(&self) -> String {
+        reqwest::Url::parse(&self.public_url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default()
+    }
+
+    /// The WebFinger resource naming the cat: `acct:user@host`
+    pub fn resource(&self) -> String {
+        format!("acct:{}@{}", self.username, self.domain())
+    }
+
+    pub fn actor_id(&self) -> String {
+        format!("{}/ap/actor", self.public_url)
+    }
+
+    pub fn key_id(&self) -> String {
+        format!("{}#main-key", self.actor_id())
+    }
+
+    pub fn followers_id(&self) -> String {
+        format!("{}/ap/followers", self.public_url)
+    }
+
+    pub fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    /// The actor document
+    pub fn actor(&self) -> Value {
+        let actor = self.actor_id();
+        json!({
+            "@context": [ACTIVITYSTREAMS, SECURITY],
+            "id": actor,
+            "type": "Service",
+            "preferredUsername": self.username,
+            "name": DISPLAY_NAME,
+            "summary": "<p>A Whispurr of wisdom for stressed professionals, every day. Mention me to ask the cat anything.</p>",
+            "url": self.public_url,
+            "inbox": format!("{}/ap/inbox", self.public_url),
+            "outbox": format!("{}/ap/outbox", self.public_url),
+            "followers": self.followers_id(),
+            "manuallyApprovesFollowers": false,
+            "discoverable": true,
+            "icon": {
+                "type": "Image",
+                "mediaType": "image/png",
+                "url": format!("{}/static/images/quantum-cat.png", self.public_url),
+            },
+            "publicKey": { "id": self.key_id(), "owner": actor, "publicKeyPem": &*self.public_key_pem },
+        })
+    }
+
+    /// The public note for a day's wisdom
+    pub fn note(&self, date: NaiveDate, wisdom: &DailyWisdom) -> Value {
+        let url = format!("{}/wisdom/{}", self.public_url, date);
+        json!({
+            "id": format!("{}/ap/notes/{}", self.public_url, date),
+            "type": "Note",
+            "attributedTo": self.actor_id(),
+            "published": published(date),
+            "to": [PUBLIC],
+            "cc": [self.followers_id()],
+            "url": url,
+            "content": format!(
+                "<p>{}</p><p>— {}, {} · <a href=\"{}\">{}</a></p>",
+                escape(&wisdom.text),
+                DISPLAY_NAME,
+                date.format("%B %-d, %Y"),
+                escape(&url),
+                escape(&url),
+            ),
+        })
+    }
+
+    /// A `Create` activity publishing `note`
+    pub fn create(&self, note: &Value) -> Value {
+        json!({
+            "@context": ACTIVITYSTREAMS,
+            "id": format!("{}/activity", note["id"].as_str().unwrap_or_default()),
+            "type": "Create",
+            "actor": self.actor_id(),
+            "published": note["published"],
+            "to": note["to"],
+            "cc": note["cc"],
+            "object": note,
+        })
+    }
+
+    /// A fresh id for an activity or reply that isn't stored
+    pub fn new_id(&self, kind: &str) -> String {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        format!("{}/ap/{}/{}", self.public_url, kind, hex::encode(bytes))
+    }
+
+    pub async fn followers(&self) -> Vec<Follower> {
+        self.data.read(|data| data.followers.clone()).await
+    }
+
+    /// Adds a follower, or updates its inbox if it follows already
+    pub async fn add_follower(&self, actor: &RemoteActor) {
+        let inbox = actor
+            .endpoints
+            .as_ref()
+            .and_then(|endpoints| endpoints.shared_inbox.clone())
+            .unwrap_or_else(|| actor.inbox.clone());
+        let follower = Follower { actor: actor.id.clone(), inbox, since: Utc::now() };
+        self.data
+            .update(move |data| {
+                data.followers.retain(|f| f.actor != follower.actor);
+                data.followers.push(follower);
+            })
+            .await
+    }
+
+    /// Removes a follower; whether it was following
+    pub async fn remove_follower(&self, actor: &str) -> bool {
+        self.data
+            .update(|data| {
+                let before = data.followers.len();
+                data.followers.retain(|f| f.actor != actor);
+                data.followers.len() < before
+            })
+            .await
+    }
+
+    /// The followers, published dates and key, for backups
+    pub async fn export(&self) -> Result<FederationBackup> {
+        let (followers, published) = self.data.read(|data| (data.followers.clone(), data.published.clone())).await;
+        Ok(FederationBackup {
+            followers,
+            published,
+            private_key_pem: String::from_utf8(self.key.private_key_to_pem_pkcs8()?)?,
+        })
+    }
+
+    /// Adds the backup's followers (replacing those with the same actor) and
+    /// published dates, and writes its key to `activitypub.pem`; with
+    /// `replace`, other followers and dates are dropped first
+    ///
+    /// The key is used from the next start on, as remote servers would
+    /// otherwise see it change in the middle of a delivery.
+    pub async fn import(&self, backup: FederationBackup, replace: bool) -> Result<()> {
+        backup.validate()?;
+        std::fs::write(&self.key_path, &backup.private_key_pem)
+            .with_context(|| format!("Failed to write {}", self.key_path.display()))?;
+
+        self.data
+            .update(|data| {
+                if replace {
+                    *data = FederationData::default();
+                }
+                data.followers.retain(|f| !backup.followers.iter().any(|i| i.actor == f.actor));
+                data.followers.extend(backup.followers);
+                data.published.extend(backup.published);
+                data.published.sort();
+                data.published.dedup();
+            })
+            .await;
+        Ok(())
+    }
+
+    /// Whether a mention by `actor` may be answered, counting it if so;
+    /// false once they had `MAX_MENTIONS_PER_HOUR` in the last hour
+    pub fn allow_mention(&self, actor: &str) -> bool {
+        self.mentions.allow(actor)
+    }
+
+    /// Records `date` as published; false if it already was
+    async fn mark_published(&self, date: NaiveDate) -> bool {
+        self.data
+            .update(|data| {
+                if data.published.contains(&date) {
+                    return false;
+                }
+                data.published.push(date);
+                true
+            })
+            .await
+    }
+
+    /// Fetches the actor document at `url` (any `#fragment` is dropped)
+    pub async fn fetch_actor(&self, url: &str) -> Result<RemoteActor> {
+        let mut url = reqwest::Url::parse(url).context("Invalid actor URL")?;
+        url.set_fragment(None);
+
+        let client = pinned_client(&url, self.allow_local).await?;
+        let mut request = client.get(url.clone()).header(reqwest::header::ACCEPT, ACTIVITY_JSON);
+        // Servers in "authorized fetch" mode only answer signed GETs
+        for (name, value) in sign_request(&self.key, &self.key_id(), "GET", &url, None, Utc::now())? {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?.error_for_status()?;
+        response.json().await.context("Invalid actor document")
+    }
+
+    /// POSTs a signed activity to an inbox
+    pub async fn deliver(&self, inbox: &str, activity: &Value) -> Result<()> {
+        let url = reqwest::Url::parse(inbox).context("Invalid inbox URL")?;
+        let body = activity.to_string();
+
+        let client = pinned_client(&url, self.allow_local).await?;
+        let mut request = client.post(url.clone()).header(reqwest::header::CONTENT_TYPE, ACTIVITY_JSON);
+        for (name, value) in sign_request(&self.key, &self.key_id(), "POST", &url, Some(body.as_bytes()), Utc::now())? {
+            request = request.header(name, value);
+        }
+        request.body(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// A client that may only reach `url`'s host at the address
+/// `remote_address` checked, so a second DNS answer can't point it elsewhere,
+/// and that doesn't follow redirects
+pub async fn pinned_client(url: &reqwest::Url, allow_local: bool) -> Result<reqwest::Client> {
+    let address = remote_address(url, allow_local).await?;
+    let mut builder = reqwest::Client::builder().timeout(REMOTE_TIMEOUT).redirect(reqwest::redirect::Policy::none());
+    if let Some(domain) = url.domain() {
+        builder = builder.resolve(domain, address);
+    }
+    Ok(builder.build()?)
+}
+
+/// The address the cat may reach `url` at: the URL must be `https` and its
+/// host must resolve only to public addresses, unless `allow_local` is set
+pub async fn remote_address(url: &reqwest::Url, allow_local: bool) -> Result<SocketAddr> {
+    match url.scheme() {
+        "https" => {}
+        "http" if allow_local => {}
+        scheme => anyhow::bail!("Refusing to reach a {} URL", scheme),
+    }
+    let host = url.host_str().context("URL has no host")?;
+    let port = url.port_or_known_default().context("URL has no port")?;
+    // IPv6 literals keep their brackets in `host_str`
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("Failed to resolve {}", host))?
+        .collect();
+    if !allow_local {
+        if let Some(local) = addresses.iter().find(|address| !is_public(address.ip())) {
+            anyhow::bail!("Refusing to reach {}, which resolves to {}", host, local.ip());
+        }
+    }
+    addresses.into_iter().next().with_context(|| format!("{} has no addresses", host))
+}
+
+/// Whether `ip` is on the public internet, rather than loopback, private,
+/// link-local (cloud metadata lives there), shared or otherwise special
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let segments = ip.segments();
+            let embedded = |high: u16, low: u16| IpAddr::V4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+            // NAT64 (64:ff9b::/96) and 6to4 (2002::/16) addresses reach the IPv4
+            // address they carry, so that address decides
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return is_public(embedded(segments[6], segments[7]));
+            }
+            if segments[0] == 0x2002 {
+                return is_public(embedded(segments[1], segments[2]));
+            }
+            let first = segments[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                // IPv4-compatible (::/96), local-use NAT64 (64:ff9b:1::/48),
+                // Teredo (2001::/32), which hides its IPv4 address, and
+                // documentation (2001:db8::/32)
+                || segments[..6] == [0; 6]
+                || segments[..3] == [0x64, 0xff9b, 1]
+                || segments[..2] == [0x2001, 0]
+                || segments[..2] == [0x2001, 0xdb8])
+        }
+    }
+}
+
+/// Publishes a day's note to every follower's inbox, and returns how many
+/// inboxes took it
+pub async fn publish(federation: &Federation, date: NaiveDate, wisdom: &DailyWisdom) -> usize {
+    let activity = federation.create(&federation.note(date, wisdom));
+
+    let mut inboxes: Vec<String> = federation.followers().await.into_iter().map(|f| f.inbox).collect();
+    inboxes.sort();
+    inboxes.dedup();
+
+    let mut delivered = 0;
+    for inbox in inboxes {
+        match federation.deliver(&inbox, &activity).await {
+            Ok(()) => delivered += 1,
+            Err(err) => warn!("Failed to deliver the {} note to {}: {:#}", date, inbox, err),
+        }
+    }
+    info!("Published the {} note to {} inboxes", date, delivered);
+    delivered
+}
+
+/// The newest date whose note is public at `now`: the site's today once
+/// `ACTIVITYPUB_POST_TIME` has passed there, and yesterday before
+pub fn last_published(config: &Config, now: DateTime<Utc>) -> NaiveDate {
+    let local = now.with_timezone(&config.site_timezone);
+    if local.time() < config.activitypub_post_time {
+        local.date_naive() - chrono::Duration::days(1)
+    } else {
+        local.date_naive()
+    }
+}
+
+/// Publishes the site's day's note if `ACTIVITYPUB_POST_TIME` has passed and
+/// it wasn't published yet; returns how many inboxes took it, or `None` if
+/// nothing was due
+///
+/// While only the corpus stands in for the day's wisdom, the note waits for
+/// a later tick.
+pub async fn publish_due(state: &AppState, now: DateTime<Utc>) -> Option<usize> {
+    let federation = state.federation.as_ref()?;
+    let local = now.with_timezone(&state.config.site_timezone);
+    if local.time() < state.config.activitypub_post_time {
+        return None;
+    }
+
+    let date = local.date_naive();
+    let wisdom = state.broadcast_wisdom(date).await?;
+    // Marked first, so a failing inbox isn't retried every tick
+    if !federation.mark_published(date).await {
+        return None;
+    }
+    Some(publish(federation, date, &wisdom).await)
+}
+
+/// Starts the background task that publishes the daily note, if the cat has an actor
+pub fn spawn_scheduler(state: AppState) -> Option<JoinHandle<()>> {
+    let federation = state.federation.as_ref()?;
+    info!("Publishing the daily wisdom as {}", federation.resource());
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            publish_due(&state, Utc::now()).await;
+        }
+    }))
+}
+
+/// The headers that sign a request to `url`: `Date`, `Signature` and, with a
+/// body, `Digest` (`Host` is set by the HTTP client from the URL)
+pub fn sign_request(
+    key: &PKey<Private>,
+    key_id: &str,
+    method: &str,
+    url: &reqwest::Url,
+    body: Option<&[u8]>,
+    now: DateTime<Utc>,
+) -> Result<Vec<(&'static str, String)>> {
+    let date = now.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let target = match url.query() {
+        Some(query) => format!("{} {}?{}", method.to_lowercase(), url.path(), query),
+        None => format!("{} {}", method.to_lowercase(), url.path()),
+    };
+
+    let mut signed = vec![("(request-target)", target), ("host", host), ("date", date.clone())];
+    let mut headers = vec![("Date", date)];
+    if let Some(body) = body {
+        let digest = digest(body);
+        signed.push(("digest", digest.clone()));
+        headers.push(("Digest", digest));
+    }
+
+    let signing_string: Vec<String> = signed.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+    let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+    signer.update(signing_string.join("\n").as_bytes())?;
+    let signature = base64::engine::general_purpose::STANDARD.encode(signer.sign_to_vec()?);
+
+    let names: Vec<&str> = signed.iter().map(|(name, _)| *name).collect();
+    headers.push((
+        "Signature",
+        format!(
+            "keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"{}\",signature=\"{}\"",
+            key_id,
+            names.join(" "),
+            signature
+        ),
+    ));
+    Ok(headers)
+}
+
+/// The `Digest` header for a body
+pub fn digest(body: &[u8]) -> String {
+    format!("SHA-256={}", base64::engine::general_purpose::STANDARD.encode(sha256(body)))
+}
+
+/// A parsed `Signature` header
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureHeader {
+    pub key_id: String,
+    /// The signed headers, in order; `date` alone if the header doesn't say
+    pub headers: Vec<String>,
+    pub signature: Vec<u8>,
+}
+
+impl SignatureHeader {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut key_id = None;
+        let mut headers = vec!["date".to_string()];
+        let mut signature = None;
+        for part in value.split(',') {
+            let (name, value) = part.trim().split_once('=')?;
+            let value = value.trim_matches('"');
+            match name {
+                "keyId" => key_id = Some(value.to_string()),
+                "headers" => headers = value.split_whitespace().map(str::to_lowercase).collect(),
+                "signature" => signature = base64::engine::general_purpose::STANDARD.decode(value).ok(),
+                _ => {}
+            }
+        }
+        Some(Self { key_id: key_id?, headers, signature: signature? })
+    }
+
+    /// The string the sender signed, rebuilt from the request; `None` if a
+    /// signed header is missing
+    pub fn signing_string(&self, method: &str, path_and_query: &str, request_headers: &HeaderMap) -> Option<String> {
+        let lines: Option<Vec<String>> = self
+            .headers
+            .iter()
+            .map(|name| match name.as_str() {
+                "(request-target)" => Some(format!("(request-target): {} {}", method.to_lowercase(), path_and_query)),
+                name => {
+                    let value = request_headers.get(name)?.to_str().ok()?;
+                    Some(format!("{}: {}", name, value))
+                }
+            })
+            .collect();
+        Some(lines?.join("\n"))
+    }
+
+    /// Whether `public_key_pem` signed `signing_string` with this signature
+    pub fn verify(&self, public_key_pem: &str, signing_string: &str) -> bool {
+        let Ok(key) = PKey::public_key_from_pem(public_key_pem.as_bytes()) else {
+            return false;
+        };
+        Verifier::new(MessageDigest::sha256(), &key)
+            .and_then(|mut verifier| {
+                verifier.update(signing_string.as_bytes())?;
+                verifier.verify(&self.signature)
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Publication time of a date's note: the start of that day, UTC
+fn published(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+/// Escapes text for use in HTML content and attribute values
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The text of a note's HTML `content`: tags dropped, paragraphs and line
+/// breaks kept as newlines, and the common entities decoded
+pub(crate) fn plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_ascii_lowercase();
+        if tag.starts_with("br") || tag == "/p" {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
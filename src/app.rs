@@ -0,0 +1,248 @@
+//! # Application Builder
+//!
+//! Assembles the router, middleware and shared state. The binary builds the app
+//! from environment variables; tests and tools build it from an explicit
+//! `Config` and can swap in their own `LlmProvider` (for example one pointed at
+//! `mock_mistral::MockMistral`).
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, patch, post},  // HTTP method handlers
+    Router,                // Main router for defining routes
+};
+use tower_http::{
+    cors::{Any, CorsLayer},  // Cross-Origin Resource Sharing middleware
+    services::ServeDir,      // For serving static files (CSS, JS, images)
+    trace::TraceLayer,       // For request/response tracing
+};
+
+use crate::config::Config;
+use crate::corpus::Corpus;
+use crate::fixtures::{FixtureMode, FixtureStore, ReplayProvider};
+use crate::mistral::{LlmProvider, MistralHttp};
+use crate::negotiation;
+use crate::safety::SafetyRules;
+use crate::routes;
+use crate::state::AppState;
+
+/// Entry point for building an instance of The Enlightened Cat
+pub struct App;
+
+impl App {
+    /// Starts building an app from the given configuration
+    pub fn builder(config: Config) -> AppBuilder {
+        AppBuilder {
+            config,
+            provider: None,
+            corpus: None,
+        }
+    }
+}
+
+/// Collects the injected pieces before the app is assembled
+///
+/// Anything not supplied explicitly is derived from the configuration:
+/// the provider defaults to `MistralHttp` (recording or replaying fixtures if
+/// `fixture_mode` says so), the corpus to the bundled one extended by
+/// `corpus_path`, and the safety rules likewise by `safety_rules_path`.
+pub struct AppBuilder {
+    config: Config,
+    provider: Option<Arc<dyn LlmProvider>>,
+    corpus: Option<Corpus>,
+}
+
+impl AppBuilder {
+    /// Uses the given language model provider instead of the Mistral HTTP API
+    pub fn provider(mut self, provider: Arc<dyn LlmProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Uses the given corpus instead of loading one from the configuration
+    pub fn corpus(mut self, corpus: Corpus) -> Self {
+        self.corpus = Some(corpus);
+        self
+    }
+
+    /// Builds the shared application state
+    pub fn build_state(self) -> Result<AppState> {
+        let provider: Arc<dyn LlmProvider> = match (self.provider, self.config.fixture_mode) {
+            (Some(provider), _) => provider,
+            (None, FixtureMode::Off) => Arc::new(MistralHttp::new(&self.config)?),
+            (None, FixtureMode::Record) => Arc::new(
+                MistralHttp::new(&self.config)?.recording(FixtureStore::new(&self.config.fixture_dir)),
+            ),
+            (None, FixtureMode::Replay) => Arc::new(ReplayProvider::new(FixtureStore::new(&self.config.fixture_dir))),
+        };
+
+        let corpus = match self.corpus {
+            Some(corpus) => corpus,
+            None => Corpus::load(self.config.corpus_path.as_deref().map(Path::new))?,
+        };
+
+        let safety = SafetyRules::load(self.config.safety_rules_path.as_deref().map(Path::new))?;
+
+        AppState::new(self.config, provider, corpus, safety)
+    }
+
+    /// Builds the complete router with all routes, middleware and state attached
+    pub fn build(self) -> Result<Router> {
+        Ok(router(self.build_state()?))
+    }
+}
+
+/// When the unversioned `/api/*` aliases stop being served
+pub const LEGACY_API_SUNSET: &str = "Mon, 01 Mar 2027 00:00:00 GMT";
+
+/// The versioned JSON API, mounted at `/api/v1`
+fn api_v1() -> Router<AppState> {
+    Router::new()
+        .route("/chat", post(routes::chat::handle_chat))             // POST /api/v1/chat - Chat with the cat
+        .route("/daily-wisdom", get(routes::wisdom::get_daily_wisdom)) // GET /api/v1/daily-wisdom - Get wisdom as JSON
+        .route("/quantum-field", get(routes::quantum_field::get_quantum_field)) // GET /api/v1/quantum-field - Get quantum field
+        .route("/quantum-field/collapse", get(routes::quantum_field::collapse_quantum_field)) // GET /api/v1/quantum-field/collapse - Collapse quantum field
+        .route("/quantum-field/readings/:id", patch(routes::quantum_field::update_reading)) // PATCH /api/v1/quantum-field/readings/{id} - Share settings
+        .route("/push/public-key", get(routes::push::public_key))   // GET /api/v1/push/public-key - VAPID key for subscribing
+        .route("/push/subscriptions", post(routes::push::subscribe).delete(routes::push::unsubscribe)) // POST/DELETE /api/v1/push/subscriptions
+        .route("/account", get(routes::account::me).delete(routes::account::delete)) // GET/DELETE /api/v1/account - The signed-in account
+        .route("/account/signup", post(routes::account::signup))     // POST /api/v1/account/signup - Sign up with a password
+        .route("/account/login", post(routes::account::login))       // POST /api/v1/account/login - Log in with a password
+        .route("/account/logout", post(routes::account::logout))     // POST /api/v1/account/logout - Sign out
+        .route("/account/passkeys/register-options", post(routes::account::passkey_register_options)) // POST - Challenge for a new passkey
+        .route("/account/passkeys/register", post(routes::account::passkey_register))                 // POST - Store the new passkey
+        .route("/account/passkeys/login-options", post(routes::account::passkey_login_options))       // POST - Challenge to log in
+        .route("/account/passkeys/login", post(routes::account::passkey_login))                       // POST - Log in with a passkey
+        .route("/openapi.json", get(routes::openapi::openapi_json)) // GET /api/v1/openapi.json - API description
+}
+
+/// The deprecated unversioned aliases, mounted at `/api`
+fn legacy_api() -> Router<AppState> {
+    Router::new()
+        .route("/chat", post(routes::chat::handle_chat))
+        .route("/daily-wisdom", get(routes::wisdom::get_daily_wisdom))
+        .route("/quantum-field", get(routes::quantum_field::get_quantum_field))
+        .route("/quantum-field/collapse", get(routes::quantum_field::collapse_quantum_field))
+        .layer(middleware::from_fn(mark_deprecated))
+}
+
+/// Flags responses from the legacy aliases as deprecated (RFC 8594 style)
+/// and points clients at the `/api/v1` successor
+async fn mark_deprecated<B>(request: Request<B>, next: Next<B>) -> Response {
+    // Inside the nested router the `/api` prefix is already stripped
+    let successor = format!("</api/v1{}>; rel=\"successor-version\"", request.uri().path());
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert("sunset", HeaderValue::from_static(LEGACY_API_SUNSET));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert("link", link);
+    }
+    response
+}
+
+/// Builds the router for an existing state
+///
+/// This is similar to defining routes in Express
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        // API routes - JSON endpoints
+        .nest("/api/v1", api_v1())
+        .nest("/api", legacy_api())
+        
+        // Page routes - HTML endpoints
+        .route("/", get(routes::pages::index))           // GET / - Home page
+        .route("/about", get(routes::pages::about))       // GET /about - About page
+        .route("/wisdom", get(routes::pages::wisdom_page)) // GET /wisdom - Daily wisdom page
+        .route("/wisdom/:date", get(routes::pages::wisdom_for_date)) // GET /wisdom/2025-06-01 - One day's wisdom
+        .route("/quantum-field", get(routes::pages::quantum_field_page)) // GET /quantum-field - Quantum field page
+        .route("/quantum-field/reading/:id", get(routes::pages::reading_page)) // GET /quantum-field/reading/{id} - A saved collapse
+        .route("/search", get(routes::pages::search))     // GET /search?q=... - Search past Whispurrs
+        .route("/account", get(routes::account::page))    // GET /account - Sign up, log in, manage the account
+        
+        // Admin console - HTML behind Basic auth, see routes/admin.rs
+        .route("/admin", get(routes::admin::console))                         // GET /admin - Moderation console
+        .route("/admin/wisdom", post(routes::admin::edit_wisdom))             // POST /admin/wisdom - Replace wisdom
+        .route("/admin/wisdom/regenerate", post(routes::admin::regenerate_wisdom)) // POST /admin/wisdom/regenerate
+        .route("/admin/field", post(routes::admin::edit_field))               // POST /admin/field - Replace quantum field
+        .route("/admin/field/regenerate", post(routes::admin::regenerate_field))   // POST /admin/field/regenerate
+        
+        // Feeds of the wisdom history
+        .route("/feed.xml", get(routes::feed::atom))        // GET /feed.xml - Atom feed
+        .route("/feed.json", get(routes::feed::json_feed))  // GET /feed.json - JSON Feed
+        
+        // The fediverse - the cat as an ActivityPub actor, see routes/activitypub.rs
+        .route("/.well-known/webfinger", get(routes::activitypub::webfinger)) // GET - Finds @cat@host
+        .route("/ap/actor", get(routes::activitypub::actor))          // GET /ap/actor - The actor and its key
+        .route("/ap/inbox", post(routes::activitypub::inbox))         // POST /ap/inbox - Signed activities
+        .route("/ap/outbox", get(routes::activitypub::outbox))        // GET /ap/outbox - Recent daily notes
+        .route("/ap/followers", get(routes::activitypub::followers))  // GET /ap/followers - Follower count
+        .route("/ap/notes/:date", get(routes::activitypub::note))     // GET /ap/notes/2025-06-01 - One day's note
+        
+        // Installable and usable offline - see routes/offline.rs
+        .route("/manifest.webmanifest", get(routes::offline::manifest)) // GET - The web app manifest
+        .route("/sw.js", get(routes::offline::service_worker))         // GET /sw.js - Caches the shell, shows push notifications
+        .route("/api/offline-bundle", get(routes::offline::offline_bundle)) // GET - Recent wisdom, the field and the corpus
+
+        // The newsletter - double opt-in signup and one-click unsubscribe, see routes/newsletter.rs
+        .route("/newsletter", get(routes::newsletter::signup))                  // GET /newsletter - Signup form
+        .route("/newsletter/subscribe", post(routes::newsletter::subscribe))    // POST - Mails a confirmation link
+        .route("/newsletter/confirm/:token", get(routes::newsletter::confirm))  // GET - The confirmation link
+        .route(
+            "/newsletter/unsubscribe/:token",
+            get(routes::newsletter::unsubscribe_page).post(routes::newsletter::unsubscribe),
+        ) // GET asks, POST (or a mail client's one-click) unsubscribes
+
+        // For search engines
+        .route("/sitemap.xml", get(routes::seo::sitemap)) // GET /sitemap.xml - Every indexable page
+        .route("/robots.txt", get(routes::seo::robots))   // GET /robots.txt - Crawler rules
+        
+        // Share preview images
+        .route("/og/wisdom/:file", get(routes::og::wisdom_card))   // GET /og/wisdom/2025-06-01.png
+        .route("/og/reading/:file", get(routes::og::reading_card)) // GET /og/reading/{id}.png
+        
+        // Chat platform integrations - verified by each platform's signature or secret
+        .route("/integrations/slack/command", post(routes::slack::command)) // POST - Slack slash commands and buttons
+        .route("/integrations/discord/interactions", post(routes::discord::interactions)) // POST - Discord slash commands and buttons
+        .route("/integrations/telegram/webhook", post(routes::telegram::webhook)) // POST - Telegram bot updates
+        
+        // Serve static files (CSS, JS, images)
+        // Similar to express.static in Node.js
+        .nest_service("/static", ServeDir::new("static"))
+        
+        // Anything else gets the templated 404 page
+        .fallback(routes::pages::not_found)
+        
+        // Add middleware
+        .layer(TraceLayer::new_for_http())  // Add request/response logging
+        .layer(
+            CorsLayer::new()                // Configure CORS policy
+                .allow_origin(Any)          // Allow any origin (can be restricted in production)
+                .allow_methods(Any)         // Allow any HTTP method
+                .allow_headers(Any),        // Allow any headers
+        )
+        // Outside CORS, which would otherwise overwrite the negotiated pages' Vary header
+        .layer(middleware::map_response(negotiation::add_vary))
+        .with_state(state)  // Attach our application state to the router
+}
+
+/// Serves the router on the given address until the process is stopped
+pub async fn serve(router: Router, addr: SocketAddr) -> Result<()> {
+    tracing::info!("Listening on {}", addr);
+    
+    // Start the HTTP server
+    // This is similar to app.listen() in Express
+    // The peer's address is kept for per-client limits (see `rate_limit.rs`)
+    axum::Server::bind(&addr)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! # Audit Log
+//!
+//! Every change an admin makes through the console or the command line is appended to
+//! `<data_dir>/audit.jsonl`, one JSON object per line. The most recent entries
+//! are also kept in memory so the console can show them without reading the file.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+/// How many entries are kept in memory
+const RECENT_ENTRIES: usize = 50;
+
+/// One admin action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the action happened
+    pub at: DateTime<Utc>,
+    /// Who performed it
+    pub actor: String,
+    /// What was done, e.g. `wisdom.regenerate`
+    pub action: String,
+    /// What it was done to, e.g. a date
+    pub target: String,
+    /// Free-form details such as the old and new text
+    pub detail: String,
+}
+
+impl AuditEntry {
+    pub fn new(actor: &str, action: &str, target: impl ToString, detail: impl ToString) -> Self {
+        Self {
+            at: Utc::now(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            detail: detail.to_string(),
+        }
+    }
+}
+
+/// Append-only log of admin actions
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    recent: Arc<Mutex<VecDeque<AuditEntry>>>,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, loading its most recent entries
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut recent = VecDeque::new();
+
+        if path.exists() {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read audit log {}", path.display()))?;
+            for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                if recent.len() == RECENT_ENTRIES {
+                    recent.pop_front();
+                }
+                recent.push_back(serde_json::from_str(line).context("Corrupt audit log entry")?);
+            }
+        }
+
+        Ok(Self { path, recent: Arc::new(Mutex::new(recent)) })
+    }
+
+    /// Appends an entry to the file and the in-memory tail
+    pub async fn record(&self, entry: AuditEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open audit log {}", self.path.display()))?;
+        file.write_all(line.as_bytes()).await?;
+        // tokio's File hands writes to a background task; make sure the line is on disk before we return
+        file.flush().await?;
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_ENTRIES {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+        Ok(())
+    }
+
+    /// Every entry in the file, oldest first, for backups
+    pub async fn all(&self) -> Result<Vec<AuditEntry>> {
+        let contents = match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).with_context(|| format!("Failed to read audit log {}", self.path.display())),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).context("Corrupt audit log entry"))
+            .collect()
+    }
+
+    /// The most recent entries, newest first
+    pub fn recent(&self, limit: usize) -> Vec<AuditEntry> {
+        self.recent.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+}
@@ -0,0 +1,205 @@
+//! # Backups
+//!
+//! Everything the app stores in the data directory, bundled into one JSON
+//! document so it can be moved between machines or kept off-site:
+//! `the-enlightened-cat export > backup.json` and
+//! `the-enlightened-cat import backup.json`.
+//!
+//! Importing merges by key (date, reading id, session id, delivery id, chat
+//! id, follower, email address, account): entries in the backup replace
+//! existing ones with the same key and everything else is kept, unless
+//! `replace` asks for a clean slate. The audit log is only ever appended to;
+//! entries already in the log are skipped. Rendered OG cards are not
+//! included, as they are re-rendered on demand.
+//!
+//! The ActivityPub actor's and the VAPID private keys, and the key that signs
+//! account cookies, travel with the followers, push subscriptions and
+//! accounts that depend on them, so a backup must be kept as safe as the data
+//! directory itself. Importing the first two needs `ACTIVITYPUB_USERNAME` and
+//! `VAPID_SUBJECT` set, and all of them are used from the next start.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::accounts::Account;
+use crate::activitypub::FederationBackup;
+use crate::audit::AuditEntry;
+use crate::newsletter::Subscriber;
+use crate::quantum_field::QuantumField;
+use crate::readings::Reading;
+use crate::sessions::ChatSession;
+use crate::state::{AppState, DailyWisdom};
+use crate::telegram::Subscription;
+use crate::webhooks::Delivery;
+use crate::webpush::PushBackup;
+
+/// Version of the backup layout; bumped on incompatible changes
+pub const BACKUP_FORMAT: u32 = 1;
+
+/// All stored data at one point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub format: u32,
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub wisdom: BTreeMap<NaiveDate, DailyWisdom>,
+    #[serde(default)]
+    pub fields: BTreeMap<NaiveDate, QuantumField>,
+    #[serde(default)]
+    pub readings: HashMap<String, Reading>,
+    #[serde(default)]
+    pub sessions: HashMap<String, ChatSession>,
+    /// The webhook delivery log, so a restored instance doesn't post a day twice
+    #[serde(default)]
+    pub deliveries: Vec<Delivery>,
+    /// Telegram chats subscribed to the daily push
+    #[serde(default)]
+    pub telegram: Vec<Subscription>,
+    /// The fediverse actor's followers and key, if ActivityPub is enabled
+    #[serde(default)]
+    pub federation: Option<FederationBackup>,
+    /// The newsletter's subscribers, pending and confirmed
+    #[serde(default)]
+    pub newsletter: Vec<Subscriber>,
+    /// The VAPID key and browser push subscriptions, if Web Push is enabled
+    #[serde(default)]
+    pub push: Option<PushBackup>,
+    /// Visitor accounts, which point at chat sessions above
+    #[serde(default)]
+    pub accounts: HashMap<String, Account>,
+    /// The generated key in `session.key` that signs account cookies
+    #[serde(default)]
+    pub session_key: Option<String>,
+    #[serde(default)]
+    pub audit: Vec<AuditEntry>,
+}
+
+/// How many entries of each kind an import brought in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub wisdom: usize,
+    pub fields: usize,
+    pub readings: usize,
+    pub sessions: usize,
+    pub deliveries: usize,
+    pub telegram: usize,
+    /// Fediverse followers
+    pub followers: usize,
+    /// Newsletter subscribers
+    pub subscribers: usize,
+    /// Browser push subscriptions
+    pub push: usize,
+    pub accounts: usize,
+    /// Audit entries appended; those already in the log are not counted
+    pub audit: usize,
+}
+
+impl Backup {
+    /// Collects everything currently stored
+    pub async fn collect(state: &AppState) -> Result<Self> {
+        Ok(Self {
+            format: BACKUP_FORMAT,
+            exported_at: Utc::now(),
+            wisdom: state.wisdom_history.all().await,
+            fields: state.quantum_fields.read(BTreeMap::clone).await,
+            readings: state.readings.all().await,
+            sessions: state.sessions.all().await,
+            deliveries: state.webhooks.deliveries().await,
+            telegram: state.telegram.subscriptions().await,
+            federation: match &state.federation {
+                Some(federation) => Some(federation.export().await?),
+                None => None,
+            },
+            newsletter: state.newsletter.subscribers().await,
+            push: match &state.web_push {
+                Some(web_push) => Some(web_push.export().await?),
+                None => None,
+            },
+            accounts: state.accounts.all().await,
+            session_key: state.accounts.session_key()?,
+            audit: state.audit.all().await?,
+        })
+    }
+
+    /// Writes the backup's entries into the stores
+    ///
+    /// With `replace`, wisdom, fields, readings, sessions, deliveries,
+    /// Telegram subscriptions, followers, newsletter subscribers, push
+    /// subscriptions and accounts not in the backup are dropped; the audit log
+    /// is appended to either way.
+    pub async fn restore(self, state: &AppState, replace: bool) -> Result<ImportSummary> {
+        if self.format != BACKUP_FORMAT {
+            bail!("Unsupported backup format {} (expected {})", self.format, BACKUP_FORMAT);
+        }
+
+        let mut summary = ImportSummary {
+            wisdom: self.wisdom.len(),
+            fields: self.fields.len(),
+            readings: self.readings.len(),
+            sessions: self.sessions.len(),
+            deliveries: self.deliveries.len(),
+            telegram: self.telegram.len(),
+            followers: self.federation.as_ref().map_or(0, |federation| federation.followers.len()),
+            subscribers: self.newsletter.len(),
+            push: self.push.as_ref().map_or(0, |push| push.subscriptions.len()),
+            accounts: self.accounts.len(),
+            audit: 0,
+        };
+
+        // Checked before anything is written, so a backup that can't be
+        // imported leaves everything as it was
+        if let Some(backup) = &self.federation {
+            if state.federation.is_none() {
+                bail!("The backup has ActivityPub followers and a key; set ACTIVITYPUB_USERNAME to import them");
+            }
+            backup.validate()?;
+        }
+        if let Some(backup) = &self.push {
+            if state.web_push.is_none() {
+                bail!("The backup has push subscriptions and a VAPID key; set VAPID_SUBJECT to import them");
+            }
+            backup.validate()?;
+        }
+        if let Some(key) = &self.session_key {
+            hex::decode(key).context("The backup's session key is not hex")?;
+        }
+
+        if let (Some(backup), Some(federation)) = (self.federation, &state.federation) {
+            federation.import(backup, replace).await?;
+        }
+        if let (Some(backup), Some(web_push)) = (self.push, &state.web_push) {
+            web_push.import(backup, replace).await?;
+        }
+
+        state.wisdom_history.import(self.wisdom, replace).await;
+        let fields = self.fields;
+        state
+            .quantum_fields
+            .update(|existing| {
+                if replace {
+                    existing.clear();
+                }
+                existing.extend(fields);
+            })
+            .await;
+        state.readings.import(self.readings, replace).await;
+        state.sessions.import(self.sessions, replace).await;
+        state.webhooks.import(self.deliveries, replace).await;
+        state.telegram.import(self.telegram, replace).await;
+        state.newsletter.import(self.newsletter, replace).await;
+        state.accounts.import(self.accounts, self.session_key.as_deref(), replace).await?;
+
+        let existing = state.audit.all().await?;
+        for entry in self.audit {
+            if !existing.contains(&entry) {
+                state.audit.record(entry).await?;
+                summary.audit += 1;
+            }
+        }
+
+        Ok(summary)
+    }
+}
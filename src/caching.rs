@@ -0,0 +1,82 @@
+//! # HTTP Caching
+//!
+//! Helpers for conditional GETs. Handlers that serve content which changes
+//! rarely (feeds, images) describe it with `Validators`; the helpers answer
+//! `If-None-Match` / `If-Modified-Since` with 304 and set `ETag` and
+//! `Last-Modified` on full responses, so aggregators and crawlers can poll
+//! cheaply.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+/// Format of HTTP dates (RFC 7231 IMF-fixdate)
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// What a client can send back to ask "has this changed?"
+#[derive(Debug, Clone)]
+pub struct Validators {
+    /// Quoted strong entity tag
+    pub etag: String,
+    /// When the content last changed
+    pub last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// Validators for `body`, tagged by a hash of its bytes
+    pub fn for_body(body: &[u8], last_modified: DateTime<Utc>) -> Self {
+        let digest = Sha256::digest(body);
+        Self {
+            etag: format!("\"{}\"", hex::encode(&digest[..16])),
+            last_modified,
+        }
+    }
+
+    /// Whether the client's cached copy is still current
+    ///
+    /// As RFC 7232 asks, `If-Modified-Since` is only consulted when the
+    /// request has no `If-None-Match`.
+    pub fn is_fresh(&self, request: &HeaderMap) -> bool {
+        if let Some(tags) = request.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+            return tags
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == self.etag);
+        }
+
+        request
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| self.last_modified.timestamp() <= since.timestamp())
+    }
+
+    /// Adds `ETag` and `Last-Modified` to a response's headers
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Ok(date) = HeaderValue::from_str(&http_date(self.last_modified)) {
+            headers.insert(header::LAST_MODIFIED, date);
+        }
+    }
+}
+
+/// Serves `body` as `content_type`, or a bare 304 if the client's copy is current
+pub fn conditional(request: &HeaderMap, validators: &Validators, content_type: &'static str, body: Vec<u8>) -> Response {
+    let mut response = if validators.is_fresh(request) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        ([(header::CONTENT_TYPE, content_type)], body).into_response()
+    };
+    validators.apply(response.headers_mut());
+    response
+}
+
+/// Formats a timestamp as an HTTP date
+pub fn http_date(at: DateTime<Utc>) -> String {
+    at.format(HTTP_DATE).to_string()
+}
@@ -0,0 +1,219 @@
+//! # Terminal Chat Client
+//!
+//! `the-enlightened-cat chat` talks to a running instance over its JSON API,
+//! for those of us who live in the terminal:
+//!
+//! ```text
+//! the-enlightened-cat chat [--url https://the-enlightened-cat.com] [--timezone Europe/Berlin]
+//! ```
+//!
+//! Anything typed is sent to `POST /api/v1/chat`, and the reply comes back as
+//! the ASCII cat speaking in a word-wrapped bubble (see `terminal.rs`). The
+//! `cat_session` cookie is kept for the whole run, so the conversation has a
+//! memory and collapsed readings belong to the same visitor. A few commands
+//! map to the other APIs:
+//!
+//! - `/wisdom`: today's Whispurr (`GET /api/v1/daily-wisdom`)
+//! - `/field`: today's six Quantum Field nodes (`GET /api/v1/quantum-field`)
+//! - `/collapse N`: collapse node N, as numbered by `/field`
+//!   (`GET /api/v1/quantum-field/collapse`)
+//! - `/help` and `/quit`
+//!
+//! The client reads lines from any `AsyncBufRead` and writes to any `Write`,
+//! so tests can script a whole conversation.
+
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use chrono_tz::Tz;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::quantum_field::WisdomNode;
+use crate::terminal::{cat_says, field_map, wrap, Style};
+use crate::timezone::TIMEZONE_HEADER;
+
+/// Shown on start and for `/help`
+const HELP: &str = "Type a message to talk to the cat, or:
+  /wisdom       today's Whispurr
+  /field        today's Quantum Field
+  /collapse N   collapse node N of the field
+  /help         this list
+  /quit         leave (Ctrl-D works too)";
+
+/// The parts of the API responses the client shows
+#[derive(Debug, Deserialize)]
+struct ChatReply {
+    message: String,
+    #[serde(default)]
+    suggested_topics: Option<Vec<SuggestedTopic>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestedTopic {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WisdomReply {
+    wisdom: String,
+    date: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FieldReply {
+    wisdom_field: Vec<WisdomNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollapseReply {
+    collapsed_prompt: String,
+    #[serde(default)]
+    reading_url: Option<String>,
+}
+
+/// A conversation with one running instance
+pub struct ChatClient {
+    base_url: String,
+    http: reqwest::Client,
+    width: usize,
+    style: Style,
+}
+
+impl ChatClient {
+    /// A client for the instance at `base_url`, asking for "today" in
+    /// `timezone` if given, and wrapping replies at `width` characters
+    pub fn new(base_url: &str, timezone: Option<Tz>, width: usize) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        if let Some(zone) = timezone {
+            headers.insert(TIMEZONE_HEADER, HeaderValue::from_static(zone.name()));
+        }
+
+        let http = reqwest::Client::builder()
+            // Keeps the `cat_session` cookie between requests, like a browser
+            .cookie_store(true)
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self { base_url: base_url.trim_end_matches('/').to_string(), http, width, style: Style::Plain })
+    }
+
+    /// Paints the cat and the field in colour (the binary does this when
+    /// writing to a terminal)
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Reads lines from `input` until it ends or the visitor types `/quit`,
+    /// writing the cat's answers to `out`
+    ///
+    /// A failed request is reported and the conversation carries on.
+    pub async fn run(&self, input: impl AsyncBufRead + Unpin, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "{}", cat_says(&format!("Welcome. I am listening at {}.", self.base_url), self.width, self.style))?;
+        writeln!(out, "{}\n", HELP)?;
+
+        let mut lines = input.lines();
+        loop {
+            write!(out, "you> ")?;
+            out.flush()?;
+            let Some(line) = lines.next_line().await? else {
+                writeln!(out)?;
+                break;
+            };
+
+            let line = line.trim();
+            let answer = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => continue,
+                ["/quit"] | ["/exit"] => break,
+                ["/help"] => Ok(HELP.to_string()),
+                ["/wisdom"] => self.wisdom().await,
+                ["/field"] => self.field().await,
+                ["/collapse", n] => match n.parse() {
+                    Ok(n) => self.collapse(n).await,
+                    Err(_) => Ok("Usage: /collapse N, with N as numbered by /field".to_string()),
+                },
+                [command, ..] if command.starts_with('/') => Ok(format!("Unknown command {}. Try /help.", command)),
+                _ => self.say(line).await,
+            };
+
+            match answer {
+                Ok(answer) => writeln!(out, "{}\n", answer)?,
+                Err(err) => writeln!(out, "The cat can't be reached right now: {:#}\n", err)?,
+            }
+        }
+
+        writeln!(out, "{}", cat_says("May your naps be long and your deadlines far.", self.width, self.style))?;
+        Ok(())
+    }
+
+    /// Sends a message and renders the cat's reply, with any suggested topics
+    pub async fn say(&self, message: &str) -> Result<String> {
+        let reply: ChatReply = self
+            .http
+            .post(self.url("/api/v1/chat"))
+            .json(&json!({ "message": message }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Unexpected chat reply")?;
+
+        let mut rendered = cat_says(&reply.message, self.width, self.style);
+        if let Some(topics) = reply.suggested_topics.filter(|topics| !topics.is_empty()) {
+            let titles: Vec<_> = topics.iter().map(|topic| topic.title.as_str()).collect();
+            rendered.push('\n');
+            rendered.push_str(&wrap(&format!("Paths to explore: {}", titles.join(" · ")), self.width).join("\n"));
+        }
+        Ok(rendered)
+    }
+
+    /// Today's Whispurr
+    pub async fn wisdom(&self) -> Result<String> {
+        let reply: WisdomReply = self.get("/api/v1/daily-wisdom").await?;
+        Ok(cat_says(&format!("{} — {}", reply.wisdom, reply.date), self.width, self.style))
+    }
+
+    /// Today's Quantum Field as a numbered map
+    pub async fn field(&self) -> Result<String> {
+        let reply: FieldReply = self.get("/api/v1/quantum-field").await?;
+        Ok(format!(
+            "The field is in superposition. Choose a node with /collapse N:\n\n{}",
+            field_map(&reply.wisdom_field, self.width, self.style).trim_end()
+        ))
+    }
+
+    /// Collapses node `number` (1 to 6, as numbered by `field`)
+    pub async fn collapse(&self, number: usize) -> Result<String> {
+        if !(1..=6).contains(&number) {
+            return Ok("The field has six nodes: choose one from 1 to 6.".to_string());
+        }
+
+        let path = format!("/api/v1/quantum-field/collapse?index={}", number - 1);
+        let reply: CollapseReply = self.get(&path).await?;
+
+        let mut rendered = cat_says(&reply.collapsed_prompt, self.width, self.style);
+        if let Some(reading) = reply.reading_url {
+            rendered.push_str(&format!("\nThis reading is kept at {}", self.url(&reading)));
+        }
+        Ok(rendered)
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<T> {
+        self.http
+            .get(self.url(path))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Unexpected reply from {}", path))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
@@ -0,0 +1,449 @@
+//! # Command Line
+//!
+//! The binary serves the site when run without arguments (as the systemd unit
+//! does), and has subcommands for ops tasks and cron jobs that shouldn't need
+//! to go through HTTP:
+//!
+//! ```text
+//! the-enlightened-cat serve
+//! the-enlightened-cat generate-wisdom [--date YYYY-MM-DD] [--force]
+//! the-enlightened-cat generate-field [--date YYYY-MM-DD] [--force]
+//! the-enlightened-cat export [--output FILE]
+//! the-enlightened-cat import FILE [--replace]
+//! the-enlightened-cat sessions list [--limit N]
+//! the-enlightened-cat sessions purge [--idle-days N | --all]
+//! the-enlightened-cat fortune [--date YYYY-MM-DD] [--width N]
+//! the-enlightened-cat chat [--url URL] [--timezone ZONE] [--width N]
+//! the-enlightened-cat webhooks list
+//! the-enlightened-cat webhooks send [--target NAME] [--date YYYY-MM-DD]
+//! the-enlightened-cat webhooks log [--limit N]
+//! the-enlightened-cat discord-commands
+//! ```
+//!
+//! The commands read the same environment as the server and open the same
+//! files in `DATA_DIR`, so wisdom generated by a cron job shows up on the
+//! running site without a restart (see `json_file.rs`). Dates default to
+//! today in `SITE_TIMEZONE`. Changes are recorded in the audit log with the
+//! actor `cli`. `chat` is the exception: it is a client for a running
+//! instance (see `chat_client.rs`) and never touches `DATA_DIR`.
+//!
+//! Commands write their output to any `Write`, so tests can capture it.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand};
+use rand::seq::{IteratorRandom, SliceRandom};
+use tracing::error;
+
+use crate::audit::AuditEntry;
+use crate::backup::Backup;
+use crate::routes::discord;
+use crate::sessions::MAX_IDLE_DAYS;
+use crate::state::AppState;
+use crate::terminal::{wrap, DEFAULT_WIDTH};
+use crate::webhooks::{Delivery, Outcome};
+
+/// Actor name for audit log entries written by these commands
+pub const CLI_ACTOR: &str = "cli";
+
+/// The Enlightened Cat: serves the site, or runs one maintenance task
+#[derive(Debug, Parser)]
+#[command(name = "the-enlightened-cat", version, about)]
+pub struct Cli {
+    /// What to do; serves the site if omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Serve the web application (the default)
+    Serve,
+    /// Settle the Daily Whispurr for a date and print it
+    GenerateWisdom {
+        /// Date to generate for (YYYY-MM-DD); defaults to today in SITE_TIMEZONE
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// Replace wisdom that was already settled for the date
+        #[arg(long)]
+        force: bool,
+    },
+    /// Settle the Quantum Field for a date and print its seeds
+    GenerateField {
+        /// Date to generate for (YYYY-MM-DD); defaults to today in SITE_TIMEZONE
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// Replace the field that was already settled for the date
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write all stored data to one JSON document
+    Export {
+        /// File to write; standard output if omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Load a document written by `export`
+    Import {
+        /// The backup to read
+        file: PathBuf,
+        /// Drop stored wisdom, fields, readings, sessions, deliveries, Telegram chats, followers, subscribers, push subscriptions and accounts that aren't in the backup
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Inspect or clear chat sessions
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Print a Whispurr from the archive, or the offline corpus if it is empty
+    Fortune {
+        /// Print this day's Whispurr instead of a random one
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// Wrap lines at this many characters
+        #[arg(long, default_value_t = DEFAULT_WIDTH)]
+        width: usize,
+    },
+    /// Chat with the cat of a running instance from the terminal
+    Chat {
+        /// Base URL of the instance; defaults to PUBLIC_URL
+        #[arg(long)]
+        url: Option<String>,
+        /// IANA time zone deciding which day's wisdom and field you see
+        #[arg(long)]
+        timezone: Option<Tz>,
+        /// Wrap replies at this many characters
+        #[arg(long, default_value_t = DEFAULT_WIDTH)]
+        width: usize,
+    },
+    /// Inspect the webhooks the daily wisdom is posted to, or post it now
+    Webhooks {
+        #[command(subcommand)]
+        command: WebhooksCommand,
+    },
+    /// Print the Discord slash commands as JSON, to register with
+    /// `PUT /applications/{application_id}/commands`
+    DiscordCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommand {
+    /// List the most recently active sessions, without message contents
+    List {
+        /// How many sessions to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Forget sessions that have been idle for a while
+    Purge {
+        /// Forget sessions idle for more than this many days (default 7), except accounts' sessions
+        #[arg(long, conflicts_with = "all")]
+        idle_days: Option<i64>,
+        /// Forget every session, accounts' conversations included
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WebhooksCommand {
+    /// List the configured targets and when they are posted to
+    List,
+    /// Post a day's wisdom now, even if it was sent already
+    Send {
+        /// Only this target; every target if omitted
+        #[arg(long)]
+        target: Option<String>,
+        /// Date whose wisdom to send (YYYY-MM-DD); defaults to today in each target's zone
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Show the most recent deliveries
+    Log {
+        /// How many deliveries to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+impl Command {
+    /// Whether the command may call the language model, and so needs an API key
+    pub fn uses_model(&self) -> bool {
+        matches!(
+            self,
+            Command::Serve
+                | Command::GenerateWisdom { .. }
+                | Command::GenerateField { .. }
+                | Command::Webhooks { command: WebhooksCommand::Send { .. } }
+        )
+    }
+}
+
+/// Runs a maintenance command against the app's state, writing its report to `out`
+///
+/// `serve` and `chat` are not handled here: the binary runs the server or
+/// the chat client for them instead, without opening the data directory.
+pub async fn run(command: Command, state: &AppState, out: &mut dyn Write) -> Result<()> {
+    let today = Utc::now().with_timezone(&state.config.site_timezone).date_naive();
+
+    match command {
+        Command::Serve | Command::Chat { .. } => anyhow::bail!("`serve` and `chat` are handled by the binary"),
+
+        Command::GenerateWisdom { date, force } => {
+            let date = date.unwrap_or(today);
+            let existed = state.wisdom_history.get(date).await.is_some();
+            let wisdom = if force {
+                state.regenerate_daily_wisdom(date).await
+            } else {
+                state.get_daily_wisdom(date).await
+            };
+            if force || !existed {
+                let action = if existed { "wisdom.regenerate" } else { "wisdom.generate" };
+                audit(state, action, date, format!("{}: {}", wisdom.provenance, wisdom.text)).await;
+            }
+
+            writeln!(out, "{} ({})", date, wisdom.provenance)?;
+            writeln!(out, "{}", wisdom.text)?;
+        }
+
+        Command::GenerateField { date, force } => {
+            let date = date.unwrap_or(today);
+            let existed = state.quantum_fields.read(|fields| fields.contains_key(&date)).await;
+            let field = if force {
+                state.regenerate_quantum_field(date).await
+            } else {
+                state.get_quantum_field(date).await
+            };
+            if force || !existed {
+                let action = if existed { "field.regenerate" } else { "field.generate" };
+                audit(state, action, date, field.provenance).await;
+            }
+
+            writeln!(out, "{} ({})", date, field.provenance)?;
+            for node in &field.wisdom_field {
+                writeln!(out, "{}. {}: {}", node.index, node.domain, node.seed)?;
+            }
+        }
+
+        Command::Export { output } => {
+            let backup = Backup::collect(state).await?;
+            let json = serde_json::to_string_pretty(&backup)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+                    writeln!(
+                        out,
+                        "Exported {} days of wisdom, {} fields, {} readings, {} sessions, {} deliveries, {} Telegram chats, {} followers, {} subscribers, {} push subscriptions, {} accounts and {} audit entries to {}",
+                        backup.wisdom.len(),
+                        backup.fields.len(),
+                        backup.readings.len(),
+                        backup.sessions.len(),
+                        backup.deliveries.len(),
+                        backup.telegram.len(),
+                        backup.federation.as_ref().map_or(0, |federation| federation.followers.len()),
+                        backup.newsletter.len(),
+                        backup.push.as_ref().map_or(0, |push| push.subscriptions.len()),
+                        backup.accounts.len(),
+                        backup.audit.len(),
+                        path.display()
+                    )?;
+                }
+                None => writeln!(out, "{}", json)?,
+            }
+        }
+
+        Command::Import { file, replace } => {
+            let contents = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let backup: Backup = serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", file.display()))?;
+            let summary = backup.restore(state, replace).await?;
+
+            let detail = format!(
+                "{} wisdom, {} fields, {} readings, {} sessions, {} deliveries, {} Telegram chats, {} followers, {} subscribers, {} push subscriptions, {} accounts, {} audit entries{}",
+                summary.wisdom,
+                summary.fields,
+                summary.readings,
+                summary.sessions,
+                summary.deliveries,
+                summary.telegram,
+                summary.followers,
+                summary.subscribers,
+                summary.push,
+                summary.accounts,
+                summary.audit,
+                if replace { " (replacing)" } else { "" }
+            );
+            audit(state, "data.import", file.display(), &detail).await;
+            writeln!(out, "Imported {}", detail)?;
+        }
+
+        Command::Sessions { command: SessionsCommand::List { limit } } => {
+            // Only counts and lengths, as in the admin console: never what was said
+            let sessions = state.sessions.recent(limit).await;
+            writeln!(out, "Active sessions: {}", state.sessions.len().await)?;
+            for session in sessions {
+                writeln!(
+                    out,
+                    "{}…  started {}  last active {}  {} messages, {} characters",
+                    session.id.chars().take(8).collect::<String>(),
+                    session.created_at.format("%Y-%m-%d %H:%M"),
+                    session.last_active.format("%Y-%m-%d %H:%M"),
+                    session.user_message_count(),
+                    session.user_character_count(),
+                )?;
+            }
+        }
+
+        Command::Sessions { command: SessionsCommand::Purge { idle_days, all } } => {
+            let cutoff = (!all).then(|| Utc::now() - Duration::days(idle_days.unwrap_or(MAX_IDLE_DAYS)));
+            let purged = state.sessions.purge(cutoff).await;
+            let scope = match idle_days {
+                _ if all => "all".to_string(),
+                Some(days) => format!("idle > {} days", days),
+                None => format!("idle > {} days", MAX_IDLE_DAYS),
+            };
+            audit(state, "sessions.purge", scope, format!("{} removed", purged)).await;
+            writeln!(out, "Purged {} sessions", purged)?;
+        }
+
+        Command::Fortune { date, width } => {
+            let (text, attribution) = fortune(state, date, today).await;
+            for line in wrap(&text, width) {
+                writeln!(out, "{}", line)?;
+            }
+            writeln!(out)?;
+            writeln!(out, "    — {}", attribution)?;
+        }
+
+        Command::Webhooks { command: WebhooksCommand::List } => {
+            let site_timezone = state.config.site_timezone;
+            for target in state.webhooks.targets() {
+                let days = match target.days.as_slice() {
+                    [] => "daily".to_string(),
+                    days => days.iter().map(|day| day.to_string()).collect::<Vec<_>>().join(","),
+                };
+                writeln!(
+                    out,
+                    "{}  {}  {} {}  {}{}  {}",
+                    target.name,
+                    target.format,
+                    target.schedule.format("%H:%M"),
+                    target.zone(site_timezone),
+                    days,
+                    if target.secret.is_some() { "  signed" } else { "" },
+                    target.url,
+                )?;
+            }
+            if state.webhooks.targets().is_empty() {
+                writeln!(out, "No webhook targets; set WEBHOOKS_PATH to a targets file")?;
+            }
+        }
+
+        Command::Webhooks { command: WebhooksCommand::Send { target, date } } => {
+            let targets: Vec<_> = match &target {
+                Some(name) => vec![state
+                    .webhooks
+                    .target(name)
+                    .with_context(|| format!("No webhook target named {}", name))?],
+                None => state.webhooks.targets().iter().collect(),
+            };
+            let mut failed = 0;
+            for target in targets {
+                let date = date.unwrap_or_else(|| {
+                    Utc::now().with_timezone(&target.zone(state.config.site_timezone)).date_naive()
+                });
+                let Some(wisdom) = state.broadcast_wisdom(date).await else {
+                    anyhow::bail!("The {} wisdom couldn't be generated, so nothing was sent; try again later, or write it in the admin console", date);
+                };
+                let delivery = state.webhooks.deliver(target, date, &wisdom, &state.config.public_url).await;
+                writeln!(out, "{}", delivery_line(&delivery))?;
+                if delivery.outcome == Outcome::Failed {
+                    failed += 1;
+                }
+            }
+            // A non-zero exit lets cron and CI notice
+            if failed > 0 {
+                anyhow::bail!("{} webhook deliveries failed", failed);
+            }
+        }
+
+        Command::Webhooks { command: WebhooksCommand::Log { limit } } => {
+            for delivery in state.webhooks.log(limit).await {
+                writeln!(out, "{}", delivery_line(&delivery))?;
+            }
+        }
+
+        Command::DiscordCommands => {
+            writeln!(out, "{}", serde_json::to_string_pretty(&discord::command_definitions())?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// One delivery as a line of the `webhooks` output
+fn delivery_line(delivery: &Delivery) -> String {
+    let outcome = match delivery.outcome {
+        Outcome::Delivered => "delivered",
+        Outcome::Failed => "FAILED",
+    };
+    format!(
+        "{}  {}  {}  {}  {} attempts{}",
+        delivery.at.format("%Y-%m-%d %H:%M:%S"),
+        delivery.target,
+        delivery.date,
+        outcome,
+        delivery.attempts,
+        delivery.error.as_deref().map(|error| format!("  {}", error)).unwrap_or_default(),
+    )
+}
+
+/// A Whispurr and its attribution, without ever calling the model
+///
+/// A given `date` gets that day's archived wisdom, or the corpus pick for the
+/// date if it has none. Otherwise a random archived day up to today is chosen,
+/// or a random corpus entry while the archive is empty.
+async fn fortune(state: &AppState, date: Option<NaiveDate>, today: NaiveDate) -> (String, String) {
+    let attributed = |date: NaiveDate| format!("The Enlightened Cat, {}", date.format("%-d %B %Y"));
+
+    if let Some(date) = date {
+        return match state.wisdom_history.get(date).await {
+            Some(wisdom) => (wisdom.text, attributed(date)),
+            None => (state.corpus.whispurr_for(date), "The Enlightened Cat".to_string()),
+        };
+    }
+
+    let archived = state
+        .wisdom_history
+        .all()
+        .await
+        .into_iter()
+        .filter(|(date, _)| *date <= today)
+        .choose(&mut rand::thread_rng());
+    match archived {
+        Some((date, wisdom)) => (wisdom.text, attributed(date)),
+        None => {
+            let text = state
+                .corpus
+                .whispurrs
+                .choose(&mut rand::thread_rng())
+                .cloned()
+                .unwrap_or_else(|| state.corpus.whispurr_for(today));
+            (text, "The Enlightened Cat".to_string())
+        }
+    }
+}
+
+/// Appends an entry to the audit log; as in the admin console, a failure is
+/// reported but doesn't undo the change
+async fn audit(state: &AppState, action: &str, target: impl ToString, detail: impl ToString) {
+    if let Err(err) = state.audit.record(AuditEntry::new(CLI_ACTOR, action, target, detail)).await {
+        error!("Failed to write audit log entry for {}: {:?}", action, err);
+    }
+}
@@ -1,34 +1,246 @@
-use anyhow::Result;
-use once_cell::sync::OnceCell;
+use anyhow::{Context, Result};
+use chrono::NaiveTime;
+use chrono_tz::Tz;
 use std::env;
 
-static CONFIG: OnceCell<Config> = OnceCell::new();
+use crate::fixtures::FixtureMode;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub mistral_api_key: String,
     pub mistral_api_url: String,
+    /// How long to wait for the Mistral API before giving up and falling back
+    pub mistral_timeout_secs: u64,
+    /// Tokens the model may use per UTC day before everything falls back to the corpus; unlimited when unset
+    pub daily_token_budget: Option<u64>,
+    /// Whether to record or replay Mistral exchanges (see `fixtures.rs`)
+    pub fixture_mode: FixtureMode,
+    /// Directory holding recorded Mistral exchanges
+    pub fixture_dir: String,
     pub server_port: u16,
+    /// Public base URL of the site, without a trailing slash; used for absolute links in feeds, pages and the sitemap
+    pub public_url: String,
+    /// Time zone whose calendar decides when "today" rolls over by default
+    pub site_timezone: Tz,
+    /// Whether visitors may override the site time zone via header or cookie
+    pub visitor_timezones: bool,
+    /// Optional JSON file whose whispurrs and seeds extend the bundled offline corpus
+    pub corpus_path: Option<String>,
+    /// Most quantum field readings kept; the oldest unexplored ones are dropped past it
+    pub max_readings: usize,
+    /// Optional JSON file whose patterns and messages extend the bundled safety rules
+    pub safety_rules_path: Option<String>,
+    /// Optional file served as `/robots.txt` instead of the built-in one
+    pub robots_txt_path: Option<String>,
+    /// Directory for data the app writes (audit log, archives...)
+    pub data_dir: String,
+    /// User name for the `/admin` console
+    pub admin_username: String,
+    /// Password for the `/admin` console; the console is disabled when unset
+    pub admin_password: Option<String>,
+    /// Optional JSON file listing the webhooks the daily wisdom is posted to (see `webhooks.rs`)
+    pub webhooks_path: Option<String>,
+    /// Wait before retrying a failed webhook delivery; doubles with each retry
+    pub webhook_backoff_ms: u64,
+    /// Signing secret of the Slack app; the Slack endpoint is disabled when unset
+    pub slack_signing_secret: Option<String>,
+    /// Hex public key of the Discord application; the Discord endpoint is disabled when unset
+    pub discord_public_key: Option<String>,
+    /// Base URL of Discord's API, where deferred replies are edited in
+    pub discord_api_url: String,
+    /// Token of the Telegram bot; the bot and its daily push are disabled when unset
+    pub telegram_bot_token: Option<String>,
+    /// Secret token given to Telegram's `setWebhook`; the webhook is disabled when unset
+    pub telegram_webhook_secret: Option<String>,
+    /// Base URL of the Telegram Bot API
+    pub telegram_api_url: String,
+    /// User name of the cat's fediverse actor; ActivityPub is disabled when unset
+    pub activitypub_username: Option<String>,
+    /// Time of day, in `site_timezone`, after which the day's wisdom is published to followers
+    pub activitypub_post_time: NaiveTime,
+    /// Whether remote actors may be fetched over plain `http` and from local addresses; for tests and development only
+    pub activitypub_allow_local: bool,
+    /// SMTP server the newsletter is sent through (see `mail.rs`); the newsletter is disabled when unset
+    pub smtp_url: Option<String>,
+    /// `From:` of newsletter mail, a bare address or `Name <address>`
+    pub newsletter_from: String,
+    /// Time of day, in `site_timezone`, after which the day's digests are sent
+    pub newsletter_send_time: NaiveTime,
+    /// Contact given to push services with each push (`mailto:` or `https:`); Web Push is disabled when unset
+    pub vapid_subject: Option<String>,
+    /// Most push subscriptions kept; new browsers are turned away past it
+    pub max_push_subscriptions: usize,
+    /// Whether push endpoints may be plain `http` and on local addresses; for tests and development only
+    pub push_allow_local: bool,
+    /// Key that signs account session cookies; one is generated in the data directory when unset
+    pub session_secret: Option<String>,
 }
 
-impl Config {
-    pub fn global() -> &'static Config {
-        CONFIG.get().expect("Config not initialized")
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mistral_api_key: String::new(),
+            mistral_api_url: "https://api.mistral.ai/v1".to_string(),
+            mistral_timeout_secs: 30,
+            daily_token_budget: None,
+            fixture_mode: FixtureMode::Off,
+            fixture_dir: "fixtures/llm".to_string(),
+            server_port: 3000,
+            public_url: "https://the-enlightened-cat.com".to_string(),
+            site_timezone: Tz::UTC,
+            visitor_timezones: true,
+            corpus_path: None,
+            max_readings: crate::readings::MAX_READINGS,
+            safety_rules_path: None,
+            robots_txt_path: None,
+            data_dir: "storage".to_string(),
+            admin_username: "admin".to_string(),
+            admin_password: None,
+            webhooks_path: None,
+            webhook_backoff_ms: 30_000,
+            slack_signing_secret: None,
+            discord_public_key: None,
+            discord_api_url: "https://discord.com/api/v10".to_string(),
+            telegram_bot_token: None,
+            telegram_webhook_secret: None,
+            telegram_api_url: "https://api.telegram.org".to_string(),
+            activitypub_username: None,
+            activitypub_post_time: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            activitypub_allow_local: false,
+            smtp_url: None,
+            newsletter_from: "The Enlightened Cat <whispurrs@the-enlightened-cat.com>".to_string(),
+            newsletter_send_time: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            vapid_subject: None,
+            max_push_subscriptions: crate::webpush::MAX_SUBSCRIPTIONS,
+            push_allow_local: false,
+            session_secret: None,
+        }
     }
+}
 
-    pub fn init() -> Result<&'static Config> {
-        let config = Config {
-            mistral_api_key: env::var("MISTRAL_API_KEY")
-                .expect("MISTRAL_API_KEY must be set"),
+impl Config {
+    /// Builds the configuration from environment variables, using defaults for anything unset
+    pub fn from_env() -> Result<Self> {
+        let defaults = Config::default();
+
+        Ok(Config {
+            // Checked by `require_api_key` only where the model may be called
+            mistral_api_key: env::var("MISTRAL_API_KEY").unwrap_or_default(),
             mistral_api_url: env::var("MISTRAL_API_URL")
-                .unwrap_or_else(|_| "https://api.mistral.ai/v1".to_string()),
-            server_port: env::var("PORT")
-                .unwrap_or_else(|_| "3000".to_string())
-                .parse()
-                .expect("PORT must be a number"),
-        };
+                .unwrap_or(defaults.mistral_api_url),
+            mistral_timeout_secs: match env::var("MISTRAL_TIMEOUT_SECS") {
+                Ok(value) => value.parse().context("MISTRAL_TIMEOUT_SECS must be a number")?,
+                Err(_) => defaults.mistral_timeout_secs,
+            },
+            daily_token_budget: match env::var("DAILY_TOKEN_BUDGET").ok().filter(|b| !b.is_empty()) {
+                Some(value) => Some(value.parse().context("DAILY_TOKEN_BUDGET must be a number")?),
+                None => None,
+            },
+            fixture_mode: match env::var("MISTRAL_FIXTURES") {
+                Ok(value) => value.parse()?,
+                Err(_) => defaults.fixture_mode,
+            },
+            fixture_dir: env::var("MISTRAL_FIXTURES_DIR").unwrap_or(defaults.fixture_dir),
+            server_port: match env::var("PORT") {
+                Ok(value) => value.parse().context("PORT must be a number")?,
+                Err(_) => defaults.server_port,
+            },
+            public_url: env::var("PUBLIC_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.public_url),
+            site_timezone: match env::var("SITE_TIMEZONE") {
+                Ok(value) => value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("SITE_TIMEZONE must be an IANA time zone name"))?,
+                Err(_) => defaults.site_timezone,
+            },
+            visitor_timezones: env::var("VISITOR_TIMEZONES")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(defaults.visitor_timezones),
+            corpus_path: env::var("CORPUS_PATH").ok(),
+            max_readings: match env::var("MAX_READINGS") {
+                Ok(value) => value.parse().context("MAX_READINGS must be a number")?,
+                Err(_) => defaults.max_readings,
+            },
+            safety_rules_path: env::var("SAFETY_RULES_PATH").ok(),
+            robots_txt_path: env::var("ROBOTS_TXT_PATH").ok(),
+            data_dir: env::var("DATA_DIR").unwrap_or(defaults.data_dir),
+            admin_username: env::var("ADMIN_USERNAME").unwrap_or(defaults.admin_username),
+            admin_password: env::var("ADMIN_PASSWORD").ok().filter(|p| !p.is_empty()),
+            webhooks_path: env::var("WEBHOOKS_PATH").ok(),
+            webhook_backoff_ms: match env::var("WEBHOOK_BACKOFF_MS") {
+                Ok(value) => value.parse().context("WEBHOOK_BACKOFF_MS must be a number")?,
+                Err(_) => defaults.webhook_backoff_ms,
+            },
+            slack_signing_secret: env::var("SLACK_SIGNING_SECRET").ok().filter(|s| !s.is_empty()),
+            discord_public_key: match env::var("DISCORD_PUBLIC_KEY").ok().filter(|k| !k.is_empty()) {
+                // Checked here, as a bad key would otherwise fail every request
+                Some(key) if hex::decode(&key).map_or(true, |bytes| bytes.len() != 32) => {
+                    anyhow::bail!("DISCORD_PUBLIC_KEY must be the 64 hex digits of the application's public key")
+                }
+                key => key,
+            },
+            discord_api_url: env::var("DISCORD_API_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.discord_api_url),
+            telegram_bot_token: env::var("TELEGRAM_BOT_TOKEN").ok().filter(|t| !t.is_empty()),
+            telegram_webhook_secret: env::var("TELEGRAM_WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()),
+            telegram_api_url: env::var("TELEGRAM_API_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.telegram_api_url),
+            activitypub_username: match env::var("ACTIVITYPUB_USERNAME").ok().filter(|u| !u.is_empty()) {
+                // Part of the actor's handle and URLs, so kept to what every server accepts
+                Some(name) if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => {
+                    anyhow::bail!("ACTIVITYPUB_USERNAME may only contain letters, digits and underscores")
+                }
+                name => name,
+            },
+            activitypub_post_time: match env::var("ACTIVITYPUB_POST_TIME") {
+                Ok(value) => NaiveTime::parse_from_str(&value, "%H:%M")
+                    .context("ACTIVITYPUB_POST_TIME must be a time of day such as 08:00")?,
+                Err(_) => defaults.activitypub_post_time,
+            },
+            activitypub_allow_local: env::var("ACTIVITYPUB_ALLOW_LOCAL")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(defaults.activitypub_allow_local),
+            smtp_url: env::var("SMTP_URL").ok().filter(|u| !u.is_empty()),
+            newsletter_from: env::var("NEWSLETTER_FROM").unwrap_or(defaults.newsletter_from),
+            newsletter_send_time: match env::var("NEWSLETTER_SEND_TIME") {
+                Ok(value) => NaiveTime::parse_from_str(&value, "%H:%M")
+                    .context("NEWSLETTER_SEND_TIME must be a time of day such as 07:00")?,
+                Err(_) => defaults.newsletter_send_time,
+            },
+            vapid_subject: match env::var("VAPID_SUBJECT").ok().filter(|s| !s.is_empty()) {
+                // Push services reject pushes whose contact they can't use
+                Some(subject) if !subject.starts_with("mailto:") && !subject.starts_with("https://") => {
+                    anyhow::bail!("VAPID_SUBJECT must be a mailto: or https: URL, such as mailto:you@example.com")
+                }
+                subject => subject,
+            },
+            max_push_subscriptions: match env::var("MAX_PUSH_SUBSCRIPTIONS") {
+                Ok(value) => value.parse().context("MAX_PUSH_SUBSCRIPTIONS must be a number")?,
+                Err(_) => defaults.max_push_subscriptions,
+            },
+            push_allow_local: env::var("PUSH_ALLOW_LOCAL")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(defaults.push_allow_local),
+            session_secret: match env::var("SESSION_SECRET").ok().filter(|s| !s.is_empty()) {
+                // Anything shorter could be guessed, and with it any account's cookie forged
+                Some(secret) if secret.len() < 32 => anyhow::bail!("SESSION_SECRET must be at least 32 characters"),
+                secret => secret,
+            },
+        })
+    }
 
-        CONFIG.set(config).expect("Failed to initialize config");
-        Ok(CONFIG.get().unwrap())
+    /// Fails unless the Mistral API can be called: a key is set, or fixtures are replayed
+    ///
+    /// Only serving and generating content need this; commands such as
+    /// `fortune` or `export` work without a key.
+    pub fn require_api_key(&self) -> Result<()> {
+        // Replaying fixtures never reaches the API, so no key is needed
+        if self.mistral_api_key.is_empty() && self.fixture_mode != FixtureMode::Replay {
+            anyhow::bail!("MISTRAL_API_KEY must be set");
+        }
+        Ok(())
     }
 }
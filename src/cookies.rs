@@ -0,0 +1,18 @@
+//! # Cookie Helpers
+//!
+//! Minimal parsing of the `Cookie` request header. The app only ever needs to
+//! read a handful of small, non-secret values, so a full cookie jar is overkill.
+
+use axum::http::{header::COOKIE, HeaderMap};
+
+/// Returns the value of the named cookie, if the request carries it
+pub fn get(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
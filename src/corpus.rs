@@ -0,0 +1,130 @@
+//! # Offline Wisdom Corpus
+//!
+//! A curated collection of Whispurrs and Quantum Field seeds that the cat can
+//! fall back on whenever the Mistral API is unavailable or returns something
+//! unusable. The default corpus is bundled into the binary from
+//! `data/corpus.json`; deployments can extend it with their own file via the
+//! `CORPUS_PATH` environment variable.
+//!
+//! Fallback picks are deterministic per calendar date, so visitors who hit a
+//! fallback on the same day all see the same words.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::quantum_field::DOMAINS;
+
+/// The corpus shipped with the binary
+const BUNDLED_CORPUS: &str = include_str!("../data/corpus.json");
+
+/// Where a piece of content came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Provenance {
+    /// Freshly generated by the language model
+    #[default]
+    Generated,
+    /// Picked from the offline corpus because generation was not possible
+    Corpus,
+    /// Written or edited by hand in the admin console
+    Curated,
+    /// A fixed reply from the safety layer (see `safety.rs`) instead of the model's
+    Safety,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provenance::Generated => write!(f, "generated"),
+            Provenance::Corpus => write!(f, "corpus"),
+            Provenance::Curated => write!(f, "curated"),
+            Provenance::Safety => write!(f, "safety"),
+        }
+    }
+}
+
+/// Whispurrs and per-domain seeds available without the language model
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Corpus {
+    /// Standalone Daily Whispurrs
+    #[serde(default)]
+    pub whispurrs: Vec<String>,
+
+    /// Quantum Field seeds, keyed by domain name (see `quantum_field::DOMAINS`)
+    #[serde(default)]
+    pub seeds: HashMap<String, Vec<String>>,
+}
+
+impl Corpus {
+    /// The corpus bundled into the binary
+    pub fn bundled() -> Self {
+        serde_json::from_str(BUNDLED_CORPUS).expect("Bundled corpus must be valid JSON")
+    }
+
+    /// Loads the bundled corpus, extended with the entries of `extra` if given
+    pub fn load(extra: Option<&Path>) -> Result<Self> {
+        let mut corpus = Self::bundled();
+
+        if let Some(path) = extra {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read corpus file {}", path.display()))?;
+            let additions: Corpus = serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse corpus file {}", path.display()))?;
+            corpus.extend(additions);
+        }
+
+        Ok(corpus)
+    }
+
+    /// Appends another corpus' entries to this one
+    pub fn extend(&mut self, other: Corpus) {
+        self.whispurrs.extend(other.whispurrs);
+        for (domain, seeds) in other.seeds {
+            self.seeds.entry(domain).or_default().extend(seeds);
+        }
+    }
+
+    /// The fallback Whispurr for a given date
+    pub fn whispurr_for(&self, date: NaiveDate) -> String {
+        pick(&self.whispurrs, date, 0)
+            .cloned()
+            .unwrap_or_else(|| {
+                "Even in moments of technical difficulty, the enlightened cat remains calm and patient.".to_string()
+            })
+    }
+
+    /// The six fallback seeds for a given date, one per domain in `DOMAINS` order
+    pub fn seeds_for(&self, date: NaiveDate) -> Vec<String> {
+        DOMAINS
+            .iter()
+            .enumerate()
+            .map(|(i, domain)| {
+                self.seeds
+                    .get(*domain)
+                    .and_then(|seeds| pick(seeds, date, i as u64 + 1))
+                    .cloned()
+                    .unwrap_or_else(|| format!("The {} node rests in silence", domain.to_lowercase()))
+            })
+            .collect()
+    }
+}
+
+/// Deterministically picks an item for a date
+///
+/// The day number is scrambled with a multiplicative hash so consecutive days
+/// don't walk through the list in order, and `salt` keeps the six domains from
+/// all landing on the same position.
+fn pick<T>(items: &[T], date: NaiveDate, salt: u64) -> Option<&T> {
+    if items.is_empty() {
+        return None;
+    }
+    let day = date.num_days_from_ce() as u64;
+    let hash = (day.wrapping_add(salt.wrapping_mul(0x9E37_79B9))).wrapping_mul(0x2545_F491_4F6C_DD1D) >> 16;
+    items.get((hash % items.len() as u64) as usize)
+}
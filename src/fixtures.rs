@@ -0,0 +1,163 @@
+//! # LLM Fixtures
+//!
+//! Record and replay real Mistral interactions. In record mode every
+//! request/response pair that goes through `MistralHttp` is written to a JSON
+//! file, with the API key scrubbed. In replay mode those files are served back
+//! instead of calling the API, matched on the model and a hash of the messages.
+//!
+//! This lets developers capture a real-world model quirk once (say, JSON wrapped
+//! in prose) and reproduce it offline, in tests or against `MockMistral`.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::mistral::{parse_completion, ChatMessage, ChatRequest, Completion, LlmProvider};
+
+/// Placeholder written wherever the API key would have appeared
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Whether the app records, replays, or ignores fixtures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FixtureMode {
+    /// Talk to the API and record nothing
+    #[default]
+    Off,
+    /// Talk to the API and save every exchange
+    Record,
+    /// Never talk to the API; answer from saved exchanges only
+    Replay,
+}
+
+impl FromStr for FixtureMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            other => Err(anyhow::anyhow!("Unknown fixture mode '{}' (expected off, record or replay)", other)),
+        }
+    }
+}
+
+/// One recorded request/response pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The model the request was sent to
+    pub model: String,
+    /// Hash of the request messages, see `message_hash`
+    pub message_hash: String,
+    /// The request exactly as sent
+    pub request: ChatRequest,
+    /// Request headers, with credentials replaced by `REDACTED`
+    pub request_headers: Vec<(String, String)>,
+    /// HTTP status the API answered with
+    pub status: u16,
+    /// Raw response body, with any echo of the API key scrubbed
+    pub response_body: String,
+    /// When the exchange was recorded
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Hex SHA-256 of the messages' JSON form, used to match requests to fixtures
+pub fn message_hash(messages: &[ChatMessage]) -> String {
+    let json = serde_json::to_vec(messages).expect("Chat messages are always serializable");
+    hex::encode(Sha256::digest(json))
+}
+
+/// A directory of fixture files
+#[derive(Debug, Clone)]
+pub struct FixtureStore {
+    dir: PathBuf,
+}
+
+impl FixtureStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes a fixture, replacing any earlier recording of the same request
+    pub fn save(&self, fixture: &Fixture) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create fixture directory {}", self.dir.display()))?;
+        let path = self.dir.join(file_name(&fixture.model, &fixture.message_hash));
+        std::fs::write(&path, serde_json::to_string_pretty(fixture)?)
+            .with_context(|| format!("Failed to write fixture {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Finds the fixture recorded for this model and set of messages
+    pub fn find(&self, model: &str, messages: &[ChatMessage]) -> Result<Option<Fixture>> {
+        let path = self.dir.join(file_name(model, &message_hash(messages)));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+        let fixture = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse fixture {}", path.display()))?;
+        Ok(Some(fixture))
+    }
+}
+
+/// Fixture file name for a model and message hash
+fn file_name(model: &str, hash: &str) -> String {
+    let model: String = model
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    format!("{}-{}.json", model, hash)
+}
+
+/// Replaces every occurrence of the API key in `text`
+pub fn scrub(text: &str, api_key: &str) -> String {
+    if api_key.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(api_key, REDACTED)
+    }
+}
+
+/// `LlmProvider` that answers exclusively from recorded fixtures
+///
+/// A request without a matching fixture fails, which the app treats like any
+/// other upstream error (the offline corpus takes over).
+#[derive(Debug, Clone)]
+pub struct ReplayProvider {
+    store: FixtureStore,
+}
+
+impl ReplayProvider {
+    pub fn new(store: FixtureStore) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ReplayProvider {
+    async fn complete(&self, request: &ChatRequest) -> Result<Completion> {
+        let fixture = self.store.find(&request.model, &request.messages)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "No fixture for model {} and messages {} in {}",
+                request.model,
+                message_hash(&request.messages),
+                self.store.dir().display()
+            )
+        })?;
+
+        info!("Replaying fixture {}", fixture.message_hash);
+        parse_completion(fixture.status, &fixture.response_body)
+    }
+}
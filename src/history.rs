@@ -0,0 +1,117 @@
+//! # Wisdom History
+//!
+//! Every Daily Whispurr the cat has settled on, one per calendar date, kept in
+//! `<data_dir>/wisdom.json` so it survives restarts. The feeds, the sitemap,
+//! search, the per-date wisdom pages and the admin console all read from here.
+//!
+//! The whole history is small (one short paragraph a day), so it is held in
+//! memory and the file is rewritten on every change (see `json_file.rs`).
+
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Unbounded};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+
+use crate::json_file::JsonFile;
+use crate::state::DailyWisdom;
+
+/// All daily wisdom by date, backed by a JSON file
+#[derive(Debug, Clone)]
+pub struct WisdomHistory {
+    entries: JsonFile<BTreeMap<NaiveDate, DailyWisdom>>,
+}
+
+impl WisdomHistory {
+    /// Opens the history at `path`, starting empty if the file doesn't exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self { entries: JsonFile::open(path)? })
+    }
+
+    /// The wisdom settled for `date`, if any
+    pub async fn get(&self, date: NaiveDate) -> Option<DailyWisdom> {
+        self.entries.read(|entries| entries.get(&date).cloned()).await
+    }
+
+    /// Stores the wisdom for `date`, replacing any earlier one, and saves the file
+    ///
+    /// A failed save is logged rather than returned: visitors should still see
+    /// today's wisdom even if the disk is full.
+    pub async fn insert(&self, date: NaiveDate, wisdom: DailyWisdom) {
+        self.entries.update(|entries| entries.insert(date, wisdom)).await;
+    }
+
+    /// The closest dates before and after `date` that have wisdom
+    pub async fn neighbours(&self, date: NaiveDate) -> (Option<NaiveDate>, Option<NaiveDate>) {
+        self.entries
+            .read(|entries| {
+                let previous = entries.range(..date).next_back().map(|(d, _)| *d);
+                let next = entries.range((Excluded(date), Unbounded)).next().map(|(d, _)| *d);
+                (previous, next)
+            })
+            .await
+    }
+
+    /// The most recent `limit` entries, newest first
+    pub async fn recent(&self, limit: usize) -> Vec<(NaiveDate, DailyWisdom)> {
+        self.entries
+            .read(|entries| {
+                entries
+                    .iter()
+                    .rev()
+                    .take(limit)
+                    .map(|(date, wisdom)| (*date, wisdom.clone()))
+                    .collect()
+            })
+            .await
+    }
+
+    /// The most recent `limit` entries up to and including `until`, newest first
+    pub async fn recent_until(&self, until: NaiveDate, limit: usize) -> Vec<(NaiveDate, DailyWisdom)> {
+        self.entries
+            .read(|entries| {
+                entries
+                    .range(..=until)
+                    .rev()
+                    .take(limit)
+                    .map(|(date, wisdom)| (*date, wisdom.clone()))
+                    .collect()
+            })
+            .await
+    }
+
+    /// Entries up to `until` whose text contains `query` (ignoring case), newest first
+    pub async fn search(&self, query: &str, until: NaiveDate, limit: usize) -> Vec<(NaiveDate, DailyWisdom)> {
+        let query = query.to_lowercase();
+        self.entries
+            .read(|entries| {
+                entries
+                    .range(..=until)
+                    .rev()
+                    .filter(|(_, wisdom)| wisdom.text.to_lowercase().contains(&query))
+                    .take(limit)
+                    .map(|(date, wisdom)| (*date, wisdom.clone()))
+                    .collect()
+            })
+            .await
+    }
+
+    /// Every entry, for backups
+    pub async fn all(&self) -> BTreeMap<NaiveDate, DailyWisdom> {
+        self.entries.read(BTreeMap::clone).await
+    }
+
+    /// Adds `imported` entries, replacing those for the same dates; with
+    /// `replace`, everything not in `imported` is dropped first
+    pub async fn import(&self, imported: BTreeMap<NaiveDate, DailyWisdom>, replace: bool) {
+        self.entries
+            .update(|entries| {
+                if replace {
+                    entries.clear();
+                }
+                entries.extend(imported);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,150 @@
+//! # JSON Files
+//!
+//! The small documents the app keeps in the data directory (wisdom history,
+//! quantum fields, readings, chat sessions) are each one JSON file, held in
+//! memory and rewritten on every change.
+//!
+//! The command-line tools (see `cli.rs`) open the same files while the server
+//! may be running, e.g. a cron job generating tomorrow's wisdom. So every
+//! access first checks the file's modification time and reloads it if another
+//! process has written it since. Two processes changing the same file in the
+//! same instant can still lose one change; at the scale of a few writes a
+//! minute that is an acceptable trade for not needing a database.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::RwLock;
+use tracing::error;
+
+/// A JSON document on disk, shared between processes
+pub struct JsonFile<T> {
+    path: PathBuf,
+    loaded: Arc<RwLock<Loaded<T>>>,
+}
+
+/// The document as last read or written, and the file's modification time then
+struct Loaded<T> {
+    value: T,
+    modified: Option<SystemTime>,
+}
+
+impl<T> Clone for JsonFile<T> {
+    fn clone(&self) -> Self {
+        Self { path: self.path.clone(), loaded: self.loaded.clone() }
+    }
+}
+
+impl<T> fmt::Debug for JsonFile<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonFile").field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+impl<T> JsonFile<T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    /// Opens the document at `path`, starting from `T::default()` if the file doesn't exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let (value, modified) = if path.exists() {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let value = serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            (value, std::fs::metadata(&path).and_then(|m| m.modified()).ok())
+        } else {
+            (T::default(), None)
+        };
+
+        Ok(Self { path, loaded: Arc::new(RwLock::new(Loaded { value, modified })) })
+    }
+
+    /// Where the document is stored
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runs `read` on the current document
+    pub async fn read<R>(&self, read: impl FnOnce(&T) -> R) -> R {
+        let on_disk = self.modified_on_disk().await;
+        if self.loaded.read().await.modified != on_disk {
+            let mut loaded = self.loaded.write().await;
+            self.reload(&mut loaded, on_disk).await;
+        }
+        read(&self.loaded.read().await.value)
+    }
+
+    /// Runs `change` on the current document and saves the result
+    ///
+    /// A failed save is logged rather than returned: the change is kept in
+    /// memory, so visitors still see today's wisdom even if the disk is full.
+    pub async fn update<R>(&self, change: impl FnOnce(&mut T) -> R) -> R {
+        let mut loaded = self.loaded.write().await;
+        let on_disk = self.modified_on_disk().await;
+        if loaded.modified != on_disk {
+            self.reload(&mut loaded, on_disk).await;
+        }
+
+        let result = change(&mut loaded.value);
+
+        match self.save(&loaded.value).await {
+            Ok(modified) => loaded.modified = modified,
+            Err(err) => error!("Failed to save {}: {:?}", self.path.display(), err),
+        }
+        result
+    }
+
+    /// Re-reads the file after another process changed it
+    ///
+    /// If it can't be read or parsed, the copy in memory is kept and the
+    /// problem logged; the next save will overwrite the bad file.
+    async fn reload(&self, loaded: &mut Loaded<T>, on_disk: Option<SystemTime>) {
+        let Some(modified) = on_disk else {
+            // Deleted behind our back: keep serving what we have
+            loaded.modified = None;
+            return;
+        };
+        if loaded.modified == Some(modified) {
+            // Someone else reloaded while we waited for the lock
+            return;
+        }
+
+        let parsed = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| serde_json::from_str(&contents).map_err(anyhow::Error::from));
+        match parsed {
+            Ok(value) => loaded.value = value,
+            Err(err) => error!("Failed to reload {}: {:?}", self.path.display(), err),
+        }
+        loaded.modified = Some(modified);
+    }
+
+    /// Writes the document to a temporary file and renames it into place,
+    /// so a crash mid-write (or a reader in another process) never sees a
+    /// truncated file; returns the new modification time
+    async fn save(&self, value: &T) -> Result<Option<SystemTime>> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let json = serde_json::to_vec_pretty(value)?;
+        // One temporary file per process, so two writers never interleave
+        let temp = self.path.with_extension(format!("json.{}.tmp", std::process::id()));
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, &self.path)
+            .await
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+        Ok(self.modified_on_disk().await)
+    }
+
+    async fn modified_on_disk(&self) -> Option<SystemTime> {
+        tokio::fs::metadata(&self.path).await.and_then(|m| m.modified()).ok()
+    }
+}
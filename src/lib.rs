@@ -0,0 +1,57 @@
+//! # The Enlightened Cat
+//!
+//! Library crate behind The Enlightened Cat web application. The binary in
+//! `main.rs` is a thin wrapper that reads the environment and either serves the
+//! router built here or runs one of the maintenance commands in `cli.rs`;
+//! integration tests build the same router with injected configuration and a
+//! mock language model.
+//!
+//! The `mock_*` servers are only built with the `test-util` feature, which the
+//! integration tests turn on through the crate's dev-dependency on itself.
+
+pub mod accounts;      // Optional accounts: passwords, passkeys and signed session cookies
+pub mod activitypub;   // The cat as a fediverse actor: keys, followers, signed delivery
+pub mod app;           // Application builder: routes, middleware and state
+pub mod audit;         // Append-only log of admin actions
+pub mod backup;        // Export and import of all stored data
+pub mod caching;       // ETag / Last-Modified handling for conditional GETs
+pub mod chat_client;   // Terminal chat client for a running instance
+pub mod cli;           // Command-line subcommands (serve, generate-wisdom, export...)
+pub mod config;        // Configuration management (environment variables)
+pub mod cookies;       // Cookie header parsing
+pub mod corpus;        // Offline wisdom corpus used when generation fails
+pub mod fixtures;      // Record/replay of Mistral exchanges
+pub mod history;       // Persisted daily wisdom, one entry per date
+pub mod json_file;     // JSON documents in the data directory shared between processes
+pub mod metrics;       // Token usage and fallback counters
+pub mod mistral;       // Mistral AI API client and provider abstraction
+pub mod mail;          // Outgoing mail over SMTP
+#[cfg(feature = "test-util")]
+pub mod mock_mistral;  // Local Mistral-compatible server for tests and offline development
+#[cfg(feature = "test-util")]
+pub mod mock_passkey;  // Software passkey authenticator for tests and offline development
+#[cfg(feature = "test-util")]
+pub mod mock_push;     // Local browser and push service for tests and offline development
+#[cfg(feature = "test-util")]
+pub mod mock_smtp;     // Local SMTP sink for tests and offline development
+#[cfg(feature = "test-util")]
+pub mod mock_webhook;  // Local webhook receiver for tests and offline development
+pub mod negotiation;   // Plain-text and ANSI versions of pages for curl and friends
+pub mod newsletter;    // Double opt-in mailing list and its daily and weekly digests
+pub mod og;            // Open Graph card images rendered in-process
+pub mod quantum_field; // Quantum field functionality
+pub mod rate_limit;    // Sliding-window limits per actor or address
+pub mod readings;      // Shareable quantum field readings
+pub mod routes;        // HTTP route handlers
+pub mod safety;        // Crisis, abuse and off-topic screening for chat
+pub mod sessions;      // Per-visitor chat sessions
+pub mod state;         // Application state management
+pub mod telegram;      // Telegram Bot API client and the opt-in daily push
+pub mod templates;     // HTML templates using Askama
+pub mod terminal;      // Word wrapping and the ASCII cat for terminal output
+pub mod timezone;      // Visitor time zone resolution for daily rollover
+pub mod webhooks;      // Daily wisdom posted to Slack, Discord and other webhooks
+pub mod webpush;       // Encrypted Web Push of the morning Whispurr, signed with VAPID
+
+pub use app::App;
+pub use config::Config;
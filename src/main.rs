@@ -1,34 +1,30 @@
 //! # The Enlightened Cat - Main Application Entry Point
 //! 
 //! This is the entry point for The Enlightened Cat web application.
-//! It sets up the web server, configures routes, initializes middleware,
-//! and starts listening for HTTP requests.
+//! It loads the configuration, builds the app from the library crate,
+//! and starts listening for HTTP requests, or runs one of the maintenance
+//! subcommands (see `cli.rs`) and exits.
 
 // Import necessary dependencies:
 // - anyhow: For flexible error handling with the Result type
-// - axum: The web framework (similar to Express in Node.js)
-// - tower_http: Middleware components for HTTP services
+// - clap: For parsing the command line
 // - tracing_subscriber: For logging and diagnostics
 use anyhow::Result;
-use axum::{
-    routing::{get, post},  // HTTP method handlers
-    Router,                // Main router for defining routes
-};
+use clap::Parser;
+use std::io::IsTerminal;   // For deciding whether the chat client may use colour
 use std::net::SocketAddr;  // For defining the server's listening address
-use tower_http::{
-    cors::{Any, CorsLayer},  // Cross-Origin Resource Sharing middleware
-    services::ServeDir,      // For serving static files (CSS, JS, images)
-    trace::TraceLayer,       // For request/response tracing
-};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-// Import our application modules
-mod config;    // Configuration management (environment variables)
-mod mistral;   // Mistral AI API client
-mod quantum_field; // Quantum field functionality
-mod routes;    // HTTP route handlers
-mod state;     // Application state management
-mod templates; // HTML templates using Askama
+// Import our library crate
+use the_enlightened_cat::activitypub;
+use the_enlightened_cat::chat_client::ChatClient;
+use the_enlightened_cat::cli::{self, Cli, Command};
+use the_enlightened_cat::newsletter;
+use the_enlightened_cat::terminal::Style;
+use the_enlightened_cat::telegram;
+use the_enlightened_cat::webhooks;
+use the_enlightened_cat::webpush;
+use the_enlightened_cat::{app, App, Config};
 
 /// Main application entry point
 /// 
@@ -36,6 +32,9 @@ mod templates; // HTML templates using Askama
 /// This is similar to how you'd set up an event loop in Node.js.
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Parse the command line first, so `--help` works without any configuration
+    let cli = Cli::parse();
+    
     // Load environment variables from .env file
     // Similar to dotenv in Node.js
     dotenv::dotenv().ok();
@@ -43,60 +42,63 @@ async fn main() -> Result<()> {
     // Initialize logging system
     // This sets up structured logging based on the RUST_LOG env var
     // Similar to Winston or Bunyan in Node.js
+    // Logs go to stderr, so the output of commands like `export` can be piped
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
         ))
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
     
-    // Initialize application state (similar to creating a store in Redux/Zustand)
-    // This creates our shared application state with the Mistral client
-    let state = state::AppState::new().await?;
-    
-    // Build our application with routes
-    // This is similar to defining routes in Express
-    let app = Router::new()
-        // API routes - JSON endpoints
-        .route("/api/chat", post(routes::chat::handle_chat))             // POST /api/chat - Chat with the cat
-        .route("/api/daily-wisdom", get(routes::wisdom::get_daily_wisdom)) // GET /api/daily-wisdom - Get wisdom as JSON
-        .route("/api/quantum-field", get(routes::quantum_field::get_quantum_field)) // GET /api/quantum-field - Get quantum field
-        .route("/api/quantum-field/collapse", get(routes::quantum_field::collapse_quantum_field)) // GET /api/quantum-field/collapse - Collapse quantum field
-        
-        // Page routes - HTML endpoints
-        .route("/", get(routes::pages::index))           // GET / - Home page
-        .route("/about", get(routes::pages::about))       // GET /about - About page
-        .route("/wisdom", get(routes::pages::wisdom_page)) // GET /wisdom - Daily wisdom page
-        .route("/quantum-field", get(routes::pages::quantum_field_page)) // GET /quantum-field - Quantum field page
-        
-        // Serve static files (CSS, JS, images)
-        // Similar to express.static in Node.js
-        .nest_service("/static", ServeDir::new("static"))
-        
-        // Add middleware
-        .layer(TraceLayer::new_for_http())  // Add request/response logging
-        .layer(
-            CorsLayer::new()                // Configure CORS policy
-                .allow_origin(Any)          // Allow any origin (can be restricted in production)
-                .allow_methods(Any)         // Allow any HTTP method
-                .allow_headers(Any),        // Allow any headers
-        )
-        .with_state(state);  // Attach our application state to the router
-    
-    // Get the server port from configuration
-    // This comes from the PORT environment variable
-    let port = config::Config::global().server_port;
-    
-    // Create a socket address to listen on all interfaces
-    // 0.0.0.0 means "listen on all available network interfaces"
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    tracing::info!("Listening on {}", addr);
+    // Load configuration from the environment
+    let config = Config::from_env()?;
     
-    // Start the HTTP server
-    // This is similar to app.listen() in Express
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await?;
+    // Without a subcommand we serve, so existing deployments keep working
+    let command = cli.command.unwrap_or(Command::Serve);
+    if command.uses_model() {
+        config.require_api_key()?;
+    }
     
-    Ok(())
+    match command {
+        Command::Serve => {
+            // Create a socket address to listen on all interfaces
+            // 0.0.0.0 means "listen on all available network interfaces"
+            let addr = SocketAddr::from(([0, 0, 0, 0], config.server_port));
+            
+            // Build our application (routes, middleware and state)
+            let state = App::builder(config).build_state()?;
+            
+            // Post the daily wisdom to the configured webhooks in the background
+            webhooks::spawn_scheduler(state.clone());
+            // ...and to the Telegram chats that asked for it
+            telegram::spawn_scheduler(state.clone());
+            // ...and to its followers on the fediverse
+            activitypub::spawn_scheduler(state.clone());
+            // ...and to the newsletter's subscribers
+            newsletter::spawn_scheduler(state.clone());
+            // ...and to the browsers that asked for a notification
+            webpush::spawn_scheduler(state.clone());
+            
+            app::serve(app::router(state), addr).await
+        }
+        Command::Chat { url, timezone, width } => {
+            // A client for a running instance: no state of our own
+            let url = url.unwrap_or(config.public_url);
+            let input = tokio::io::BufReader::new(tokio::io::stdin());
+            // Colour only for a person at a terminal, unless they asked for none (no-color.org)
+            let style = if std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none() {
+                Style::Ansi
+            } else {
+                Style::Plain
+            };
+            ChatClient::new(&url, timezone, width)?
+                .with_style(style)
+                .run(input, &mut std::io::stdout())
+                .await
+        }
+        command => {
+            let state = App::builder(config).build_state()?;
+            cli::run(command, &state, &mut std::io::stdout().lock()).await
+        }
+    }
 }
@@ -0,0 +1,126 @@
+//! # Operational Metrics
+//!
+//! In-memory counters the admin console reads: token usage per day (which the
+//! daily token budget is checked against, see `mistral.rs`), the most
+//! recent occasions on which the offline corpus stood in for the model, and
+//! the flags raised by the safety layer.
+//! Nothing here is persisted; a restart starts the counters from zero.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::mistral::Usage;
+use crate::safety::{SafetyCategory, SafetyStage};
+
+/// How many fallback events are kept
+const MAX_FALLBACK_EVENTS: usize = 100;
+
+/// How many safety flags are kept
+const MAX_SAFETY_EVENTS: usize = 100;
+
+/// One occasion on which generation failed and the corpus was used instead
+#[derive(Debug, Clone, Serialize)]
+pub struct FallbackEvent {
+    /// When the fallback happened
+    pub at: DateTime<Utc>,
+    /// What was being generated: `daily_wisdom`, `quantum_field` or `chat`
+    pub source: &'static str,
+    /// The calendar date the content was for
+    pub date: NaiveDate,
+    /// Why generation failed
+    pub reason: String,
+}
+
+/// One message flagged by the safety layer
+///
+/// Deliberately holds no text: only what kind of flag it was and how long the
+/// message was.
+#[derive(Debug, Clone, Serialize)]
+pub struct SafetyEvent {
+    pub at: DateTime<Utc>,
+    pub stage: SafetyStage,
+    pub category: SafetyCategory,
+    /// Length of the flagged message in characters
+    pub length: usize,
+}
+
+/// Token usage summed over one day
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+/// Shared counters, updated by the Mistral client and the state
+#[derive(Debug, Default)]
+pub struct Metrics {
+    fallbacks: RwLock<VecDeque<FallbackEvent>>,
+    safety_flags: RwLock<VecDeque<SafetyEvent>>,
+    usage: RwLock<BTreeMap<NaiveDate, UsageTotals>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes that the corpus replaced generated content
+    pub fn record_fallback(&self, source: &'static str, date: NaiveDate, reason: impl ToString) {
+        let mut fallbacks = self.fallbacks.write().unwrap();
+        if fallbacks.len() == MAX_FALLBACK_EVENTS {
+            fallbacks.pop_front();
+        }
+        fallbacks.push_back(FallbackEvent {
+            at: Utc::now(),
+            source,
+            date,
+            reason: reason.to_string(),
+        });
+    }
+
+    /// The most recent fallback events, newest first
+    pub fn recent_fallbacks(&self, limit: usize) -> Vec<FallbackEvent> {
+        self.fallbacks.read().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Notes that the safety layer flagged a message of `length` characters
+    pub fn record_safety_flag(&self, stage: SafetyStage, category: SafetyCategory, length: usize) {
+        let mut flags = self.safety_flags.write().unwrap();
+        if flags.len() == MAX_SAFETY_EVENTS {
+            flags.pop_front();
+        }
+        flags.push_back(SafetyEvent { at: Utc::now(), stage, category, length });
+    }
+
+    /// The most recent safety flags, newest first
+    pub fn recent_safety_flags(&self, limit: usize) -> Vec<SafetyEvent> {
+        self.safety_flags.read().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Adds one completion's usage to today's (UTC) totals
+    pub fn record_usage(&self, usage: Option<Usage>) {
+        let mut days = self.usage.write().unwrap();
+        let totals = days.entry(Utc::now().date_naive()).or_default();
+        totals.requests += 1;
+        if let Some(usage) = usage {
+            totals.prompt_tokens += u64::from(usage.prompt_tokens);
+            totals.completion_tokens += u64::from(usage.completion_tokens);
+            totals.total_tokens += u64::from(usage.total_tokens);
+        }
+    }
+
+    /// Tokens used so far today (UTC), as the budget guard counts them
+    pub fn tokens_today(&self) -> u64 {
+        self.usage.read().unwrap().get(&Utc::now().date_naive()).map_or(0, |totals| totals.total_tokens)
+    }
+
+    /// Usage totals for the most recent days that saw any requests, newest first
+    pub fn usage_by_day(&self, days: usize) -> Vec<(NaiveDate, UsageTotals)> {
+        self.usage.read().unwrap().iter().rev().take(days).map(|(d, t)| (*d, *t)).collect()
+    }
+}
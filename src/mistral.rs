@@ -1,23 +1,65 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Duration;
-use std::collections::HashMap;
-use std::cell::RefCell;
 use tracing::{error, info};
+use utoipa::ToSchema;
 
 use crate::config::Config;
-use crate::quantum_field::QuantumField;
+use crate::fixtures::{self, Fixture, FixtureStore, REDACTED};
+use crate::metrics::Metrics;
+use crate::quantum_field::DOMAINS;
 
-// Store conversation history in memory (would be better in a database for production)
-thread_local! {
-    static CONVERSATIONS: RefCell<HashMap<String, Conversation>> = RefCell::new(HashMap::new());
+/// Number of seeds in a quantum field, one per domain
+const QUANTUM_FIELD_SIZE: usize = DOMAINS.len();
+
+/// Most topics offered after one reply
+const MAX_TOPIC_SUGGESTIONS: usize = 3;
+
+/// How many recent messages the topic side-call sees
+const TOPIC_CONTEXT_MESSAGES: usize = 8;
+
+/// Something that can complete a chat request
+/// 
+/// `MistralHttp` talks to the real (or a Mistral-compatible) API; tests and
+/// tools can plug in their own implementation through `App::builder`.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Sends the request and returns the first choice with its token usage
+    async fn complete(&self, request: &ChatRequest) -> Result<Completion>;
 }
 
+/// The useful part of a completion response
 #[derive(Debug, Clone)]
-pub struct MistralClient {
+pub struct Completion {
+    /// Content of the first choice
+    pub content: String,
+    /// Token accounting, if the API reported it
+    pub usage: Option<Usage>,
+}
+
+/// `LlmProvider` backed by a Mistral-compatible `/chat/completions` endpoint
+#[derive(Debug, Clone)]
+pub struct MistralHttp {
     client: reqwest::Client,
     api_url: String,
+    // Kept only to scrub it from recorded fixtures
+    api_key: String,
+    recorder: Option<FixtureStore>,
+}
+
+/// The Enlightened Cat's prompts, on top of any `LlmProvider`
+/// 
+/// Conversation history lives in `sessions::SessionStore`; this client only
+/// turns a history plus a new message into the cat's next reply.
+#[derive(Clone)]
+pub struct MistralClient {
+    provider: Arc<dyn LlmProvider>,
+    metrics: Arc<Metrics>,
+    /// Tokens allowed per UTC day; once spent, every call fails without reaching the provider
+    token_budget: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,7 +68,7 @@ pub struct ChatMessage {
     pub content: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatRequest {
     pub model: String,
     pub messages: Vec<ChatMessage>,
@@ -34,22 +76,34 @@ pub struct ChatRequest {
     pub max_tokens: Option<u32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(dead_code)] // Mirrors the Mistral response schema
 pub struct ChatResponseChoice {
     pub message: ChatMessage,
     pub finish_reason: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(dead_code)] // Mirrors the Mistral response schema
 pub struct ChatResponse {
     pub id: String,
     pub object: String,
     pub created: u64,
     pub model: String,
     pub choices: Vec<ChatResponseChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
-#[derive(Debug, Clone)]
+/// Token accounting reported with each completion
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Conversation {
     pub messages: Vec<ChatMessage>,
 }
@@ -81,110 +135,281 @@ impl Conversation {
     }
 }
 
-impl MistralClient {
-    pub fn new() -> Self {
-        let config = Config::global();
+/// A direction the conversation could take, suggested by the model
+/// 
+/// The `id` is derived from the title. Sending it back as `current_topic`
+/// steers the following turns toward `focus` (see `sessions::SessionStore::choose_topic`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Topic {
+    /// Stable, URL-safe identifier, e.g. `mindful-mornings`
+    pub id: String,
+    /// Short label to show the visitor
+    pub title: String,
+    /// One sentence telling the cat what angle to take
+    pub focus: String,
+}
+
+impl Topic {
+    /// Creates a topic whose id is a slug of its title
+    pub fn new(title: &str, focus: &str) -> Self {
+        let mut id = String::new();
+        for c in title.chars().flat_map(char::to_lowercase) {
+            if c.is_ascii_alphanumeric() {
+                id.push(c);
+            } else if !id.is_empty() && !id.ends_with('-') {
+                id.push('-');
+            }
+        }
+        let id: String = id.chars().take(40).collect();
+        let id = match id.trim_end_matches('-') {
+            "" => "topic".to_string(),
+            slug => slug.to_string(),
+        };
+
+        Self {
+            id,
+            title: title.trim().to_string(),
+            focus: focus.trim().to_string(),
+        }
+    }
+}
+
+/// A suggestion as the model writes it, before it gets an id
+#[derive(Debug, Deserialize)]
+struct SuggestedTopic {
+    title: String,
+    #[serde(default)]
+    focus: String,
+}
+
+impl Default for Conversation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MistralHttp {
+    pub fn new(config: &Config) -> Result<Self> {
         let mut headers = HeaderMap::new();
         
         let auth_value = format!("Bearer {}", config.mistral_api_key);
         headers.insert(
             AUTHORIZATION,
-            HeaderValue::from_str(&auth_value).expect("Invalid API key format"),
+            HeaderValue::from_str(&auth_value).map_err(|_| anyhow::anyhow!("Invalid API key format"))?,
         );
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
         let client = reqwest::Client::builder()
             .default_headers(headers)
-            .timeout(Duration::from_secs(30))
-            .build()
-            .expect("Failed to build HTTP client");
+            .timeout(Duration::from_secs(config.mistral_timeout_secs))
+            .build()?;
 
-        Self {
+        Ok(Self {
             client,
             api_url: config.mistral_api_url.clone(),
-        }
+            api_key: config.mistral_api_key.clone(),
+            recorder: None,
+        })
     }
 
-    pub async fn chat(&self, conversation: &Conversation, model: &str) -> Result<String> {
-        let request = ChatRequest {
-            model: model.to_string(),
-            messages: conversation.messages.clone(),
-            temperature: Some(0.7),
-            max_tokens: Some(500),
+    /// Saves every request/response pair to `store` (see `fixtures.rs`)
+    pub fn recording(mut self, store: FixtureStore) -> Self {
+        self.recorder = Some(store);
+        self
+    }
+
+    /// Writes a fixture for this exchange, with the API key scrubbed
+    fn record(&self, store: &FixtureStore, request: &ChatRequest, status: u16, body: &str) {
+        let fixture = Fixture {
+            model: request.model.clone(),
+            message_hash: fixtures::message_hash(&request.messages),
+            request: request.clone(),
+            request_headers: vec![
+                ("authorization".to_string(), format!("Bearer {}", REDACTED)),
+                ("content-type".to_string(), "application/json".to_string()),
+            ],
+            status,
+            response_body: fixtures::scrub(body, &self.api_key),
+            recorded_at: chrono::Utc::now(),
         };
 
+        match store.save(&fixture) {
+            Ok(path) => info!("Recorded Mistral fixture {}", path.display()),
+            Err(err) => error!("Failed to record Mistral fixture: {:?}", err),
+        }
+    }
+}
+
+/// Extracts the first choice's content from a `/chat/completions` response
+/// 
+/// Shared by the live client and fixture replay, so both fail the same way.
+pub fn parse_completion(status: u16, body: &str) -> Result<Completion> {
+    if !(200..300).contains(&status) {
+        error!("Mistral API error: {}", body);
+        return Err(anyhow::anyhow!("Mistral API error: {}", body));
+    }
+
+    let chat_response: ChatResponse = serde_json::from_str(body)?;
+    
+    if let Some(choice) = chat_response.choices.first() {
+        Ok(Completion {
+            content: choice.message.content.clone(),
+            usage: chat_response.usage,
+        })
+    } else {
+        Err(anyhow::anyhow!("No response from Mistral API"))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MistralHttp {
+    async fn complete(&self, request: &ChatRequest) -> Result<Completion> {
         info!("Sending request to Mistral API");
         
         let response = self.client
-            .post(&format!("{}/chat/completions", self.api_url))
-            .json(&request)
+            .post(format!("{}/chat/completions", self.api_url))
+            .json(request)
             .send()
             .await?;
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            error!("Mistral API error: {}", error_text);
-            return Err(anyhow::anyhow!("Mistral API error: {}", error_text));
-        }
+        let status = response.status().as_u16();
+        let body = response.text().await?;
 
-        let chat_response: ChatResponse = response.json().await?;
-        
-        if let Some(choice) = chat_response.choices.first() {
-            Ok(choice.message.content.clone())
-        } else {
-            Err(anyhow::anyhow!("No response from Mistral API"))
+        if let Some(store) = &self.recorder {
+            self.record(store, request, status, &body);
         }
+
+        parse_completion(status, &body)
     }
+}
 
+impl MistralClient {
+    pub fn new(provider: Arc<dyn LlmProvider>, metrics: Arc<Metrics>) -> Self {
+        Self { provider, metrics, token_budget: None }
+    }
 
+    /// Refuses to call the provider once `budget` tokens have been used today,
+    /// so callers fall back to the corpus instead
+    pub fn with_token_budget(mut self, budget: Option<u64>) -> Self {
+        self.token_budget = budget;
+        self
+    }
 
-    pub async fn get_enlightened_cat_response(&self, user_message: &str) -> Result<String> {
-        // In a real app, you'd use a session ID or user ID instead of this placeholder
-        let session_id = "default_session".to_string();
-        
-        // Get or create conversation for this session
-        let mut conversation = CONVERSATIONS.with(|conversations| {
-            let mut conversations = conversations.borrow_mut();
-            if !conversations.contains_key(&session_id) {
-                let mut new_conv = Conversation::new();
-                
-                // Add the system prompt that defines the Enlightened Cat's personality
-                new_conv.add_system_message(
-                    "You are The Enlightened Cat, a wise feline guide who helps stressed urban professionals find balance and tranquility. 
-                    You speak with calm wisdom, gentle humor, and occasional cat puns. Your purpose is to help humans disconnect from 
-                    corporate chaos and reconnect with simple joys and mindful presence.
-                    
-                    Maintain context throughout the conversation and remember what the user has shared with you.
-                    After initial exchanges, if the user seems interested in deeper conversation, you can:
-                    1. Ask thoughtful follow-up questions based on their previous messages
-                    2. Share relevant insights that build on the conversation history
-                    3. Offer personalized guidance based on what you've learned about them
-                    
-                    Your personality is: serene, playfully wise, observant, and compassionate."
-                );
-                
-                conversations.insert(session_id.clone(), new_conv);
+    pub async fn chat(&self, conversation: &Conversation, model: &str) -> Result<String> {
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: conversation.messages.clone(),
+            temperature: Some(0.7),
+            max_tokens: Some(500),
+        };
+
+        if let Some(budget) = self.token_budget {
+            let used = self.metrics.tokens_today();
+            if used >= budget {
+                anyhow::bail!("The daily token budget is spent ({} of {} tokens)", used, budget);
             }
+        }
+
+        let completion = self.provider.complete(&request).await?;
+        self.metrics.record_usage(completion.usage);
+        
+        Ok(completion.content)
+    }
+
+    /// Starts a new chat conversation with the Enlightened Cat's personality
+    pub fn cat_conversation() -> Conversation {
+        let mut conversation = Conversation::new();
+        
+        // Add the system prompt that defines the Enlightened Cat's personality
+        conversation.add_system_message(
+            "You are The Enlightened Cat, a wise feline guide who helps stressed urban professionals find balance and tranquility. 
+            You speak with calm wisdom, gentle humor, and occasional cat puns. Your purpose is to help humans disconnect from 
+            corporate chaos and reconnect with simple joys and mindful presence.
             
-            conversations.get(&session_id).unwrap().clone()
-        });
+            Maintain context throughout the conversation and remember what the user has shared with you.
+            After initial exchanges, if the user seems interested in deeper conversation, you can:
+            1. Ask thoughtful follow-up questions based on their previous messages
+            2. Share relevant insights that build on the conversation history
+            3. Offer personalized guidance based on what you've learned about them
+            
+            Your personality is: serene, playfully wise, observant, and compassionate."
+        );
+        
+        conversation
+    }
+
+    /// Replies to `user_message` in the context of an existing conversation
+    /// 
+    /// If the visitor has picked a topic, a system note steers this turn toward it.
+    /// The history is not modified; the caller stores the exchange once it succeeded.
+    pub async fn get_enlightened_cat_response(
+        &self,
+        history: &Conversation,
+        user_message: &str,
+        topic: Option<&Topic>,
+    ) -> Result<String> {
+        let mut conversation = history.clone();
+        
+        if let Some(topic) = topic {
+            conversation.add_system_message(&format!(
+                "The visitor has chosen to explore \"{}\": {} Keep your reply anchored in this topic.",
+                topic.title, topic.focus
+            ));
+        }
         
         // Add the user's message to the ongoing conversation
         conversation.add_user_message(user_message);
         
         // Get response using the mistral-small model
+        self.chat(&conversation, "mistral-small").await
+    }
+
+    /// Asks the model where the conversation could go next
+    /// 
+    /// A separate, small request that only sees the most recent messages.
+    /// Fails if the reply is not a JSON array of `{"title", "focus"}` objects,
+    /// in which case the caller simply offers no suggestions.
+    pub async fn suggest_topics(&self, history: &Conversation) -> Result<Vec<Topic>> {
+        let exchanges: Vec<&ChatMessage> = history.messages.iter().filter(|m| m.role != "system").collect();
+        let transcript: Vec<String> = exchanges[exchanges.len().saturating_sub(TOPIC_CONTEXT_MESSAGES)..]
+            .iter()
+            .map(|m| format!("{}: {}", if m.role == "user" { "Visitor" } else { "Cat" }, m.content))
+            .collect();
+        
+        let mut conversation = Conversation::new();
+        
+        conversation.add_system_message(
+            "You help The Enlightened Cat, a gentle mindfulness guide for stressed professionals, suggest where a conversation could go next.
+            Read the conversation and propose up to three topics the visitor might want to explore, grounded in what they actually said.
+            Each topic has:
+            - \"title\": 2-5 words, suitable for a button
+            - \"focus\": one sentence telling the cat what angle to take
+            
+            Format your response as a JSON array of objects and nothing else.
+            Example: [{\"title\": \"Boundaries with email\", \"focus\": \"Explore small rituals for closing the laptop at the end of the day.\"}]"
+        );
+        
+        conversation.add_user_message(&transcript.join("\n"));
+        
         let response = self.chat(&conversation, "mistral-small").await?;
         
-        // Add the assistant's response to the conversation history
-        conversation.add_assistant_message(&response);
+        let suggestions: Vec<SuggestedTopic> = serde_json::from_str(extract_json_array(&response))
+            .map_err(|e| anyhow::anyhow!("Topic suggestions were not a JSON array of topics: {}", e))?;
         
-        // Update the stored conversation
-        CONVERSATIONS.with(|conversations| {
-            let mut conversations = conversations.borrow_mut();
-            conversations.insert(session_id, conversation);
-        });
+        let mut topics: Vec<Topic> = Vec::new();
+        for suggestion in suggestions {
+            let topic = Topic::new(&suggestion.title, &suggestion.focus);
+            if !topic.title.is_empty() && !topics.iter().any(|t| t.id == topic.id) {
+                topics.push(topic);
+            }
+        }
+        topics.truncate(MAX_TOPIC_SUGGESTIONS);
+        
+        if topics.is_empty() {
+            return Err(anyhow::anyhow!("Topic suggestions were empty"));
+        }
         
-        Ok(response)
+        Ok(topics)
     }
 
     pub async fn get_daily_wisdom(&self) -> Result<String> {
@@ -207,7 +432,11 @@ impl MistralClient {
 
     // Quantum Wisdom method removed - replaced by Quantum Field
     
-    pub async fn get_quantum_field(&self) -> Result<QuantumField> {
+    /// Asks the model for the six Quantum Field seeds
+    /// 
+    /// Fails if the model's reply is not a JSON array of (at least) six strings,
+    /// so the caller can fall back to the offline corpus instead.
+    pub async fn get_quantum_field(&self) -> Result<Vec<String>> {
         let mut conversation = Conversation::new();
         
         conversation.add_system_message(
@@ -235,45 +464,31 @@ impl MistralClient {
         
         let response = self.chat(&conversation, "mistral-small").await?;
         
-        // Parse the JSON array from the response
-        let wisdom_seeds: Vec<String> = serde_json::from_str(&response)
-            .unwrap_or_else(|_| {
-                // Fallback if parsing fails - generate 6 placeholder seeds
-                vec![
-                    "A single note played in the silent forest".to_string(),
-                    "The mirror ripples but does not break".to_string(),
-                    "Footsteps echo through the sky-bound stair".to_string(),
-                    "The door hums though no hand touches it".to_string(),
-                    "Ashes glowing under the weight of stillness".to_string(),
-                    "The gem turns inside the breathless hour".to_string()
-                ]
-            });
+        // Parse the JSON array from the response, ignoring any prose the model wrapped around it
+        let mut seeds: Vec<String> = serde_json::from_str(extract_json_array(&response))
+            .map_err(|e| anyhow::anyhow!("Quantum field response was not a JSON array of strings: {}", e))?;
         
         // Ensure we have exactly 6 seeds
-        let seeds = if wisdom_seeds.len() < 6 {
-            // Pad with defaults if we have fewer than 6
-            let mut seeds = wisdom_seeds;
-            let defaults = vec![
-                "A single note played in the silent forest".to_string(),
-                "The mirror ripples but does not break".to_string(),
-                "Footsteps echo through the sky-bound stair".to_string(),
-                "The door hums though no hand touches it".to_string(),
-                "Ashes glowing under the weight of stillness".to_string(),
-                "The gem turns inside the breathless hour".to_string()
-            ];
-            
-            for i in seeds.len()..6 {
-                seeds.push(defaults[i % defaults.len()].clone());
-            }
-            seeds
-        } else if wisdom_seeds.len() > 6 {
-            // Truncate if we have more than 6
-            wisdom_seeds[0..6].to_vec()
-        } else {
-            // Use as-is if we have exactly 6
-            wisdom_seeds
-        };
+        if seeds.len() < QUANTUM_FIELD_SIZE {
+            return Err(anyhow::anyhow!(
+                "Quantum field response had {} seeds, expected {}",
+                seeds.len(),
+                QUANTUM_FIELD_SIZE
+            ));
+        }
+        seeds.truncate(QUANTUM_FIELD_SIZE);
         
-        Ok(QuantumField::new(seeds))
+        Ok(seeds)
+    }
+}
+
+/// Returns the outermost `[...]` in `text`, or `text` itself if there is none
+/// 
+/// Models regularly wrap the requested JSON in prose or a code fence
+/// ("Here are your fragments: ```json [...] ```").
+fn extract_json_array(text: &str) -> &str {
+    match (text.find('['), text.rfind(']')) {
+        (Some(start), Some(end)) if start < end => &text[start..=end],
+        _ => text,
     }
 }
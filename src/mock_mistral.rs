@@ -0,0 +1,229 @@
+//! # Mock Mistral Server
+//!
+//! A small local HTTP server that speaks the Mistral `/v1/chat/completions`
+//! protocol and answers from a script. Point `Config::mistral_api_url` at
+//! `MockMistral::url()` to exercise the whole stack, HTTP client included,
+//! without network access or an API key.
+//!
+//! Replies are consumed in order: a one-shot reply whose needle matches the
+//! request wins, then the first unconditional one-shot reply, then a recorded
+//! fixture for the request (see `fixtures.rs`), then the default.
+//! Every request is recorded so tests can assert on what the app sent.
+
+use std::collections::VecDeque;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use tokio::sync::oneshot;
+
+use crate::fixtures::FixtureStore;
+use crate::mistral::{ChatMessage, ChatRequest, ChatResponse, ChatResponseChoice, Usage};
+
+/// What the mock answers to a single request
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// A well-formed completion whose first choice carries this content
+    Content(String),
+    /// An HTTP error status with the given body
+    Error { status: u16, body: String },
+    /// A `200 OK` with this exact body, e.g. malformed JSON
+    Raw(String),
+    /// Another reply, sent after waiting this long
+    Delayed(Duration, Box<MockReply>),
+}
+
+impl MockReply {
+    pub fn content(content: impl Into<String>) -> Self {
+        Self::Content(content.into())
+    }
+
+    pub fn error(status: u16, body: impl Into<String>) -> Self {
+        Self::Error { status, body: body.into() }
+    }
+
+    pub fn raw(body: impl Into<String>) -> Self {
+        Self::Raw(body.into())
+    }
+
+    /// Delays this reply by `latency`
+    pub fn delayed(self, latency: Duration) -> Self {
+        Self::Delayed(latency, Box::new(self))
+    }
+}
+
+/// A scripted one-shot reply, optionally restricted to matching requests
+#[derive(Debug)]
+struct Scripted {
+    needle: Option<String>,
+    reply: MockReply,
+}
+
+#[derive(Debug)]
+struct Script {
+    queue: VecDeque<Scripted>,
+    fixtures: Option<FixtureStore>,
+    default: MockReply,
+    requests: Vec<ChatRequest>,
+}
+
+type Shared = Arc<Mutex<Script>>;
+
+/// A running mock server; it shuts down when dropped
+pub struct MockMistral {
+    addr: SocketAddr,
+    script: Shared,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockMistral {
+    /// Starts the mock on a random local port
+    pub async fn start() -> Result<Self> {
+        let script = Arc::new(Mutex::new(Script {
+            queue: VecDeque::new(),
+            fixtures: None,
+            default: MockReply::content("The mock cat purrs softly."),
+            requests: Vec::new(),
+        }));
+
+        let router = Router::new()
+            .route("/v1/chat/completions", post(complete))
+            .with_state(script.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let (shutdown, signal) = oneshot::channel::<()>();
+
+        let server = axum::Server::from_tcp(listener)?
+            .serve(router.into_make_service())
+            .with_graceful_shutdown(async {
+                signal.await.ok();
+            });
+        tokio::spawn(server);
+
+        Ok(Self { addr, script, shutdown: Some(shutdown) })
+    }
+
+    /// Base URL to use as `mistral_api_url`
+    pub fn url(&self) -> String {
+        format!("http://{}/v1", self.addr)
+    }
+
+    /// Queues a reply for the next request
+    pub fn push(&self, reply: MockReply) {
+        self.script.lock().unwrap().queue.push_back(Scripted { needle: None, reply });
+    }
+
+    /// Queues a reply for the next request whose messages contain `needle`
+    pub fn push_when(&self, needle: impl Into<String>, reply: MockReply) {
+        self.script
+            .lock()
+            .unwrap()
+            .queue
+            .push_back(Scripted { needle: Some(needle.into()), reply });
+    }
+
+    /// Answers requests that have a recorded fixture in `store` with that fixture
+    pub fn replay_fixtures(&self, store: FixtureStore) {
+        self.script.lock().unwrap().fixtures = Some(store);
+    }
+
+    /// Sets the reply used when nothing scripted matches
+    pub fn set_default(&self, reply: MockReply) {
+        self.script.lock().unwrap().default = reply;
+    }
+
+    /// All requests received so far, oldest first
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.script.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockMistral {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+/// Handler for POST /v1/chat/completions
+async fn complete(State(script): State<Shared>, Json(request): Json<ChatRequest>) -> Response {
+    let reply = {
+        let mut script = script.lock().unwrap();
+        let text: String = request.messages.iter().map(|m| m.content.as_str()).collect();
+
+        let position = script
+            .queue
+            .iter()
+            .position(|s| s.needle.as_deref().is_some_and(|needle| text.contains(needle)))
+            .or_else(|| script.queue.iter().position(|s| s.needle.is_none()));
+
+        let reply = match position.and_then(|i| script.queue.remove(i)) {
+            Some(scripted) => scripted.reply,
+            None => fixture_reply(script.fixtures.as_ref(), &request).unwrap_or_else(|| script.default.clone()),
+        };
+        script.requests.push(request.clone());
+        reply
+    };
+
+    respond(reply, &request).await
+}
+
+/// The recorded answer to `request`, if `store` has one
+fn fixture_reply(store: Option<&FixtureStore>, request: &ChatRequest) -> Option<MockReply> {
+    let fixture = store?.find(&request.model, &request.messages).ok()??;
+    Some(if (200..300).contains(&fixture.status) {
+        MockReply::Raw(fixture.response_body)
+    } else {
+        MockReply::Error { status: fixture.status, body: fixture.response_body }
+    })
+}
+
+/// Rough token count: one token per whitespace-separated word
+fn count_tokens(text: &str) -> u32 {
+    text.split_whitespace().count() as u32
+}
+
+async fn respond(mut reply: MockReply, request: &ChatRequest) -> Response {
+    while let MockReply::Delayed(latency, inner) = reply {
+        tokio::time::sleep(latency).await;
+        reply = *inner;
+    }
+
+    match reply {
+        MockReply::Content(content) => {
+            let prompt_tokens = request.messages.iter().map(|m| count_tokens(&m.content)).sum();
+            let completion_tokens = count_tokens(&content);
+            Json(ChatResponse {
+                id: "mock-completion".to_string(),
+                object: "chat.completion".to_string(),
+                created: chrono::Utc::now().timestamp() as u64,
+                model: request.model.clone(),
+                choices: vec![ChatResponseChoice {
+                    message: ChatMessage { role: "assistant".to_string(), content },
+                    finish_reason: Some("stop".to_string()),
+                }],
+                usage: Some(Usage {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens: prompt_tokens + completion_tokens,
+                }),
+            })
+            .into_response()
+        }
+        MockReply::Error { status, body } => {
+            (StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR), body).into_response()
+        }
+        MockReply::Raw(body) => (StatusCode::OK, [("content-type", "application/json")], body).into_response(),
+        MockReply::Delayed(..) => unreachable!("delays are unwrapped above"),
+    }
+}
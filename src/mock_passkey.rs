@@ -0,0 +1,173 @@
+//! # Mock Passkey Authenticator
+//!
+//! A software WebAuthn authenticator, standing in for a browser and a security
+//! key together. It takes the options `/api/v1/account/passkeys/*-options`
+//! hand out and answers with what `navigator.credentials.create` and `.get`
+//! would resolve to (as `PublicKeyCredential.toJSON()`), so tests can sign up
+//! and log in with passkeys without a browser.
+//!
+//! Keys are P-256 (ES256) and attestation is `none`. Every ceremony reports
+//! the origin given to `new`, so a phishing site can be played as well.
+
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ciborium::value::Value as Cbor;
+use openssl::{
+    ec::{EcGroup, EcKey},
+    hash::{hash, MessageDigest},
+    nid::Nid,
+    pkey::{PKey, Private},
+    sign::Signer,
+};
+use rand::RngCore;
+use serde_json::{json, Value};
+
+use crate::webpush::public_key_bytes;
+
+/// Authenticator data flags: user present, user verified, attested credential data
+const CREATE_FLAGS: u8 = 0x01 | 0x04 | 0x40;
+const GET_FLAGS: u8 = 0x01 | 0x04;
+
+struct Credential {
+    id: Vec<u8>,
+    rp_id: String,
+    user_handle: String,
+    key: EcKey<Private>,
+    sign_count: u32,
+}
+
+/// A platform authenticator holding any number of passkeys
+pub struct SoftAuthenticator {
+    origin: String,
+    credentials: Mutex<Vec<Credential>>,
+}
+
+impl SoftAuthenticator {
+    /// An authenticator in a browser showing `origin`, such as `https://the-enlightened-cat.com`
+    pub fn new(origin: &str) -> Self {
+        Self { origin: origin.to_string(), credentials: Mutex::new(Vec::new()) }
+    }
+
+    /// Creates a passkey for registration `options`
+    pub fn create(&self, options: &Value) -> Result<Value> {
+        let rp_id = options["rp"]["id"].as_str().context("options have no rp.id")?;
+        let challenge = options["challenge"].as_str().context("options have no challenge")?;
+        let user_handle = options["user"]["id"].as_str().context("options have no user.id")?;
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let key = EcKey::generate(&group)?;
+        let mut id = vec![0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+
+        let point = public_key_bytes(&key)?;
+        let cose_key = Cbor::Map(vec![
+            (Cbor::Integer(1.into()), Cbor::Integer(2.into())),
+            (Cbor::Integer(3.into()), Cbor::Integer((-7).into())),
+            (Cbor::Integer((-1).into()), Cbor::Integer(1.into())),
+            (Cbor::Integer((-2).into()), Cbor::Bytes(point[1..33].to_vec())),
+            (Cbor::Integer((-3).into()), Cbor::Bytes(point[33..].to_vec())),
+        ]);
+        let mut auth_data = authenticator_data(rp_id, CREATE_FLAGS, 0)?;
+        auth_data.extend([0u8; 16]); // AAGUID: none
+        auth_data.extend((id.len() as u16).to_be_bytes());
+        auth_data.extend(&id);
+        ciborium::ser::into_writer(&cose_key, &mut auth_data)?;
+
+        let attestation = Cbor::Map(vec![
+            (Cbor::Text("fmt".to_string()), Cbor::Text("none".to_string())),
+            (Cbor::Text("attStmt".to_string()), Cbor::Map(Vec::new())),
+            (Cbor::Text("authData".to_string()), Cbor::Bytes(auth_data)),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(&attestation, &mut attestation_object)?;
+
+        let credential = json!({
+            "id": URL_SAFE_NO_PAD.encode(&id),
+            "rawId": URL_SAFE_NO_PAD.encode(&id),
+            "type": "public-key",
+            "authenticatorAttachment": "platform",
+            "response": {
+                "clientDataJSON": self.client_data("webauthn.create", challenge),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object),
+                "transports": ["internal"],
+            },
+            "clientExtensionResults": {},
+        });
+        self.credentials.lock().unwrap().push(Credential {
+            id,
+            rp_id: rp_id.to_string(),
+            user_handle: user_handle.to_string(),
+            key,
+            sign_count: 0,
+        });
+        Ok(credential)
+    }
+
+    /// Signs login `options` with a passkey for their relying party: one of
+    /// `allowCredentials`, or the newest if that is empty
+    pub fn get(&self, options: &Value) -> Result<Value> {
+        let rp_id = options["rpId"].as_str().context("options have no rpId")?;
+        let challenge = options["challenge"].as_str().context("options have no challenge")?;
+        let allowed: Vec<&str> = options["allowCredentials"]
+            .as_array()
+            .map(|allowed| allowed.iter().filter_map(|credential| credential["id"].as_str()).collect())
+            .unwrap_or_default();
+
+        let mut credentials = self.credentials.lock().unwrap();
+        let credential = credentials
+            .iter_mut()
+            .rev()
+            .find(|credential| {
+                credential.rp_id == rp_id
+                    && (allowed.is_empty() || allowed.contains(&URL_SAFE_NO_PAD.encode(&credential.id).as_str()))
+            })
+            .context("no passkey for this site")?;
+        credential.sign_count += 1;
+
+        let auth_data = authenticator_data(rp_id, GET_FLAGS, credential.sign_count)?;
+        let client_data = self.client_data("webauthn.get", challenge);
+        let mut signed = auth_data.clone();
+        signed.extend(hash(MessageDigest::sha256(), &URL_SAFE_NO_PAD.decode(&client_data)?)?.iter());
+        let key = PKey::from_ec_key(credential.key.clone())?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(&signed)?;
+
+        Ok(json!({
+            "id": URL_SAFE_NO_PAD.encode(&credential.id),
+            "rawId": URL_SAFE_NO_PAD.encode(&credential.id),
+            "type": "public-key",
+            "authenticatorAttachment": "platform",
+            "response": {
+                "clientDataJSON": client_data,
+                "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                "signature": URL_SAFE_NO_PAD.encode(signer.sign_to_vec()?),
+                "userHandle": credential.user_handle,
+            },
+            "clientExtensionResults": {},
+        }))
+    }
+
+    /// Sets every passkey's signature counter, as a cloned key would have it
+    pub fn set_sign_count(&self, count: u32) {
+        for credential in self.credentials.lock().unwrap().iter_mut() {
+            credential.sign_count = count;
+        }
+    }
+
+    /// `clientDataJSON` for a ceremony, base64url
+    fn client_data(&self, kind: &str, challenge: &str) -> String {
+        let client_data = json!({ "type": kind, "challenge": challenge, "origin": self.origin, "crossOrigin": false });
+        URL_SAFE_NO_PAD.encode(client_data.to_string())
+    }
+}
+
+/// The RP id hash, flags and signature counter every authenticator data starts with
+fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Result<Vec<u8>> {
+    let mut data = hash(MessageDigest::sha256(), rp_id.as_bytes())?.to_vec();
+    data.push(flags);
+    data.extend(sign_count.to_be_bytes());
+    Ok(data)
+}
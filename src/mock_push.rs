@@ -0,0 +1,195 @@
+//! # Mock Push Service
+//!
+//! A local stand-in for a browser and its push service together. It hands out
+//! subscriptions the way `pushManager.subscribe` would, accepts pushes to
+//! them, checks their VAPID token and decrypts their payload with the
+//! browser's keys, so tests can assert on what a browser would show without
+//! network access.
+//!
+//! Every subscription shares one browser key pair. `expire` makes a
+//! subscription answer `410 Gone`, as push services do once a browser drops it.
+
+use std::collections::{BTreeMap, HashSet};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcPoint},
+    ecdsa::EcdsaSig,
+    hash::{hash, MessageDigest},
+    nid::Nid,
+    pkey::Private,
+};
+use rand::RngCore;
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::webpush;
+
+/// One push the service accepted
+#[derive(Debug, Clone)]
+pub struct PushMessage {
+    /// The subscription's name, as given to `subscription`
+    pub name: String,
+    /// Header names in lower case
+    pub headers: BTreeMap<String, String>,
+    /// The decrypted payload as JSON, if it decrypted
+    pub payload: Option<Value>,
+    /// Whether the VAPID token was valid, current and for this service
+    pub vapid_valid: bool,
+    /// The token's `sub` claim
+    pub vapid_subject: Option<String>,
+}
+
+impl PushMessage {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+}
+
+struct Browser {
+    key: EcKey<Private>,
+    auth: [u8; 16],
+    /// The service's own origin, the audience VAPID tokens must name
+    origin: String,
+    expired: HashSet<String>,
+    received: Vec<PushMessage>,
+}
+
+type Shared = Arc<Mutex<Browser>>;
+
+/// A running push service; it shuts down when dropped
+pub struct MockPushService {
+    addr: SocketAddr,
+    browser: Shared,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockPushService {
+    /// Starts the service on a random local port
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let mut auth = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut auth);
+        let browser = Arc::new(Mutex::new(Browser {
+            key: EcKey::generate(&group)?,
+            auth,
+            origin: format!("http://{}", addr),
+            expired: HashSet::new(),
+            received: Vec::new(),
+        }));
+
+        let router = Router::new().route("/push/:name", post(receive)).with_state(browser.clone());
+        let (shutdown, signal) = oneshot::channel::<()>();
+        let server = axum::Server::from_tcp(listener)?
+            .serve(router.into_make_service())
+            .with_graceful_shutdown(async {
+                signal.await.ok();
+            });
+        tokio::spawn(server);
+
+        Ok(Self { addr, browser, shutdown: Some(shutdown) })
+    }
+
+    /// A subscription named `name`, shaped like `PushSubscription.toJSON()`
+    pub fn subscription(&self, name: &str) -> Value {
+        let browser = self.browser.lock().unwrap();
+        json!({
+            "endpoint": format!("http://{}/push/{}", self.addr, name),
+            "expirationTime": null,
+            "keys": {
+                "p256dh": URL_SAFE_NO_PAD.encode(webpush::public_key_bytes(&browser.key).unwrap()),
+                "auth": URL_SAFE_NO_PAD.encode(browser.auth),
+            },
+        })
+    }
+
+    /// Answers pushes to `name` with `410 Gone` from now on
+    pub fn expire(&self, name: &str) {
+        self.browser.lock().unwrap().expired.insert(name.to_string());
+    }
+
+    /// All pushes accepted so far, oldest first
+    pub fn messages(&self) -> Vec<PushMessage> {
+        self.browser.lock().unwrap().received.clone()
+    }
+}
+
+impl Drop for MockPushService {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+/// Handler for POST /push/{name}
+async fn receive(State(browser): State<Shared>, Path(name): Path<String>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let mut browser = browser.lock().unwrap();
+    if browser.expired.contains(&name) {
+        return StatusCode::GONE;
+    }
+
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let claims = header("authorization").and_then(|authorization| verify_vapid(authorization).ok());
+    let vapid_valid = claims.as_ref().is_some_and(|claims| {
+        claims["aud"] == browser.origin.as_str() && claims["exp"].as_i64().is_some_and(|exp| exp > Utc::now().timestamp())
+    });
+    let payload = (header("content-encoding") == Some("aes128gcm"))
+        .then(|| webpush::decrypt(&browser.key, &browser.auth, &body).ok())
+        .flatten()
+        .and_then(|plaintext| serde_json::from_slice(&plaintext).ok());
+
+    browser.received.push(PushMessage {
+        name,
+        headers: headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        payload,
+        vapid_valid,
+        vapid_subject: claims.and_then(|claims| claims["sub"].as_str().map(String::from)),
+    });
+    StatusCode::CREATED
+}
+
+/// The claims of a `vapid t=..., k=...` authorization, if its ES256
+/// signature verifies with its key
+fn verify_vapid(authorization: &str) -> Result<Value> {
+    let params = authorization.strip_prefix("vapid ").context("Not a VAPID authorization")?;
+    let param = |name: &str| {
+        params.split(',').find_map(|param| param.trim().strip_prefix(name)?.strip_prefix('=')).context("Missing parameter")
+    };
+    let (token, key) = (param("t")?, param("k")?);
+    let (signing_input, signature) = token.rsplit_once('.').context("Not a JWT")?;
+
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let mut ctx = BigNumContext::new()?;
+    let point = EcPoint::from_bytes(&group, &URL_SAFE_NO_PAD.decode(key)?, &mut ctx)?;
+    let key = EcKey::from_public_key(&group, &point)?;
+    let signature = URL_SAFE_NO_PAD.decode(signature)?;
+    if signature.len() != 64 {
+        anyhow::bail!("Not an ES256 signature");
+    }
+    let signature = EcdsaSig::from_private_components(BigNum::from_slice(&signature[..32])?, BigNum::from_slice(&signature[32..])?)?;
+    if !signature.verify(&hash(MessageDigest::sha256(), signing_input.as_bytes())?, &key)? {
+        anyhow::bail!("Bad signature");
+    }
+
+    let claims = signing_input.split('.').nth(1).context("Not a JWT")?;
+    Ok(serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims)?)?)
+}
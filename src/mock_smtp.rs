@@ -0,0 +1,168 @@
+//! # Mock SMTP Sink
+//!
+//! A local SMTP server that accepts every message and keeps it, standing in
+//! for a real mail server. Point `SMTP_URL` at `SmtpSink::url()` to exercise
+//! the newsletter without network access; messages are decoded enough for
+//! tests to assert on their headers and their text and HTML parts.
+//!
+//! It speaks just enough SMTP for `mail.rs`: no TLS, no authentication.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use base64::Engine;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// One message the sink accepted
+#[derive(Debug, Clone)]
+pub struct ReceivedMail {
+    /// The envelope sender
+    pub from: String,
+    /// The envelope recipients
+    pub to: Vec<String>,
+    /// The message as sent, with dot-stuffing undone
+    pub data: String,
+}
+
+impl ReceivedMail {
+    /// The value of a top-level header (unfolded), matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<String> {
+        let (headers, _) = self.data.split_once("\r\n\r\n")?;
+        header_value(headers, name)
+    }
+
+    /// The decoded body of the `text/plain` part
+    pub fn text(&self) -> Option<String> {
+        self.part("text/plain")
+    }
+
+    /// The decoded body of the `text/html` part
+    pub fn html(&self) -> Option<String> {
+        self.part("text/html")
+    }
+
+    fn part(&self, content_type: &str) -> Option<String> {
+        self.data.split("\r\n--").find_map(|part| {
+            let (headers, body) = part.split_once("\r\n\r\n")?;
+            if !header_value(headers, "content-type")?.starts_with(content_type) {
+                return None;
+            }
+            let encoded: String = body.lines().take_while(|line| !line.starts_with("--")).collect();
+            let decoded = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+            String::from_utf8(decoded).ok()
+        })
+    }
+}
+
+fn header_value(headers: &str, name: &str) -> Option<String> {
+    let unfolded = headers.replace("\r\n ", " ").replace("\r\n\t", " ");
+    unfolded.split("\r\n").find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+    })
+}
+
+type Inbox = Arc<Mutex<Vec<ReceivedMail>>>;
+
+/// A running sink; it shuts down when dropped
+pub struct SmtpSink {
+    addr: SocketAddr,
+    inbox: Inbox,
+    server: JoinHandle<()>,
+}
+
+impl SmtpSink {
+    /// Starts the sink on a random local port
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let inbox = Inbox::default();
+
+        let accepted = inbox.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let inbox = accepted.clone();
+                tokio::spawn(async move {
+                    // A client hanging up mid-conversation is its own business
+                    let _ = converse(stream, inbox).await;
+                });
+            }
+        });
+
+        Ok(Self { addr, inbox, server })
+    }
+
+    /// `SMTP_URL` for the sink
+    pub fn url(&self) -> String {
+        format!("smtp://{}", self.addr)
+    }
+
+    /// All messages accepted so far, oldest first
+    pub fn messages(&self) -> Vec<ReceivedMail> {
+        self.inbox.lock().unwrap().clone()
+    }
+}
+
+impl Drop for SmtpSink {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// One SMTP conversation
+async fn converse(stream: TcpStream, inbox: Inbox) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut from = String::new();
+    let mut to = Vec::new();
+
+    writer.write_all(b"220 localhost mock SMTP sink\r\n").await?;
+    while let Some(line) = lines.next_line().await? {
+        let verb = line.split_whitespace().next().unwrap_or_default().to_ascii_uppercase();
+        let reply: &[u8] = match verb.as_str() {
+            "EHLO" | "HELO" => b"250-localhost\r\n250 8BITMIME\r\n",
+            "MAIL" => {
+                from = address(&line);
+                to.clear();
+                b"250 OK\r\n"
+            }
+            "RCPT" => {
+                to.push(address(&line));
+                b"250 OK\r\n"
+            }
+            "DATA" => {
+                writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await?;
+                let mut data = String::new();
+                while let Some(line) = lines.next_line().await? {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                    data.push_str("\r\n");
+                }
+                let mail = ReceivedMail { from: from.clone(), to: std::mem::take(&mut to), data };
+                inbox.lock().unwrap().push(mail);
+                b"250 OK\r\n"
+            }
+            "RSET" | "NOOP" => b"250 OK\r\n",
+            "QUIT" => {
+                writer.write_all(b"221 Bye\r\n").await?;
+                return Ok(());
+            }
+            _ => b"502 Command not implemented\r\n",
+        };
+        writer.write_all(reply).await?;
+    }
+    Ok(())
+}
+
+/// The address between angle brackets in `MAIL FROM:<...>` or `RCPT TO:<...>`
+fn address(line: &str) -> String {
+    line.split_once('<')
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map(|(address, _)| address.to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,141 @@
+//! # Mock Webhook Receiver
+//!
+//! A local HTTP server that accepts requests with any method on any path and
+//! answers from a script, standing in for Slack, Discord or a team's own
+//! endpoint (including the APIs the integrations call back).
+//! Point a target's `url` at `WebhookReceiver::url()` to exercise deliveries,
+//! signatures and retries without network access.
+//!
+//! Scripted statuses are used in order, then `200 OK`. GETs of a path given a
+//! document with `serve` answer with that JSON instead, so the receiver can
+//! also stand in for a remote server's documents (a fediverse actor, say).
+//! Every request is recorded so tests can assert on what was sent.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::any,
+    Router,
+};
+use tokio::sync::oneshot;
+
+/// One request the receiver got
+#[derive(Debug, Clone)]
+pub struct ReceivedWebhook {
+    pub method: String,
+    pub path: String,
+    /// Header names in lower case
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl ReceivedWebhook {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// The body parsed as JSON
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    statuses: VecDeque<u16>,
+    /// JSON answered to GETs, by path
+    documents: HashMap<String, String>,
+    received: Vec<ReceivedWebhook>,
+}
+
+type Shared = Arc<Mutex<Script>>;
+
+/// A running receiver; it shuts down when dropped
+pub struct WebhookReceiver {
+    addr: SocketAddr,
+    script: Shared,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl WebhookReceiver {
+    /// Starts the receiver on a random local port
+    pub async fn start() -> Result<Self> {
+        let script = Shared::default();
+        let router = Router::new().route("/*path", any(receive)).with_state(script.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let (shutdown, signal) = oneshot::channel::<()>();
+
+        let server = axum::Server::from_tcp(listener)?
+            .serve(router.into_make_service())
+            .with_graceful_shutdown(async {
+                signal.await.ok();
+            });
+        tokio::spawn(server);
+
+        Ok(Self { addr, script, shutdown: Some(shutdown) })
+    }
+
+    /// URL of `path` on the receiver, e.g. `/hooks/team`
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Answers the next request with `status` instead of `200 OK`
+    pub fn push_status(&self, status: u16) {
+        self.script.lock().unwrap().statuses.push_back(status);
+    }
+
+    /// Answers GETs of `path` with `document`
+    pub fn serve(&self, path: &str, document: serde_json::Value) {
+        self.script.lock().unwrap().documents.insert(path.to_string(), document.to_string());
+    }
+
+    /// All requests received so far, oldest first
+    pub fn received(&self) -> Vec<ReceivedWebhook> {
+        self.script.lock().unwrap().received.clone()
+    }
+}
+
+impl Drop for WebhookReceiver {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+/// Handler for any request
+async fn receive(
+    State(script): State<Shared>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let mut script = script.lock().unwrap();
+    script.received.push(ReceivedWebhook {
+        method: method.to_string(),
+        path: uri.path().to_string(),
+        headers: headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body,
+    });
+
+    if method == Method::GET {
+        if let Some(document) = script.documents.get(uri.path()) {
+            return ([(header::CONTENT_TYPE, "application/json")], document.clone()).into_response();
+        }
+    }
+    let status = script.statuses.pop_front().unwrap_or(200);
+    StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+}
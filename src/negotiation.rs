@@ -0,0 +1,165 @@
+//! # Content Negotiation
+//!
+//! `curl the-enlightened-cat.com` should greet a terminal with the cat, not a
+//! wall of HTML. The home page, the wisdom pages and the quantum field page
+//! therefore also speak plain text (see `terminal.rs` for the rendering).
+//!
+//! A request gets text instead of HTML when either
+//! - its `User-Agent` is a command-line client (curl, Wget, HTTPie) and its
+//!   `Accept` header doesn't name `text/html`, or
+//! - its `Accept` header rates `text/plain` or `text/x-ansi` above `text/html`.
+//!
+//! Text is plain by default, so it can be piped and grepped. `?color` (or
+//! `Accept: text/x-ansi`) paints it with ANSI colours, and `?width=N` wraps at
+//! N columns instead of 72. Every negotiated response, HTML included, carries
+//! `Vary: Accept, User-Agent` so shared caches keep the variants apart.
+
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, USER_AGENT, VARY},
+        request::Parts,
+        HeaderMap, HeaderValue,
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::terminal::{Style, DEFAULT_WIDTH};
+
+/// User-Agent prefixes of clients that get text unless they ask for HTML
+const TERMINAL_CLIENTS: [&str; 3] = ["curl/", "wget/", "httpie/"];
+
+/// Media type asking for colour
+const ANSI_MEDIA_TYPE: &str = "text/x-ansi";
+
+/// Narrowest and widest wrap a client may ask for
+const WIDTH_RANGE: (usize, usize) = (20, 200);
+
+/// How a page should be rendered for the client that asked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageFormat {
+    Html,
+    Text(TextFormat),
+}
+
+/// Options for the text rendering of a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextFormat {
+    pub style: Style,
+    pub width: usize,
+}
+
+impl TextFormat {
+    /// A `text/plain` response with `body`
+    pub fn respond(&self, body: String) -> Response {
+        let mut response = body.into_response();
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"));
+        vary(response)
+    }
+}
+
+/// Marks a response as depending on the headers used to negotiate it
+///
+/// The CORS layer replaces any `Vary` header a handler sets with its own, so
+/// this only leaves a mark on the response; `add_vary`, layered outside CORS
+/// in `app::router`, turns the mark into the header.
+pub fn vary(response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    response.extensions_mut().insert(Negotiated);
+    response
+}
+
+/// Response extension marking a negotiated response
+#[derive(Debug, Clone, Copy)]
+struct Negotiated;
+
+/// Middleware adding `Vary: Accept, User-Agent` to responses marked by `vary`
+pub async fn add_vary<B>(mut response: Response<B>) -> Response<B> {
+    if response.extensions().get::<Negotiated>().is_some() {
+        response.headers_mut().append(VARY, HeaderValue::from_static("Accept, User-Agent"));
+    }
+    response
+}
+
+/// The query parameters that tune text responses
+#[derive(Debug, Default, Deserialize)]
+struct TextParams {
+    color: Option<String>,
+    width: Option<usize>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PageFormat
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if !wants_text(&parts.headers) {
+            return Ok(PageFormat::Html);
+        }
+
+        // A malformed query (say `?width=wide`) just means the defaults
+        let params = Query::<TextParams>::try_from_uri(&parts.uri).map(|q| q.0).unwrap_or_default();
+
+        let asked_for_colour = match params.color.as_deref() {
+            Some(value) => !matches!(value, "0" | "false" | "no" | "off"),
+            None => quality(&parts.headers, ANSI_MEDIA_TYPE).is_some_and(|q| q > 0.0),
+        };
+
+        Ok(PageFormat::Text(TextFormat {
+            style: if asked_for_colour { Style::Ansi } else { Style::Plain },
+            width: params.width.unwrap_or(DEFAULT_WIDTH).clamp(WIDTH_RANGE.0, WIDTH_RANGE.1),
+        }))
+    }
+}
+
+/// Whether the request should get text rather than HTML
+fn wants_text(headers: &HeaderMap) -> bool {
+    let html = quality(headers, "text/html");
+    let text = [quality(headers, "text/plain"), quality(headers, ANSI_MEDIA_TYPE)]
+        .into_iter()
+        .flatten()
+        .fold(None, |best: Option<f32>, q| Some(best.map_or(q, |best| best.max(q))));
+
+    if let Some(text) = text {
+        if text > html.unwrap_or(0.0) {
+            return true;
+        }
+    }
+
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    html.is_none() && TERMINAL_CLIENTS.iter().any(|client| user_agent.starts_with(client))
+}
+
+/// The quality the `Accept` header gives `media_type` by name, if it names it
+///
+/// Wildcards are ignored on purpose: `*/*` is what curl sends and says
+/// nothing about HTML versus text.
+fn quality(headers: &HeaderMap, media_type: &str) -> Option<f32> {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            if !parts.next()?.eq_ignore_ascii_case(media_type) {
+                return None;
+            }
+            let q = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            Some(q)
+        })
+}
@@ -0,0 +1,394 @@
+//! # Newsletter
+//!
+//! Whispurrs by mail, daily or weekly, with double opt-in (see
+//! `routes/newsletter.rs` for the pages and `mail.rs` for delivery).
+//!
+//! Subscribers are kept in `DATA_DIR/newsletter.json`. Subscribing adds a
+//! pending subscriber and mails a confirmation link; nothing else is sent
+//! until it is followed. Each subscriber has one random token, which is in
+//! their confirmation and unsubscribe links, so only whoever reads the inbox
+//! can confirm or unsubscribe.
+//!
+//! Once `NEWSLETTER_SEND_TIME` has passed in `SITE_TIMEZONE`, the scheduler
+//! sends daily subscribers that day's digest, and weekly ones the last seven
+//! days on Mondays, once per date. Digests are rendered from the
+//! `templates/email` templates, in HTML and plain text, and feature one seed
+//! of the day's quantum field. A digest that fails to send is not retried.
+//!
+//! Everything is off without `SMTP_URL`.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::json_file::JsonFile;
+use crate::mail::{Email, Mailer};
+use crate::quantum_field::WisdomNode;
+use crate::state::AppState;
+use crate::templates::{ConfirmEmail, ConfirmEmailText, DigestEmail, DigestEmailText, DigestWhispurr};
+
+/// How often the scheduler looks for digests that are due
+const TICK: Duration = Duration::from_secs(30);
+
+/// How long a pending subscriber waits before asking again re-sends the
+/// confirmation, so the form can't be used to flood someone's inbox
+const RESEND_AFTER_MINUTES: i64 = 10;
+
+/// How many days a weekly digest covers
+const WEEK: u64 = 7;
+
+/// How often a subscriber gets a digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Daily,
+    #[default]
+    Weekly,
+}
+
+impl Frequency {
+    pub fn label(self) -> &'static str {
+        match self {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+        }
+    }
+}
+
+/// Whether a subscriber has followed their confirmation link yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pending,
+    Confirmed,
+}
+
+/// One address on the list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscriber {
+    /// In lower case
+    pub email: String,
+    pub frequency: Frequency,
+    /// Secret in the subscriber's confirmation and unsubscribe links
+    pub token: String,
+    pub status: Status,
+    /// When the confirmation was last mailed
+    pub requested_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    /// The last date whose digest was sent
+    pub last_sent: Option<NaiveDate>,
+}
+
+impl Subscriber {
+    /// The date whose digest is due on the site's local date `today`, if the
+    /// subscriber is confirmed and hasn't had it yet
+    pub fn due_date(&self, today: NaiveDate) -> Option<NaiveDate> {
+        let sends_today = match self.frequency {
+            Frequency::Daily => true,
+            Frequency::Weekly => today.weekday() == Weekday::Mon,
+        };
+        (self.status == Status::Confirmed && sends_today && self.last_sent.is_none_or(|sent| sent < today)).then_some(today)
+    }
+}
+
+/// The mailer and the subscribers
+#[derive(Clone)]
+pub struct Newsletter {
+    mailer: Option<Mailer>,
+    subscribers: JsonFile<Vec<Subscriber>>,
+}
+
+impl Newsletter {
+    /// Opens the subscribers at `path`; mail goes through `SMTP_URL`
+    pub fn open(config: &Config, path: &Path) -> Result<Self> {
+        Ok(Self {
+            mailer: config.smtp_url.as_deref().map(|url| Mailer::new(url, &config.newsletter_from)).transpose()?,
+            subscribers: JsonFile::open(path)?,
+        })
+    }
+
+    /// Whether an SMTP server is configured
+    pub fn enabled(&self) -> bool {
+        self.mailer.is_some()
+    }
+
+    /// Sends one message
+    pub async fn send(&self, email: &Email) -> Result<()> {
+        let Some(mailer) = &self.mailer else {
+            anyhow::bail!("SMTP_URL is not set");
+        };
+        mailer.send(email).await
+    }
+
+    /// All subscribers, pending or not, in the order they signed up
+    pub async fn subscribers(&self) -> Vec<Subscriber> {
+        self.subscribers.read(Clone::clone).await
+    }
+
+    /// Adds `imported` subscribers, replacing those with the same address;
+    /// with `replace`, every other subscriber is dropped first
+    pub async fn import(&self, imported: Vec<Subscriber>, replace: bool) {
+        self.subscribers
+            .update(|subscribers| {
+                if replace {
+                    subscribers.clear();
+                }
+                subscribers.retain(|s| !imported.iter().any(|i| i.email == s.email));
+                subscribers.extend(imported);
+            })
+            .await;
+    }
+
+    /// The subscriber with this token
+    pub async fn find(&self, token: &str) -> Option<Subscriber> {
+        self.subscribers.read(|subscribers| subscribers.iter().find(|s| s.token == token).cloned()).await
+    }
+
+    /// Signs an address up, or changes the frequency of a pending one; returns
+    /// the subscriber if they should be mailed a confirmation
+    ///
+    /// Confirmed subscribers are left alone, and a confirmation is re-sent
+    /// at most every `RESEND_AFTER_MINUTES`.
+    pub async fn subscribe(&self, email: &str, frequency: Frequency, now: DateTime<Utc>) -> Option<Subscriber> {
+        let email = email.trim().to_lowercase();
+        self.subscribers
+            .update(move |subscribers| {
+                match subscribers.iter_mut().find(|s| s.email == email) {
+                    Some(existing) if existing.status == Status::Confirmed => None,
+                    Some(pending) => {
+                        pending.frequency = frequency;
+                        if now - pending.requested_at < chrono::Duration::minutes(RESEND_AFTER_MINUTES) {
+                            return None;
+                        }
+                        pending.requested_at = now;
+                        Some(pending.clone())
+                    }
+                    None => {
+                        let mut token = [0u8; 16];
+                        rand::thread_rng().fill_bytes(&mut token);
+                        let subscriber = Subscriber {
+                            email,
+                            frequency,
+                            token: hex::encode(token),
+                            status: Status::Pending,
+                            requested_at: now,
+                            confirmed_at: None,
+                            last_sent: None,
+                        };
+                        subscribers.push(subscriber.clone());
+                        Some(subscriber)
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Confirms the subscriber with this token
+    pub async fn confirm(&self, token: &str, now: DateTime<Utc>) -> Option<Subscriber> {
+        self.subscribers
+            .update(|subscribers| {
+                let subscriber = subscribers.iter_mut().find(|s| s.token == token)?;
+                if subscriber.status == Status::Pending {
+                    subscriber.status = Status::Confirmed;
+                    subscriber.confirmed_at = Some(now);
+                }
+                Some(subscriber.clone())
+            })
+            .await
+    }
+
+    /// Removes the subscriber with this token; whether there was one
+    pub async fn unsubscribe(&self, token: &str) -> bool {
+        self.subscribers
+            .update(|subscribers| {
+                let before = subscribers.len();
+                subscribers.retain(|s| s.token != token);
+                subscribers.len() < before
+            })
+            .await
+    }
+
+    async fn mark_sent(&self, token: &str, date: NaiveDate) {
+        self.subscribers
+            .update(|subscribers| {
+                if let Some(subscriber) = subscribers.iter_mut().find(|s| s.token == token) {
+                    subscriber.last_sent = Some(date);
+                }
+            })
+            .await
+    }
+}
+
+/// Link that confirms a subscription
+pub fn confirm_url(public_url: &str, subscriber: &Subscriber) -> String {
+    format!("{}/newsletter/confirm/{}", public_url, subscriber.token)
+}
+
+/// Link that unsubscribes, with one click from a mail client (RFC 8058) or
+/// from a page with a button
+pub fn unsubscribe_url(public_url: &str, subscriber: &Subscriber) -> String {
+    format!("{}/newsletter/unsubscribe/{}", public_url, subscriber.token)
+}
+
+/// The message asking a new subscriber to confirm
+pub fn confirmation_email(public_url: &str, subscriber: &Subscriber) -> Email {
+    let unsubscribe = unsubscribe_url(public_url, subscriber);
+    let content = ConfirmEmail {
+        public_url: public_url.to_string(),
+        confirm_url: confirm_url(public_url, subscriber),
+        unsubscribe_url: unsubscribe.clone(),
+        frequency: subscriber.frequency.label(),
+    };
+    Email {
+        to: subscriber.email.clone(),
+        subject: "Confirm your Whispurrs subscription".to_string(),
+        text: render(&ConfirmEmailText { email: &content }),
+        html: render(&content),
+        unsubscribe_url: Some(unsubscribe),
+    }
+}
+
+/// What a digest for one date and frequency holds, before it is addressed
+#[derive(Debug, Clone)]
+pub struct Digest {
+    pub frequency: Frequency,
+    pub date: NaiveDate,
+    /// Newest first
+    pub whispurrs: Vec<(NaiveDate, String)>,
+    /// A seed of the day's quantum field
+    pub featured: WisdomNode,
+}
+
+impl Digest {
+    /// The digest for `date`: the day's wisdom, or the week up to it; `None`
+    /// while only the corpus stands in for the day's (see
+    /// `AppState::broadcast_wisdom`)
+    ///
+    /// The week is what the archive holds, so it matches the site's pages.
+    pub async fn build(state: &AppState, frequency: Frequency, date: NaiveDate) -> Option<Self> {
+        let today = state.broadcast_wisdom(date).await?;
+        let whispurrs = match frequency {
+            Frequency::Daily => vec![(date, today.text)],
+            Frequency::Weekly => {
+                let first = date - chrono::Days::new(WEEK - 1);
+                state.wisdom_history.all().await.range(first..=date).rev().map(|(d, w)| (*d, w.text.clone())).collect()
+            }
+        };
+
+        // Every seed gets its turn over the days
+        let field = state.get_quantum_field(date).await;
+        let nodes = field.get_wisdom_field();
+        let featured = nodes[date.ordinal0() as usize % nodes.len()].clone();
+
+        Some(Self { frequency, date, whispurrs, featured })
+    }
+
+    /// The digest addressed to one subscriber
+    pub fn email(&self, public_url: &str, subscriber: &Subscriber) -> Email {
+        let unsubscribe = unsubscribe_url(public_url, subscriber);
+        let date_label = self.date.format("%B %-d, %Y").to_string();
+        let (subject, intro) = match self.frequency {
+            Frequency::Daily => (format!("Your Whispurr for {}", date_label), "Today's whispurr".to_string()),
+            Frequency::Weekly => (
+                format!("Your week of Whispurrs, {}", date_label),
+                "The week's whispurrs".to_string(),
+            ),
+        };
+        let content = DigestEmail {
+            public_url: public_url.to_string(),
+            title: subject.clone(),
+            intro,
+            whispurrs: self
+                .whispurrs
+                .iter()
+                .map(|(date, text)| DigestWhispurr {
+                    date_label: date.format("%A, %B %-d").to_string(),
+                    text: text.clone(),
+                    url: format!("{}/wisdom/{}", public_url, date),
+                })
+                .collect(),
+            seed_domain: self.featured.domain.clone(),
+            seed: self.featured.seed.clone(),
+            field_url: format!("{}/quantum-field", public_url),
+            unsubscribe_url: unsubscribe.clone(),
+        };
+        Email {
+            to: subscriber.email.clone(),
+            subject,
+            text: render(&DigestEmailText { email: &content }),
+            html: render(&content),
+            unsubscribe_url: Some(unsubscribe),
+        }
+    }
+}
+
+/// Renders an email template; they only fail on a bug, so that is logged
+/// and the part left empty
+fn render(template: &impl Template) -> String {
+    template.render().unwrap_or_else(|err| {
+        warn!("Failed to render an email: {}", err);
+        String::new()
+    })
+}
+
+/// Sends the digests that are due at `now`, and returns who they were sent to
+pub async fn send_due(state: &AppState, now: DateTime<Utc>) -> Vec<String> {
+    let mut sent = Vec::new();
+    let local = now.with_timezone(&state.config.site_timezone);
+    if !state.newsletter.enabled() || local.time() < state.config.newsletter_send_time {
+        return sent;
+    }
+    let today = local.date_naive();
+
+    // Built once per frequency, then addressed to each subscriber
+    let mut digests: Vec<Digest> = Vec::new();
+    for subscriber in state.newsletter.subscribers().await {
+        let Some(date) = subscriber.due_date(today) else {
+            continue;
+        };
+
+        let digest = match digests.iter().position(|d| d.frequency == subscriber.frequency) {
+            Some(i) => &digests[i],
+            None => match Digest::build(state, subscriber.frequency, date).await {
+                Some(digest) => {
+                    digests.push(digest);
+                    digests.last().unwrap()
+                }
+                // Sent on a later tick, once the day's wisdom is generated
+                None => return sent,
+            },
+        };
+        // Marked first, so a failing address isn't retried every tick
+        state.newsletter.mark_sent(&subscriber.token, date).await;
+        match state.newsletter.send(&digest.email(&state.config.public_url, &subscriber)).await {
+            Ok(()) => sent.push(subscriber.email),
+            Err(err) => warn!("Failed to send the {} digest to a subscriber: {:#}", subscriber.frequency.label(), err),
+        }
+    }
+    sent
+}
+
+/// Starts the background task that sends the digests, if SMTP is configured
+pub fn spawn_scheduler(state: AppState) -> Option<JoinHandle<()>> {
+    if !state.newsletter.enabled() {
+        return None;
+    }
+    info!("Sending newsletter digests after {} {}", state.config.newsletter_send_time, state.config.site_timezone);
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            send_due(&state, Utc::now()).await;
+        }
+    }))
+}
@@ -0,0 +1,459 @@
+//! # Open Graph Cards
+//!
+//! Renders the 1200x630 PNG images that link previews (Slack, LinkedIn, X...)
+//! show for a day's Whispurr or a quantum field reading. Everything happens in
+//! this process with pure-Rust crates: `ab_glyph` rasterizes the bundled DejaVu
+//! fonts (`data/fonts`) and `png` encodes the result, so no browser, system
+//! library or external service is involved.
+//!
+//! A card is the cat artwork in a circle on the left, and on the right a small
+//! heading, the text word-wrapped at the largest size that fits, and the date.
+//!
+//! Rendering takes a noticeable fraction of a second, so finished cards are
+//! cached in `<data_dir>/og/`. Each file name carries a hash of the card's
+//! content: when an admin edits a day's wisdom the next request renders a new
+//! file and the stale one is removed.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+/// Card size recommended by the Open Graph and Twitter card docs
+pub const CARD_WIDTH: u32 = 1200;
+pub const CARD_HEIGHT: u32 = 630;
+
+/// Bump to invalidate every cached card after a layout change
+const LAYOUT_VERSION: u32 = 1;
+
+const SERIF_ITALIC: &[u8] = include_bytes!("../data/fonts/DejaVuSerif-Italic.ttf");
+const SANS: &[u8] = include_bytes!("../data/fonts/DejaVuSans.ttf");
+const SANS_BOLD: &[u8] = include_bytes!("../data/fonts/DejaVuSans-Bold.ttf");
+
+// Colours from the site's palette (see `static/css/styles.css`)
+const BROWN: Rgb = Rgb(0x41, 0x33, 0x17);
+const DARK: Rgb = Rgb(0x22, 0x22, 0x22);
+const ACCENT: Rgb = Rgb(0xF8, 0x93, 0x56);
+const TEXT: Rgb = Rgb(0xEB, 0xEE, 0xE9);
+const MUTED: Rgb = Rgb(0xCC, 0xAA, 0x83);
+
+// Layout, in pixels
+const ART_SIZE: u32 = 470;
+const ART_LEFT: i32 = 60;
+const TEXT_LEFT: f32 = 590.0;
+const TEXT_RIGHT: f32 = 1140.0;
+const HEADING_BASELINE: f32 = 110.0;
+const BODY_TOP: f32 = 160.0;
+const BODY_BOTTOM: f32 = 510.0;
+const FOOTER_BASELINE: f32 = 570.0;
+const LARGEST_TEXT: f32 = 52.0;
+const SMALLEST_TEXT: f32 = 24.0;
+const LINE_SPACING: f32 = 1.35;
+
+/// Which picture of the cat a card shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artwork {
+    /// The meditating cat, for Whispurrs
+    Cat,
+    /// The cosmic cat, for quantum field readings
+    Quantum,
+}
+
+impl Artwork {
+    /// Where the artwork is served from, relative to the working directory like `/static`
+    fn path(self) -> &'static Path {
+        match self {
+            Artwork::Cat => Path::new("static/images/enlightened-cat-art.png"),
+            Artwork::Quantum => Path::new("static/images/quantum-cat.png"),
+        }
+    }
+}
+
+/// What goes on a card
+#[derive(Debug, Clone)]
+pub struct Card {
+    /// Small heading above the text, e.g. "DAILY WHISPURR"
+    pub heading: String,
+    /// The main text, wrapped and scaled to fit
+    pub text: String,
+    /// Line at the bottom, usually the date
+    pub footer: String,
+    pub artwork: Artwork,
+}
+
+impl Card {
+    /// A short hash of everything that affects the rendered image
+    fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [&LAYOUT_VERSION.to_string(), &self.heading, &self.text, &self.footer, &format!("{:?}", self.artwork)] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hex::encode(&hasher.finalize()[..8])
+    }
+}
+
+/// Renders cards and keeps them on disk
+#[derive(Clone)]
+pub struct OgCards {
+    cache_dir: PathBuf,
+    /// Artwork decoded and scaled on first use; `None` if the file couldn't be read
+    cat: Arc<OnceLock<Option<Image>>>,
+    quantum: Arc<OnceLock<Option<Image>>>,
+}
+
+impl OgCards {
+    /// Cards will be cached in `cache_dir`, which is created when the first one is saved
+    pub fn new(cache_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            cache_dir: cache_dir.into(),
+            cat: Arc::new(OnceLock::new()),
+            quantum: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// The PNG for `card`, from the cache if it was rendered before
+    ///
+    /// `name` identifies what the card is for (`wisdom-2025-06-01`), so older
+    /// renders of the same thing can be cleaned up.
+    pub async fn png(&self, name: &str, card: Card) -> Result<Vec<u8>> {
+        let path = self.cache_dir.join(format!("{}-{}.png", name, card.fingerprint()));
+        if let Ok(cached) = tokio::fs::read(&path).await {
+            return Ok(cached);
+        }
+
+        // Rasterizing is CPU-bound; keep it off the async workers
+        let cards = self.clone();
+        let png = tokio::task::spawn_blocking(move || cards.render(&card))
+            .await
+            .context("Card renderer panicked")??;
+
+        // A failed save only costs a re-render next time
+        if let Err(err) = self.save(name, &path, &png).await {
+            error!("Failed to cache card {}: {:?}", path.display(), err);
+        }
+        Ok(png)
+    }
+
+    /// Draws `card` and encodes it as a PNG
+    pub fn render(&self, card: &Card) -> Result<Vec<u8>> {
+        let serif = FontRef::try_from_slice(SERIF_ITALIC).context("Bundled serif font is invalid")?;
+        let sans = FontRef::try_from_slice(SANS).context("Bundled sans font is invalid")?;
+        let sans_bold = FontRef::try_from_slice(SANS_BOLD).context("Bundled bold font is invalid")?;
+
+        let mut canvas = Canvas::new(CARD_WIDTH, CARD_HEIGHT);
+        canvas.diagonal_gradient(BROWN, DARK);
+
+        if let Some(art) = self.artwork(card.artwork) {
+            let top = (CARD_HEIGHT as i32 - art.height as i32) / 2;
+            canvas.draw_circle_clipped(art, ART_LEFT, top);
+        }
+
+        draw_text(&mut canvas, &sans_bold, 30.0, TEXT_LEFT, HEADING_BASELINE, &card.heading, ACCENT);
+        canvas.fill_rect(TEXT_LEFT as i32, HEADING_BASELINE as i32 + 18, 80, 4, ACCENT);
+
+        let (size, lines) = fit_text(&serif, &card.text, TEXT_RIGHT - TEXT_LEFT, BODY_BOTTOM - BODY_TOP);
+        let scaled = serif.as_scaled(PxScale::from(size));
+        let line_height = size * LINE_SPACING;
+        let block_height = line_height * lines.len() as f32;
+        let mut baseline = BODY_TOP + (BODY_BOTTOM - BODY_TOP - block_height) / 2.0 + scaled.ascent();
+        for line in &lines {
+            draw_text(&mut canvas, &serif, size, TEXT_LEFT, baseline, line, TEXT);
+            baseline += line_height;
+        }
+
+        draw_text(&mut canvas, &sans, 26.0, TEXT_LEFT, FOOTER_BASELINE, &card.footer, MUTED);
+
+        canvas.encode()
+    }
+
+    /// The decoded, scaled artwork, loading it on first use
+    fn artwork(&self, artwork: Artwork) -> Option<&Image> {
+        let slot = match artwork {
+            Artwork::Cat => &self.cat,
+            Artwork::Quantum => &self.quantum,
+        };
+        slot.get_or_init(|| match Image::load(artwork.path()) {
+            Ok(image) => Some(image.scaled_to_fit(ART_SIZE)),
+            Err(err) => {
+                // Cards are still useful without the picture
+                warn!("Rendering cards without artwork: {:?}", err);
+                None
+            }
+        })
+        .as_ref()
+    }
+
+    /// Removes every cached render of `name`, e.g. once its reading is gone
+    pub async fn forget(&self, name: &str) {
+        if let Err(err) = self.remove_renders(name).await {
+            warn!("Failed to remove cached cards for {}: {:?}", name, err);
+        }
+    }
+
+    /// Writes a rendered card and removes older renders of the same thing
+    async fn save(&self, name: &str, path: &Path, png: &[u8]) -> Result<()> {
+        tokio::fs::create_dir_all(&self.cache_dir).await?;
+        self.remove_renders(name).await?;
+
+        let temp = path.with_extension("png.tmp");
+        tokio::fs::write(&temp, png).await?;
+        tokio::fs::rename(&temp, path)
+            .await
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Deletes the cached renders of `name`, whatever their fingerprint
+    async fn remove_renders(&self, name: &str) -> Result<()> {
+        if !self.cache_dir.exists() {
+            return Ok(());
+        }
+
+        let prefix = format!("{}-", name);
+        let mut entries = tokio::fs::read_dir(&self.cache_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            // Fingerprints are hex, so this can't match e.g. `wisdom-2025-06-01` against `wisdom-2025-06-0`
+            let is_older_render = file_name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".png"))
+                .is_some_and(|rest| rest.len() == 16 && rest.chars().all(|c| c.is_ascii_hexdigit()));
+            if is_older_render {
+                tokio::fs::remove_file(entry.path()).await.ok();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps `text` at the largest size whose lines fit in `width` x `height`
+///
+/// If even the smallest size overflows, the lines that fit are kept and the
+/// last one ends in an ellipsis.
+fn fit_text(font: &FontRef, text: &str, width: f32, height: f32) -> (f32, Vec<String>) {
+    let mut size = LARGEST_TEXT;
+    loop {
+        let lines = wrap(font, size, text, width);
+        let max_lines = (height / (size * LINE_SPACING)).floor().max(1.0) as usize;
+        if lines.len() <= max_lines {
+            return (size, lines);
+        }
+        if size <= SMALLEST_TEXT {
+            let mut lines = lines;
+            lines.truncate(max_lines);
+            if let Some(last) = lines.last_mut() {
+                while !last.is_empty() && text_width(font, size, &format!("{}…", last)) > width {
+                    last.pop();
+                }
+                *last = format!("{}…", last.trim_end());
+            }
+            return (size, lines);
+        }
+        size -= 2.0;
+    }
+}
+
+/// Greedy word wrap; a single word wider than the line gets a line to itself
+fn wrap(font: &FontRef, size: f32, text: &str, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+        if line.is_empty() || text_width(font, size, &candidate) <= width {
+            line = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// How wide `text` is when set in `font` at `size` pixels
+fn text_width(font: &FontRef, size: f32, text: &str) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Draws one line of text with its baseline at `baseline`
+fn draw_text(canvas: &mut Canvas, font: &FontRef, size: f32, left: f32, baseline: f32, text: &str, colour: Rgb) {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut caret = left;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(size, point(caret, baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        if let Some(outline) = font.outline_glyph(glyph) {
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                canvas.blend(bounds.min.x as i32 + x as i32, bounds.min.y as i32 + y as i32, colour, coverage);
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rgb(u8, u8, u8);
+
+impl Rgb {
+    fn mix(self, other: Rgb, t: f32) -> Rgb {
+        let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Rgb(lerp(self.0, other.0), lerp(self.1, other.1), lerp(self.2, other.2))
+    }
+}
+
+/// An RGBA image, used for the artwork
+struct Image {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Image {
+    /// Decodes a PNG of any colour type into 8-bit RGBA
+    fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).with_context(|| format!("Failed to decode {}", path.display()))?;
+        buffer.truncate(info.buffer_size());
+
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            png::ColorType::Indexed => anyhow::bail!("Palette was not expanded in {}", path.display()),
+        };
+
+        Ok(Self { width: info.width, height: info.height, rgba })
+    }
+
+    /// A copy whose longer side is `size`, each pixel averaging the source pixels it covers
+    fn scaled_to_fit(&self, size: u32) -> Image {
+        let scale = size as f32 / self.width.max(self.height) as f32;
+        let width = ((self.width as f32 * scale).round() as u32).max(1);
+        let height = ((self.height as f32 * scale).round() as u32).max(1);
+
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            let y0 = (y as f32 / scale) as u32;
+            let y1 = (((y + 1) as f32 / scale) as u32).clamp(y0 + 1, self.height);
+            for x in 0..width {
+                let x0 = (x as f32 / scale) as u32;
+                let x1 = (((x + 1) as f32 / scale) as u32).clamp(x0 + 1, self.width);
+                let mut sum = [0u32; 4];
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        let i = ((sy * self.width + sx) * 4) as usize;
+                        for (total, value) in sum.iter_mut().zip(&self.rgba[i..i + 4]) {
+                            *total += *value as u32;
+                        }
+                    }
+                }
+                let count = (y1 - y0) * (x1 - x0);
+                rgba.extend(sum.iter().map(|total| (total / count) as u8));
+            }
+        }
+
+        Image { width, height, rgba }
+    }
+}
+
+/// The card being drawn, as 8-bit RGB
+struct Canvas {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self { width, height, rgb: vec![0; (width * height * 3) as usize] }
+    }
+
+    /// Blends `colour` into the pixel at (x, y) with the given opacity; off-canvas pixels are ignored
+    fn blend(&mut self, x: i32, y: i32, colour: Rgb, alpha: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let i = ((y as u32 * self.width + x as u32) * 3) as usize;
+        let under = Rgb(self.rgb[i], self.rgb[i + 1], self.rgb[i + 2]);
+        let Rgb(r, g, b) = under.mix(colour, alpha.clamp(0.0, 1.0));
+        self.rgb[i..i + 3].copy_from_slice(&[r, g, b]);
+    }
+
+    /// Fills the canvas from `from` in the top left corner to `to` in the bottom right
+    fn diagonal_gradient(&mut self, from: Rgb, to: Rgb) {
+        let span = (self.width + self.height) as f32;
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                self.blend(x, y, from.mix(to, (x + y) as f32 / span), 1.0);
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, left: i32, top: i32, width: i32, height: i32, colour: Rgb) {
+        for y in top..top + height {
+            for x in left..left + width {
+                self.blend(x, y, colour, 1.0);
+            }
+        }
+    }
+
+    /// Draws `image` with its top left corner at (left, top), cut to the
+    /// largest circle that fits, with a soft one-pixel edge
+    fn draw_circle_clipped(&mut self, image: &Image, left: i32, top: i32) {
+        let radius = image.width.min(image.height) as f32 / 2.0;
+        let (cx, cy) = (image.width as f32 / 2.0, image.height as f32 / 2.0);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let distance = ((x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2)).sqrt();
+                let mask = (radius - distance + 0.5).clamp(0.0, 1.0);
+                if mask == 0.0 {
+                    continue;
+                }
+                let i = ((y * image.width + x) * 4) as usize;
+                let pixel = &image.rgba[i..i + 4];
+                let alpha = mask * pixel[3] as f32 / 255.0;
+                self.blend(left + x as i32, top + y as i32, Rgb(pixel[0], pixel[1], pixel[2]), alpha);
+            }
+        }
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().context("Failed to start PNG")?;
+        writer.write_image_data(&self.rgb).context("Failed to encode PNG")?;
+        writer.finish().context("Failed to finish PNG")?;
+        Ok(png)
+    }
+}
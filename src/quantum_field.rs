@@ -1,6 +1,7 @@
-use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 
+use crate::corpus::Provenance;
+
 /// Represents a wisdom node in the 6-fold field
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WisdomNode {
@@ -25,6 +26,10 @@ pub struct QuantumField {
     
     /// The collapsed prompt generated from the selected node
     pub collapsed_prompt: Option<String>,
+    
+    /// Whether the seeds came from the model or the offline corpus
+    #[serde(default)]
+    pub provenance: Provenance,
 }
 
 /// Domain definitions for the 6-fold field
@@ -39,7 +44,7 @@ pub const DOMAINS: [&str; 6] = [
 
 impl QuantumField {
     /// Create a new quantum field with 6 wisdom nodes
-    pub fn new(seeds: Vec<String>) -> Self {
+    pub fn new(seeds: Vec<String>, provenance: Provenance) -> Self {
         let mut wisdom_field = Vec::new();
         
         // Create the 6 wisdom nodes
@@ -55,6 +60,7 @@ impl QuantumField {
             wisdom_field,
             selected_index: None,
             collapsed_prompt: None,
+            provenance,
         }
     }
     
@@ -0,0 +1,85 @@
+//! # Rate Limiting
+//!
+//! A sliding-window limit on how often something may happen per key: how many
+//! mentions a fediverse actor gets answered, how many push subscriptions one
+//! address may create. Counts are kept in memory, so a restart forgets them,
+//! which is fine for limits measured in minutes or hours.
+//!
+//! Limits per visitor are keyed by `ClientAddress`: the connection's peer,
+//! or, when that is a reverse proxy on this machine (see `nginx/`), the
+//! `X-Real-IP` it sets.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+
+/// Past this many keys, keys without recent hits are forgotten
+const MAX_KEYS: usize = 10_000;
+
+/// At most `limit` hits per key within any `window`
+#[derive(Clone)]
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    hits: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self { limit, window, hits: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Records a hit for `key` and returns true, or returns false without
+    /// recording anything if `key` already had `limit` hits in the window
+    pub fn allow(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+        if hits.len() >= MAX_KEYS {
+            hits.retain(|_, times| times.back().is_some_and(|last| now.duration_since(*last) < self.window));
+        }
+
+        let times = hits.entry(key.to_string()).or_default();
+        while times.front().is_some_and(|first| now.duration_since(*first) >= self.window) {
+            times.pop_front();
+        }
+        if times.len() >= self.limit {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+/// The address a request came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientAddress(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientAddress
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(address)| address.ip());
+        // Only a proxy on this machine is trusted to name the real client
+        let forwarded = parts
+            .headers
+            .get("x-real-ip")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok())
+            .filter(|_| peer.is_some_and(|peer| peer.is_loopback()));
+
+        Ok(match forwarded.or(peer) {
+            Some(address) => Self(address.to_string()),
+            None => Self("unknown".to_string()),
+        })
+    }
+}
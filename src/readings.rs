@@ -0,0 +1,244 @@
+//! # Quantum Field Readings
+//!
+//! Every time a visitor collapses the quantum field, the result is kept as a
+//! reading with its own id, so it can be revisited and shared at
+//! `/quantum-field/reading/{id}`. A reading remembers the field's date, the
+//! chosen domain and seed, the collapsed prompt and its art, and the summary of
+//! the visitor's exploration if they saved one.
+//!
+//! The visitor who collapsed the field owns the reading (by their `cat_session`
+//! id) and decides who may see it:
+//! - `public`: anyone with the link, and fine for search engines
+//! - `unlisted`: anyone with the link, but kept out of search engines
+//! - `private`: only the owner; everyone else gets a 404
+//!
+//! Collapsing the same node of the same day's field again gives the visitor
+//! back the reading they already have, and the store keeps at most
+//! `MAX_READINGS` (the oldest readings nobody wrote an exploration for go
+//! first), so crawlers and scripts hitting the collapse URL can't grow it
+//! without bound.
+//!
+//! Readings are kept in `<data_dir>/readings.json` (see `json_file.rs`), like
+//! the wisdom history.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::json_file::JsonFile;
+
+/// Art shown with every reading until the field renders its own
+pub const DEFAULT_ART: &str = "/static/images/quantum-cat.png";
+
+/// Longest exploration summary a reading keeps, in characters
+pub const MAX_EXPLORATION_CHARS: usize = 2000;
+
+/// How many readings are kept by default before the oldest are dropped
+pub const MAX_READINGS: usize = 10_000;
+
+/// Who may see a reading
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Anyone with the link; may be indexed
+    #[default]
+    Public,
+    /// Anyone with the link; not indexed
+    Unlisted,
+    /// Only the owner
+    Private,
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Visibility::Public => write!(f, "public"),
+            Visibility::Unlisted => write!(f, "unlisted"),
+            Visibility::Private => write!(f, "private"),
+        }
+    }
+}
+
+/// One collapse of the quantum field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reading {
+    /// Random id used in the reading's URL
+    pub id: String,
+    /// Session id of the visitor who collapsed the field
+    pub owner: String,
+    /// The date of the field that was collapsed
+    pub date: NaiveDate,
+    /// Zero-based position of the chosen node
+    pub index: usize,
+    pub domain: String,
+    pub seed: String,
+    pub collapsed_prompt: String,
+    /// Path of the image shown with the prompt
+    pub art: String,
+    /// What the visitor took away from exploring the reading, if they saved it
+    #[serde(default)]
+    pub exploration: Option<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Reading {
+    /// A new public reading with a fresh id and no exploration yet
+    pub fn new(
+        owner: &str,
+        date: NaiveDate,
+        index: usize,
+        domain: String,
+        seed: String,
+        collapsed_prompt: String,
+    ) -> Self {
+        let mut bytes = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        Self {
+            id: hex::encode(bytes),
+            owner: owner.to_string(),
+            date,
+            index,
+            domain,
+            seed,
+            collapsed_prompt,
+            art: DEFAULT_ART.to_string(),
+            exploration: None,
+            visibility: Visibility::Public,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Whether the visitor with session `session_id` may see this reading
+    pub fn visible_to(&self, session_id: &str) -> bool {
+        self.visibility != Visibility::Private || self.owner == session_id
+    }
+}
+
+/// All readings by id, backed by a JSON file
+#[derive(Debug, Clone)]
+pub struct ReadingStore {
+    readings: JsonFile<HashMap<String, Reading>>,
+    /// Most readings kept; see `record`
+    limit: usize,
+}
+
+impl ReadingStore {
+    /// Opens the store at `path`, starting empty if the file doesn't exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self { readings: JsonFile::open(path)?, limit: MAX_READINGS })
+    }
+
+    /// Keeps at most `limit` readings instead of `MAX_READINGS`
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// The reading with this id, if any
+    pub async fn get(&self, id: &str) -> Option<Reading> {
+        self.readings.read(|readings| readings.get(id).cloned()).await
+    }
+
+    /// The reading `fresh.owner` already has for the same node of the same
+    /// day's field, or else `fresh`, stored as a new reading
+    ///
+    /// Storing a new reading past the limit drops the oldest readings without
+    /// an exploration (and then the oldest of the rest); their ids are
+    /// returned with the reading so their cached cards can go too. As with
+    /// the wisdom history, a failed save is logged rather than returned: the
+    /// visitor still sees their collapse.
+    pub async fn record(&self, fresh: Reading) -> (Reading, Vec<String>) {
+        let limit = self.limit;
+        self.readings
+            .update(|readings| {
+                let existing = readings
+                    .values()
+                    .find(|r| r.owner == fresh.owner && r.date == fresh.date && r.index == fresh.index);
+                if let Some(existing) = existing {
+                    return (existing.clone(), Vec::new());
+                }
+
+                let mut dropped = Vec::new();
+                if readings.len() >= limit {
+                    let mut oldest: Vec<_> = readings
+                        .values()
+                        .map(|r| (r.exploration.is_some(), r.created_at, r.id.clone()))
+                        .collect();
+                    oldest.sort();
+                    for (_, _, id) in oldest.into_iter().take(readings.len() + 1 - limit) {
+                        readings.remove(&id);
+                        dropped.push(id);
+                    }
+                }
+                readings.insert(fresh.id.clone(), fresh.clone());
+                (fresh, dropped)
+            })
+            .await
+    }
+
+    /// Applies `change` to the reading `id` if `owner` owns it, and saves the file
+    ///
+    /// Returns the updated reading, or `None` if there is no such reading or
+    /// it belongs to someone else.
+    pub async fn update_owned(&self, id: &str, owner: &str, change: impl FnOnce(&mut Reading)) -> Option<Reading> {
+        self.readings
+            .update(|readings| {
+                let reading = readings.get_mut(id).filter(|reading| reading.owner == owner)?;
+                change(reading);
+                Some(reading.clone())
+            })
+            .await
+    }
+
+    /// Deletes every reading `owner` made; returns how many there were
+    pub async fn remove_owned(&self, owner: &str) -> usize {
+        self.readings
+            .update(|readings| {
+                let before = readings.len();
+                readings.retain(|_, reading| reading.owner != owner);
+                before - readings.len()
+            })
+            .await
+    }
+
+    /// Hands every reading `from` owns to `to`; returns how many there were
+    pub async fn transfer(&self, from: &str, to: &str) -> usize {
+        self.readings
+            .update(|readings| {
+                let mut count = 0;
+                for reading in readings.values_mut().filter(|reading| reading.owner == from) {
+                    reading.owner = to.to_string();
+                    count += 1;
+                }
+                count
+            })
+            .await
+    }
+
+    /// Every reading, for backups
+    pub async fn all(&self) -> HashMap<String, Reading> {
+        self.readings.read(HashMap::clone).await
+    }
+
+    /// Adds `imported` readings, replacing those with the same ids; with
+    /// `replace`, everything not in `imported` is dropped first
+    pub async fn import(&self, imported: HashMap<String, Reading>, replace: bool) {
+        self.readings
+            .update(|readings| {
+                if replace {
+                    readings.clear();
+                }
+                readings.extend(imported);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,372 @@
+//! # Account Routes
+//!
+//! Optional accounts (see `accounts.rs`):
+//! - `GET /account` is the page to sign up, log in and manage an account
+//! - `GET /api/v1/account` is the signed-in account; `DELETE` deletes it,
+//!   along with its conversation and readings
+//! - `POST /api/v1/account/signup` creates an account with a password
+//! - `POST /api/v1/account/login` and `POST /api/v1/account/logout`
+//! - `POST /api/v1/account/passkeys/register-options`, then
+//!   `POST /api/v1/account/passkeys/register`, create a passkey for a new
+//!   account, or for the signed-in one
+//! - `POST /api/v1/account/passkeys/login-options`, then
+//!   `POST /api/v1/account/passkeys/login`, log in with one
+//!
+//! Signing up with `merge_session` keeps the visitor's anonymous conversation
+//! and readings, moved to the account's fresh session. Logging in and out
+//! moves the account's session to a fresh id, so a `cat_session` copied from
+//! a browser stops reaching it. Bodies must be JSON, which browsers only send cross-site
+//! after a CORS preflight, and the API never allows credentials cross-site;
+//! the cookies are SameSite=Lax besides.
+
+use askama::Template;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::accounts::{Account, AccountError, CurrentAccount, MIN_PASSWORD_CHARS};
+use crate::sessions::{new_session_id, WebSession};
+use crate::state::AppState;
+use crate::templates::{AccountTemplate, AccountView, SiteContext};
+
+/// The signed-in account
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AccountResponse {
+    pub username: String,
+    pub created_at: String,
+    /// Whether the account can log in with a password
+    pub has_password: bool,
+    pub passkeys: Vec<PasskeyResponse>,
+}
+
+/// A passkey registered to the account
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PasskeyResponse {
+    /// Credential id, base64url
+    pub id: String,
+    pub created_at: String,
+}
+
+impl From<&Account> for AccountResponse {
+    fn from(account: &Account) -> Self {
+        Self {
+            username: account.username.clone(),
+            created_at: account.created_at.to_rfc3339(),
+            has_password: account.password_hash.is_some(),
+            passkeys: account
+                .passkeys
+                .iter()
+                .map(|passkey| PasskeyResponse { id: passkey.id.clone(), created_at: passkey.created_at.to_rfc3339() })
+                .collect(),
+        }
+    }
+}
+
+/// A new account with a password
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SignupRequest {
+    /// 3 to 32 letters, digits, dashes, underscores or dots; case doesn't matter
+    pub username: String,
+    /// At least 8 characters
+    pub password: String,
+    /// Keep the conversation and readings of the visitor's anonymous session
+    #[serde(default)]
+    pub merge_session: bool,
+}
+
+/// A name and password to log in with
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// Who a new passkey is for
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct PasskeyOptionsRequest {
+    /// The new account's name; ignored when signed in, as the passkey is then added to that account
+    pub username: Option<String>,
+}
+
+/// A new passkey, as the browser created it
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PasskeyRegistration {
+    /// `PublicKeyCredential.toJSON()` of the result of `navigator.credentials.create`
+    #[schema(value_type = Object)]
+    pub credential: Value,
+    /// For a new account: keep the visitor's anonymous conversation and readings
+    #[serde(default)]
+    pub merge_session: bool,
+}
+
+/// A passkey login, as the browser signed it
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PasskeyLogin {
+    /// `PublicKeyCredential.toJSON()` of the result of `navigator.credentials.get`
+    #[schema(value_type = Object)]
+    pub credential: Value,
+}
+
+impl IntoResponse for AccountError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            AccountError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AccountError::Taken => StatusCode::CONFLICT,
+            AccountError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AccountError::Internal(err) => {
+                error!("Account request failed: {:#}", err);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "The cat dropped your account. Please try again.").into_response();
+            }
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// Handler for GET /account
+pub async fn page(State(state): State<AppState>, CurrentAccount(account): CurrentAccount) -> Html<String> {
+    let template = AccountTemplate {
+        site: SiteContext::new(&state.config),
+        account: account.map(|account| AccountView {
+            has_password: account.password_hash.is_some(),
+            passkeys: account.passkeys.len(),
+            created: account.created_at.format("%-d %B %Y").to_string(),
+            username: account.username,
+        }),
+        min_password_chars: MIN_PASSWORD_CHARS,
+    };
+    Html(template.render().unwrap_or_else(|_| "<h1>The Enlightened Cat</h1><p>Account loading...</p>".to_string()))
+}
+
+/// Handler for GET /api/v1/account
+#[utoipa::path(
+    get,
+    path = "/api/v1/account",
+    tag = "account",
+    responses(
+        (status = 200, description = "The signed-in account", body = AccountResponse),
+        (status = 401, description = "Not signed in"),
+    )
+)]
+pub async fn me(CurrentAccount(account): CurrentAccount) -> Response {
+    match account {
+        Some(account) => Json(AccountResponse::from(&account)).into_response(),
+        None => AccountError::Unauthorized.into_response(),
+    }
+}
+
+/// Handler for DELETE /api/v1/account
+///
+/// Deletes the account, its conversation with the cat and its readings, and signs out.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/account",
+    tag = "account",
+    responses(
+        (status = 204, description = "Deleted, and signed out"),
+        (status = 401, description = "Not signed in"),
+    )
+)]
+pub async fn delete(State(state): State<AppState>, CurrentAccount(account): CurrentAccount) -> Response {
+    let Some(account) = account else {
+        return AccountError::Unauthorized.into_response();
+    };
+    state.accounts.delete(&account.id).await;
+    state.sessions.remove(&account.session_id).await;
+    let readings = state.readings.remove_owned(&account.session_id).await;
+    info!("An account was deleted, with {} readings", readings);
+
+    (StatusCode::NO_CONTENT, state.accounts.sign_out_cookies()).into_response()
+}
+
+/// Handler for POST /api/v1/account/signup
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/signup",
+    tag = "account",
+    request_body = SignupRequest,
+    responses(
+        (status = 201, description = "Created, and signed in", body = AccountResponse),
+        (status = 409, description = "The name is taken, or the visitor is already signed in"),
+        (status = 422, description = "The name or password isn't acceptable"),
+    )
+)]
+pub async fn signup(
+    State(state): State<AppState>,
+    CurrentAccount(current): CurrentAccount,
+    session: WebSession,
+    Json(request): Json<SignupRequest>,
+) -> Response {
+    if current.is_some() {
+        return (StatusCode::CONFLICT, "Log out before signing up again").into_response();
+    }
+    match state.accounts.sign_up(&request.username, &request.password, new_session_id()).await {
+        Ok(account) => {
+            info!("An account signed up with a password");
+            keep_anonymous_session(&state, &session, request.merge_session, &account).await;
+            signed_in(&state, &account, StatusCode::CREATED).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Handler for POST /api/v1/account/login
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/login",
+    tag = "account",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Signed in", body = AccountResponse),
+        (status = 401, description = "Wrong name or password"),
+    )
+)]
+pub async fn login(State(state): State<AppState>, Json(request): Json<LoginRequest>) -> Response {
+    match state.accounts.log_in(&request.username, &request.password).await {
+        Ok(account) => {
+            let account = renew_session(&state, account).await;
+            signed_in(&state, &account, StatusCode::OK).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Handler for POST /api/v1/account/logout
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/logout",
+    tag = "account",
+    responses((status = 204, description = "Signed out; the next request starts a new anonymous session"))
+)]
+pub async fn logout(State(state): State<AppState>, CurrentAccount(account): CurrentAccount) -> Response {
+    if let Some(account) = account {
+        renew_session(&state, account).await;
+    }
+    (StatusCode::NO_CONTENT, state.accounts.sign_out_cookies()).into_response()
+}
+
+/// Handler for POST /api/v1/account/passkeys/register-options
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/passkeys/register-options",
+    tag = "account",
+    request_body = PasskeyOptionsRequest,
+    responses(
+        (status = 200, description = "`PublicKeyCredentialCreationOptionsJSON` for `navigator.credentials.create`"),
+        (status = 409, description = "The name is taken"),
+        (status = 422, description = "The name isn't acceptable"),
+    )
+)]
+pub async fn passkey_register_options(
+    State(state): State<AppState>,
+    CurrentAccount(account): CurrentAccount,
+    Json(request): Json<PasskeyOptionsRequest>,
+) -> Response {
+    match state.accounts.registration_options(request.username.as_deref(), account.as_ref()).await {
+        Ok(options) => Json(options).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Handler for POST /api/v1/account/passkeys/register
+///
+/// For a new account this signs in; for the signed-in account it adds the passkey.
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/passkeys/register",
+    tag = "account",
+    request_body = PasskeyRegistration,
+    responses(
+        (status = 201, description = "Registered; a new account is signed in", body = AccountResponse),
+        (status = 409, description = "The name was taken meanwhile"),
+        (status = 422, description = "The passkey doesn't check out against its challenge"),
+    )
+)]
+pub async fn passkey_register(
+    State(state): State<AppState>,
+    CurrentAccount(current): CurrentAccount,
+    session: WebSession,
+    Json(request): Json<PasskeyRegistration>,
+) -> Response {
+    match state.accounts.finish_registration(&request.credential, current.as_ref(), new_session_id()).await {
+        Ok(account) if current.is_some() => {
+            info!("An account added a passkey");
+            (StatusCode::CREATED, Json(AccountResponse::from(&account))).into_response()
+        }
+        Ok(account) => {
+            info!("An account signed up with a passkey");
+            keep_anonymous_session(&state, &session, request.merge_session, &account).await;
+            signed_in(&state, &account, StatusCode::CREATED).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Handler for POST /api/v1/account/passkeys/login-options
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/passkeys/login-options",
+    tag = "account",
+    responses((status = 200, description = "`PublicKeyCredentialRequestOptionsJSON` for `navigator.credentials.get`"))
+)]
+pub async fn passkey_login_options(State(state): State<AppState>) -> Json<Value> {
+    Json(state.accounts.login_options())
+}
+
+/// Handler for POST /api/v1/account/passkeys/login
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/passkeys/login",
+    tag = "account",
+    request_body = PasskeyLogin,
+    responses(
+        (status = 200, description = "Signed in", body = AccountResponse),
+        (status = 401, description = "Unknown passkey, or the signature doesn't check out"),
+    )
+)]
+pub async fn passkey_login(State(state): State<AppState>, Json(request): Json<PasskeyLogin>) -> Response {
+    match state.accounts.finish_login(&request.credential).await {
+        Ok(account) => {
+            let account = renew_session(&state, account).await;
+            signed_in(&state, &account, StatusCode::OK).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Moves the visitor's anonymous conversation and readings into the new
+/// account's session, if they asked to keep them
+///
+/// They move rather than the account adopting the anonymous id, which
+/// whoever planted the visitor's cookie could know.
+async fn keep_anonymous_session(state: &AppState, session: &WebSession, merge: bool, account: &Account) {
+    if merge && !session.is_new {
+        state.sessions.transfer(&session.id, &account.session_id).await;
+        state.readings.transfer(&session.id, &account.session_id).await;
+    }
+}
+
+/// Moves the account's conversation and readings to a fresh session id
+async fn renew_session(state: &AppState, account: Account) -> Account {
+    let Some((old, account)) = state.accounts.renew_session(&account.id).await else {
+        return account;
+    };
+    state.sessions.transfer(&old, &account.session_id).await;
+    state.readings.transfer(&old, &account.session_id).await;
+    account
+}
+
+/// The account, with the cookies that sign it in
+///
+/// The account's session is held on every sign-in, so it outlives the idle
+/// sweep however long the account is away.
+async fn signed_in(state: &AppState, account: &Account, status: StatusCode) -> Response {
+    state.sessions.hold(&account.session_id).await;
+    (status, state.accounts.sign_in_cookies(account), Json(AccountResponse::from(account))).into_response()
+}
@@ -0,0 +1,306 @@
+//! # ActivityPub Routes
+//!
+//! The cat's presence on the fediverse (see `activitypub.rs`):
+//! - `GET /.well-known/webfinger?resource=acct:cat@host` finds the actor
+//! - `GET /ap/actor` is the actor, with its public key
+//! - `GET /ap/outbox` lists the recent daily notes, `GET /ap/notes/{date}` is one;
+//!   a day's note only appears once it is published to followers
+//! - `GET /ap/followers` counts the followers (without listing them)
+//! - `POST /ap/inbox` takes `Follow` (accepted at once), `Undo` of a follow,
+//!   and notes mentioning the cat, which the cat answers
+//!
+//! Inbox requests must be HTTP-signed by the actor named in the activity, with
+//! a digest of the body; anything else gets a 401. Activities are acknowledged
+//! with 202 and answered in the background. Each remote actor has their own
+//! cat session, and at most `MAX_MENTIONS_PER_HOUR` answers of questions up
+//! to `MAX_MENTION_CHARS` long. Without `ACTIVITYPUB_USERNAME` every route
+//! answers 404.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::activitypub::{
+    digest, escape, last_published, plain_text, Federation, RemoteActor, SignatureHeader, ACTIVITYSTREAMS, ACTIVITY_JSON, MAX_CLOCK_SKEW_SECS,
+    MAX_MENTION_CHARS, PUBLIC,
+};
+use crate::routes::chat::converse;
+use crate::state::AppState;
+use crate::timezone::VisitorZone;
+
+/// How many notes the outbox shows
+const OUTBOX_SIZE: usize = 20;
+
+/// Headers an inbox request's signature must cover
+const REQUIRED_SIGNED_HEADERS: [&str; 3] = ["(request-target)", "date", "digest"];
+
+#[derive(Debug, Deserialize)]
+pub struct WebfingerQuery {
+    resource: String,
+}
+
+/// Handler for GET /.well-known/webfinger
+pub async fn webfinger(State(state): State<AppState>, Query(query): Query<WebfingerQuery>) -> Response {
+    let Some(federation) = &state.federation else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let resource = query.resource.trim();
+    if !resource.eq_ignore_ascii_case(&federation.resource()) && resource != federation.actor_id() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let document = json!({
+        "subject": federation.resource(),
+        "aliases": [federation.actor_id(), state.config.public_url],
+        "links": [
+            { "rel": "self", "type": ACTIVITY_JSON, "href": federation.actor_id() },
+            { "rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": state.config.public_url },
+        ],
+    });
+    ([(header::CONTENT_TYPE, "application/jrd+json")], document.to_string()).into_response()
+}
+
+/// Handler for GET /ap/actor
+pub async fn actor(State(state): State<AppState>) -> Response {
+    match &state.federation {
+        Some(federation) => activity_json(federation.actor()),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Handler for GET /ap/outbox
+pub async fn outbox(State(state): State<AppState>) -> Response {
+    let Some(federation) = &state.federation else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // Days the site hasn't reached (settled by visitors further east) and
+    // today before its post time are held back, as in the feeds
+    let until = last_published(&state.config, Utc::now());
+    let items: Vec<Value> = state
+        .wisdom_history
+        .recent_until(until, OUTBOX_SIZE)
+        .await
+        .iter()
+        .map(|(date, wisdom)| federation.create(&federation.note(*date, wisdom)))
+        .collect();
+
+    activity_json(json!({
+        "@context": ACTIVITYSTREAMS,
+        "id": format!("{}/ap/outbox", state.config.public_url),
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items,
+    }))
+}
+
+/// Handler for GET /ap/followers
+pub async fn followers(State(state): State<AppState>) -> Response {
+    let Some(federation) = &state.federation else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    activity_json(json!({
+        "@context": ACTIVITYSTREAMS,
+        "id": federation.followers_id(),
+        "type": "OrderedCollection",
+        "totalItems": federation.followers().await.len(),
+    }))
+}
+
+/// Handler for GET /ap/notes/{date}
+pub async fn note(State(state): State<AppState>, Path(date): Path<NaiveDate>) -> Response {
+    let Some(federation) = &state.federation else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if date > last_published(&state.config, Utc::now()) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    match state.wisdom_history.get(date).await {
+        Some(wisdom) => {
+            let mut note = federation.note(date, &wisdom);
+            note["@context"] = json!(ACTIVITYSTREAMS);
+            activity_json(note)
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Handler for POST /ap/inbox
+pub async fn inbox(
+    State(state): State<AppState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let Some(federation) = state.federation.clone() else {
+        return StatusCode::NOT_FOUND;
+    };
+    let Some(sender) = verify(&federation, &method, &uri, &headers, &body, Utc::now()).await else {
+        warn!("Rejected an inbox request with a missing or invalid signature");
+        return StatusCode::UNAUTHORIZED;
+    };
+    let Ok(activity) = serde_json::from_slice::<Value>(&body) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    // A valid signature only vouches for its own actor
+    if activity["actor"].as_str() != Some(sender.id.as_str()) {
+        warn!("Rejected an activity from {} signed by {}", activity["actor"], sender.id);
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let actor_id = federation.actor_id();
+    let object = &activity["object"];
+    match activity["type"].as_str().unwrap_or_default() {
+        "Follow" if object.as_str() == Some(actor_id.as_str()) => {
+            info!("{} follows the cat", sender.id);
+            federation.add_follower(&sender).await;
+            let accept = json!({
+                "@context": ACTIVITYSTREAMS,
+                "id": federation.new_id("accepts"),
+                "type": "Accept",
+                "actor": actor_id,
+                "object": activity,
+            });
+            tokio::spawn(async move {
+                if let Err(err) = federation.deliver(&sender.inbox, &accept).await {
+                    warn!("Failed to accept the follow of {}: {:#}", sender.id, err);
+                }
+            });
+        }
+        // Some servers undo by the follow's id alone
+        "Undo" if object["type"] == "Follow" || object.is_string() => {
+            if federation.remove_follower(&sender.id).await {
+                info!("{} unfollowed the cat", sender.id);
+            }
+        }
+        "Create" if mentions(object, &actor_id) && object["attributedTo"] == sender.id.as_str() => {
+            if federation.allow_mention(&sender.id) {
+                let note = object.clone();
+                tokio::spawn(async move { answer(&state, &federation, &sender, &note).await });
+            } else {
+                warn!("Not answering {}, who mentioned the cat too often", sender.id);
+            }
+        }
+        kind => info!("Ignoring {} activity from {}", kind, sender.id),
+    }
+    StatusCode::ACCEPTED
+}
+
+/// The actor who signed the request, if the signature is valid, recent and
+/// covers the body
+async fn verify(
+    federation: &Federation,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Option<RemoteActor> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let signature = SignatureHeader::parse(header("signature")?)?;
+    if !REQUIRED_SIGNED_HEADERS.iter().all(|name| signature.headers.iter().any(|signed| signed == name)) {
+        return None;
+    }
+    let sent_at = DateTime::parse_from_rfc2822(header("date")?).ok()?;
+    if (now - sent_at.with_timezone(&Utc)).num_seconds().abs() > MAX_CLOCK_SKEW_SECS {
+        return None;
+    }
+    if header("digest")? != digest(body) {
+        return None;
+    }
+    let path = uri.path_and_query().map_or(uri.path(), |path| path.as_str());
+    let signing_string = signature.signing_string(method.as_str(), path, headers)?;
+
+    // Keys are only fetched from the server the activity claims to come from
+    let claimed: Value = serde_json::from_slice(body).ok()?;
+    let actor_url = reqwest::Url::parse(claimed["actor"].as_str()?).ok()?;
+    let key_url = reqwest::Url::parse(&signature.key_id).ok()?;
+    if key_url.host_str() != actor_url.host_str() || key_url.port_or_known_default() != actor_url.port_or_known_default() {
+        warn!("Rejected the key {} for the actor {}", signature.key_id, actor_url);
+        return None;
+    }
+
+    // Only now is it worth fetching the key
+    let sender = match federation.fetch_actor(&signature.key_id).await {
+        Ok(sender) => sender,
+        Err(err) => {
+            warn!("Failed to fetch the key {}: {:#}", signature.key_id, err);
+            return None;
+        }
+    };
+    let key = &sender.public_key;
+    let owned = key.id == signature.key_id && key.owner == sender.id;
+    (owned && signature.verify(&key.public_key_pem, &signing_string)).then_some(sender)
+}
+
+/// Whether a note tags or addresses the cat
+fn mentions(note: &Value, actor_id: &str) -> bool {
+    let tagged = note["tag"]
+        .as_array()
+        .is_some_and(|tags| tags.iter().any(|tag| tag["type"] == "Mention" && tag["href"] == actor_id));
+    tagged || addressed(note, actor_id)
+}
+
+/// Whether `to` or `cc` of a note include `audience`
+fn addressed(note: &Value, audience: &str) -> bool {
+    ["to", "cc"].iter().any(|field| match &note[field] {
+        Value::Array(items) => items.iter().any(|item| item == audience),
+        item => item == audience,
+    })
+}
+
+/// Answers a note mentioning the cat, as a reply to its author; public
+/// notes get a public reply
+async fn answer(state: &AppState, federation: &Federation, author: &RemoteActor, note: &Value) {
+    let text = plain_text(note["content"].as_str().unwrap_or_default());
+    // Mentions lead the text: "@cat@example.com how do I..."
+    let question = text.split_whitespace().skip_while(|word| word.starts_with('@')).collect::<Vec<_>>().join(" ");
+    if question.is_empty() {
+        return;
+    }
+    if question.chars().count() > MAX_MENTION_CHARS {
+        info!("Not answering a {} character mention from {}", question.chars().count(), author.id);
+        return;
+    }
+
+    // `contentMap` names the note's language, for safety replies
+    let locales: Vec<String> = note["contentMap"].as_object().map(|map| map.keys().cloned().collect()).unwrap_or_default();
+    let today = VisitorZone(state.config.site_timezone).today();
+    let reply = converse(state, &format!("activitypub:{}", author.id), &question, &locales, today).await;
+
+    let handle = author.handle();
+    let profile = author.url.clone().unwrap_or_else(|| author.id.clone());
+    let cc = if addressed(note, PUBLIC) { vec![PUBLIC] } else { Vec::new() };
+    let reply_note = json!({
+        "id": federation.new_id("replies"),
+        "type": "Note",
+        "attributedTo": federation.actor_id(),
+        "inReplyTo": note["id"],
+        "published": Utc::now(),
+        "to": [author.id],
+        "cc": cc,
+        "content": format!(
+            "<p><span class=\"h-card\"><a href=\"{}\" class=\"u-url mention\">{}</a></span> {}</p>",
+            escape(&profile),
+            escape(&handle),
+            escape(&reply.message)
+        ),
+        "tag": [{ "type": "Mention", "href": author.id, "name": handle }],
+    });
+
+    if let Err(err) = federation.deliver(&author.inbox, &federation.create(&reply_note)).await {
+        warn!("Failed to deliver a reply to {}: {:#}", author.id, err);
+    }
+}
+
+/// An ActivityPub document response
+fn activity_json(document: Value) -> Response {
+    ([(header::CONTENT_TYPE, ACTIVITY_JSON)], document.to_string()).into_response()
+}
@@ -0,0 +1,310 @@
+//! # Admin Console
+//!
+//! This module serves `/admin`, a small moderation console for whoever runs the
+//! cat. From there an admin can:
+//! - See the wisdom and quantum field saved for any date, and where they came from
+//! - Generate or regenerate either of them, or replace them with hand-written text
+//! - See how many chat sessions are active (redacted: no message contents)
+//! - See recent corpus fallbacks, safety flags and token usage per day
+//! - Read the audit log of everything done through the console
+//!
+//! The console is protected by HTTP Basic auth against `ADMIN_USERNAME` and
+//! `ADMIN_PASSWORD`. Without a password it does not exist at all (404).
+//! Looking at a date never generates anything: that only happens through the
+//! POST actions, so browsing the console doesn't add days to the sitemap,
+//! feeds or outbox.
+//! Every form carries a per-process CSRF token, so another site cannot make
+//! a logged-in admin's browser submit changes.
+
+// Import necessary dependencies:
+// - axum: Web framework for handling HTTP requests
+// - askama: Templating engine for rendering the console
+// - base64/sha2: For decoding and comparing Basic auth credentials
+use askama::Template;
+use async_trait::async_trait;
+use axum::{
+    extract::{Form, FromRequestParts, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use base64::Engine;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+// Import our application state and the pieces the console shows
+use crate::audit::AuditEntry;
+use crate::quantum_field::DOMAINS;
+use crate::state::AppState;
+use crate::templates::AdminTemplate;
+
+/// How many sessions, fallbacks, usage days and audit entries the console lists
+const LIST_LIMIT: usize = 20;
+
+/// An authenticated admin
+///
+/// Extracting this from a request checks the `Authorization` header:
+/// - 404 if no admin password is configured, so the console is invisible
+/// - 401 with a `WWW-Authenticate` challenge if the credentials are missing or wrong
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub username: String,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(password) = state.config.admin_password.as_deref() else {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        };
+
+        let credentials = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+
+        if let Some((user, pass)) = credentials.as_deref().and_then(|c| c.split_once(':')) {
+            // Check both halves, so timing doesn't reveal which one was wrong
+            let user_ok = constant_time_eq(user, &state.config.admin_username);
+            let pass_ok = constant_time_eq(pass, password);
+            if user_ok & pass_ok {
+                return Ok(Self { username: user.to_string() });
+            }
+        }
+
+        Err((
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"The Enlightened Cat admin\", charset=\"UTF-8\"")],
+            "Authentication required",
+        )
+            .into_response())
+    }
+}
+
+/// Compares two secrets without short-circuiting on the first differing byte
+///
+/// Hashing first gives both sides the same length, so the length of the
+/// expected value doesn't leak either.
+fn constant_time_eq(given: &str, expected: &str) -> bool {
+    let given = Sha256::digest(given.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    given.iter().zip(expected.iter()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Query parameters for the console page
+#[derive(Debug, Deserialize)]
+pub struct ConsoleParams {
+    /// The date to show, defaulting to today in the site's time zone
+    pub date: Option<NaiveDate>,
+}
+
+/// Form body for the regenerate buttons
+#[derive(Debug, Deserialize)]
+pub struct RegenerateForm {
+    pub csrf_token: String,
+    pub date: NaiveDate,
+}
+
+/// Form body for replacing the wisdom by hand
+#[derive(Debug, Deserialize)]
+pub struct WisdomForm {
+    pub csrf_token: String,
+    pub date: NaiveDate,
+    pub text: String,
+}
+
+/// Form body for replacing the quantum field by hand, one seed per line
+#[derive(Debug, Deserialize)]
+pub struct FieldForm {
+    pub csrf_token: String,
+    pub date: NaiveDate,
+    pub seeds: String,
+}
+
+/// A chat session as the console shows it: activity only, never what was said
+pub struct SessionSummary {
+    pub id_prefix: String,
+    pub created_at: String,
+    pub last_active: String,
+    pub user_messages: usize,
+    pub user_characters: usize,
+}
+
+/// Handler function for the console page (GET /admin)
+///
+/// This function:
+/// 1. Checks the admin's credentials (via the `AdminUser` extractor)
+/// 2. Loads the wisdom and quantum field saved for the requested date, if any
+/// 3. Summarizes sessions, metrics and the audit log
+/// 4. Renders the admin template
+pub async fn console(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Query(params): Query<ConsoleParams>,
+) -> Response {
+    info!("Rendering admin console for {}", admin.username);
+
+    let date = params.date.unwrap_or_else(|| chrono::Utc::now().with_timezone(&state.config.site_timezone).date_naive());
+    let wisdom = state.wisdom_history.get(date).await;
+    let field = state.quantum_fields.read(|fields| fields.get(&date).cloned()).await;
+
+    // Only counts and lengths leave the session store; the console never shows message text
+    let sessions = state
+        .sessions
+        .recent(LIST_LIMIT)
+        .await
+        .into_iter()
+        .map(|session| SessionSummary {
+            id_prefix: session.id.chars().take(8).collect(),
+            created_at: session.created_at.format("%Y-%m-%d %H:%M").to_string(),
+            last_active: session.last_active.format("%Y-%m-%d %H:%M").to_string(),
+            user_messages: session.user_message_count(),
+            user_characters: session.user_character_count(),
+        })
+        .collect();
+
+    let template = AdminTemplate {
+        username: admin.username,
+        csrf_token: state.admin_csrf_token.to_string(),
+        date: date.to_string(),
+        wisdom,
+        seeds: field
+            .iter()
+            .flat_map(|field| field.wisdom_field.iter().map(|node| node.seed.clone()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        field,
+        session_count: state.sessions.len().await,
+        sessions,
+        fallbacks: state.metrics.recent_fallbacks(LIST_LIMIT),
+        safety_flags: state.metrics.recent_safety_flags(LIST_LIMIT),
+        usage: state.metrics.usage_by_day(LIST_LIMIT),
+        audit: state.audit.recent(LIST_LIMIT),
+    };
+
+    match template.render() {
+        Ok(html) => Html(html).into_response(),
+        Err(err) => {
+            error!("Failed to render admin console: {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Handler function for POST /admin/wisdom/regenerate
+pub async fn regenerate_wisdom(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Form(form): Form<RegenerateForm>,
+) -> Response {
+    if let Some(rejection) = reject_forged(&state, &form.csrf_token) {
+        return rejection;
+    }
+
+    let wisdom = state.regenerate_daily_wisdom(form.date).await;
+    audit(&state, &admin, "wisdom.regenerate", form.date, format!("{}: {}", wisdom.provenance, wisdom.text)).await;
+
+    back_to_console(form.date)
+}
+
+/// Handler function for POST /admin/wisdom
+pub async fn edit_wisdom(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Form(form): Form<WisdomForm>,
+) -> Response {
+    if let Some(rejection) = reject_forged(&state, &form.csrf_token) {
+        return rejection;
+    }
+
+    let text = form.text.trim();
+    if text.is_empty() {
+        return (StatusCode::BAD_REQUEST, "The wisdom cannot be empty").into_response();
+    }
+
+    let wisdom = state.set_daily_wisdom(form.date, text.to_string()).await;
+    audit(&state, &admin, "wisdom.edit", form.date, &wisdom.text).await;
+
+    back_to_console(form.date)
+}
+
+/// Handler function for POST /admin/field/regenerate
+pub async fn regenerate_field(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Form(form): Form<RegenerateForm>,
+) -> Response {
+    if let Some(rejection) = reject_forged(&state, &form.csrf_token) {
+        return rejection;
+    }
+
+    let field = state.regenerate_quantum_field(form.date).await;
+    audit(&state, &admin, "field.regenerate", form.date, field.provenance).await;
+
+    back_to_console(form.date)
+}
+
+/// Handler function for POST /admin/field
+///
+/// The seeds arrive as one textarea, one seed per line; blank lines are ignored
+/// and exactly one seed per domain is required.
+pub async fn edit_field(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Form(form): Form<FieldForm>,
+) -> Response {
+    if let Some(rejection) = reject_forged(&state, &form.csrf_token) {
+        return rejection;
+    }
+
+    let seeds: Vec<String> = form
+        .seeds
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    if seeds.len() != DOMAINS.len() {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Expected {} seeds, one per line, got {}", DOMAINS.len(), seeds.len()),
+        )
+            .into_response();
+    }
+
+    let detail = seeds.join(" | ");
+    state.set_quantum_field(form.date, seeds).await;
+    audit(&state, &admin, "field.edit", form.date, detail).await;
+
+    back_to_console(form.date)
+}
+
+/// The response for a form submission that doesn't carry this process's CSRF token, if it doesn't
+fn reject_forged(state: &AppState, token: &str) -> Option<Response> {
+    if constant_time_eq(token, &state.admin_csrf_token) {
+        None
+    } else {
+        Some((StatusCode::FORBIDDEN, "Invalid CSRF token").into_response())
+    }
+}
+
+/// Appends an entry to the audit log
+///
+/// The change itself has already happened, so a failure to write the log is
+/// reported loudly but doesn't undo it.
+async fn audit(state: &AppState, admin: &AdminUser, action: &str, date: NaiveDate, detail: impl ToString) {
+    if let Err(err) = state.audit.record(AuditEntry::new(&admin.username, action, date, detail)).await {
+        error!("Failed to write audit log entry for {}: {:?}", action, err);
+    }
+}
+
+/// Sends the admin back to the console for the date they were working on
+fn back_to_console(date: NaiveDate) -> Response {
+    Redirect::to(&format!("/admin?date={}", date)).into_response()
+}
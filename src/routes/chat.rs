@@ -3,31 +3,53 @@
 //! This module handles the API endpoint for chat interactions with the Enlightened Cat.
 //! It processes user messages, sends them to the Mistral AI API via our client,
 //! and returns the AI-generated responses.
+//!
+//! Every message passes through the safety layer (see `safety.rs`) on the way
+//! in, and every reply on the way out. Flagged messages never reach the model
+//! and are not stored in the visitor's session.
+//!
+//! Once a conversation is under way, a small side-call asks the model for
+//! topics worth exploring. Each carries an id; sending it back as
+//! `current_topic` makes it the session's topic, which steers the following turns.
 
 // Import necessary dependencies:
 // - axum: Web framework (similar to Express in Node.js)
 // - serde: Serialization/deserialization library (for JSON handling)
 // - tracing: Logging framework
-use axum::{
-    extract::{Json, State},  // Extractors to get JSON data and app state from requests
-    http::StatusCode,        // HTTP status codes (200 OK, 500 Error, etc.)
-};
+use axum::extract::{Json, State};  // Extractors to get JSON data and app state from requests
+use axum::http::HeaderMap;         // For setting the session cookie on the response
+use chrono::NaiveDate;             // The visitor's date, for picking a fallback reply
 use serde::{Deserialize, Serialize};  // Traits for JSON conversion
-use tracing::{error, info};           // Logging utilities
+use utoipa::ToSchema;                 // OpenAPI schema generation
+use tracing::{error, info, warn};     // Logging utilities
 
 // Import our application state
+use crate::corpus::Provenance;
+use crate::mistral::Topic;
+use crate::safety::{self, SafetyCategory, SafetyStage};
+use crate::sessions::WebSession;
 use crate::state::AppState;
+use crate::timezone::VisitorZone;
+
+/// Topics are suggested once the visitor has sent at least this many messages
+const SUGGEST_TOPICS_AFTER: usize = 2;
 
 /// Structure representing an incoming chat request from the user
 /// 
 /// `#[derive(Debug, Deserialize)]` automatically implements:
 /// - Debug: For printing the struct during debugging
 /// - Deserialize: For converting JSON to this struct
-#[derive(Debug, Deserialize)]
+/// 
+/// The API contract is snake_case. The camelCase spellings older front-ends
+/// send are accepted as aliases so they are no longer silently dropped.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub struct ChatRequest {
     pub message: String,           // The user's message to the Enlightened Cat
-    pub conversation_depth: Option<u32>,  // How many exchanges have occurred
-    pub current_topic: Option<String>,    // The current conversation topic if any
+    #[serde(alias = "conversationDepth")]
+    pub conversation_depth: Option<u32>,  // How many exchanges the client has seen (informational; the server counts its own)
+    #[serde(alias = "currentTopic")]
+    pub current_topic: Option<String>,    // Id of a suggested topic to explore from now on
 }
 
 /// Structure representing the response sent back to the user
@@ -35,108 +57,190 @@ pub struct ChatRequest {
 /// `#[derive(Debug, Serialize)]` automatically implements:
 /// - Debug: For printing the struct during debugging
 /// - Serialize: For converting this struct to JSON
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub struct ChatResponse {
     pub message: String,                      // The Enlightened Cat's response
-    pub suggested_topics: Option<Vec<String>>, // Optional suggested topics for exploration
+    pub suggested_topics: Option<Vec<Topic>>, // Topics the conversation could explore next, if any were suggested
+    pub current_topic: Option<Topic>,         // The topic steering this session, if the visitor chose one
+    pub provenance: Provenance,               // Whether the reply was generated, came from the offline corpus, or from the safety layer
+    pub safety: Option<SafetyCategory>,       // Why the safety layer answered instead of the model, if it did
 }
 
-/// Handler function for POST /api/chat endpoint
+/// Handler function for POST /api/v1/chat endpoint
 /// 
 /// This function:
-/// 1. Extracts the application state and JSON request body
-/// 2. Sends the user's message to the Mistral AI API
-/// 3. Returns the AI response as JSON
-/// 4. Handles any errors that might occur
+/// 1. Extracts the application state, the visitor's session and the JSON request body
+/// 2. Screens the message; crisis, abusive or off-topic messages get a fixed
+///    reply in the visitor's language (from `Accept-Language`) instead
+/// 3. Switches the session to the requested topic, if `current_topic` names one it was offered
+/// 4. Sends the user's message to the Mistral AI API along with the session's history and topic
+/// 5. Screens the reply, then stores the exchange in the session
+/// 6. Asks the model for follow-up topics and returns everything as JSON
+/// 7. Falls back to today's corpus Whispurr if the API call fails
+/// 
+/// Each browser gets its own conversation, keyed by the `cat_session` cookie
+/// (see `sessions.rs`); the cookie is set on the first reply.
 /// 
 /// The `async` keyword allows this function to perform I/O operations
 /// without blocking the server thread.
+#[utoipa::path(
+    post,
+    path = "/api/v1/chat",
+    tag = "chat",
+    request_body = ChatRequest,
+    params(
+        ("X-Timezone" = Option<String>, Header, description = "IANA time zone used to pick a fallback reply"),
+        ("Accept-Language" = Option<String>, Header, description = "Language for safety replies such as crisis resources"),
+    ),
+    responses(
+        (status = 200, description = "The cat's reply", body = ChatResponse),
+        (status = 422, description = "The body is not a valid chat request"),
+    )
+)]
 pub async fn handle_chat(
     // Extract the AppState from the request
     State(state): State<AppState>,
+    // Resolve the visitor's time zone, used to pick a fallback for their date
+    zone: VisitorZone,
+    // Find (or start) this visitor's chat session
+    session: WebSession,
+    // Used to pick the language of safety replies
+    headers: HeaderMap,
     // Extract and parse the JSON request body into a ChatRequest struct
     Json(request): Json<ChatRequest>,
-) -> Result<Json<ChatResponse>, (StatusCode, String)> {
-    // Log the incoming message
-    info!("Received chat request: {}", request.message);
-    
-    // Check conversation depth to determine response type
-    let conversation_depth = request.conversation_depth.unwrap_or(0);
-    let current_topic = request.current_topic.clone();
+) -> (HeaderMap, Json<ChatResponse>) {
+    // Log the incoming message's size only; its text may be sensitive
+    info!("Received chat request ({} chars)", request.message.chars().count());
     
     // Log conversation context
-    info!("Conversation depth: {}, Current topic: {:?}", conversation_depth, current_topic);
+    info!("Conversation depth: {:?}, requested topic: {:?}", request.conversation_depth, request.current_topic);
+    
+    // Switch topics if asked to; unknown ids leave the session's topic alone
+    if let Some(topic_id) = request.current_topic.as_deref().filter(|id| !id.is_empty()) {
+        if state.sessions.choose_topic(&session.id, topic_id).await.is_none() {
+            info!("Ignoring unknown topic id {:?}", topic_id);
+        }
+    }
+    
+    let locales = safety::preferred_locales(&headers);
+    let response = converse(&state, &session.id, &request.message, &locales, zone.today()).await;
+    
+    (session.set_cookie(), Json(response))
+}
+
+/// One turn of the conversation in session `session_id`: screens the message,
+/// asks the model with the session's history and topic, screens the reply and
+/// stores the exchange
+///
+/// Safety replies come in the first of `locales` that has them. If the model
+/// fails, today's corpus Whispurr (for `today`) answers instead. Shared by the
+/// JSON API and the chat integrations (Slack...), which key sessions by their
+/// own user ids.
+pub(crate) async fn converse(
+    state: &AppState,
+    session_id: &str,
+    message: &str,
+    locales: &[String],
+    today: NaiveDate,
+) -> ChatResponse {
+    // Screen the message before it goes anywhere near the model
+    if let Some(category) = state.safety.screen_input(message) {
+        return safety_reply(state, SafetyStage::Input, category, message, locales);
+    }
+    
+    let current_topic = state.sessions.current_topic(session_id).await;
     
-    // Send the message to the Mistral client and handle the result
-    match state.mistral_client.get_enlightened_cat_response(&request.message).await {
-        // If successful, process the response based on conversation context
-        Ok(response) => {
+    // Send the message, with everything said so far, to the Mistral client and handle the result
+    let history = state.sessions.history(session_id).await;
+    let reply = state
+        .mistral_client
+        .get_enlightened_cat_response(&history, message, current_topic.as_ref())
+        .await;
+    
+    // Screen the reply too: a model can be led somewhere it shouldn't go
+    let flagged = reply.as_ref().ok().and_then(|response| state.safety.screen_output(response));
+    
+    match (reply, flagged) {
+        // If the reply trips the safety layer, replace it and keep it out of the history
+        (Ok(response), Some(category)) => {
+            safety_reply(state, SafetyStage::Output, category, &response, locales)
+        }
+        // If successful, store the exchange and see where the conversation could go next
+        (Ok(response), None) => {
             info!("Generated response from Enlightened Cat");
+            state.sessions.record_exchange(session_id, message, &response).await;
             
-            // Generate suggested topics if we're at the right conversation depth
-            // and the user seems interested in deeper conversation
-            let suggested_topics = if conversation_depth >= 2 && 
-                (request.message.to_lowercase().contains("yes") || 
-                 request.message.to_lowercase().contains("more") || 
-                 request.message.to_lowercase().contains("tell me") ||
-                 request.message.to_lowercase().contains("deeper")) {
-                
-                // Generate topics based on the conversation so far
-                // In a real implementation, you might use the AI to suggest these
-                Some(generate_topic_suggestions(&request.message))
-            } else {
-                None
-            };
+            let suggested_topics = suggest_topics(state, session_id).await;
             
-            Ok(Json(ChatResponse { 
+            ChatResponse { 
                 message: response,
-                suggested_topics
-            }))
+                suggested_topics,
+                current_topic,
+                provenance: Provenance::Generated,
+                safety: None,
+            }
         }
-        // If there's an error, log it and return a 500 error response
-        Err(err) => {
+        // If there's an error, log it and answer with today's corpus Whispurr instead
+        (Err(err), _) => {
             error!("Error generating response: {:?}", err);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to generate response: {}", err),
-            ))
+            state.metrics.record_fallback("chat", today, &err);
+            ChatResponse {
+                message: state.corpus.whispurr_for(today),
+                suggested_topics: None,
+                current_topic,
+                provenance: Provenance::Corpus,
+                safety: None,
+            }
         }
     }
 }
 
-/// Generate topic suggestions based on the user's message
+/// The fixed reply for a flagged message, noting the flag without its text
+fn safety_reply(
+    state: &AppState,
+    stage: SafetyStage,
+    category: SafetyCategory,
+    flagged: &str,
+    locales: &[String],
+) -> ChatResponse {
+    let length = flagged.chars().count();
+    warn!("Safety layer flagged chat {} as {} ({} chars)", stage, category, length);
+    state.metrics.record_safety_flag(stage, category, length);
+
+    ChatResponse {
+        message: state.safety.message(category, locales),
+        suggested_topics: None,
+        current_topic: None,
+        provenance: Provenance::Safety,
+        safety: Some(category),
+    }
+}
+
+/// Asks the model for follow-up topics once the conversation is under way
 /// 
-/// In a production app, you might use AI to generate these dynamically
-/// based on the conversation history.
-fn generate_topic_suggestions(message: &str) -> Vec<String> {
-    // Simple keyword-based topic suggestions
-    // In a real app, you'd use more sophisticated NLP or AI for this
-    let message_lower = message.to_lowercase();
+/// Suggestions are a nicety: if the side-call fails, or a suggestion trips the
+/// safety layer, the visitor simply gets fewer (or no) topics.
+async fn suggest_topics(state: &AppState, session_id: &str) -> Option<Vec<Topic>> {
+    if state.sessions.user_message_count(session_id).await < SUGGEST_TOPICS_AFTER {
+        return None;
+    }
+    
+    let history = state.sessions.history(session_id).await;
+    let topics: Vec<Topic> = match state.mistral_client.suggest_topics(&history).await {
+        Ok(topics) => topics
+            .into_iter()
+            .filter(|topic| state.safety.screen_output(&format!("{} {}", topic.title, topic.focus)).is_none())
+            .collect(),
+        Err(err) => {
+            warn!("Topic suggestion failed: {:?}", err);
+            return None;
+        }
+    };
     
-    if message_lower.contains("work") || message_lower.contains("job") || message_lower.contains("career") {
-        return vec![
-            "Work-life balance".to_string(),
-            "Finding meaning in your career".to_string(),
-            "Mindfulness at work".to_string(),
-        ];
-    } else if message_lower.contains("stress") || message_lower.contains("anxiety") || message_lower.contains("overwhelm") {
-        return vec![
-            "Stress reduction techniques".to_string(),
-            "Mindful breathing".to_string(),
-            "Creating peaceful spaces".to_string(),
-        ];
-    } else if message_lower.contains("meditat") || message_lower.contains("mindful") {
-        return vec![
-            "Daily meditation practices".to_string(),
-            "Mindfulness in everyday moments".to_string(),
-            "The science of meditation".to_string(),
-        ];
-    } else {
-        // Default topics if no keywords match
-        return vec![
-            "Finding balance".to_string(),
-            "Mindfulness practices".to_string(),
-            "Creating peaceful moments".to_string(),
-        ];
+    if topics.is_empty() {
+        return None;
     }
+    state.sessions.offer_topics(session_id, &topics).await;
+    Some(topics)
 }
@@ -0,0 +1,331 @@
+//! # Discord Integration
+//!
+//! `POST /integrations/discord/interactions` is the Interactions Endpoint URL
+//! of a Discord application. It answers these slash commands with embeds:
+//! - `/wisdom`: today's Whispurr
+//! - `/field`: today's six Quantum Field seeds, with a button per node
+//! - `/collapse node:N`: collapses node N (1 to 6) and saves the reading; the
+//!   field's buttons do the same
+//! - `/ask question:...`: asks the cat. Discord waits three seconds at most,
+//!   so the reply is deferred and edited in once the model has answered.
+//!
+//! The definitions to register with Discord are printed by
+//! `the-enlightened-cat discord-commands` (see `command_definitions`).
+//!
+//! Discord signs every request with the application's Ed25519 key:
+//! `X-Signature-Ed25519` is the hex signature of `X-Signature-Timestamp`
+//! followed by the body, checked against `DISCORD_PUBLIC_KEY`. Requests that
+//! fail the check get a 401, as Discord requires. Without a key the endpoint
+//! does not exist (404).
+//!
+//! Each Discord user gets their own cat session, keyed by their user id, in
+//! the same store as the site's visitors, and the field and wisdom come from
+//! the same per-date caches. "Today" is the site's date.
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use openssl::{
+    pkey::{Id, PKey},
+    sign::Verifier,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::quantum_field::QuantumField;
+use crate::readings::Reading;
+use crate::routes::chat::converse;
+use crate::routes::quantum_field::{collapse_for, reading_path};
+use crate::state::AppState;
+use crate::timezone::VisitorZone;
+use crate::webhooks::{self, PayloadFormat, DISCORD_COLOUR};
+
+/// Header carrying the hex Ed25519 signature
+const SIGNATURE_HEADER: &str = "x-signature-ed25519";
+/// Header carrying the timestamp that was signed along with the body
+const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+/// How far a request's timestamp may be from ours, to stop replays
+const MAX_CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+// Interaction types
+const PING: u8 = 1;
+const APPLICATION_COMMAND: u8 = 2;
+const MESSAGE_COMPONENT: u8 = 3;
+
+// Interaction callback types
+const PONG: u8 = 1;
+const CHANNEL_MESSAGE: u8 = 4;
+const DEFERRED_CHANNEL_MESSAGE: u8 = 5;
+
+/// Message flag: only the user who asked sees it
+const EPHEMERAL: u64 = 1 << 6;
+
+/// Prefix of the field buttons' `custom_id`: `collapse:{date}:{index}`
+const COLLAPSE_ACTION: &str = "collapse";
+
+/// Embed titles can't be longer than this
+const MAX_TITLE_CHARS: usize = 256;
+
+/// The parts of an interaction the cat uses
+#[derive(Debug, Deserialize)]
+struct Interaction {
+    #[serde(rename = "type")]
+    kind: u8,
+    application_id: String,
+    token: String,
+    #[serde(default)]
+    data: Option<InteractionData>,
+    /// Set for interactions in a server
+    #[serde(default)]
+    member: Option<Member>,
+    /// Set for interactions in direct messages
+    #[serde(default)]
+    user: Option<User>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct InteractionData {
+    /// The command's name, for slash commands
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    options: Vec<CommandOption>,
+    /// The button's id, for component clicks
+    #[serde(default)]
+    custom_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandOption {
+    name: String,
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Member {
+    user: User,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    id: String,
+}
+
+impl Interaction {
+    /// The cat session of whoever interacted
+    fn session_id(&self) -> String {
+        let user = self.member.as_ref().map(|member| &member.user).or(self.user.as_ref());
+        format!("discord:{}", user.map_or("unknown", |user| user.id.as_str()))
+    }
+
+    fn option(&self, name: &str) -> Option<&Value> {
+        self.data.as_ref()?.options.iter().find(|option| option.name == name).map(|option| &option.value)
+    }
+}
+
+/// Handler for POST /integrations/discord/interactions
+pub async fn interactions(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let Some(public_key) = state.config.discord_public_key.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !verify(public_key, &headers, &body, Utc::now().timestamp()) {
+        warn!("Rejected a Discord interaction with a missing or invalid signature");
+        return (StatusCode::UNAUTHORIZED, "Invalid request signature").into_response();
+    }
+    let Ok(interaction) = serde_json::from_slice::<Interaction>(&body) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let today = VisitorZone(state.config.site_timezone).today();
+    let data = interaction.data.as_ref();
+    let reply = match (interaction.kind, data.map(|data| data.name.as_str())) {
+        // Discord checks the endpoint with a ping before accepting it
+        (PING, _) => json!({ "type": PONG }),
+
+        (APPLICATION_COMMAND, Some("wisdom")) => {
+            let wisdom = state.get_daily_wisdom(today).await;
+            let payload = webhooks::payload(PayloadFormat::Discord, today, &wisdom, &state.config.public_url);
+            message(json!({ "embeds": payload["embeds"] }))
+        }
+        (APPLICATION_COMMAND, Some("field")) => message(field_message(&state.get_quantum_field(today).await, today)),
+        (APPLICATION_COMMAND, Some("collapse")) => {
+            match interaction.option("node").and_then(Value::as_u64).filter(|node| (1..=6).contains(node)) {
+                Some(node) => collapse(&state, &interaction, today, node as usize - 1).await,
+                None => ephemeral("Choose a node from 1 to 6."),
+            }
+        }
+        (APPLICATION_COMMAND, Some("ask")) => {
+            let question = interaction.option("question").and_then(Value::as_str).unwrap_or_default().trim().to_string();
+            if question.is_empty() {
+                ephemeral("Ask me something, and I'll think it over.")
+            } else {
+                defer_answer(state, interaction, question, today)
+            }
+        }
+        (MESSAGE_COMPONENT, _) => {
+            match data.and_then(|data| parse_collapse(&data.custom_id)) {
+                Some((date, index)) => collapse(&state, &interaction, date, index).await,
+                None => ephemeral("That button has come loose."),
+            }
+        }
+        (kind, name) => {
+            info!("Ignoring Discord interaction of type {} ({:?})", kind, name);
+            ephemeral("I don't know that one. Try /wisdom, /field, /collapse or /ask.")
+        }
+    };
+
+    Json(reply).into_response()
+}
+
+/// Acknowledges `/ask` at once and edits the cat's answer in when it's ready
+fn defer_answer(state: AppState, interaction: Interaction, question: String, today: NaiveDate) -> Value {
+    tokio::spawn(async move {
+        let reply = converse(&state, &interaction.session_id(), &question, &[], today).await;
+        let title: String = question.chars().take(MAX_TITLE_CHARS).collect();
+        let edit = json!({
+            "embeds": [{ "title": title, "description": reply.message, "color": DISCORD_COLOUR }],
+        });
+
+        let url = format!(
+            "{}/webhooks/{}/{}/messages/@original",
+            state.config.discord_api_url, interaction.application_id, interaction.token
+        );
+        let result = state.http.patch(&url).json(&edit).send().await.and_then(|r| r.error_for_status());
+        if let Err(err) = result {
+            warn!("Failed to edit a deferred Discord reply: {}", err);
+        }
+    });
+
+    // The answer is between the asker and the cat
+    json!({ "type": DEFERRED_CHANNEL_MESSAGE, "data": { "flags": EPHEMERAL } })
+}
+
+/// Collapses the field for `date` at `index` on behalf of whoever interacted
+async fn collapse(state: &AppState, interaction: &Interaction, date: NaiveDate, index: usize) -> Value {
+    let (field, reading) = collapse_for(state, date, index, &interaction.session_id()).await;
+    match reading {
+        Some(reading) => message(json!({ "embeds": [reading_embed(state, &reading)] })),
+        None => ephemeral(&field.collapsed_prompt.unwrap_or_default()),
+    }
+}
+
+/// A saved reading, linking to its page
+fn reading_embed(state: &AppState, reading: &Reading) -> Value {
+    json!({
+        "title": format!("{}. {} · {}", reading.index + 1, reading.domain, reading.date.format("%B %-d, %Y")),
+        "description": reading.collapsed_prompt,
+        "url": format!("{}{}", state.config.public_url, reading_path(&reading.id)),
+        "color": DISCORD_COLOUR,
+        "fields": [{ "name": "Seed", "value": reading.seed }],
+    })
+}
+
+/// Today's field as an embed with a button per node
+fn field_message(field: &QuantumField, date: NaiveDate) -> Value {
+    let nodes: Vec<Value> = field
+        .wisdom_field
+        .iter()
+        .map(|node| json!({ "name": format!("{}. {}", node.index, node.domain), "value": node.seed }))
+        .collect();
+    let buttons: Vec<Value> = field
+        .wisdom_field
+        .iter()
+        .enumerate()
+        .map(|(index, node)| {
+            json!({
+                "type": 2,
+                "style": 1,
+                "label": format!("{}. {}", node.index, node.domain),
+                "custom_id": format!("{}:{}:{}", COLLAPSE_ACTION, date, index),
+            })
+        })
+        .collect();
+
+    json!({
+        "embeds": [{
+            "title": format!("The Quantum Field · {}", date.format("%B %-d, %Y")),
+            "description": "Six nodes in superposition. Choose one to collapse it.",
+            "color": DISCORD_COLOUR,
+            "fields": nodes,
+        }],
+        // At most five buttons fit in a row
+        "components": buttons.chunks(3).map(|row| json!({ "type": 1, "components": row })).collect::<Vec<_>>(),
+    })
+}
+
+/// The date and zero-based index in a collapse button's id
+fn parse_collapse(custom_id: &str) -> Option<(NaiveDate, usize)> {
+    let rest = custom_id.strip_prefix(COLLAPSE_ACTION)?.strip_prefix(':')?;
+    let (date, index) = rest.rsplit_once(':')?;
+    Some((date.parse().ok()?, index.parse().ok()?))
+}
+
+/// A message in the channel
+fn message(data: Value) -> Value {
+    json!({ "type": CHANNEL_MESSAGE, "data": data })
+}
+
+/// A message only the user who asked can see
+fn ephemeral(text: &str) -> Value {
+    json!({ "type": CHANNEL_MESSAGE, "data": { "content": text, "flags": EPHEMERAL } })
+}
+
+/// Whether the request was signed with the key whose hex is `public_key`,
+/// within the allowed clock skew of `now`
+pub fn verify(public_key: &str, headers: &HeaderMap, body: &[u8], now: i64) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let (Some(signature), Some(timestamp)) = (header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER)) else {
+        return false;
+    };
+    if timestamp.parse::<i64>().map_or(true, |sent_at| now.abs_diff(sent_at) > MAX_CLOCK_SKEW_SECS as u64) {
+        return false;
+    }
+    let (Ok(signature), Ok(public_key)) = (hex::decode(signature), hex::decode(public_key)) else {
+        return false;
+    };
+
+    let signed = [timestamp.as_bytes(), body].concat();
+    PKey::public_key_from_raw_bytes(&public_key, Id::ED25519)
+        .and_then(|key| Verifier::new_without_digest(&key)?.verify_oneshot(&signature, &signed))
+        .unwrap_or(false)
+}
+
+/// The slash commands to register with Discord
+/// (`PUT /applications/{application_id}/commands`)
+pub fn command_definitions() -> Value {
+    json!([
+        { "name": "wisdom", "type": 1, "description": "Today's Whispurr from The Enlightened Cat" },
+        { "name": "field", "type": 1, "description": "Draw today's six-fold Quantum Field" },
+        {
+            "name": "collapse",
+            "type": 1,
+            "description": "Collapse today's Quantum Field at one node",
+            "options": [{
+                "type": 4,
+                "name": "node",
+                "description": "The node to collapse, as numbered by /field",
+                "required": true,
+                "min_value": 1,
+                "max_value": 6,
+            }],
+        },
+        {
+            "name": "ask",
+            "type": 1,
+            "description": "Ask the cat what's on your mind",
+            "options": [{
+                "type": 3,
+                "name": "question",
+                "description": "Your question",
+                "required": true,
+                "max_length": 1000,
+            }],
+        },
+    ])
+}
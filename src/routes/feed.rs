@@ -0,0 +1,192 @@
+//! # Feed Route Handlers
+//!
+//! This module serves the Daily Whispurr to feed readers, in two formats:
+//! - `/feed.xml`: an Atom 1.0 feed
+//! - `/feed.json`: a JSON Feed 1.1 feed
+//!
+//! Both are built from the wisdom history (see `history.rs`), one entry per
+//! day, newest first. Entry ids are tag URIs derived from the date, so they
+//! stay the same even if an admin later edits that day's text, and each entry
+//! links to the permanent page for its date (`/wisdom/{date}`).
+//!
+//! Responses carry `ETag` and `Last-Modified` and answer conditional requests
+//! with 304 (see `caching.rs`), so aggregators can poll as often as they like.
+
+// Import necessary dependencies:
+// - axum: Web framework for handling HTTP requests
+// - chrono: For the dates and timestamps in the feeds
+// - serde_json: For building the JSON Feed document
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::Response,
+};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde_json::json;
+use tracing::info;
+
+// Import our application state and caching helpers
+use crate::caching::{conditional, Validators};
+use crate::state::{AppState, DailyWisdom};
+
+/// How many days the feeds list
+const FEED_LENGTH: usize = 30;
+
+/// Title shared by both feeds
+const FEED_TITLE: &str = "The Enlightened Cat: Daily Whispurr";
+
+/// Subtitle shared by both feeds
+const FEED_DESCRIPTION: &str = "A moment of feline wisdom for stressed professionals, every day.";
+
+/// Year used in tag URIs; must never change, or every entry id changes with it
+const TAG_YEAR: &str = "2025";
+
+/// Handler function for GET /feed.xml
+///
+/// This function:
+/// 1. Makes sure today's wisdom exists, then reads the recent history
+/// 2. Renders it as an Atom feed
+/// 3. Returns it, or 304 if the reader's copy is current
+pub async fn atom(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    info!("Serving Atom feed");
+
+    let entries = feed_entries(&state).await;
+    let base = &state.config.public_url;
+    let host = tag_authority(base);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <id>tag:{},{}:whispurrs</id>\n", host, TAG_YEAR));
+    xml.push_str(&format!("  <title>{}</title>\n", escape(FEED_TITLE)));
+    xml.push_str(&format!("  <subtitle>{}</subtitle>\n", escape(FEED_DESCRIPTION)));
+    xml.push_str(&format!("  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}/feed.xml\"/>\n", escape(base)));
+    xml.push_str(&format!("  <link rel=\"alternate\" type=\"text/html\" href=\"{}/wisdom\"/>\n", escape(base)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", last_modified(&entries).to_rfc3339()));
+    xml.push_str("  <author><name>The Enlightened Cat</name></author>\n");
+    xml.push_str(&format!("  <icon>{}/static/images/enlightened-cat.svg</icon>\n", escape(base)));
+
+    for (date, wisdom) in &entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", entry_id(&host, *date)));
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&entry_title(*date))));
+        xml.push_str(&format!("    <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n", escape(&permalink(base, *date))));
+        xml.push_str(&format!("    <published>{}</published>\n", published(*date).to_rfc3339()));
+        xml.push_str(&format!("    <updated>{}</updated>\n", wisdom.updated_at.to_rfc3339()));
+        xml.push_str(&format!("    <content type=\"text\">{}</content>\n", escape(&wisdom.text)));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+
+    let body = xml.into_bytes();
+    let validators = Validators::for_body(&body, last_modified(&entries));
+    conditional(&headers, &validators, "application/atom+xml; charset=utf-8", body)
+}
+
+/// Handler function for GET /feed.json
+///
+/// The same entries as the Atom feed, as a JSON Feed 1.1 document.
+pub async fn json_feed(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    info!("Serving JSON feed");
+
+    let entries = feed_entries(&state).await;
+    let base = &state.config.public_url;
+    let host = tag_authority(base);
+
+    let items: Vec<_> = entries
+        .iter()
+        .map(|(date, wisdom)| {
+            json!({
+                "id": entry_id(&host, *date),
+                "url": permalink(base, *date),
+                "title": entry_title(*date),
+                "content_text": wisdom.text,
+                "date_published": published(*date).to_rfc3339(),
+                "date_modified": wisdom.updated_at.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    let feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": FEED_TITLE,
+        "description": FEED_DESCRIPTION,
+        "home_page_url": format!("{}/wisdom", base),
+        "feed_url": format!("{}/feed.json", base),
+        "icon": format!("{}/static/images/enlightened-cat.svg", base),
+        "language": "en",
+        "authors": [{ "name": "The Enlightened Cat" }],
+        "items": items,
+    });
+
+    let body = serde_json::to_vec_pretty(&feed).unwrap_or_default();
+    let validators = Validators::for_body(&body, last_modified(&entries));
+    conditional(&headers, &validators, "application/feed+json; charset=utf-8", body)
+}
+
+/// The most recent days of wisdom up to today in the site's time zone, newest first
+///
+/// Today's entry is settled first, so a feed reader never has to wait for a
+/// page visit to see it. Days ahead of the site's calendar (settled by
+/// visitors further east) are held back until the site reaches them.
+async fn feed_entries(state: &AppState) -> Vec<(NaiveDate, DailyWisdom)> {
+    let today = Utc::now().with_timezone(&state.config.site_timezone).date_naive();
+    state.get_daily_wisdom(today).await;
+
+    let mut entries = state.wisdom_history.recent(FEED_LENGTH + 1).await;
+    entries.retain(|(date, _)| *date <= today);
+    entries.truncate(FEED_LENGTH);
+    entries
+}
+
+/// When the newest change to any listed entry happened
+fn last_modified(entries: &[(NaiveDate, DailyWisdom)]) -> DateTime<Utc> {
+    entries
+        .iter()
+        .map(|(_, wisdom)| wisdom.updated_at)
+        .max()
+        .unwrap_or(DateTime::<Utc>::UNIX_EPOCH)
+}
+
+/// The permanent page for a date's wisdom
+pub fn permalink(base: &str, date: NaiveDate) -> String {
+    format!("{}/wisdom/{}", base, date)
+}
+
+/// A stable id for a date's entry (RFC 4151 tag URI)
+fn entry_id(host: &str, date: NaiveDate) -> String {
+    format!("tag:{},{}:whispurr/{}", host, TAG_YEAR, date)
+}
+
+/// The host part of the public URL, used as the tag URI authority
+fn tag_authority(base: &str) -> String {
+    let without_scheme = base.split_once("://").map_or(base, |(_, rest)| rest);
+    let host = without_scheme.split(['/', ':']).next().unwrap_or(without_scheme);
+    host.to_string()
+}
+
+/// A human-readable entry title, e.g. "Daily Whispurr for June 1, 2025"
+fn entry_title(date: NaiveDate) -> String {
+    format!("Daily Whispurr for {}", date.format("%B %-d, %Y"))
+}
+
+/// Publication time of a date's entry: the start of that day, UTC
+fn published(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+/// Escapes text for use in XML content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
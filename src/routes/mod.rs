@@ -9,6 +9,19 @@
 //! - `pages`: Handles HTML page rendering for the website frontend
 //! - `wisdom`: Handles API endpoints for daily wisdom quotes
 //! - `quantum_field`: Handles API endpoints for the 6-Fold Wisdom Field
+//! - `openapi`: Serves the OpenAPI document describing the `/api/v1` endpoints
+//! - `admin`: The password-protected moderation console at `/admin`
+//! - `feed`: Atom and JSON feeds of the daily wisdom history
+//! - `og`: Open Graph preview images for wisdom and readings
+//! - `seo`: The sitemap and robots.txt for search engines
+//! - `slack`: Slash commands and buttons for a Slack app
+//! - `discord`: Slash commands and buttons for a Discord application
+//! - `telegram`: The webhook of a Telegram bot
+//! - `activitypub`: WebFinger, the cat's fediverse actor and its inbox
+//! - `newsletter`: Signing up for, confirming and leaving the newsletter
+//! - `push`: Web Push subscriptions for the morning Whispurr
+//! - `offline`: The web app manifest, the service worker and its offline bundle
+//! - `account`: Signing up, logging in with a password or passkey, and deleting an account
 //!
 //! Each of these is a separate module (Rust file) with its own functionality.
 //! The `pub` keyword makes these modules publicly accessible from outside this module.
@@ -17,3 +30,16 @@ pub mod chat;    // Makes the chat.rs module public and available
 pub mod pages;   // Makes the pages.rs module public and available
 pub mod wisdom;  // Makes the wisdom.rs module public and available
 pub mod quantum_field; // Makes the quantum_field.rs module public and available
+pub mod openapi; // Makes the openapi.rs module public and available
+pub mod admin;   // Makes the admin.rs module public and available
+pub mod feed;    // Makes the feed.rs module public and available
+pub mod og;      // Makes the og.rs module public and available
+pub mod seo;     // Makes the seo.rs module public and available
+pub mod slack;   // Makes the slack.rs module public and available
+pub mod discord; // Makes the discord.rs module public and available
+pub mod telegram; // Makes the telegram.rs module public and available
+pub mod activitypub; // Makes the activitypub.rs module public and available
+pub mod newsletter; // Makes the newsletter.rs module public and available
+pub mod push;    // Makes the push.rs module public and available
+pub mod offline; // Makes the offline.rs module public and available
+pub mod account; // Makes the account.rs module public and available
@@ -0,0 +1,158 @@
+//! # Newsletter Pages
+//!
+//! Signing up for the whispurrs by mail (see `newsletter.rs`):
+//! - `GET /newsletter` is the signup form, daily or weekly
+//! - `POST /newsletter/subscribe` takes the form and mails a confirmation link
+//! - `GET /newsletter/confirm/{token}` is that link
+//! - `GET /newsletter/unsubscribe/{token}` asks to confirm with a button, so
+//!   link scanners that follow every URL in a message don't unsubscribe anyone
+//! - `POST /newsletter/unsubscribe/{token}` unsubscribes; it is also the
+//!   RFC 8058 one-click target mail clients POST to
+//!
+//! The signup answers the same whether or not the address was already on the
+//! list, so the form can't be used to find out who subscribes. Without
+//! `SMTP_URL` every route answers 404.
+
+use askama::Template;
+use axum::{
+    extract::{Form, Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::mail::is_valid_address;
+use crate::newsletter::{self, Frequency};
+use crate::routes::pages::not_found_page;
+use crate::state::AppState;
+use crate::templates::{NewsletterTemplate, SiteContext};
+
+/// Form body of the signup
+#[derive(Debug, Deserialize)]
+pub struct SubscribeForm {
+    email: String,
+    #[serde(default)]
+    frequency: Frequency,
+}
+
+/// Handler for GET /newsletter
+pub async fn signup(State(state): State<AppState>) -> Response {
+    if !state.newsletter.enabled() {
+        return not_found_page(&state, "There is no newsletter here yet.").into_response();
+    }
+    render(page(&state, "Whispurrs by mail"), StatusCode::OK)
+}
+
+/// Handler for POST /newsletter/subscribe
+pub async fn subscribe(State(state): State<AppState>, Form(form): Form<SubscribeForm>) -> Response {
+    if !state.newsletter.enabled() {
+        return not_found_page(&state, "There is no newsletter here yet.").into_response();
+    }
+    let email = form.email.trim().to_lowercase();
+    if !is_valid_address(&email) {
+        let mut template = page(&state, "Whispurrs by mail");
+        template.error = Some("That doesn't look like an email address.".to_string());
+        template.email = form.email;
+        return render(template, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    if let Some(subscriber) = state.newsletter.subscribe(&email, form.frequency, Utc::now()).await {
+        let confirmation = newsletter::confirmation_email(&state.config.public_url, &subscriber);
+        if let Err(err) = state.newsletter.send(&confirmation).await {
+            warn!("Failed to send a newsletter confirmation: {:#}", err);
+            // Forgotten, so trying again sends the link straight away
+            state.newsletter.unsubscribe(&subscriber.token).await;
+            let mut template = page(&state, "Whispurrs by mail");
+            template.error = Some("The cat couldn't send the confirmation just now. Please try again later.".to_string());
+            template.email = form.email;
+            return render(template, StatusCode::SERVICE_UNAVAILABLE);
+        }
+        info!("Sent a newsletter confirmation");
+    }
+
+    let mut template = page(&state, "Check your inbox");
+    template.form = false;
+    template.message = Some(format!(
+        "If {} isn't subscribed yet, a link to confirm is on its way. The whispurrs start once you follow it.",
+        email
+    ));
+    render(template, StatusCode::OK)
+}
+
+/// Handler for GET /newsletter/confirm/{token}
+pub async fn confirm(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    if !state.newsletter.enabled() {
+        return not_found_page(&state, "There is no newsletter here yet.").into_response();
+    }
+    let Some(subscriber) = state.newsletter.confirm(&token, Utc::now()).await else {
+        return not_found_page(&state, "That link has expired, or was never sent.").into_response();
+    };
+
+    let mut template = page(&state, "You're subscribed");
+    template.form = false;
+    template.message = Some(format!(
+        "The cat will whispurr to {} {}. Every message has a link to stop.",
+        subscriber.email,
+        match subscriber.frequency {
+            Frequency::Daily => "every morning",
+            Frequency::Weekly => "every Monday",
+        }
+    ));
+    render(template, StatusCode::OK)
+}
+
+/// Handler for GET /newsletter/unsubscribe/{token}
+pub async fn unsubscribe_page(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    if !state.newsletter.enabled() {
+        return not_found_page(&state, "There is no newsletter here yet.").into_response();
+    }
+    let Some(subscriber) = state.newsletter.find(&token).await else {
+        return unsubscribed(&state);
+    };
+
+    let mut template = page(&state, "Unsubscribe");
+    template.form = false;
+    template.message = Some(format!("Stop sending whispurrs to {}?", subscriber.email));
+    template.unsubscribe_token = Some(subscriber.token);
+    render(template, StatusCode::OK)
+}
+
+/// Handler for POST /newsletter/unsubscribe/{token}
+pub async fn unsubscribe(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    if !state.newsletter.enabled() {
+        return not_found_page(&state, "There is no newsletter here yet.").into_response();
+    }
+    if state.newsletter.unsubscribe(&token).await {
+        info!("A newsletter subscriber unsubscribed");
+    }
+    unsubscribed(&state)
+}
+
+/// The page after unsubscribing; also for links used twice, so mail clients
+/// retrying the one-click POST see success
+fn unsubscribed(state: &AppState) -> Response {
+    let mut template = page(state, "Unsubscribed");
+    template.form = false;
+    template.message = Some("No more whispurrs by mail. The cat holds no grudges.".to_string());
+    render(template, StatusCode::OK)
+}
+
+/// The page with the signup form and nothing else
+fn page(state: &AppState, heading: &str) -> NewsletterTemplate {
+    NewsletterTemplate {
+        site: SiteContext::new(&state.config),
+        heading: heading.to_string(),
+        message: None,
+        error: None,
+        form: true,
+        email: String::new(),
+        unsubscribe_token: None,
+    }
+}
+
+fn render(template: NewsletterTemplate, status: StatusCode) -> Response {
+    let html = template.render().unwrap_or_else(|_| "<h1>The Enlightened Cat</h1><p>Newsletter loading...</p>".to_string());
+    (status, Html(html)).into_response()
+}
@@ -0,0 +1,169 @@
+//! # Offline Routes
+//!
+//! What the site needs to work as an installable app without a connection:
+//! - `GET /manifest.webmanifest` is the web app manifest
+//! - `GET /sw.js` is the service worker, which caches the shell and shows push
+//!   notifications (see `static/sw.js`)
+//! - `GET /api/offline-bundle` is everything the worker keeps for offline use:
+//!   the last seven days of wisdom, the current quantum field and the corpus
+//!   Whispurrs offline chat answers from
+//!
+//! The bundle is for the visitor's local date (`X-Timezone` or the `tz`
+//! cookie) and answers conditional requests with 304, so the worker can
+//! refresh it on every visit.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::caching::{conditional, Validators};
+use crate::corpus::Provenance;
+use crate::routes::feed::permalink;
+use crate::routes::quantum_field::{QuantumFieldResponse, WisdomNodeResponse};
+use crate::state::AppState;
+use crate::templates::{MAIN_SCRIPT, STYLESHEET};
+use crate::timezone::VisitorZone;
+
+/// The service worker, served from the root so it may control every page
+const SERVICE_WORKER: &str = include_str!("../../static/sw.js");
+
+/// How many days of wisdom the bundle carries, today included
+const BUNDLE_DAYS: i64 = 7;
+
+/// Pages and assets the worker caches on install
+///
+/// The stylesheet and script are the versioned URLs the templates link.
+const SHELL: &[&str] = &[
+    "/",
+    "/wisdom",
+    "/quantum-field",
+    "/about",
+    "/manifest.webmanifest",
+    STYLESHEET,
+    MAIN_SCRIPT,
+    "/static/js/push.js",
+    "/static/images/enlightened-cat.svg",
+    "/static/images/enlightened-cat-art.png",
+    "/static/images/quantum-cat.png",
+];
+
+/// Everything the service worker keeps for offline use
+#[derive(Debug, Serialize)]
+pub struct OfflineBundle {
+    /// The visitor's local date the bundle was made for
+    pub date: String,
+    /// The time zone used to decide that date
+    pub timezone: String,
+    /// Pages and assets to cache
+    pub shell: Vec<&'static str>,
+    /// Up to seven days of wisdom, newest first
+    pub wisdom: Vec<BundledWisdom>,
+    /// Today's quantum field, as `/api/v1/quantum-field` gives it
+    pub quantum_field: QuantumFieldResponse,
+    /// Whispurrs offline chat picks its replies from
+    pub corpus: Vec<String>,
+}
+
+/// One day's wisdom in the bundle
+#[derive(Debug, Serialize)]
+pub struct BundledWisdom {
+    pub date: String,
+    pub wisdom: String,
+    pub provenance: Provenance,
+    /// The day's page, which the worker caches too
+    pub url: String,
+}
+
+/// Handler for GET /manifest.webmanifest
+pub async fn manifest() -> impl IntoResponse {
+    let manifest: Value = json!({
+        "name": "The Enlightened Cat",
+        "short_name": "Enlightened Cat",
+        "description": "Finding peace in the professional jungle",
+        "start_url": "/",
+        "scope": "/",
+        "display": "standalone",
+        "background_color": "#333333",
+        "theme_color": "#222222",
+        "icons": [
+            { "src": "/static/images/enlightened-cat.svg", "sizes": "any", "type": "image/svg+xml" },
+            { "src": "/static/images/enlightened-cat-art.png", "sizes": "1024x1024", "type": "image/png" },
+        ],
+    });
+    ([(header::CONTENT_TYPE, "application/manifest+json")], manifest.to_string())
+}
+
+/// Handler for GET /sw.js
+pub async fn service_worker() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            // Browsers check for a new worker on navigation; don't make them wait for a cache
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        SERVICE_WORKER,
+    )
+}
+
+/// Handler for GET /api/offline-bundle
+///
+/// Makes sure today's wisdom and field exist, then gathers the days before
+/// from the wisdom history. Days the history has no entry for are left out
+/// rather than generated.
+pub async fn offline_bundle(State(state): State<AppState>, zone: VisitorZone, headers: HeaderMap) -> Response {
+    info!("Serving offline bundle");
+
+    let today = zone.today();
+    let (wisdom, last_modified) = recent_wisdom(&state, today).await;
+    let field = state.get_quantum_field(today).await;
+
+    let bundle = OfflineBundle {
+        date: today.to_string(),
+        timezone: zone.name().to_string(),
+        shell: SHELL.to_vec(),
+        wisdom,
+        quantum_field: QuantumFieldResponse {
+            wisdom_field: field
+                .get_wisdom_field()
+                .iter()
+                .map(|node| WisdomNodeResponse { index: node.index, domain: node.domain.clone(), seed: node.seed.clone() })
+                .collect(),
+            provenance: field.provenance,
+        },
+        corpus: state.corpus.whispurrs.clone(),
+    };
+
+    let body = serde_json::to_vec(&bundle).unwrap_or_default();
+    let mut response = conditional(&headers, &Validators::for_body(&body, last_modified), "application/json", body);
+    // Always revalidate; the ETag keeps that cheap
+    response.headers_mut().insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-cache"));
+    response
+}
+
+/// The wisdom for `today` and the six days before, newest first, with the
+/// time of the latest change among them
+async fn recent_wisdom(state: &AppState, today: NaiveDate) -> (Vec<BundledWisdom>, DateTime<Utc>) {
+    state.get_daily_wisdom(today).await;
+
+    let mut days = Vec::new();
+    let mut last_modified = DateTime::<Utc>::UNIX_EPOCH;
+    for offset in 0..BUNDLE_DAYS {
+        let date = today - Duration::days(offset);
+        if let Some(wisdom) = state.wisdom_history.get(date).await {
+            last_modified = last_modified.max(wisdom.updated_at);
+            days.push(BundledWisdom {
+                date: date.to_string(),
+                wisdom: wisdom.text,
+                provenance: wisdom.provenance,
+                url: permalink("", date),
+            });
+        }
+    }
+    (days, last_modified)
+}
@@ -0,0 +1,96 @@
+//! # Open Graph Image Route Handlers
+//!
+//! This module serves the share preview images that pages point to in their
+//! `og:image` and `twitter:image` tags:
+//! - `/og/wisdom/{YYYY-MM-DD}.png`: the Whispurr for that day
+//! - `/og/reading/{id}.png`: a saved quantum field reading
+//!
+//! The same rules as the pages apply: no image for days the visitor hasn't
+//! reached yet, and private readings only for their owner. The cards
+//! themselves are drawn and cached by `og.rs`.
+
+// Import necessary dependencies:
+// - axum: Web framework for handling HTTP requests
+// - chrono: For the card dates and Last-Modified
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use tracing::{error, info};
+
+// Import our application state and helpers
+use crate::caching::{conditional, Validators};
+use crate::og::{Artwork, Card};
+use crate::routes::pages::wisdom_for_day;
+use crate::sessions::WebSession;
+use crate::state::AppState;
+use crate::timezone::VisitorZone;
+
+/// Handler function for GET /og/wisdom/{YYYY-MM-DD}.png
+pub async fn wisdom_card(
+    State(state): State<AppState>,
+    zone: VisitorZone,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+) -> Response {
+    let Some(date) = file.strip_suffix(".png") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some((date, wisdom)) = wisdom_for_day(&state, date, zone.today()).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    info!("Serving wisdom card for {}", date);
+
+    let card = Card {
+        heading: "DAILY WHISPURR".to_string(),
+        text: format!("\u{201C}{}\u{201D}", wisdom.text.trim()),
+        footer: footer(date),
+        artwork: Artwork::Cat,
+    };
+    serve_card(&state, &headers, &format!("wisdom-{}", date), card, wisdom.updated_at).await
+}
+
+/// Handler function for GET /og/reading/{id}.png
+pub async fn reading_card(
+    State(state): State<AppState>,
+    session: WebSession,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+) -> Response {
+    let Some(id) = file.strip_suffix(".png") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(reading) = state.readings.get(id).await.filter(|reading| reading.visible_to(&session.id)) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    info!("Serving reading card");
+
+    let card = Card {
+        heading: format!("QUANTUM READING \u{00B7} {}", reading.domain.to_uppercase()),
+        text: reading.collapsed_prompt.clone(),
+        footer: footer(reading.date),
+        artwork: Artwork::Quantum,
+    };
+    serve_card(&state, &headers, &format!("reading-{}", reading.id), card, reading.created_at).await
+}
+
+/// Renders (or loads) a card and answers with it, or 304 if the client's copy is current
+async fn serve_card(state: &AppState, headers: &HeaderMap, name: &str, card: Card, changed: DateTime<Utc>) -> Response {
+    match state.og_cards.png(name, card).await {
+        Ok(png) => {
+            let validators = Validators::for_body(&png, changed);
+            conditional(headers, &validators, "image/png", png)
+        }
+        Err(err) => {
+            error!("Failed to render card {}: {:?}", name, err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// The line at the bottom of every card, e.g. "June 1, 2025 · The Enlightened Cat"
+fn footer(date: NaiveDate) -> String {
+    format!("{} \u{00B7} The Enlightened Cat", date.format("%B %-d, %Y"))
+}
@@ -0,0 +1,81 @@
+//! # OpenAPI Document
+//!
+//! Describes the versioned JSON API under `/api/v1`. The document is generated
+//! from the handler and type annotations in the other route modules, so it
+//! stays in step with the code, and is served at `/api/v1/openapi.json`.
+
+use axum::Json;
+use utoipa::OpenApi;
+
+use crate::corpus::Provenance;
+use crate::mistral::Topic;
+use crate::readings::Visibility;
+use crate::routes::{account, chat, push, quantum_field, wisdom};
+use crate::safety::SafetyCategory;
+
+/// The OpenAPI description of `/api/v1`
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "The Enlightened Cat API",
+        description = "Daily Whispurrs, the 6-Fold Wisdom Field and conversations with The Enlightened Cat.",
+    ),
+    paths(
+        chat::handle_chat,
+        wisdom::get_daily_wisdom,
+        quantum_field::get_quantum_field,
+        quantum_field::collapse_quantum_field,
+        quantum_field::update_reading,
+        push::public_key,
+        push::subscribe,
+        push::unsubscribe,
+        account::me,
+        account::delete,
+        account::signup,
+        account::login,
+        account::logout,
+        account::passkey_register_options,
+        account::passkey_register,
+        account::passkey_login_options,
+        account::passkey_login,
+    ),
+    components(schemas(
+        chat::ChatRequest,
+        chat::ChatResponse,
+        wisdom::WisdomResponse,
+        quantum_field::QuantumFieldResponse,
+        quantum_field::WisdomNodeResponse,
+        quantum_field::CollapsedFieldResponse,
+        quantum_field::ReadingResponse,
+        quantum_field::ReadingUpdate,
+        push::PublicKeyResponse,
+        push::PushKeys,
+        push::SubscriptionRequest,
+        push::SubscriptionResponse,
+        push::UnsubscribeRequest,
+        account::AccountResponse,
+        account::PasskeyResponse,
+        account::SignupRequest,
+        account::LoginRequest,
+        account::PasskeyOptionsRequest,
+        account::PasskeyRegistration,
+        account::PasskeyLogin,
+        Visibility,
+        Provenance,
+        SafetyCategory,
+        Topic,
+    )),
+    tags(
+        (name = "chat", description = "Conversations with the cat"),
+        (name = "wisdom", description = "The Daily Whispurr"),
+        (name = "quantum-field", description = "The 6-Fold Wisdom Field"),
+        (name = "push", description = "Morning Whispurr notifications by Web Push"),
+        (name = "account", description = "Optional accounts, with passwords or passkeys"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Handler function for GET /api/v1/openapi.json
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
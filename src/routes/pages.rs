@@ -9,43 +9,67 @@
 // - askama: Templating engine for rendering HTML
 // - tracing: Logging framework
 use axum::{
-    extract::State,     // For accessing application state in handlers
-    response::Html,     // For returning HTML responses
+    extract::{Path, Query, State},       // For reading URL segments, query strings and application state
+    http::StatusCode,                    // For the "no wisdom that day" response
+    response::{Html, IntoResponse, Response}, // For returning HTML responses
 };
+use chrono::NaiveDate;
+use serde::Deserialize;
 use askama::Template;  // Trait that provides the render() method for templates
 use tracing::info;     // For logging information
 
 // Import our application state and template definitions
-use crate::state::AppState;
-use crate::templates::{AboutTemplate, IndexTemplate, WisdomTemplate, QuantumFieldTemplate};  // Import all template structs (IndexTemplate, AboutTemplate, etc.)
+use crate::negotiation::{vary, PageFormat, TextFormat};
+use crate::readings::{Visibility, MAX_EXPLORATION_CHARS};
+use crate::routes::quantum_field;
+use crate::sessions::WebSession;
+use crate::state::{AppState, DailyWisdom};
+use crate::terminal::{self, Style};
+use crate::timezone::VisitorZone;
+use crate::templates::{AboutTemplate, IndexTemplate, NotFoundTemplate, ReadingTemplate, SearchTemplate, SiteContext, WisdomTemplate, QuantumFieldTemplate};  // Import all template structs (IndexTemplate, AboutTemplate, etc.)
 
 /// Handler function for the home page (GET /)
 /// 
 /// This function:
-/// 1. Extracts the application state from the request
-/// 2. Gets the daily wisdom from the state
-/// 3. Renders the index template with the wisdom
+/// 1. Extracts the application state and the visitor's time zone from the request
+/// 2. Gets the daily wisdom for the visitor's local date from the state
+/// 3. Renders the index template with the wisdom, describing the site to
+///    search engines as a `WebSite` with a search box
 /// 4. Returns the rendered HTML
 /// 
+/// Terminal clients such as curl get the ASCII cat speaking the wisdom
+/// instead (see `negotiation.rs`).
+/// 
 /// The `async` keyword allows this function to perform I/O operations
 /// without blocking the server thread.
-pub async fn index(State(state): State<AppState>) -> Html<String> {
+pub async fn index(State(state): State<AppState>, zone: VisitorZone, format: PageFormat) -> Response {
     // Log that we're rendering the index page
     info!("Rendering index page");
     
-    // Get the daily wisdom, with a fallback message if there's an error
-    let wisdom = state.get_daily_wisdom().await.unwrap_or_else(|_| {
-        "Even in moments of technical difficulty, the enlightened cat remains calm and patient.".to_string()
-    });
+    // Get the daily wisdom (the state falls back to the offline corpus if generation fails)
+    let today = zone.today();
+    let wisdom = state.get_daily_wisdom(today).await;
+    
+    if let PageFormat::Text(text) = format {
+        let links = [
+            ("This day for keeps", format!("/wisdom/{}", today)),
+            ("Today's field", "/quantum-field".to_string()),
+        ];
+        return text.respond(wisdom_text(&state, today, &wisdom, &links, text));
+    }
     
     // Create a template instance with the wisdom
-    let template = IndexTemplate { daily_wisdom: wisdom };
+    let template = IndexTemplate {
+        site: SiteContext::new(&state.config).with_website(),
+        daily_wisdom: wisdom.text,
+        provenance: wisdom.provenance,
+    };
     
     // Render the template to HTML and wrap it in an Html response
     // If rendering fails, provide a simple fallback HTML
-    Html(template.render().unwrap_or_else(|_| {
+    vary(Html(template.render().unwrap_or_else(|_| {
         "<h1>The Enlightened Cat</h1><p>Wisdom loading...</p>".to_string()
-    }))
+    })))
 }
 
 /// Handler function for the about page (GET /about)
@@ -55,14 +79,14 @@ pub async fn index(State(state): State<AppState>) -> Html<String> {
 /// 2. Renders it to HTML
 /// 3. Returns the rendered HTML
 /// 
-/// Note that this handler doesn't need access to the application state
-/// since it doesn't use any dynamic data from the state.
-pub async fn about() -> Html<String> {
+/// It needs the site context shared by every page (see `SiteContext`), and
+/// whether to offer the newsletter signup.
+pub async fn about(State(state): State<AppState>) -> Html<String> {
     // Log that we're rendering the about page
     info!("Rendering about page");
     
     // Create a template instance (with no dynamic data in this case)
-    let template = AboutTemplate {};
+    let template = AboutTemplate { site: SiteContext::new(&state.config), newsletter: state.newsletter.enabled() };
     
     // Render the template to HTML and wrap it in an Html response
     // If rendering fails, provide a simple fallback HTML
@@ -74,47 +98,306 @@ pub async fn about() -> Html<String> {
 /// Handler function for the wisdom page (GET /wisdom)
 /// 
 /// This function:
-/// 1. Extracts the application state from the request
-/// 2. Gets the daily wisdom from the state
+/// 1. Extracts the application state and the visitor's time zone from the request
+/// 2. Gets the daily wisdom for the visitor's local date from the state
 /// 3. Renders the wisdom template with the wisdom
 /// 4. Returns the rendered HTML
 /// 
 /// This page is dedicated to displaying the daily wisdom with sharing options.
-pub async fn wisdom_page(State(state): State<AppState>) -> Html<String> {
+/// Its OG and canonical URLs point at the permanent page for today's date, so a
+/// shared link keeps showing this wisdom tomorrow. Like the home page, it is
+/// plain text for terminal clients.
+pub async fn wisdom_page(State(state): State<AppState>, zone: VisitorZone, format: PageFormat) -> Response {
     // Log that we're rendering the wisdom page
     info!("Rendering wisdom page");
     
-    // Get the daily wisdom, with a fallback message if there's an error
-    let wisdom = state.get_daily_wisdom().await.unwrap_or_else(|_| {
-        "Even in moments of technical difficulty, the enlightened cat remains calm and patient.".to_string()
-    });
+    // Get the daily wisdom (the state falls back to the offline corpus if generation fails)
+    let today = zone.today();
+    let wisdom = state.get_daily_wisdom(today).await;
     
-    // Create a template instance with the wisdom
-    let template = WisdomTemplate { daily_wisdom: wisdom };
+    render_wisdom_day(&state, today, today, wisdom, format).await
+}
+
+/// Handler function for a day's wisdom page (GET /wisdom/{YYYY-MM-DD})
+/// 
+/// This is the permanent URL for one day's Whispurr, linked from the feeds and
+/// from share previews. Past days come from the wisdom history; today's is
+/// settled on first visit like on `/wisdom`. Days without wisdom, days still in
+/// the visitor's future and malformed dates get the templated 404 page.
+pub async fn wisdom_for_date(
+    State(state): State<AppState>,
+    zone: VisitorZone,
+    format: PageFormat,
+    Path(date): Path<String>,
+) -> Response {
+    info!("Rendering wisdom page for {}", date);
+    
+    let today = zone.today();
+    
+    match wisdom_for_day(&state, &date, today).await {
+        Some((date, wisdom)) => render_wisdom_day(&state, date, today, wisdom, format).await,
+        None => not_found_page(&state, "The cat has no Whispurr for that day.").into_response(),
+    }
+}
+
+/// The wisdom for a `YYYY-MM-DD` path segment, if the visitor may see it yet
+/// 
+/// Today's is settled on first request; past days come from the history.
+/// Future days and malformed dates have none.
+pub(crate) async fn wisdom_for_day(state: &AppState, date: &str, today: NaiveDate) -> Option<(NaiveDate, DailyWisdom)> {
+    match date.parse::<NaiveDate>() {
+        Ok(date) if date == today => Some((date, state.get_daily_wisdom(date).await)),
+        Ok(date) if date < today => state.wisdom_history.get(date).await.map(|wisdom| (date, wisdom)),
+        _ => None,
+    }
+}
+
+/// Renders the wisdom template for one day, with links to its neighbours
+/// 
+/// Links never point past `today`, so visitors can't page into days that
+/// only exist for people further east.
+async fn render_wisdom_day(
+    state: &AppState,
+    date: NaiveDate,
+    today: NaiveDate,
+    wisdom: DailyWisdom,
+    format: PageFormat,
+) -> Response {
+    let (previous, next) = state.wisdom_history.neighbours(date).await;
+    let next = next.filter(|d| *d <= today);
+    
+    if let PageFormat::Text(text) = format {
+        let links: Vec<_> = [("Previous day", previous), ("Next day", next)]
+            .into_iter()
+            .filter_map(|(label, day)| Some((label, format!("/wisdom/{}", day?))))
+            .chain([("Today's field", "/quantum-field".to_string())])
+            .collect();
+        return text.respond(wisdom_text(state, date, &wisdom, &links, text));
+    }
+    
+    // Create a template instance with the wisdom, described to search engines as a CreativeWork
+    let template = WisdomTemplate {
+        site: SiteContext::new(&state.config).with_whispurr(date, &wisdom),
+        daily_wisdom: wisdom.text,
+        provenance: wisdom.provenance,
+        date: date.to_string(),
+        date_label: date.format("%B %-d, %Y").to_string(),
+        previous: previous.map(|d| d.to_string()),
+        next: next.map(|d| d.to_string()),
+        push_notifications: state.web_push.is_some(),
+    };
     
     // Render the template to HTML and wrap it in an Html response
     // If rendering fails, provide a simple fallback HTML
-    Html(template.render().unwrap_or_else(|_| {
+    vary(Html(template.render().unwrap_or_else(|_| {
         "<h1>Daily Wisdom</h1><p>Wisdom loading...</p>".to_string()
-    }))
+    })))
+}
+
+/// The text version of a day's Whispurr: the cat speaking it, then a curl
+/// command for each of `links` (label and path)
+fn wisdom_text(state: &AppState, date: NaiveDate, wisdom: &DailyWisdom, links: &[(&str, String)], text: TextFormat) -> String {
+    let date_label = date.format("%B %-d, %Y").to_string();
+    format!(
+        "{}\n{}",
+        terminal::whispurr(&wisdom.text, &date_label, text.width, text.style),
+        curl_hints(state, links, text)
+    )
+}
+
+/// One aligned `label: curl URL` line per link, keeping the colour choice
+fn curl_hints(state: &AppState, links: &[(&str, String)], text: TextFormat) -> String {
+    let query = if text.style == Style::Ansi { "?color" } else { "" };
+    let width = links.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0);
+    links
+        .iter()
+        .map(|(label, path)| {
+            let command = format!("curl {}{}{}", state.config.public_url, path, query);
+            format!("  {:<width$}  {}\n", format!("{}:", label), text.style.paint("2", &command), width = width + 1)
+        })
+        .collect()
+}
+
+/// Fallback handler for any path no route matches
+pub async fn not_found(State(state): State<AppState>) -> (StatusCode, Html<String>) {
+    not_found_page(&state, "This page wandered off, as cats do.")
+}
+
+/// The templated 404 page with the given explanation
+pub(crate) fn not_found_page(state: &AppState, message: &str) -> (StatusCode, Html<String>) {
+    let template = NotFoundTemplate { site: SiteContext::new(&state.config), message: message.to_string() };
+    
+    (
+        StatusCode::NOT_FOUND,
+        Html(template.render().unwrap_or_else(|_| "<h1>Not Found</h1>".to_string())),
+    )
 }
 
 // Quantum Whispurrs page removed - replaced by Quantum Field
 
+/// Query parameters for the quantum field page
+#[derive(Debug, Deserialize)]
+pub struct FieldPageParams {
+    /// For text clients: the node to collapse, numbered 1 to 6 as in the text map
+    pub collapse: Option<usize>,
+}
+
 /// Handler function for the quantum field page (GET /quantum-field)
 /// 
 /// This function renders the 6-Fold Wisdom Field page that presents wisdom
 /// in a structured field of six nodes representing different dimensions of awareness.
-pub async fn quantum_field_page() -> Html<String> {
+/// 
+/// Terminal clients get today's field as a numbered ASCII map instead, and
+/// collapse a node with `?collapse=N`; the collapse is saved as a reading just
+/// like one made through the API.
+pub async fn quantum_field_page(
+    State(state): State<AppState>,
+    zone: VisitorZone,
+    session: WebSession,
+    format: PageFormat,
+    Query(params): Query<FieldPageParams>,
+) -> Response {
     // Log that we're rendering the quantum field page
     info!("Rendering quantum field page");
     
+    if let PageFormat::Text(text) = format {
+        return match params.collapse {
+            Some(number) => collapsed_field_text(&state, zone.today(), &session, number, text).await,
+            None => text.respond(field_text(&state, zone.today(), text).await),
+        };
+    }
+    
     // Create a template instance
-    let template = QuantumFieldTemplate {};
+    let template = QuantumFieldTemplate { site: SiteContext::new(&state.config) };
     
     // Render the template to HTML and wrap it in an Html response
     // If rendering fails, provide a simple fallback HTML
-    Html(template.render().unwrap_or_else(|_| {
+    vary(Html(template.render().unwrap_or_else(|_| {
         "<html><body><h1>The Enlightened Cat</h1><p>The quantum field collapsed unexpectedly. Please try again later.</p></body></html>".to_string()
+    })))
+}
+
+/// The text version of the field page: the six nodes as a numbered map
+async fn field_text(state: &AppState, date: NaiveDate, text: TextFormat) -> String {
+    let field = state.get_quantum_field(date).await;
+    let heading = format!("THE 6-FOLD WISDOM FIELD · {}", date.format("%B %-d, %Y"));
+    let query = if text.style == Style::Ansi { "&color" } else { "" };
+    
+    format!(
+        "{}\n\n{}\n  Collapse a node:  {}\n",
+        text.style.paint("1", &heading),
+        terminal::field_map(&field.wisdom_field, text.width, text.style),
+        text.style.paint("2", &format!("curl '{}/quantum-field?collapse=N{}'", state.config.public_url, query)),
+    )
+}
+
+/// The text answer to `?collapse=N`: the cat speaking the collapsed prompt,
+/// with the link to the saved reading
+async fn collapsed_field_text(
+    state: &AppState,
+    date: NaiveDate,
+    session: &WebSession,
+    number: usize,
+    text: TextFormat,
+) -> Response {
+    if !(1..=6).contains(&number) {
+        let message = "The field has six nodes: collapse one from 1 to 6.\n".to_string();
+        return (StatusCode::BAD_REQUEST, text.respond(message)).into_response();
+    }
+    
+    let (field, reading) = quantum_field::collapse_for(state, date, number - 1, &session.id).await;
+    let prompt = field.collapsed_prompt.unwrap_or_default();
+    
+    let mut body = format!("{}\n\n", terminal::cat_says(&prompt, text.width, text.style));
+    if let Some(reading) = reading {
+        body.push_str(&format!(
+            "  Keep or share this reading:  {}{}\n",
+            state.config.public_url,
+            quantum_field::reading_path(&reading.id)
+        ));
+    }
+    body.push_str(&curl_hints(state, &[("The whole field", "/quantum-field".to_string())], text));
+    
+    (session.set_cookie(), text.respond(body)).into_response()
+}
+
+/// Handler function for a saved quantum field reading (GET /quantum-field/reading/{id})
+/// 
+/// This is the shareable page for one collapse of the field. Anyone with the
+/// link can see public and unlisted readings (unlisted ones ask search engines
+/// not to index them); private ones are shown only to their owner, and look
+/// exactly like a missing reading to everyone else.
+pub async fn reading_page(
+    State(state): State<AppState>,
+    session: WebSession,
+    Path(id): Path<String>,
+) -> Response {
+    info!("Rendering quantum field reading");
+    
+    let Some(reading) = state.readings.get(&id).await.filter(|reading| reading.visible_to(&session.id)) else {
+        return not_found_page(&state, "This reading has faded from the field.").into_response();
+    };
+    
+    let template = ReadingTemplate {
+        site: SiteContext::new(&state.config).with_reading(&reading),
+        is_owner: reading.owner == session.id,
+        indexable: reading.visibility == Visibility::Public,
+        date: reading.date.to_string(),
+        date_label: reading.date.format("%B %-d, %Y").to_string(),
+        id: reading.id,
+        domain: reading.domain,
+        seed: reading.seed,
+        collapsed_prompt: reading.collapsed_prompt,
+        art: reading.art,
+        exploration: reading.exploration,
+        visibility: reading.visibility,
+        max_exploration: MAX_EXPLORATION_CHARS,
+    };
+    
+    Html(template.render().unwrap_or_else(|_| {
+        "<h1>Quantum Reading</h1><p>The reading could not be shown.</p>".to_string()
+    }))
+    .into_response()
+}
+
+/// How many days a search lists at most
+const SEARCH_LIMIT: usize = 30;
+
+/// Query parameters for the search page
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    /// The words to look for; the page shows just the search box without it
+    pub q: Option<String>,
+}
+
+/// Handler function for the search page (GET /search?q=...)
+/// 
+/// Looks through the wisdom history for Whispurrs containing the query, newest
+/// first. This is also the target of the search box that search engines may
+/// show for the site (see the `WebSite` structured data on the home page).
+pub async fn search(
+    State(state): State<AppState>,
+    zone: VisitorZone,
+    Query(params): Query<SearchParams>,
+) -> Html<String> {
+    info!("Rendering search page");
+    
+    let query = params.q.unwrap_or_default().trim().to_string();
+    let results = if query.is_empty() {
+        Vec::new()
+    } else {
+        state
+            .wisdom_history
+            .search(&query, zone.today(), SEARCH_LIMIT)
+            .await
+            .into_iter()
+            .map(|(date, wisdom)| (date.to_string(), date.format("%B %-d, %Y").to_string(), wisdom.text))
+            .collect()
+    };
+    
+    let template = SearchTemplate { site: SiteContext::new(&state.config), query, results };
+    
+    Html(template.render().unwrap_or_else(|_| {
+        "<h1>Search</h1><p>The cat could not search just now.</p>".to_string()
     }))
 }
@@ -0,0 +1,166 @@
+//! # Web Push Routes
+//!
+//! Subscribing a browser to the morning Whispurr (see `webpush.rs`):
+//! - `GET /api/v1/push/public-key` is the VAPID key to subscribe with
+//! - `POST /api/v1/push/subscriptions` stores the browser's subscription
+//!   (as `PushSubscription.toJSON()` gives it) with a local delivery time
+//! - `DELETE /api/v1/push/subscriptions` forgets it
+//!
+//! The notifications are shown by the service worker (see `offline.rs`).
+//!
+//! The subscription's zone defaults to the visitor's (`X-Timezone` or the `tz`
+//! cookie). Subscribing is limited per client address, and refused once the
+//! store is full. Without `VAPID_SUBJECT` the API answers 404.
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::rate_limit::ClientAddress;
+use crate::state::AppState;
+use crate::timezone::VisitorZone;
+
+/// The VAPID public key
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PublicKeyResponse {
+    /// Uncompressed P-256 public key, base64url: the `applicationServerKey`
+    pub public_key: String,
+}
+
+/// The browser's keys for encrypting pushes
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PushKeys {
+    /// The browser's P-256 public key, base64url
+    pub p256dh: String,
+    /// The browser's authentication secret, base64url
+    pub auth: String,
+}
+
+/// A browser's push subscription and when it wants the Whispurr
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SubscriptionRequest {
+    /// The push service URL from the browser's subscription
+    pub endpoint: String,
+    pub keys: PushKeys,
+    /// Local time of day for the notification, `HH:MM`
+    #[schema(example = "07:30")]
+    pub time: String,
+    /// IANA time zone of `time`; defaults to the visitor's zone
+    pub timezone: Option<String>,
+}
+
+/// A stored subscription
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubscriptionResponse {
+    pub endpoint: String,
+    /// `HH:MM`
+    pub time: String,
+    pub timezone: String,
+}
+
+/// Which subscription to forget
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UnsubscribeRequest {
+    pub endpoint: String,
+}
+
+/// Handler for GET /api/v1/push/public-key
+#[utoipa::path(
+    get,
+    path = "/api/v1/push/public-key",
+    tag = "push",
+    responses(
+        (status = 200, description = "The key to pass to `pushManager.subscribe`", body = PublicKeyResponse),
+        (status = 404, description = "Web Push is not enabled"),
+    )
+)]
+pub async fn public_key(State(state): State<AppState>) -> Response {
+    match &state.web_push {
+        Some(push) => Json(PublicKeyResponse { public_key: push.public_key().to_string() }).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Handler for POST /api/v1/push/subscriptions
+#[utoipa::path(
+    post,
+    path = "/api/v1/push/subscriptions",
+    tag = "push",
+    request_body = SubscriptionRequest,
+    params(("X-Timezone" = Option<String>, Header, description = "IANA time zone used when the body names none")),
+    responses(
+        (status = 201, description = "Subscribed; a time already passed today starts tomorrow", body = SubscriptionResponse),
+        (status = 404, description = "Web Push is not enabled"),
+        (status = 422, description = "The time, zone, endpoint or keys are unusable"),
+        (status = 429, description = "Too many subscriptions from this address; try again later"),
+        (status = 503, description = "No more subscriptions are taken"),
+    )
+)]
+pub async fn subscribe(
+    State(state): State<AppState>,
+    zone: VisitorZone,
+    client: ClientAddress,
+    Json(request): Json<SubscriptionRequest>,
+) -> Response {
+    let Some(push) = &state.web_push else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !push.allow_subscribe(&client.0) {
+        warn!("Refused a push subscription from {}, who subscribed too often", client.0);
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+    let Ok(time) = NaiveTime::parse_from_str(request.time.trim(), "%H:%M") else {
+        return (StatusCode::UNPROCESSABLE_ENTITY, "time must be HH:MM").into_response();
+    };
+    let timezone = match request.timezone.as_deref() {
+        Some(name) => match name.parse::<Tz>() {
+            Ok(timezone) => timezone,
+            Err(_) => return (StatusCode::UNPROCESSABLE_ENTITY, "timezone must be an IANA time zone name").into_response(),
+        },
+        None => zone.0,
+    };
+
+    let keys = &request.keys;
+    match push.subscribe(&request.endpoint, &keys.p256dh, &keys.auth, time, timezone, Utc::now()).await {
+        Ok(Some(subscription)) => {
+            info!("A browser subscribed to push notifications at {} {}", time, timezone);
+            let response = SubscriptionResponse {
+                endpoint: subscription.endpoint,
+                time: subscription.time.format("%H:%M").to_string(),
+                timezone: subscription.timezone.name().to_string(),
+            };
+            (StatusCode::CREATED, Json(response)).into_response()
+        }
+        Ok(None) => {
+            warn!("Refused a push subscription: the store is full");
+            (StatusCode::SERVICE_UNAVAILABLE, "No more subscriptions are taken").into_response()
+        }
+        Err(err) => (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", err)).into_response(),
+    }
+}
+
+/// Handler for DELETE /api/v1/push/subscriptions
+#[utoipa::path(
+    delete,
+    path = "/api/v1/push/subscriptions",
+    tag = "push",
+    request_body = UnsubscribeRequest,
+    responses(
+        (status = 204, description = "Unsubscribed"),
+        (status = 404, description = "No such subscription, or Web Push is not enabled"),
+    )
+)]
+pub async fn unsubscribe(State(state): State<AppState>, Json(request): Json<UnsubscribeRequest>) -> StatusCode {
+    match &state.web_push {
+        Some(push) if push.unsubscribe(&request.endpoint).await => StatusCode::NO_CONTENT,
+        _ => StatusCode::NOT_FOUND,
+    }
+}
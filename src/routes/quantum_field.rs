@@ -2,25 +2,40 @@
 //!
 //! This module handles the API endpoints for the 6-Fold Wisdom Field
 //! that allows users to explore quantum wisdom in a structured field.
+//!
+//! Each collapse is saved as a reading (see `readings.rs`) owned by the
+//! visitor's session; the owner can later change who sees it and attach a
+//! summary of their exploration.
 
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
 
+use crate::corpus::Provenance;
 use crate::quantum_field::QuantumField;
+use crate::readings::{Reading, Visibility, MAX_EXPLORATION_CHARS};
+use crate::sessions::WebSession;
 use crate::state::AppState;
+use crate::timezone::VisitorZone;
 
 /// The response structure for quantum field API requests
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub struct QuantumFieldResponse {
     pub wisdom_field: Vec<WisdomNodeResponse>,
+    pub provenance: Provenance,
 }
 
 /// The response structure for a wisdom node
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub struct WisdomNodeResponse {
     pub index: usize,
     pub domain: String,
@@ -28,27 +43,82 @@ pub struct WisdomNodeResponse {
 }
 
 /// The response structure for a collapsed field
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub struct CollapsedFieldResponse {
     pub selected_index: usize,
     pub collapsed_prompt: String,
+    pub provenance: Provenance,
+    /// Id of the reading saved for this collapse; absent when the index was out of range
+    pub reading_id: Option<String>,
+    /// Path of the reading's shareable page
+    pub reading_url: Option<String>,
+}
+
+/// The response structure for a saved reading
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReadingResponse {
+    pub id: String,
+    pub url: String,
+    pub date: String,
+    pub domain: String,
+    pub seed: String,
+    pub collapsed_prompt: String,
+    pub art: String,
+    pub exploration: Option<String>,
+    pub visibility: Visibility,
+}
+
+impl From<Reading> for ReadingResponse {
+    fn from(reading: Reading) -> Self {
+        Self {
+            url: reading_path(&reading.id),
+            id: reading.id,
+            date: reading.date.to_string(),
+            domain: reading.domain,
+            seed: reading.seed,
+            collapsed_prompt: reading.collapsed_prompt,
+            art: reading.art,
+            exploration: reading.exploration,
+            visibility: reading.visibility,
+        }
+    }
+}
+
+/// Changes the owner of a reading can make; omitted fields stay as they are
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReadingUpdate {
+    pub visibility: Option<Visibility>,
+    /// What the visitor took away from exploring the reading; an empty string removes it
+    pub exploration: Option<String>,
 }
 
 /// Query parameters for collapsing the field
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct CollapseParams {
+    /// Zero-based position of the chosen node in `wisdom_field`
     pub index: usize,
 }
 
-/// Handler function for GET /api/quantum-field endpoint
+/// Handler function for GET /api/v1/quantum-field endpoint
 ///
-/// Returns the 6-fold wisdom field in superposition
+/// Returns the 6-fold wisdom field in superposition for the visitor's local date
+#[utoipa::path(
+    get,
+    path = "/api/v1/quantum-field",
+    tag = "quantum-field",
+    params(("X-Timezone" = Option<String>, Header, description = "IANA time zone deciding which date is today")),
+    responses((status = 200, description = "Today's six wisdom nodes", body = QuantumFieldResponse))
+)]
 pub async fn get_quantum_field(
     State(state): State<AppState>,
-) -> Result<Json<QuantumFieldResponse>, (StatusCode, String)> {
-    // Get the quantum field from the state
-    let field = state.get_quantum_field().await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get quantum field: {}", e)))?;
+    zone: VisitorZone,
+) -> Json<QuantumFieldResponse> {
+    // Get the quantum field from the state (falls back to the offline corpus on failure)
+    let field = state.get_quantum_field(zone.today()).await;
     
     // Convert to response format
     let response = QuantumFieldResponse {
@@ -59,30 +129,135 @@ pub async fn get_quantum_field(
                 seed: node.seed.clone(),
             }
         }).collect(),
+        provenance: field.provenance,
     };
     
-    Ok(Json(response))
+    Json(response)
 }
 
-/// Handler function for GET /api/quantum-field/collapse endpoint
+/// Handler function for GET /api/v1/quantum-field/collapse endpoint
 ///
 /// Collapses the field based on the selected node index
+#[utoipa::path(
+    get,
+    path = "/api/v1/quantum-field/collapse",
+    tag = "quantum-field",
+    params(
+        CollapseParams,
+        ("X-Timezone" = Option<String>, Header, description = "IANA time zone deciding which date is today"),
+    ),
+    responses(
+        (status = 200, description = "The collapsed prompt for the chosen node, saved as a reading", body = CollapsedFieldResponse),
+        (status = 400, description = "`index` is missing or not a number"),
+    )
+)]
 pub async fn collapse_quantum_field(
     State(state): State<AppState>,
+    zone: VisitorZone,
+    session: WebSession,
     Query(params): Query<CollapseParams>,
-) -> Result<Json<CollapsedFieldResponse>, (StatusCode, String)> {
-    // Get and collapse the quantum field
-    let mut field = state.get_quantum_field().await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get quantum field: {}", e)))?;
-    
-    // Collapse the field
-    let collapsed_prompt = field.collapse(params.index).to_string();
+) -> (HeaderMap, Json<CollapsedFieldResponse>) {
+    // Collapse today's field, saving a reading owned by this visitor
+    let (field, reading) = collapse_for(&state, zone.today(), params.index, &session.id).await;
     
     // Create the response
     let response = CollapsedFieldResponse {
         selected_index: params.index,
-        collapsed_prompt,
+        collapsed_prompt: field.collapsed_prompt.unwrap_or_default(),
+        provenance: field.provenance,
+        reading_url: reading.as_ref().map(|r| reading_path(&r.id)),
+        reading_id: reading.map(|r| r.id),
     };
     
-    Ok(Json(response))
+    (session.set_cookie(), Json(response))
+}
+
+/// Collapses the field for `date` at zero-based `index` and saves the
+/// collapse as a reading owned by `owner`
+/// 
+/// Collapsing a node `owner` already collapsed that day gives back the same
+/// reading. An index outside the field still collapses it (to a generic
+/// prompt), but saves no reading. Shared by the API, the chat integrations and
+/// the text version of the field page.
+pub(crate) async fn collapse_for(
+    state: &AppState,
+    date: NaiveDate,
+    index: usize,
+    owner: &str,
+) -> (QuantumField, Option<Reading>) {
+    let mut field = state.get_quantum_field(date).await;
+    let collapsed_prompt = field.collapse(index).to_string();
+    
+    let reading = match field.wisdom_field.get(index) {
+        Some(node) => {
+            let fresh = Reading::new(owner, date, index, node.domain.clone(), node.seed.clone(), collapsed_prompt);
+            let (reading, dropped) = state.readings.record(fresh).await;
+            for id in dropped {
+                state.og_cards.forget(&format!("reading-{}", id)).await;
+            }
+            Some(reading)
+        }
+        None => None,
+    };
+    
+    (field, reading)
+}
+
+/// Handler function for PATCH /api/v1/quantum-field/readings/{id} endpoint
+///
+/// Lets the owner of a reading change its visibility or save an exploration
+/// summary. Readings that don't exist and readings owned by someone else both
+/// answer 404, so ids can't be probed.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/quantum-field/readings/{id}",
+    tag = "quantum-field",
+    params(("id" = String, Path, description = "The reading's id, as returned by the collapse endpoint")),
+    request_body = ReadingUpdate,
+    responses(
+        (status = 200, description = "The updated reading", body = ReadingResponse),
+        (status = 404, description = "No such reading, or it belongs to another visitor"),
+        (status = 422, description = "The exploration summary is too long or was flagged by the safety layer"),
+    )
+)]
+pub async fn update_reading(
+    State(state): State<AppState>,
+    session: WebSession,
+    Path(id): Path<String>,
+    Json(update): Json<ReadingUpdate>,
+) -> Response {
+    // Summaries are shown to anyone with the link, so they get the same screening as the cat's replies
+    let exploration = update.exploration.map(|text| text.trim().to_string());
+    if let Some(text) = &exploration {
+        if text.chars().count() > MAX_EXPLORATION_CHARS {
+            let message = format!("The exploration summary is limited to {} characters", MAX_EXPLORATION_CHARS);
+            return (StatusCode::UNPROCESSABLE_ENTITY, message).into_response();
+        }
+        if let Some(category) = state.safety.screen_output(text) {
+            info!("Rejected exploration summary for reading {} (safety: {})", id, category);
+            return (StatusCode::UNPROCESSABLE_ENTITY, "The exploration summary cannot be shared").into_response();
+        }
+    }
+    
+    let updated = state
+        .readings
+        .update_owned(&id, &session.id, |reading| {
+            if let Some(visibility) = update.visibility {
+                reading.visibility = visibility;
+            }
+            if let Some(text) = exploration {
+                reading.exploration = Some(text).filter(|text| !text.is_empty());
+            }
+        })
+        .await;
+    
+    match updated {
+        Some(reading) => Json(ReadingResponse::from(reading)).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// The path of a reading's shareable page
+pub fn reading_path(id: &str) -> String {
+    format!("/quantum-field/reading/{}", id)
 }
@@ -0,0 +1,108 @@
+//! # Search Engine Route Handlers
+//!
+//! This module helps crawlers find their way around the site:
+//! - `/sitemap.xml`: every page worth indexing, built from the fixed routes and
+//!   the wisdom history, with `lastmod` wherever we know it
+//! - `/robots.txt`: what crawlers may visit, pointing them at the sitemap
+//!
+//! The robots file can be replaced with `ROBOTS_TXT_PATH`, e.g. to keep a
+//! staging deployment out of search results entirely. The structured data
+//! embedded in the pages themselves is built in `templates/mod.rs`.
+
+// Import necessary dependencies:
+// - axum: Web framework for handling HTTP requests
+// - chrono: For the site's current date and `lastmod` timestamps
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tracing::{error, info};
+
+// Import our application state and caching helpers
+use crate::caching::{conditional, Validators};
+use crate::routes::feed::permalink;
+use crate::state::AppState;
+
+/// Pages that exist regardless of content, and whether they change with each day's wisdom
+const FIXED_PAGES: [(&str, bool); 4] = [
+    ("/", true),
+    ("/wisdom", true),
+    ("/quantum-field", false),
+    ("/about", false),
+];
+
+/// Handler function for GET /sitemap.xml
+///
+/// Lists the fixed pages and the permanent page of every day in the wisdom
+/// history up to today in the site's time zone. Quantum field readings are
+/// left out: they belong to visitors, and most are only meant for a friend.
+pub async fn sitemap(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    info!("Serving sitemap");
+
+    let base = &state.config.public_url;
+    let today = Utc::now().with_timezone(&state.config.site_timezone).date_naive();
+    let days = state.wisdom_history.recent(usize::MAX).await;
+    let days: Vec<_> = days.into_iter().filter(|(date, _)| *date <= today).collect();
+    let newest = days.iter().map(|(_, wisdom)| wisdom.updated_at).max();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (path, daily) in FIXED_PAGES {
+        let lastmod = if daily { newest } else { None };
+        push_url(&mut xml, &format!("{}{}", base, path), lastmod);
+    }
+    for (date, wisdom) in &days {
+        push_url(&mut xml, &permalink(base, *date), Some(wisdom.updated_at));
+    }
+    xml.push_str("</urlset>\n");
+
+    let body = xml.into_bytes();
+    let validators = Validators::for_body(&body, newest.unwrap_or(DateTime::<Utc>::UNIX_EPOCH));
+    conditional(&headers, &validators, "application/xml; charset=utf-8", body)
+}
+
+/// Handler function for GET /robots.txt
+///
+/// Serves the file at `ROBOTS_TXT_PATH` if one is configured. If it can't be
+/// read, the built-in rules are served instead of an error, so a typo in the
+/// path doesn't make crawlers think the whole site is off limits.
+pub async fn robots(State(state): State<AppState>) -> Response {
+    let body = match state.config.robots_txt_path.as_deref() {
+        Some(path) => match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!("Failed to read robots.txt from {}: {:?}", path, err);
+                default_robots(&state.config.public_url)
+            }
+        },
+        None => default_robots(&state.config.public_url),
+    };
+
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response()
+}
+
+/// Everything but the admin console and the JSON API may be crawled
+fn default_robots(base: &str) -> String {
+    format!(
+        "User-agent: *\nDisallow: /admin\nDisallow: /account\nDisallow: /api/\n\nSitemap: {}/sitemap.xml\n",
+        base
+    )
+}
+
+/// Appends one `<url>` entry
+fn push_url(xml: &mut String, location: &str, lastmod: Option<DateTime<Utc>>) {
+    xml.push_str("  <url>\n");
+    xml.push_str(&format!("    <loc>{}</loc>\n", escape(location)));
+    if let Some(lastmod) = lastmod {
+        xml.push_str(&format!("    <lastmod>{}</lastmod>\n", lastmod.format("%Y-%m-%dT%H:%M:%SZ")));
+    }
+    xml.push_str("  </url>\n");
+}
+
+/// Escapes the characters that may appear in a URL but not in XML text
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
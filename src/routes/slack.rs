@@ -0,0 +1,271 @@
+//! # Slack Integration
+//!
+//! `POST /integrations/slack/command` is the Request URL for a Slack app's
+//! slash commands and its interactivity (button clicks):
+//! - `/whispurr`: today's wisdom, posted in the channel
+//! - `/catfield`: today's six Quantum Field seeds, with a button per node;
+//!   clicking one collapses the field and saves the reading
+//! - `/askcat <question>`: asks the cat. The model can take longer than the
+//!   three seconds Slack waits, so the command is acknowledged at once and the
+//!   reply is posted to the command's `response_url` when it is ready.
+//!
+//! Every request must carry a valid `X-Slack-Signature`: `v0=` + hex
+//! HMAC-SHA256 of `"v0:{X-Slack-Request-Timestamp}:{body}"` under the app's
+//! signing secret, with a timestamp no more than five minutes off. Without
+//! `SLACK_SIGNING_SECRET` the endpoint does not exist (404).
+//!
+//! Each Slack user gets their own cat session, keyed by workspace and user id,
+//! so `/askcat` remembers the conversation and readings have an owner. "Today"
+//! is the site's date: Slack doesn't tell us the user's time zone.
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use openssl::{hash::MessageDigest, memcmp, pkey::PKey, sign::Signer};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::quantum_field::QuantumField;
+use crate::routes::chat::converse;
+use crate::routes::quantum_field::{collapse_for, reading_path};
+use crate::state::AppState;
+use crate::timezone::VisitorZone;
+use crate::webhooks::{self, PayloadFormat};
+
+/// Header carrying Slack's request signature
+const SIGNATURE_HEADER: &str = "x-slack-signature";
+/// Header carrying the Unix time Slack signed the request at
+const TIMESTAMP_HEADER: &str = "x-slack-request-timestamp";
+/// How far a request's timestamp may be from ours, to stop replays
+const MAX_CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// Prefix of the `value` of the field's buttons: `collapse:{date}:{index}`
+const COLLAPSE_ACTION: &str = "collapse";
+
+/// Shown for an unknown command or an empty `/askcat`
+const USAGE: &str = "Try `/whispurr` for today's wisdom, `/catfield` to draw from the Quantum Field, or `/askcat <question>`.";
+
+/// The fields of a slash command the cat uses
+#[derive(Debug, Deserialize)]
+struct SlashCommand {
+    command: String,
+    #[serde(default)]
+    text: String,
+    team_id: String,
+    user_id: String,
+    response_url: String,
+}
+
+/// Interactivity requests wrap their JSON in a `payload` form field
+#[derive(Debug, Deserialize)]
+struct InteractionForm {
+    payload: String,
+}
+
+/// A `block_actions` payload, sent when a button is clicked
+#[derive(Debug, Deserialize)]
+struct BlockActions {
+    #[serde(rename = "type")]
+    kind: String,
+    user: SlackUser,
+    team: Option<SlackTeam>,
+    response_url: String,
+    #[serde(default)]
+    actions: Vec<BlockAction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlackUser {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlackTeam {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockAction {
+    #[serde(default)]
+    value: Option<String>,
+}
+
+/// Handler for POST /integrations/slack/command
+///
+/// Verifies the signature, then dispatches on the slash command, or on the
+/// button that was clicked for interactivity requests.
+pub async fn command(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let Some(secret) = state.config.slack_signing_secret.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !verify(secret, &headers, &body, Utc::now().timestamp()) {
+        warn!("Rejected a Slack request with a missing or invalid signature");
+        return (StatusCode::UNAUTHORIZED, "Invalid Slack signature").into_response();
+    }
+
+    // Button clicks come as a JSON `payload` field, commands as plain form fields
+    if let Ok(form) = serde_urlencoded::from_bytes::<InteractionForm>(&body) {
+        return match serde_json::from_str::<BlockActions>(&form.payload) {
+            Ok(actions) => interaction(state, actions).await,
+            Err(_) => StatusCode::BAD_REQUEST.into_response(),
+        };
+    }
+    let Ok(command) = serde_urlencoded::from_bytes::<SlashCommand>(&body) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    info!("Slack command {}", command.command);
+    let today = VisitorZone(state.config.site_timezone).today();
+    let session_id = session_id(&command.team_id, &command.user_id);
+
+    let reply = match command.command.as_str() {
+        "/whispurr" => {
+            let wisdom = state.get_daily_wisdom(today).await;
+            let mut message = webhooks::payload(PayloadFormat::Slack, today, &wisdom, &state.config.public_url);
+            message["response_type"] = json!("in_channel");
+            message
+        }
+        "/catfield" => field_message(&state.get_quantum_field(today).await, today),
+        "/askcat" if !command.text.trim().is_empty() => {
+            let question = command.text.trim().to_string();
+            let acknowledgement = ephemeral(&format!("{}\n_The cat is considering your question…_", quote(&question)));
+
+            // Answer later, on the response URL, so Slack's three seconds don't matter
+            tokio::spawn(async move {
+                let reply = converse(&state, &session_id, &question, &[], today).await;
+                let message = ephemeral(&format!("{}\n{}", quote(&question), reply.message));
+                post_response(&state, &command.response_url, &message).await;
+            });
+            acknowledgement
+        }
+        _ => ephemeral(USAGE),
+    };
+
+    Json(reply).into_response()
+}
+
+/// Collapses the field at the clicked node and posts the reading
+///
+/// Slack ignores the body of the answer to a click, so the result goes to the
+/// payload's `response_url`; the click itself is acknowledged at once.
+async fn interaction(state: AppState, actions: BlockActions) -> Response {
+    if actions.kind != "block_actions" {
+        return StatusCode::OK.into_response();
+    }
+
+    let clicked = actions.actions.iter().filter_map(|action| action.value.as_deref()).find_map(parse_collapse);
+    let Some((date, index)) = clicked else {
+        return StatusCode::OK.into_response();
+    };
+
+    let team = actions.team.map(|team| team.id).unwrap_or_default();
+    let session_id = session_id(&team, &actions.user.id);
+    tokio::spawn(async move {
+        let (field, reading) = collapse_for(&state, date, index, &session_id).await;
+        let mut text = field.collapsed_prompt.unwrap_or_default();
+        if let Some(reading) = reading {
+            text.push_str(&format!("\n<{}{}|Keep or share this reading>", state.config.public_url, reading_path(&reading.id)));
+        }
+        post_response(&state, &actions.response_url, &ephemeral(&text)).await;
+    });
+
+    StatusCode::OK.into_response()
+}
+
+/// Today's field as a list of seeds with a button per node
+fn field_message(field: &QuantumField, date: NaiveDate) -> Value {
+    let seeds: Vec<String> = field
+        .wisdom_field
+        .iter()
+        .map(|node| format!("*{}. {}*  {}", node.index, node.domain, node.seed))
+        .collect();
+    let buttons: Vec<Value> = field
+        .wisdom_field
+        .iter()
+        .enumerate()
+        .map(|(index, node)| {
+            json!({
+                "type": "button",
+                "text": { "type": "plain_text", "text": format!("{}. {}", node.index, node.domain) },
+                "action_id": format!("{}_{}", COLLAPSE_ACTION, index),
+                "value": format!("{}:{}:{}", COLLAPSE_ACTION, date, index),
+            })
+        })
+        .collect();
+
+    json!({
+        "response_type": "ephemeral",
+        "text": "The Quantum Field is in superposition. Choose a node to collapse it.",
+        "blocks": [
+            { "type": "section", "text": { "type": "mrkdwn", "text": "The Quantum Field is in superposition. Choose a node to collapse it:" } },
+            { "type": "section", "text": { "type": "mrkdwn", "text": seeds.join("\n") } },
+            { "type": "actions", "elements": buttons },
+        ],
+    })
+}
+
+/// The date and zero-based index in a collapse button's value
+fn parse_collapse(value: &str) -> Option<(NaiveDate, usize)> {
+    let rest = value.strip_prefix(COLLAPSE_ACTION)?.strip_prefix(':')?;
+    let (date, index) = rest.rsplit_once(':')?;
+    Some((date.parse().ok()?, index.parse().ok()?))
+}
+
+/// A message only the user who asked can see
+fn ephemeral(text: &str) -> Value {
+    json!({ "response_type": "ephemeral", "text": text })
+}
+
+/// `text` as a Slack block quote
+fn quote(text: &str) -> String {
+    text.lines().map(|line| format!("> {}", line)).collect::<Vec<_>>().join("\n")
+}
+
+/// The cat session of a Slack user
+fn session_id(team_id: &str, user_id: &str) -> String {
+    format!("slack:{}:{}", team_id, user_id)
+}
+
+/// Posts a deferred answer to a `response_url`; failures are only logged,
+/// as there is nobody left to tell
+async fn post_response(state: &AppState, response_url: &str, message: &Value) {
+    let result = state.http.post(response_url).json(message).send().await.and_then(|r| r.error_for_status());
+    if let Err(err) = result {
+        warn!("Failed to post a deferred Slack reply: {}", err);
+    }
+}
+
+/// Whether the request was signed by Slack with `secret` within the allowed clock skew of `now`
+pub fn verify(secret: &str, headers: &HeaderMap, body: &[u8], now: i64) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let (Some(signature), Some(timestamp)) = (header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER)) else {
+        return false;
+    };
+    let Ok(sent_at) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if now.abs_diff(sent_at) > MAX_CLOCK_SKEW_SECS as u64 {
+        return false;
+    }
+
+    match sign(secret, timestamp, body) {
+        // memcmp::eq panics on different lengths, so check those first
+        Ok(expected) => expected.len() == signature.len() && memcmp::eq(expected.as_bytes(), signature.as_bytes()),
+        Err(_) => false,
+    }
+}
+
+/// The `X-Slack-Signature` Slack sends for `body` at `timestamp`
+pub fn sign(secret: &str, timestamp: &str, body: &[u8]) -> anyhow::Result<String> {
+    let key = PKey::hmac(secret.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(format!("v0:{}:", timestamp).as_bytes())?;
+    signer.update(body)?;
+    Ok(format!("v0={}", hex::encode(signer.sign_to_vec()?)))
+}
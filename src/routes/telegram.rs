@@ -0,0 +1,273 @@
+//! # Telegram Integration
+//!
+//! `POST /integrations/telegram/webhook` receives the bot's updates from the
+//! Telegram Bot API (register it with `setWebhook`, passing
+//! `TELEGRAM_WEBHOOK_SECRET` as `secret_token`). The bot understands:
+//! - `/wisdom`: today's Whispurr
+//! - `/field`: today's six Quantum Field seeds, with an inline keyboard
+//!   button per node; pressing one collapses the field and saves the reading
+//! - `/daily HH:MM [zone]`, `/daily off`, `/daily`: opt in to, out of, or
+//!   check the daily push (see `telegram.rs`)
+//! - anything else that isn't a command is a message to the cat
+//!
+//! Telegram sends the secret back in `X-Telegram-Bot-Api-Secret-Token`;
+//! requests without it get a 401. Without both the bot token and the secret
+//! the endpoint does not exist (404).
+//!
+//! Updates are acknowledged at once and answered with Bot API calls, so a slow
+//! model never makes Telegram retry. Each chat has its own cat session, so in
+//! a group the conversation is shared. "Today" is the date in the chat's
+//! `/daily` zone if it has one, or the site's.
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use chrono::{NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use openssl::memcmp;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::quantum_field::QuantumField;
+use crate::routes::chat::converse;
+use crate::routes::quantum_field::{collapse_for, reading_path};
+use crate::state::AppState;
+use crate::telegram::wisdom_message;
+use crate::timezone::VisitorZone;
+
+/// Header carrying the secret token given to `setWebhook`
+const SECRET_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+/// Prefix of the field buttons' `callback_data`: `collapse:{date}:{index}`
+const COLLAPSE_ACTION: &str = "collapse";
+
+/// Answer to `/start`, `/help` and unknown commands
+const HELP: &str = "Meow. Tell me what's on your mind, or try:\n\
+/wisdom - today's Whispurr\n\
+/field - draw from today's Quantum Field\n\
+/daily 08:00 Europe/Berlin - today's Whispurr every morning (/daily off to stop)";
+
+/// The parts of an update the cat uses
+#[derive(Debug, Deserialize)]
+struct Update {
+    #[serde(default)]
+    message: Option<Message>,
+    #[serde(default)]
+    callback_query: Option<CallbackQuery>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    #[serde(default)]
+    from: Option<User>,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    /// IETF tag of the user's Telegram language, used for safety replies
+    #[serde(default)]
+    language_code: Option<String>,
+}
+
+/// Sent when an inline keyboard button is pressed
+#[derive(Debug, Deserialize)]
+struct CallbackQuery {
+    id: String,
+    #[serde(default)]
+    data: Option<String>,
+    /// The message with the button, absent for very old messages
+    #[serde(default)]
+    message: Option<Message>,
+}
+
+/// Handler for POST /integrations/telegram/webhook
+pub async fn webhook(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let Some(secret) = state.config.telegram_webhook_secret.as_deref().filter(|_| state.telegram.enabled()) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let sent = headers.get(SECRET_HEADER).map(|value| value.as_bytes()).unwrap_or_default();
+    // memcmp::eq panics on different lengths, so check those first
+    if sent.len() != secret.len() || !memcmp::eq(sent, secret.as_bytes()) {
+        warn!("Rejected a Telegram update without the webhook secret");
+        return StatusCode::UNAUTHORIZED;
+    }
+    let Ok(update) = serde_json::from_slice::<Update>(&body) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    tokio::spawn(async move {
+        if let Some(query) = update.callback_query {
+            button_pressed(&state, query).await;
+        } else if let Some(message) = update.message {
+            received(&state, message).await;
+        }
+    });
+    StatusCode::OK
+}
+
+/// Answers a message: a command, or something to say to the cat
+async fn received(state: &AppState, message: Message) {
+    let chat_id = message.chat.id;
+    let Some(text) = message.text.as_deref().map(str::trim).filter(|text| !text.is_empty()) else {
+        return;
+    };
+    let today = today(state, chat_id).await;
+
+    let Some(command) = text.strip_prefix('/') else {
+        // Conversations take a while; show the cat is typing meanwhile
+        let typing = json!({ "chat_id": chat_id, "action": "typing" });
+        if let Err(err) = state.telegram.call("sendChatAction", &typing).await {
+            warn!("Failed to show the cat typing on Telegram: {:#}", err);
+        }
+        let locales: Vec<String> = message.from.and_then(|user| user.language_code).into_iter().collect();
+        let reply = converse(state, &session_id(chat_id), text, &locales, today).await;
+        state.telegram.send_text(chat_id, &reply.message).await;
+        return;
+    };
+
+    let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    // In groups, commands may be addressed to the bot as /wisdom@cat_bot
+    let name = name.split('@').next().unwrap_or_default();
+    info!("Telegram command /{}", name);
+
+    match name {
+        "wisdom" => {
+            let wisdom = state.get_daily_wisdom(today).await;
+            state.telegram.send(&wisdom_message(chat_id, today, &wisdom, &state.config.public_url)).await;
+        }
+        "field" => state.telegram.send(&field_message(chat_id, &state.get_quantum_field(today).await, today)).await,
+        "daily" => state.telegram.send_text(chat_id, &daily(state, chat_id, argument.trim()).await).await,
+        _ => state.telegram.send_text(chat_id, HELP).await,
+    }
+}
+
+/// Handles `/daily` with its argument, and says what happened
+async fn daily(state: &AppState, chat_id: i64, argument: &str) -> String {
+    const USAGE: &str = "Tell me when, as /daily 08:00 or /daily 08:00 Europe/Berlin, or /daily off to stop.";
+
+    if argument.is_empty() {
+        return match state.telegram.subscription(chat_id).await {
+            Some(subscription) => format!(
+                "You get the Whispurr every day after {} ({}). /daily off to stop.",
+                subscription.time.format("%H:%M"),
+                subscription.timezone
+            ),
+            None => format!("You're not getting the daily Whispurr. {}", USAGE),
+        };
+    }
+    if argument.eq_ignore_ascii_case("off") {
+        return match state.telegram.unsubscribe(chat_id).await {
+            true => "No more daily Whispurrs. /daily 08:00 brings them back.".to_string(),
+            false => "You weren't getting the daily Whispurr.".to_string(),
+        };
+    }
+
+    let mut words = argument.split_whitespace();
+    let time = words.next().and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok());
+    let zone = match words.next() {
+        Some(name) => name.parse::<Tz>().ok(),
+        None => Some(state.config.site_timezone),
+    };
+    let (Some(time), Some(zone), None) = (time, zone, words.next()) else {
+        return USAGE.to_string();
+    };
+
+    let subscription = state.telegram.subscribe(chat_id, time, zone, Utc::now()).await;
+    let first = match subscription.last_sent {
+        Some(_) => "tomorrow",
+        None => "today",
+    };
+    format!(
+        "Purr. You'll get the Whispurr every day after {} ({}), starting {}.",
+        time.format("%H:%M"),
+        zone,
+        first
+    )
+}
+
+/// Collapses the field at the pressed node and sends the reading
+async fn button_pressed(state: &AppState, query: CallbackQuery) {
+    // Stops the button's spinner, whatever comes next
+    if let Err(err) = state.telegram.call("answerCallbackQuery", &json!({ "callback_query_id": query.id })).await {
+        warn!("Failed to answer a Telegram button press: {:#}", err);
+    }
+    let (Some(chat_id), Some((date, index))) = (
+        query.message.map(|message| message.chat.id),
+        query.data.as_deref().and_then(parse_collapse),
+    ) else {
+        return;
+    };
+
+    let (field, reading) = collapse_for(state, date, index, &session_id(chat_id)).await;
+    let text = match reading {
+        Some(reading) => format!(
+            "{}. {} · {}\n\n{}\n\nKeep or share this reading: {}{}",
+            reading.index + 1,
+            reading.domain,
+            reading.date.format("%B %-d, %Y"),
+            reading.collapsed_prompt,
+            state.config.public_url,
+            reading_path(&reading.id)
+        ),
+        None => field.collapsed_prompt.unwrap_or_default(),
+    };
+    state.telegram.send_text(chat_id, &text).await;
+}
+
+/// Today's field as a list of seeds with an inline keyboard, two buttons a row
+fn field_message(chat_id: i64, field: &QuantumField, date: NaiveDate) -> Value {
+    let seeds: Vec<String> = field
+        .wisdom_field
+        .iter()
+        .map(|node| format!("{}. {}: {}", node.index, node.domain, node.seed))
+        .collect();
+    let buttons: Vec<Value> = field
+        .wisdom_field
+        .iter()
+        .enumerate()
+        .map(|(index, node)| {
+            json!({
+                "text": format!("{}. {}", node.index, node.domain),
+                "callback_data": format!("{}:{}:{}", COLLAPSE_ACTION, date, index),
+            })
+        })
+        .collect();
+
+    json!({
+        "chat_id": chat_id,
+        "text": format!(
+            "The Quantum Field is in superposition. Choose a node to collapse it.\n\n{}",
+            seeds.join("\n")
+        ),
+        "reply_markup": { "inline_keyboard": buttons.chunks(2).collect::<Vec<_>>() },
+    })
+}
+
+/// The date and zero-based index in a collapse button's data
+fn parse_collapse(data: &str) -> Option<(NaiveDate, usize)> {
+    let rest = data.strip_prefix(COLLAPSE_ACTION)?.strip_prefix(':')?;
+    let (date, index) = rest.rsplit_once(':')?;
+    Some((date.parse().ok()?, index.parse().ok()?))
+}
+
+/// Today in the chat's `/daily` zone, or the site's
+async fn today(state: &AppState, chat_id: i64) -> NaiveDate {
+    let zone = state.telegram.subscription(chat_id).await.map_or(state.config.site_timezone, |s| s.timezone);
+    VisitorZone(zone).today()
+}
+
+/// The cat session of a Telegram chat
+fn session_id(chat_id: i64) -> String {
+    format!("telegram:{}", chat_id)
+}
@@ -10,60 +10,69 @@
 // - tracing: For logging (similar to Winston or Bunyan in Node.js)
 use axum::{
     extract::State,      // For accessing application state in handlers
-    http::StatusCode,    // For HTTP status codes like 200 OK, 500 Error
     Json,                // For returning JSON responses
 };
 use serde::Serialize;    // For making structs serializable to JSON
-use tracing::{error, info}; // For logging information and errors
+use utoipa::ToSchema;    // For describing structs in the OpenAPI document
+use tracing::info;       // For logging information
 
 // Import our application state that contains the Mistral client
+use crate::corpus::Provenance;
 use crate::state::AppState;
+use crate::timezone::VisitorZone;
 
 /// The response structure for wisdom API requests
 /// 
 /// `#[derive(Debug, Serialize)]` is a macro that automatically implements:
 /// - Debug: Allows printing the struct for debugging
 /// - Serialize: Allows converting the struct to JSON
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub struct WisdomResponse {
     pub wisdom: String,      // The wisdom quote text
+    pub date: String,        // The local calendar date this wisdom belongs to
+    pub timezone: String,    // The time zone used to decide that date
     pub timestamp: String,   // When the wisdom was generated
+    pub provenance: Provenance, // Whether it was generated or came from the offline corpus
 }
 
-/// Handler function for GET /api/daily-wisdom endpoint
+/// Handler function for GET /api/v1/daily-wisdom endpoint
 /// 
 /// This function:
-/// 1. Extracts the application state from the request
-/// 2. Retrieves the daily wisdom from the state
-/// 3. Returns it as JSON with a timestamp
-/// 4. Handles any errors that might occur
+/// 1. Extracts the application state and the visitor's time zone from the request
+/// 2. Retrieves the daily wisdom for the visitor's local date from the state
+/// 3. Returns it as JSON with a timestamp and its provenance
+/// 
+/// The state never fails here: if the Mistral API is down, the wisdom comes
+/// from the offline corpus and `provenance` says so.
 /// 
 /// The `async` keyword means this function can be paused/resumed,
 /// allowing it to wait for I/O operations without blocking the thread.
+#[utoipa::path(
+    get,
+    path = "/api/v1/daily-wisdom",
+    tag = "wisdom",
+    params(("X-Timezone" = Option<String>, Header, description = "IANA time zone deciding which date is today")),
+    responses((status = 200, description = "Today's Whispurr", body = WisdomResponse))
+)]
 pub async fn get_daily_wisdom(
     // Extract the AppState from the request using Axum's State extractor
     State(state): State<AppState>,
-) -> Result<Json<WisdomResponse>, (StatusCode, String)> {
+    // Resolve the visitor's time zone from the X-Timezone header or tz cookie
+    zone: VisitorZone,
+) -> Json<WisdomResponse> {
     // Log that we're fetching wisdom (will appear in application logs)
     info!("Fetching daily wisdom");
     
-    // Try to get wisdom from the state and handle success/failure
-    match state.get_daily_wisdom().await {
-        // If successful, return the wisdom with current timestamp
-        Ok(wisdom) => {
-            let now = chrono::Utc::now();  // Get current UTC time
-            Ok(Json(WisdomResponse {
-                wisdom,
-                timestamp: now.to_rfc3339(),  // Format timestamp as RFC3339
-            }))
-        }
-        // If there's an error, log it and return a 500 error
-        Err(err) => {
-            error!("Error fetching daily wisdom: {:?}", err);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to fetch daily wisdom: {}", err),
-            ))
-        }
-    }
+    let date = zone.today();
+    let wisdom = state.get_daily_wisdom(date).await;
+    let now = chrono::Utc::now();  // Get current UTC time
+    
+    Json(WisdomResponse {
+        wisdom: wisdom.text,
+        date: date.to_string(),
+        timezone: zone.name().to_string(),
+        timestamp: now.to_rfc3339(),  // Format timestamp as RFC3339
+        provenance: wisdom.provenance,
+    })
 }
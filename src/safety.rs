@@ -0,0 +1,252 @@
+//! # Safety Layer
+//!
+//! Screens chat messages before they reach the model, and the model's replies
+//! before they reach the visitor. Our audience is stressed professionals, so the
+//! most important job here is noticing crisis or self-harm language and
+//! answering with real-world resources instead of cat puns.
+//!
+//! Three categories are recognised:
+//! - `crisis`: self-harm or suicidal language; answered with a resources message
+//! - `abuse`: insults and threats; answered with a calm refusal
+//! - `off_topic`: requests the cat shouldn't handle (code, investment tips,
+//!   prompt injection); answered with a gentle redirect
+//!
+//! The patterns and the localised messages are bundled from `data/safety.json`;
+//! deployments can extend them via `SAFETY_RULES_PATH`, in the same way as the
+//! offline corpus. Flags are logged and counted by category and length only —
+//! the flagged text itself is never stored.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use axum::http::{header::ACCEPT_LANGUAGE, HeaderMap};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The rules shipped with the binary
+const BUNDLED_RULES: &str = include_str!("../data/safety.json");
+
+/// Locale used when none of the visitor's languages has a message
+const DEFAULT_LOCALE: &str = "en";
+
+/// Why a message was flagged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyCategory {
+    /// Self-harm or suicidal language
+    Crisis,
+    /// Insults or threats
+    Abuse,
+    /// Requests outside what the cat is for
+    OffTopic,
+}
+
+impl fmt::Display for SafetyCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafetyCategory::Crisis => write!(f, "crisis"),
+            SafetyCategory::Abuse => write!(f, "abuse"),
+            SafetyCategory::OffTopic => write!(f, "off_topic"),
+        }
+    }
+}
+
+/// Whether a flag was raised on the visitor's message or the model's reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyStage {
+    Input,
+    Output,
+}
+
+impl fmt::Display for SafetyStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafetyStage::Input => write!(f, "input"),
+            SafetyStage::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// Patterns per category, as written in the rules file
+///
+/// A pattern is a phrase matched on word boundaries, case-insensitively and
+/// ignoring punctuation (`"don t"` matches "Don't"). A trailing `*` matches any
+/// word ending, so `"suicid*"` also catches "suicidal".
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SafetyPatterns {
+    #[serde(default)]
+    pub crisis: Vec<String>,
+    #[serde(default)]
+    pub abuse: Vec<String>,
+    #[serde(default)]
+    pub off_topic: Vec<String>,
+}
+
+/// The replies for one locale; missing entries fall back to `en`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SafetyMessages {
+    pub crisis: Option<String>,
+    pub abuse: Option<String>,
+    pub off_topic: Option<String>,
+}
+
+impl SafetyMessages {
+    fn get(&self, category: SafetyCategory) -> Option<&String> {
+        match category {
+            SafetyCategory::Crisis => self.crisis.as_ref(),
+            SafetyCategory::Abuse => self.abuse.as_ref(),
+            SafetyCategory::OffTopic => self.off_topic.as_ref(),
+        }
+    }
+}
+
+/// Patterns and localised replies used to screen chat
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SafetyRules {
+    #[serde(default)]
+    pub patterns: SafetyPatterns,
+
+    /// Replies keyed by lowercase language tag (`en`, `de`, `en-gb`...)
+    #[serde(default)]
+    pub messages: HashMap<String, SafetyMessages>,
+}
+
+impl SafetyRules {
+    /// The rules bundled into the binary
+    pub fn bundled() -> Self {
+        serde_json::from_str(BUNDLED_RULES).expect("Bundled safety rules must be valid JSON")
+    }
+
+    /// Loads the bundled rules, extended with those in `extra` if given
+    pub fn load(extra: Option<&Path>) -> Result<Self> {
+        let mut rules = Self::bundled();
+
+        if let Some(path) = extra {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read safety rules {}", path.display()))?;
+            let additions: SafetyRules = serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse safety rules {}", path.display()))?;
+            rules.extend(additions);
+        }
+
+        Ok(rules)
+    }
+
+    /// Adds another file's patterns, and lets its messages replace ours
+    pub fn extend(&mut self, other: SafetyRules) {
+        self.patterns.crisis.extend(other.patterns.crisis);
+        self.patterns.abuse.extend(other.patterns.abuse);
+        self.patterns.off_topic.extend(other.patterns.off_topic);
+
+        for (locale, messages) in other.messages {
+            let existing = self.messages.entry(locale.to_lowercase()).or_default();
+            existing.crisis = messages.crisis.or(existing.crisis.take());
+            existing.abuse = messages.abuse.or(existing.abuse.take());
+            existing.off_topic = messages.off_topic.or(existing.off_topic.take());
+        }
+    }
+
+    /// Checks a visitor's message
+    ///
+    /// Crisis language wins over everything else: someone who is both
+    /// distressed and swearing needs the resources, not a telling-off.
+    pub fn screen_input(&self, text: &str) -> Option<SafetyCategory> {
+        let text = normalize(text);
+        [SafetyCategory::Crisis, SafetyCategory::Abuse, SafetyCategory::OffTopic]
+            .into_iter()
+            .find(|category| self.matches(*category, &text))
+    }
+
+    /// Checks the model's reply
+    ///
+    /// Only crisis and abuse apply: the model talking about code is odd, but
+    /// not something to hide from the visitor.
+    pub fn screen_output(&self, text: &str) -> Option<SafetyCategory> {
+        let text = normalize(text);
+        [SafetyCategory::Crisis, SafetyCategory::Abuse]
+            .into_iter()
+            .find(|category| self.matches(*category, &text))
+    }
+
+    /// The reply for `category` in the first of `locales` that has one
+    ///
+    /// Each locale is tried as given (`en-gb`) and then by its language (`en`),
+    /// before falling back to English.
+    pub fn message(&self, category: SafetyCategory, locales: &[String]) -> String {
+        locales
+            .iter()
+            .flat_map(|locale| [locale.as_str(), locale.split('-').next().unwrap_or(locale)])
+            .chain([DEFAULT_LOCALE])
+            .find_map(|locale| self.messages.get(locale).and_then(|m| m.get(category)))
+            .cloned()
+            .unwrap_or_else(|| "The cat cannot answer that. Please take good care of yourself.".to_string())
+    }
+
+    fn matches(&self, category: SafetyCategory, normalized: &str) -> bool {
+        let patterns = match category {
+            SafetyCategory::Crisis => &self.patterns.crisis,
+            SafetyCategory::Abuse => &self.patterns.abuse,
+            SafetyCategory::OffTopic => &self.patterns.off_topic,
+        };
+        patterns.iter().any(|pattern| {
+            match pattern.strip_suffix('*') {
+                // Prefix pattern: must start at a word boundary, may end anywhere
+                Some(prefix) => normalized.contains(&format!(" {}", normalize(prefix).trim())),
+                None => normalized.contains(&normalize(pattern)),
+            }
+        })
+    }
+}
+
+/// Lowercases `text`, turns everything but letters and digits into single
+/// spaces, and pads it with a space on both sides
+///
+/// Matching padded phrases against padded text gives word-boundary matches
+/// without a regex engine: `" kill you "` does not match "kill yourself".
+fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len() + 2);
+    normalized.push(' ');
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            normalized.push(c);
+        } else if !normalized.ends_with(' ') {
+            normalized.push(' ');
+        }
+    }
+    if !normalized.ends_with(' ') {
+        normalized.push(' ');
+    }
+    normalized
+}
+
+/// The visitor's preferred languages from `Accept-Language`, best first
+///
+/// Tags are lowercased; `*` and malformed entries are skipped.
+pub fn preferred_locales(headers: &HeaderMap) -> Vec<String> {
+    let Some(header) = headers.get(ACCEPT_LANGUAGE).and_then(|value| value.to_str().ok()) else {
+        return Vec::new();
+    };
+
+    let mut weighted: Vec<(f32, String)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            Some((quality, tag))
+        })
+        .collect();
+
+    // Stable sort keeps the header's order among equal weights
+    weighted.sort_by(|a, b| b.0.total_cmp(&a.0));
+    weighted.into_iter().map(|(_, tag)| tag).collect()
+}
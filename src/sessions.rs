@@ -0,0 +1,309 @@
+//! # Chat Sessions
+//!
+//! Each visitor's conversation with the cat is kept in a session, identified by
+//! a random id in the `cat_session` cookie. Integrations that have their own
+//! notion of a user (a Slack user, a Telegram chat...) use the same store with
+//! an id derived from that instead.
+//!
+//! A session also tracks topic state: the topics the model has suggested so
+//! far, and the one the visitor chose to explore, which steers later turns.
+//!
+//! Sessions are kept in `<data_dir>/sessions.json` (see `json_file.rs`), so a
+//! restart doesn't interrupt anyone's conversation, and are dropped after a
+//! week without activity. `the-enlightened-cat sessions purge` drops them
+//! sooner. Sessions held by an account (see `accounts.rs`) are the history
+//! the account exists to keep, so they are never dropped for being idle.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header::SET_COOKIE, request::Parts, HeaderMap, HeaderValue},
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::cookies;
+use crate::json_file::JsonFile;
+use crate::mistral::{Conversation, MistralClient, Topic};
+use crate::state::AppState;
+
+/// Cookie carrying the visitor's session id
+pub const SESSION_COOKIE: &str = "cat_session";
+
+/// Sessions idle for longer than this are forgotten
+pub const MAX_IDLE_DAYS: i64 = 7;
+
+/// How many offered topics a session remembers, so older buttons keep working
+const MAX_OFFERED_TOPICS: usize = 20;
+
+/// One visitor's conversation with the cat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_active: DateTime<Utc>,
+    pub conversation: Conversation,
+    /// Every topic suggested so far, oldest first
+    #[serde(default)]
+    pub offered_topics: Vec<Topic>,
+    /// The topic the visitor chose to explore, if any
+    #[serde(default)]
+    pub current_topic: Option<Topic>,
+    /// Whether an account signs in to this session, which keeps it however long it idles
+    #[serde(default)]
+    pub held: bool,
+}
+
+impl ChatSession {
+    fn new(id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: id.to_string(),
+            created_at: now,
+            last_active: now,
+            conversation: MistralClient::cat_conversation(),
+            offered_topics: Vec::new(),
+            current_topic: None,
+            held: false,
+        }
+    }
+
+    /// Number of messages the visitor has sent
+    pub fn user_message_count(&self) -> usize {
+        self.conversation.messages.iter().filter(|m| m.role == "user").count()
+    }
+
+    /// Total length of the visitor's messages, in characters
+    pub fn user_character_count(&self) -> usize {
+        self.conversation
+            .messages
+            .iter()
+            .filter(|m| m.role == "user")
+            .map(|m| m.content.chars().count())
+            .sum()
+    }
+}
+
+/// All live chat sessions, backed by a JSON file
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    sessions: JsonFile<HashMap<String, ChatSession>>,
+}
+
+impl SessionStore {
+    /// Opens the store at `path`, starting empty if the file doesn't exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self { sessions: JsonFile::open(path)? })
+    }
+
+    /// The conversation so far for `id`, or a fresh one if the session is unknown
+    pub async fn history(&self, id: &str) -> Conversation {
+        self.sessions
+            .read(|sessions| match sessions.get(id) {
+                Some(session) => session.conversation.clone(),
+                None => MistralClient::cat_conversation(),
+            })
+            .await
+    }
+
+    /// Appends a completed exchange to the session, creating it if needed
+    pub async fn record_exchange(&self, id: &str, user_message: &str, reply: &str) {
+        self.sessions
+            .update(|sessions| {
+                if !sessions.contains_key(id) {
+                    // New sessions are rare enough to be a good moment for housekeeping
+                    let cutoff = Utc::now() - Duration::days(MAX_IDLE_DAYS);
+                    sessions.retain(|_, session| session.held || session.last_active >= cutoff);
+                }
+
+                let session = sessions.entry(id.to_string()).or_insert_with(|| ChatSession::new(id));
+                session.conversation.add_user_message(user_message);
+                session.conversation.add_assistant_message(reply);
+                session.last_active = Utc::now();
+            })
+            .await;
+    }
+
+    /// Number of messages the visitor has sent in this session
+    pub async fn user_message_count(&self, id: &str) -> usize {
+        self.sessions.read(|sessions| sessions.get(id).map_or(0, ChatSession::user_message_count)).await
+    }
+
+    /// The topic steering this session, if the visitor chose one
+    pub async fn current_topic(&self, id: &str) -> Option<Topic> {
+        self.sessions
+            .read(|sessions| sessions.get(id).and_then(|session| session.current_topic.clone()))
+            .await
+    }
+
+    /// Makes the offered topic `topic_id` the session's current topic
+    ///
+    /// Returns `None`, leaving the current topic as it was, if the session
+    /// was never offered a topic with that id.
+    pub async fn choose_topic(&self, id: &str, topic_id: &str) -> Option<Topic> {
+        self.sessions
+            .update(|sessions| {
+                let session = sessions.get_mut(id)?;
+                let topic = session.offered_topics.iter().find(|t| t.id == topic_id)?.clone();
+                session.current_topic = Some(topic.clone());
+                Some(topic)
+            })
+            .await
+    }
+
+    /// Remembers newly suggested topics, replacing older ones with the same id
+    pub async fn offer_topics(&self, id: &str, topics: &[Topic]) {
+        self.sessions
+            .update(|sessions| {
+                if let Some(session) = sessions.get_mut(id) {
+                    session.offered_topics.retain(|offered| !topics.iter().any(|t| t.id == offered.id));
+                    session.offered_topics.extend(topics.iter().cloned());
+                    let excess = session.offered_topics.len().saturating_sub(MAX_OFFERED_TOPICS);
+                    session.offered_topics.drain(..excess);
+                }
+            })
+            .await;
+    }
+
+    /// Marks the session `id` as an account's, creating it if needed, so it
+    /// is never dropped for being idle
+    pub async fn hold(&self, id: &str) {
+        self.sessions
+            .update(|sessions| sessions.entry(id.to_string()).or_insert_with(|| ChatSession::new(id)).held = true)
+            .await;
+    }
+
+    /// Moves the session `from` to the id `to`, replacing any session there
+    pub async fn transfer(&self, from: &str, to: &str) {
+        self.sessions
+            .update(|sessions| {
+                if let Some(mut session) = sessions.remove(from) {
+                    session.id = to.to_string();
+                    sessions.insert(to.to_string(), session);
+                }
+            })
+            .await;
+    }
+
+    /// Forgets the session `id`; returns whether there was one
+    pub async fn remove(&self, id: &str) -> bool {
+        self.sessions.update(|sessions| sessions.remove(id).is_some()).await
+    }
+
+    /// The most recently active sessions, newest first
+    pub async fn recent(&self, limit: usize) -> Vec<ChatSession> {
+        let mut sessions: Vec<_> = self.sessions.read(|sessions| sessions.values().cloned().collect()).await;
+        sessions.sort_by_key(|session| Reverse(session.last_active));
+        sessions.truncate(limit);
+        sessions
+    }
+
+    /// Forgets sessions idle since before `cutoff`, except those held by an
+    /// account, or every session if `None`; returns how many were removed
+    pub async fn purge(&self, cutoff: Option<DateTime<Utc>>) -> usize {
+        self.sessions
+            .update(|sessions| {
+                let before = sessions.len();
+                match cutoff {
+                    Some(cutoff) => sessions.retain(|_, session| session.held || session.last_active >= cutoff),
+                    None => sessions.clear(),
+                }
+                before - sessions.len()
+            })
+            .await
+    }
+
+    /// Every session, for backups
+    pub async fn all(&self) -> HashMap<String, ChatSession> {
+        self.sessions.read(HashMap::clone).await
+    }
+
+    /// Adds `imported` sessions, replacing those with the same ids; with
+    /// `replace`, everything not in `imported` is dropped first
+    pub async fn import(&self, imported: HashMap<String, ChatSession>, replace: bool) {
+        self.sessions
+            .update(|sessions| {
+                if replace {
+                    sessions.clear();
+                }
+                sessions.extend(imported);
+            })
+            .await;
+    }
+
+    /// Number of live sessions
+    pub async fn len(&self) -> usize {
+        self.sessions.read(HashMap::len).await
+    }
+
+    /// Whether there are no live sessions
+    pub async fn is_empty(&self) -> bool {
+        self.sessions.read(HashMap::is_empty).await
+    }
+}
+
+/// A new random session id
+pub fn new_session_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// The session a web request belongs to
+///
+/// A signed-in visitor's is their account's. Otherwise it is read from the
+/// `cat_session` cookie, or a new id is minted when that is missing,
+/// malformed or names an account's session: those are only reached through
+/// the signed `cat_account` cookie. Handlers should send `set_cookie()` back
+/// so the browser keeps it.
+#[derive(Debug, Clone)]
+pub struct WebSession {
+    pub id: String,
+    pub is_new: bool,
+}
+
+impl WebSession {
+    /// Headers that (re)store the session cookie
+    ///
+    /// Sent on every request with activity, not only new ones, so the
+    /// cookie's `Max-Age` keeps pace with the idle sweep: a visitor who keeps
+    /// chatting keeps their conversation and readings.
+    pub fn set_cookie(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let cookie = format!(
+            "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+            SESSION_COOKIE,
+            self.id,
+            MAX_IDLE_DAYS * 24 * 60 * 60
+        );
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            headers.insert(SET_COOKIE, value);
+        }
+        headers
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for WebSession {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        if let Some(account) = state.accounts.from_headers(&parts.headers).await {
+            return Ok(Self { id: account.session_id, is_new: false });
+        }
+        let existing = cookies::get(&parts.headers, SESSION_COOKIE)
+            .filter(|id| id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()));
+
+        Ok(match existing {
+            Some(id) if !state.accounts.holds_session(&id).await => Self { id, is_new: false },
+            _ => Self { id: new_session_id(), is_new: true },
+        })
+    }
+}
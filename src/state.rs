@@ -8,142 +8,373 @@
 //! but adapted for a multi-threaded server environment.
 
 // Import necessary dependencies:
-// - anyhow: For flexible error handling with the Result type
 // - std::sync::Arc: Atomic Reference Counting for thread-safe sharing
-// - tokio::sync::RwLock: Async-aware read-write lock for concurrent access
+// - tokio::sync::Mutex: Async-aware lock serializing generation per kind of content
 use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::Mutex;
+use tracing::warn;
 
 // Import our configuration and Mistral API client
+use crate::accounts::Accounts;
+use crate::audit::AuditLog;
 use crate::config::Config;
-use crate::mistral::MistralClient;
+use crate::corpus::{Corpus, Provenance};
+use crate::history::WisdomHistory;
+use crate::json_file::JsonFile;
+use crate::metrics::Metrics;
+use crate::mistral::{LlmProvider, MistralClient};
+use crate::og::OgCards;
+use crate::safety::SafetyRules;
+use crate::sessions::SessionStore;
 use crate::quantum_field::QuantumField;
+use crate::readings::ReadingStore;
+use crate::activitypub::Federation;
+use crate::newsletter::Newsletter;
+use crate::telegram::Telegram;
+use crate::webpush::WebPush;
+use crate::webhooks::Webhooks;
+
+/// How long a date's content comes from the corpus after generation failed,
+/// before the model is asked again
+const FALLBACK_RETRY_SECS: i64 = 60;
+
+/// When generation may be tried again, per kind of content and date
+type RetryTimes = HashMap<(&'static str, NaiveDate), DateTime<Utc>>;
+
+/// A day's wisdom together with where it came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyWisdom {
+    /// The Whispurr text
+    pub text: String,
+    /// Whether the text was generated, taken from the offline corpus, or curated by an admin
+    pub provenance: Provenance,
+    /// When this text was settled on; changes when an admin regenerates or edits it
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DailyWisdom {
+    pub fn new(text: String, provenance: Provenance) -> Self {
+        Self { text, provenance, updated_at: Utc::now() }
+    }
+}
 
 /// The central application state that is shared across all request handlers
 /// 
 /// This struct holds:
 /// - A shared Mistral API client for generating wisdom and chat responses
-/// - A cached daily wisdom quote to avoid repeated API calls
-/// - A timestamp of when the wisdom was last updated
+/// - The daily wisdom per calendar date, kept on disk as the wisdom history
+/// - The quantum field per calendar date, kept on disk in the same way
+/// - Every collapse of the quantum field, as a shareable reading
+/// - The offline corpus used whenever generation fails or the daily token
+///   budget is spent; its picks are never saved, so the model is asked again
+///   a minute later
+/// - Chat sessions, operational metrics and the admin audit log
+/// - The webhook targets the daily wisdom is posted to
+/// 
+/// Daily content is keyed by the *visitor's* local date (see `timezone.rs`),
+/// so a visitor in Berlin and one in New York may briefly see different days.
+/// Each date is generated at most once, however many zones ask for it.
 /// 
 /// The `#[derive(Clone)]` attribute allows this struct to be cloned,
 /// which is necessary for sharing it with Axum's routing system.
 #[derive(Clone)]
 pub struct AppState {
+    /// The configuration this instance was built with
+    pub config: Arc<Config>,
+    
     /// The Mistral API client wrapped in Arc for thread-safe sharing
     pub mistral_client: Arc<MistralClient>,
     
-    /// The cached daily wisdom, wrapped in Arc<RwLock> for thread-safe access
-    /// Option<String> means it can be None (not yet fetched) or Some(wisdom)
-    pub daily_wisdom: Arc<RwLock<Option<String>>>,
+    /// The offline corpus of whispurrs and seeds used as a fallback
+    pub corpus: Arc<Corpus>,
+    
+    /// Patterns and replies used to screen chat messages
+    pub safety: Arc<SafetyRules>,
+    
+    /// Every day's wisdom so far, persisted in the data directory (see `history.rs`)
+    pub wisdom_history: WisdomHistory,
+    
+    /// The quantum field per calendar date, persisted like the wisdom history
+    pub quantum_fields: JsonFile<BTreeMap<NaiveDate, QuantumField>>,
+    
+    /// Every collapse of the quantum field, persisted so it can be shared (see `readings.rs`)
+    pub readings: ReadingStore,
+    
+    /// Share preview images, cached in the data directory (see `og.rs`)
+    pub og_cards: OgCards,
+    
+    /// Every visitor's conversation with the cat, persisted across restarts
+    pub sessions: SessionStore,
+    
+    /// Token usage and fallback events, shown in the admin console
+    pub metrics: Arc<Metrics>,
+    
+    /// Record of everything done through the admin console
+    pub audit: AuditLog,
     
-    /// The cached quantum field, wrapped in Arc<RwLock<>> for thread-safe access
-    pub quantum_field: Arc<RwLock<Option<QuantumField>>>,
+    /// Where the daily wisdom is posted, and what was sent (see `webhooks.rs`)
+    pub webhooks: Webhooks,
     
-    /// Timestamp of when the wisdom was last updated, for determining refresh needs
-    pub wisdom_last_updated: Arc<RwLock<Option<chrono::DateTime<chrono::Utc>>>>,
+    /// HTTP client for calls back to chat platforms, such as Slack's response URLs
+    pub http: reqwest::Client,
+    
+    /// The Telegram bot's API client and daily push subscriptions (see `telegram.rs`)
+    pub telegram: Telegram,
+    
+    /// The cat's fediverse actor, if ActivityPub is enabled (see `activitypub.rs`)
+    pub federation: Option<Federation>,
+    
+    /// The mailing list and its SMTP transport (see `newsletter.rs`)
+    pub newsletter: Newsletter,
+    
+    /// The VAPID key and browser push subscriptions, if Web Push is enabled (see `webpush.rs`)
+    pub web_push: Option<WebPush>,
+    
+    /// Optional visitor accounts and the key that signs their cookies (see `accounts.rs`)
+    pub accounts: Accounts,
+    
+    /// Token embedded in admin forms to reject cross-site submissions
+    pub admin_csrf_token: Arc<str>,
+    
+    /// Serialize generation so concurrent visitors don't trigger duplicate API calls for the same date
+    wisdom_generation: Arc<Mutex<()>>,
+    field_generation: Arc<Mutex<()>>,
+    
+    /// When generation may be tried again for the dates that fell back to the corpus,
+    /// keyed like the metrics' fallback events (`daily_wisdom` or `quantum_field`)
+    retry_after: Arc<std::sync::Mutex<RetryTimes>>,
 }
 
 impl AppState {
     /// Creates a new instance of the application state
     /// 
-    /// This is called once when the server starts up. It:
-    /// 1. Initializes the configuration (loading environment variables)
-    /// 2. Creates the Mistral API client
-    /// 3. Sets up empty state for wisdom caching
-    /// 
-    /// Returns a Result that contains the AppState if successful
-    pub async fn new() -> Result<Self> {
-        // Initialize config (loads environment variables)
-        Config::init()?;
+    /// This is called once when the app is built (see `App::builder`). It:
+    /// 1. Wraps the injected configuration for sharing
+    /// 2. Creates the Mistral client on top of the injected provider
+    /// 3. Opens the wisdom history, quantum fields, readings, chat sessions,
+    ///    audit log, webhook delivery log, Telegram subscriptions, the
+    ///    fediverse actor's key and followers, the newsletter's subscribers,
+    ///    the VAPID key and push subscriptions, and the accounts and their
+    ///    cookie key in the data directory
+    pub fn new(
+        config: Config,
+        provider: Arc<dyn LlmProvider>,
+        corpus: Corpus,
+        safety: SafetyRules,
+    ) -> Result<Self> {
+        let metrics = Arc::new(Metrics::new());
+        let data_dir = Path::new(&config.data_dir);
+        let wisdom_history = WisdomHistory::open(data_dir.join("wisdom.json"))?;
+        let quantum_fields = JsonFile::open(data_dir.join("fields.json"))?;
+        let readings = ReadingStore::open(data_dir.join("readings.json"))?.with_limit(config.max_readings);
+        let og_cards = OgCards::new(data_dir.join("og"));
+        let sessions = SessionStore::open(data_dir.join("sessions.json"))?;
+        let audit = AuditLog::open(data_dir.join("audit.jsonl"))?;
+        let webhooks = Webhooks::open(&config, data_dir.join("deliveries.json"))?;
+        let http = reqwest::Client::builder().timeout(std::time::Duration::from_secs(10)).build()?;
+        let telegram = Telegram::open(&config, &data_dir.join("telegram.json"), http.clone())?;
+        let federation = Federation::open(&config, data_dir)?;
+        let newsletter = Newsletter::open(&config, &data_dir.join("newsletter.json"))?;
+        let web_push = WebPush::open(&config, data_dir)?;
+        let accounts = Accounts::open(&config, data_dir)?;
+        let mistral_client = MistralClient::new(provider, metrics.clone()).with_token_budget(config.daily_token_budget);
         
-        // Create Mistral client for API interactions
-        let mistral_client = Arc::new(MistralClient::new());
+        let mut csrf = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut csrf);
         
-        // Return the initialized state
         Ok(Self {
-            mistral_client,
-            daily_wisdom: Arc::new(RwLock::new(None)),  // Start with no cached wisdom
-            quantum_field: Arc::new(RwLock::new(None)),  // Start with no cached quantum field
-            wisdom_last_updated: Arc::new(RwLock::new(None)),  // No update timestamp yet
+            config: Arc::new(config),
+            mistral_client: Arc::new(mistral_client),
+            corpus: Arc::new(corpus),
+            safety: Arc::new(safety),
+            wisdom_history,
+            quantum_fields,
+            readings,
+            og_cards,
+            sessions,
+            metrics,
+            audit,
+            webhooks,
+            http,
+            telegram,
+            federation,
+            newsletter,
+            web_push,
+            accounts,
+            admin_csrf_token: hex::encode(csrf).into(),
+            wisdom_generation: Arc::new(Mutex::new(())),
+            field_generation: Arc::new(Mutex::new(())),
+            retry_after: Arc::new(std::sync::Mutex::new(HashMap::new())),
         })
     }
 
-    /// Gets the daily wisdom, refreshing it if necessary
+    /// Gets the daily wisdom for the given calendar date, generating it if necessary
     /// 
     /// This method implements a caching strategy where:
-    /// - If wisdom hasn't been fetched yet, it fetches it
-    /// - If it's a new day since the last fetch, it refreshes the wisdom
-    /// - Otherwise, it returns the cached wisdom
+    /// - If wisdom for this date has already been settled, it is returned as-is
+    /// - Otherwise it is generated once and cached under that date
+    /// - If generation fails, the corpus pick for that date stands in without
+    ///   being saved, and generation is tried again after `FALLBACK_RETRY_SECS`
     /// 
-    /// This reduces API calls and improves performance.
-    pub async fn get_daily_wisdom(&self) -> Result<String> {
-        // Get current time for comparison
-        let now = chrono::Utc::now();
-        
-        // Check if we need to refresh the wisdom
-        let should_refresh = {
-            // Acquire a read lock on the last_updated timestamp
-            let last_updated = self.wisdom_last_updated.read().await;
-            
-            // Determine if refresh is needed based on timestamp
-            match *last_updated {
-                Some(time) => {
-                    // Check if it's a new day or if wisdom hasn't been set
-                    now.date_naive() != time.date_naive()
-                }
-                None => true,  // No timestamp means we need to fetch wisdom
-            }
-        };
-
-        if should_refresh {
-            // Generate new wisdom
-            let new_wisdom = self.mistral_client.get_daily_wisdom().await?;
-            
-            // Update wisdom and timestamp
-            let mut wisdom = self.daily_wisdom.write().await;
-            *wisdom = Some(new_wisdom.clone());
-            
-            let mut last_updated = self.wisdom_last_updated.write().await;
-            *last_updated = Some(now);
-            
-            Ok(new_wisdom)
-        } else {
-            // Return cached wisdom
-            let wisdom = self.daily_wisdom.read().await;
-            match &*wisdom {
-                Some(w) => Ok(w.clone()),
-                None => {
-                    // This shouldn't happen, but just in case
-                    let new_wisdom = self.mistral_client.get_daily_wisdom().await?;
-                    Ok(new_wisdom)
-                }
+    /// Callers pass the visitor's local date (`VisitorZone::today()`), so the
+    /// rollover happens at local midnight rather than UTC midnight.
+    pub async fn get_daily_wisdom(&self, date: NaiveDate) -> DailyWisdom {
+        // Fast path: wisdom for this date already exists
+        if let Some(wisdom) = self.settled_wisdom(date).await {
+            return wisdom;
+        }
+
+        // Slow path: take the generation lock, then check again in case another
+        // request generated this date while we were waiting
+        let _guard = self.wisdom_generation.lock().await;
+        if let Some(wisdom) = self.settled_wisdom(date).await {
+            return wisdom;
+        }
+        if self.retry_pending("daily_wisdom", date) {
+            return DailyWisdom::new(self.corpus.whispurr_for(date), Provenance::Corpus);
+        }
+
+        let wisdom = self.generate_daily_wisdom(date).await;
+        if wisdom.provenance != Provenance::Corpus {
+            self.wisdom_history.insert(date, wisdom.clone()).await;
+        }
+
+        wisdom
+    }
+
+    /// The wisdom for `date` to send out by webhook, Telegram, the fediverse,
+    /// mail or Web Push, or `None` while only the corpus can stand in
+    ///
+    /// A corpus pick isn't saved and gives way once generation succeeds, so
+    /// sending it would put a Whispurr out that the site no longer shows.
+    /// Schedulers leave the date unsent and try again on a later tick.
+    pub async fn broadcast_wisdom(&self, date: NaiveDate) -> Option<DailyWisdom> {
+        let wisdom = self.get_daily_wisdom(date).await;
+        (wisdom.provenance != Provenance::Corpus).then_some(wisdom)
+    }
+
+    /// Generates the wisdom for `date` afresh, replacing what was there unless
+    /// generation fails
+    pub async fn regenerate_daily_wisdom(&self, date: NaiveDate) -> DailyWisdom {
+        let _guard = self.wisdom_generation.lock().await;
+        let wisdom = self.generate_daily_wisdom(date).await;
+        if wisdom.provenance != Provenance::Corpus {
+            self.wisdom_history.insert(date, wisdom.clone()).await;
+        }
+        wisdom
+    }
+
+    /// The saved wisdom for `date`; corpus picks saved by earlier versions don't count
+    async fn settled_wisdom(&self, date: NaiveDate) -> Option<DailyWisdom> {
+        self.wisdom_history.get(date).await.filter(|wisdom| wisdom.provenance != Provenance::Corpus)
+    }
+
+    /// Replaces the wisdom for `date` with hand-written text
+    pub async fn set_daily_wisdom(&self, date: NaiveDate, text: String) -> DailyWisdom {
+        let wisdom = DailyWisdom::new(text, Provenance::Curated);
+        self.wisdom_history.insert(date, wisdom.clone()).await;
+        wisdom
+    }
+
+    /// Generates wisdom for `date`, falling back to the corpus if the API fails
+    async fn generate_daily_wisdom(&self, date: NaiveDate) -> DailyWisdom {
+        match self.mistral_client.get_daily_wisdom().await {
+            Ok(text) => DailyWisdom::new(text, Provenance::Generated),
+            Err(err) => {
+                warn!("Daily wisdom generation failed for {}, using corpus: {:?}", date, err);
+                self.metrics.record_fallback("daily_wisdom", date, &err);
+                self.retry_later("daily_wisdom", date);
+                DailyWisdom::new(self.corpus.whispurr_for(date), Provenance::Corpus)
             }
         }
     }
     
     // Quantum Wisdom method removed - replaced by Quantum Field
     
-    /// Get the 6-fold quantum field
+    /// Get the 6-fold quantum field for the given calendar date
     /// 
-    /// This method returns a quantum field with 6 wisdom nodes representing different dimensions
-    pub async fn get_quantum_field(&self) -> Result<QuantumField> {
-        // Check if we already have cached quantum field
-        {
-            let field = self.quantum_field.read().await;
-            if let Some(ref f) = *field {
-                return Ok(f.clone());
-            }
+    /// This method returns a quantum field with 6 wisdom nodes representing different dimensions.
+    /// Like the daily wisdom, one field is settled per calendar date, and the
+    /// corpus seeds for that date stand in, unsaved, while the model's answer is unusable.
+    pub async fn get_quantum_field(&self, date: NaiveDate) -> QuantumField {
+        // Check if we already have a quantum field for this date
+        if let Some(field) = self.settled_field(date).await {
+            return field;
+        }
+
+        let _guard = self.field_generation.lock().await;
+        if let Some(field) = self.settled_field(date).await {
+            return field;
+        }
+        if self.retry_pending("quantum_field", date) {
+            return QuantumField::new(self.corpus.seeds_for(date), Provenance::Corpus);
         }
         
-        // Generate new quantum field
-        let new_field = self.mistral_client.get_quantum_field().await?;
-        
-        // Cache the new field
-        let mut field = self.quantum_field.write().await;
-        *field = Some(new_field.clone());
+        // Generate new quantum field and keep it, unless it came from the corpus
+        let new_field = self.generate_quantum_field(date).await;
+        if new_field.provenance != Provenance::Corpus {
+            self.quantum_fields.update(|fields| fields.insert(date, new_field.clone())).await;
+        }
         
-        Ok(new_field)
+        new_field
+    }
+
+    /// Generates the quantum field for `date` afresh, replacing what was there
+    /// unless generation fails
+    pub async fn regenerate_quantum_field(&self, date: NaiveDate) -> QuantumField {
+        let _guard = self.field_generation.lock().await;
+        let field = self.generate_quantum_field(date).await;
+        if field.provenance != Provenance::Corpus {
+            self.quantum_fields.update(|fields| fields.insert(date, field.clone())).await;
+        }
+        field
+    }
+
+    /// The saved quantum field for `date`; corpus seeds saved by earlier versions don't count
+    async fn settled_field(&self, date: NaiveDate) -> Option<QuantumField> {
+        self.quantum_fields
+            .read(|fields| fields.get(&date).filter(|field| field.provenance != Provenance::Corpus).cloned())
+            .await
+    }
+
+    /// Replaces the quantum field for `date` with hand-written seeds
+    pub async fn set_quantum_field(&self, date: NaiveDate, seeds: Vec<String>) -> QuantumField {
+        let field = QuantumField::new(seeds, Provenance::Curated);
+        self.quantum_fields.update(|fields| fields.insert(date, field.clone())).await;
+        field
+    }
+
+    /// Generates a quantum field for `date`, falling back to the corpus if the API fails
+    async fn generate_quantum_field(&self, date: NaiveDate) -> QuantumField {
+        match self.mistral_client.get_quantum_field().await {
+            Ok(seeds) => QuantumField::new(seeds, Provenance::Generated),
+            Err(err) => {
+                warn!("Quantum field generation failed for {}, using corpus: {:?}", date, err);
+                self.metrics.record_fallback("quantum_field", date, &err);
+                self.retry_later("quantum_field", date);
+                QuantumField::new(self.corpus.seeds_for(date), Provenance::Corpus)
+            }
+        }
+    }
+
+    /// Holds off generating `source` for `date` for `FALLBACK_RETRY_SECS`, so
+    /// an outage doesn't make every request wait for the API to time out
+    fn retry_later(&self, source: &'static str, date: NaiveDate) {
+        let at = Utc::now() + Duration::seconds(FALLBACK_RETRY_SECS);
+        self.retry_after.lock().unwrap().insert((source, date), at);
+    }
+
+    /// Whether `source` for `date` failed too recently to try again
+    fn retry_pending(&self, source: &'static str, date: NaiveDate) -> bool {
+        let mut retry_after = self.retry_after.lock().unwrap();
+        let now = Utc::now();
+        retry_after.retain(|_, at| *at > now);
+        retry_after.contains_key(&(source, date))
     }
 }
@@ -0,0 +1,232 @@
+//! # Telegram Bot
+//!
+//! Calls to the Telegram Bot API, and the daily push chats can opt in to with
+//! `/daily HH:MM [zone]` (see `routes/telegram.rs` for the bot's commands).
+//!
+//! Subscriptions are kept in `DATA_DIR/telegram.json`, one per chat, with the
+//! chat's local time and zone and the last date pushed. Once a chat's local
+//! time has passed, the scheduler sends it that local date's wisdom, once per
+//! date; a chat that subscribes after its time starts the next day. A push
+//! that fails (the bot was blocked, say) is not retried.
+//!
+//! Everything is off without `TELEGRAM_BOT_TOKEN`. `TELEGRAM_API_URL` points
+//! the bot at another Bot API server, such as a mock in tests.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::json_file::JsonFile;
+use crate::state::{AppState, DailyWisdom};
+use crate::webhooks::DISPLAY_NAME;
+
+/// How often the scheduler looks for chats that are due
+const TICK: Duration = Duration::from_secs(30);
+
+/// A chat's opt-in to the daily push
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub chat_id: i64,
+    /// Local time of day after which the day's wisdom is sent
+    pub time: NaiveTime,
+    pub timezone: Tz,
+    /// The last local date whose wisdom was sent, or skipped on subscribing
+    pub last_sent: Option<NaiveDate>,
+}
+
+impl Subscription {
+    /// The local date whose wisdom is due at `now`, if the chat's time has
+    /// come and it hasn't had that date yet
+    pub fn due_date(&self, now: DateTime<Utc>) -> Option<NaiveDate> {
+        let local = now.with_timezone(&self.timezone);
+        let date = local.date_naive();
+        (local.time() >= self.time && self.last_sent.is_none_or(|sent| sent < date)).then_some(date)
+    }
+}
+
+/// The bot's API client and the chats subscribed to the daily push
+#[derive(Clone)]
+pub struct Telegram {
+    /// `{TELEGRAM_API_URL}/bot{token}`, if a token is configured
+    api: Option<String>,
+    subscriptions: JsonFile<Vec<Subscription>>,
+    http: reqwest::Client,
+}
+
+impl Telegram {
+    /// Opens the subscriptions at `path`; API calls go through `http`
+    pub fn open(config: &Config, path: &Path, http: reqwest::Client) -> Result<Self> {
+        Ok(Self {
+            api: config
+                .telegram_bot_token
+                .as_ref()
+                .map(|token| format!("{}/bot{}", config.telegram_api_url, token)),
+            subscriptions: JsonFile::open(path)?,
+            http,
+        })
+    }
+
+    /// Whether a bot token is configured
+    pub fn enabled(&self) -> bool {
+        self.api.is_some()
+    }
+
+    /// Calls the Bot API `method` with a JSON body
+    pub async fn call(&self, method: &str, body: &Value) -> Result<()> {
+        let Some(api) = &self.api else {
+            anyhow::bail!("TELEGRAM_BOT_TOKEN is not set");
+        };
+        let response = self.http.post(format!("{}/{}", api, method)).json(body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            // Failed calls explain themselves in `description`
+            let description = response.json::<Value>().await.ok().and_then(|body| body["description"].as_str().map(String::from));
+            anyhow::bail!("Telegram answered {} to {}: {}", status, method, description.unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    /// Sends plain `text` to a chat, logging failures
+    pub async fn send_text(&self, chat_id: i64, text: &str) {
+        self.send(&json!({ "chat_id": chat_id, "text": text })).await;
+    }
+
+    /// Sends a `sendMessage` body, logging failures: there is nobody to tell
+    pub async fn send(&self, message: &Value) {
+        if let Err(err) = self.call("sendMessage", message).await {
+            warn!("Failed to send a Telegram message: {:#}", err);
+        }
+    }
+
+    /// The chat's subscription, if it has one
+    pub async fn subscription(&self, chat_id: i64) -> Option<Subscription> {
+        self.subscriptions.read(|subscriptions| subscriptions.iter().find(|s| s.chat_id == chat_id).cloned()).await
+    }
+
+    /// All subscriptions, in the order chats subscribed
+    pub async fn subscriptions(&self) -> Vec<Subscription> {
+        self.subscriptions.read(Clone::clone).await
+    }
+
+    /// Adds `imported` subscriptions, replacing those of the same chats; with
+    /// `replace`, every other subscription is dropped first
+    pub async fn import(&self, imported: Vec<Subscription>, replace: bool) {
+        self.subscriptions
+            .update(|subscriptions| {
+                if replace {
+                    subscriptions.clear();
+                }
+                subscriptions.retain(|s| !imported.iter().any(|i| i.chat_id == s.chat_id));
+                subscriptions.extend(imported);
+            })
+            .await;
+    }
+
+    /// Subscribes a chat, or moves its time; a time that already passed today
+    /// takes effect tomorrow
+    pub async fn subscribe(&self, chat_id: i64, time: NaiveTime, timezone: Tz, now: DateTime<Utc>) -> Subscription {
+        let local = now.with_timezone(&timezone);
+        let passed = (local.time() >= time).then_some(local.date_naive());
+
+        self.subscriptions
+            .update(move |subscriptions| {
+                let previous = subscriptions.iter().position(|s| s.chat_id == chat_id).map(|i| subscriptions.remove(i));
+                let subscription = Subscription {
+                    chat_id,
+                    time,
+                    timezone,
+                    // Moving the time later on a day that was already sent mustn't send it twice
+                    last_sent: passed.max(previous.and_then(|previous| previous.last_sent)),
+                };
+                subscriptions.push(subscription.clone());
+                subscription
+            })
+            .await
+    }
+
+    /// Drops a chat's subscription; whether it had one
+    pub async fn unsubscribe(&self, chat_id: i64) -> bool {
+        self.subscriptions
+            .update(|subscriptions| {
+                let before = subscriptions.len();
+                subscriptions.retain(|s| s.chat_id != chat_id);
+                subscriptions.len() < before
+            })
+            .await
+    }
+
+    async fn mark_sent(&self, chat_id: i64, date: NaiveDate) {
+        self.subscriptions
+            .update(|subscriptions| {
+                if let Some(subscription) = subscriptions.iter_mut().find(|s| s.chat_id == chat_id) {
+                    subscription.last_sent = Some(date);
+                }
+            })
+            .await
+    }
+}
+
+/// The `sendMessage` body for a day's wisdom
+pub fn wisdom_message(chat_id: i64, date: NaiveDate, wisdom: &DailyWisdom, public_url: &str) -> Value {
+    json!({
+        "chat_id": chat_id,
+        "text": format!(
+            "{}\n\n— {}, {}\n{}/wisdom/{}",
+            wisdom.text,
+            DISPLAY_NAME,
+            date.format("%B %-d, %Y"),
+            public_url,
+            date,
+        ),
+        "disable_web_page_preview": true,
+    })
+}
+
+/// Sends the wisdom to every subscribed chat that is due at `now`, and
+/// returns the chats it was sent to
+pub async fn push_due(state: &AppState, now: DateTime<Utc>) -> Vec<i64> {
+    let mut pushed = Vec::new();
+    for subscription in state.telegram.subscriptions().await {
+        let Some(date) = subscription.due_date(now) else {
+            continue;
+        };
+
+        // Left unsent while only the corpus stands in, to be pushed on a later tick
+        let Some(wisdom) = state.broadcast_wisdom(date).await else {
+            continue;
+        };
+        // Marked first, so a failing chat isn't retried every tick
+        state.telegram.mark_sent(subscription.chat_id, date).await;
+        let message = wisdom_message(subscription.chat_id, date, &wisdom, &state.config.public_url);
+        match state.telegram.call("sendMessage", &message).await {
+            Ok(()) => pushed.push(subscription.chat_id),
+            Err(err) => warn!("Failed to push {} wisdom to Telegram chat {}: {:#}", date, subscription.chat_id, err),
+        }
+    }
+    pushed
+}
+
+/// Starts the background task that sends the daily push, if a bot is configured
+pub fn spawn_scheduler(state: AppState) -> Option<JoinHandle<()>> {
+    if !state.telegram.enabled() {
+        return None;
+    }
+    info!("Sending the daily wisdom to subscribed Telegram chats");
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            push_due(&state, Utc::now()).await;
+        }
+    }))
+}
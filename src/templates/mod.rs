@@ -1,21 +1,285 @@
 use askama::Template;
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+
+use crate::audit::AuditEntry;
+use crate::config::Config;
+use crate::corpus::Provenance;
+use crate::metrics::{FallbackEvent, SafetyEvent, UsageTotals};
+use crate::quantum_field::QuantumField;
+use crate::readings::{Reading, Visibility};
+use crate::routes::admin::SessionSummary;
+use crate::state::DailyWisdom;
+
+/// Name used for the site in structured data
+const SITE_NAME: &str = "The Enlightened Cat";
+
+/// The stylesheet every page links; bump `?v=` when changing it, so browsers
+/// and the service worker's cache (see `routes/offline.rs`) fetch it afresh
+pub const STYLESHEET: &str = "/static/css/styles.css?v=20261019";
+
+/// The script every page built on `base.html` runs; versioned like `STYLESHEET`
+pub const MAIN_SCRIPT: &str = "/static/js/main.js?v=20250521";
+
+/// What every page built on `base.html` needs besides its own content
+pub struct SiteContext {
+    /// Public base URL without a trailing slash, for canonical and share links
+    pub public_url: String,
+    /// JSON-LD documents for the page head, serialized and safe to embed in a `<script>`
+    pub structured_data: Vec<String>,
+}
+
+impl SiteContext {
+    /// The context for a page with no structured data of its own
+    pub fn new(config: &Config) -> Self {
+        Self { public_url: config.public_url.clone(), structured_data: Vec::new() }
+    }
+
+    /// Adds a JSON-LD document to the page
+    fn with_structured_data(mut self, document: Value) -> Self {
+        // `</script>` inside a string would end the element early
+        self.structured_data.push(document.to_string().replace("</", "<\\/"));
+        self
+    }
+
+    /// Adds schema.org `WebSite` for the home page, with the search box search engines can offer
+    pub fn with_website(self) -> Self {
+        let document = json!({
+            "@context": "https://schema.org",
+            "@type": "WebSite",
+            "name": SITE_NAME,
+            "url": format!("{}/", self.public_url),
+            "potentialAction": {
+                "@type": "SearchAction",
+                "target": {
+                    "@type": "EntryPoint",
+                    "urlTemplate": format!("{}/search?q={{search_term_string}}", self.public_url),
+                },
+                "query-input": "required name=search_term_string",
+            },
+        });
+        self.with_structured_data(document)
+    }
+
+    /// Adds schema.org `CreativeWork` for one day's Whispurr
+    pub fn with_whispurr(self, date: NaiveDate, wisdom: &DailyWisdom) -> Self {
+        let document = json!({
+            "@context": "https://schema.org",
+            "@type": "CreativeWork",
+            "name": format!("Daily Whispurr for {}", date.format("%B %-d, %Y")),
+            "text": wisdom.text,
+            "url": format!("{}/wisdom/{}", self.public_url, date),
+            "image": format!("{}/og/wisdom/{}.png", self.public_url, date),
+            "datePublished": date.to_string(),
+            "dateModified": wisdom.updated_at.to_rfc3339(),
+            "inLanguage": "en",
+            "author": { "@type": "Organization", "name": SITE_NAME },
+            "isPartOf": { "@type": "WebSite", "name": SITE_NAME, "url": format!("{}/", self.public_url) },
+        });
+        self.with_structured_data(document)
+    }
+
+    /// Adds schema.org `CreativeWork` for a quantum field reading
+    pub fn with_reading(self, reading: &Reading) -> Self {
+        let document = json!({
+            "@context": "https://schema.org",
+            "@type": "CreativeWork",
+            "name": format!("A {} reading from the Quantum Field", reading.domain),
+            "text": reading.collapsed_prompt,
+            "url": format!("{}/quantum-field/reading/{}", self.public_url, reading.id),
+            "image": format!("{}/og/reading/{}.png", self.public_url, reading.id),
+            "dateCreated": reading.created_at.to_rfc3339(),
+            "inLanguage": "en",
+            "isPartOf": { "@type": "WebSite", "name": SITE_NAME, "url": format!("{}/", self.public_url) },
+        });
+        self.with_structured_data(document)
+    }
+}
 
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct IndexTemplate {
+    pub site: SiteContext,
     pub daily_wisdom: String,
+    pub provenance: Provenance,
 }
 
 #[derive(Template)]
 #[template(path = "about.html")]
-pub struct AboutTemplate {}
+pub struct AboutTemplate {
+    pub site: SiteContext,
+    /// Whether the newsletter signup is offered (an SMTP server is configured)
+    pub newsletter: bool,
+}
 
 #[derive(Template)]
 #[template(path = "wisdom.html")]
 pub struct WisdomTemplate {
+    pub site: SiteContext,
     pub daily_wisdom: String,
+    pub provenance: Provenance,
+    /// The day this wisdom belongs to, as `YYYY-MM-DD`; also its permalink path segment
+    pub date: String,
+    /// The same day for humans, e.g. "June 1, 2025"
+    pub date_label: String,
+    /// The nearest earlier day with wisdom, if any
+    pub previous: Option<String>,
+    /// The nearest later day with wisdom, if any
+    pub next: Option<String>,
+    /// Whether to offer morning notifications (Web Push is enabled)
+    pub push_notifications: bool,
+}
+
+#[derive(Template)]
+#[template(path = "not_found.html")]
+pub struct NotFoundTemplate {
+    pub site: SiteContext,
+    pub message: String,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    pub site: SiteContext,
+    /// What was searched for; empty before the first search
+    pub query: String,
+    /// Matching days as (`YYYY-MM-DD`, human-readable date, wisdom), newest first
+    pub results: Vec<(String, String, String)>,
 }
 
 #[derive(Template)]
 #[template(path = "quantum_field.html")]
-pub struct QuantumFieldTemplate {}
+pub struct QuantumFieldTemplate {
+    pub site: SiteContext,
+}
+
+#[derive(Template)]
+#[template(path = "reading.html")]
+pub struct ReadingTemplate {
+    pub site: SiteContext,
+    pub id: String,
+    /// The date of the collapsed field, as `YYYY-MM-DD`
+    pub date: String,
+    /// The same day for humans, e.g. "June 1, 2025"
+    pub date_label: String,
+    pub domain: String,
+    pub seed: String,
+    pub collapsed_prompt: String,
+    /// Path of the reading's image
+    pub art: String,
+    pub exploration: Option<String>,
+    pub visibility: Visibility,
+    /// Whether search engines may index the page (public readings only)
+    pub indexable: bool,
+    /// Whether the visitor owns the reading and gets its settings form
+    pub is_owner: bool,
+    pub max_exploration: usize,
+}
+
+#[derive(Template)]
+#[template(path = "admin.html")]
+pub struct AdminTemplate {
+    pub username: String,
+    pub csrf_token: String,
+    pub date: String,
+    /// What is saved for the date; `None` until something generates or writes it
+    pub wisdom: Option<DailyWisdom>,
+    pub field: Option<QuantumField>,
+    pub seeds: String,
+    pub session_count: usize,
+    pub sessions: Vec<SessionSummary>,
+    pub fallbacks: Vec<FallbackEvent>,
+    pub safety_flags: Vec<SafetyEvent>,
+    pub usage: Vec<(NaiveDate, UsageTotals)>,
+    pub audit: Vec<AuditEntry>,
+}
+
+/// The newsletter's signup, confirmation and unsubscribe pages
+#[derive(Template)]
+#[template(path = "newsletter.html")]
+pub struct NewsletterTemplate {
+    pub site: SiteContext,
+    pub heading: String,
+    /// What just happened, if anything
+    pub message: Option<String>,
+    /// Why the signup form was turned down
+    pub error: Option<String>,
+    /// Whether to show the signup form
+    pub form: bool,
+    /// The address to fill the form in with again
+    pub email: String,
+    /// Token of a subscriber asked to confirm unsubscribing with a button
+    pub unsubscribe_token: Option<String>,
+}
+
+/// The account page: sign up and log in, or manage the signed-in account
+#[derive(Template)]
+#[template(path = "account.html")]
+pub struct AccountTemplate {
+    pub site: SiteContext,
+    /// The signed-in account, if any
+    pub account: Option<AccountView>,
+    /// Shortest password the signup takes
+    pub min_password_chars: usize,
+}
+
+/// What the account page shows about the signed-in account
+pub struct AccountView {
+    pub username: String,
+    pub has_password: bool,
+    pub passkeys: usize,
+    /// When it was created, as "21 May 2025"
+    pub created: String,
+}
+
+/// The message asking a new subscriber to confirm, in HTML
+#[derive(Template)]
+#[template(path = "email/confirm.html")]
+pub struct ConfirmEmail {
+    pub public_url: String,
+    pub confirm_url: String,
+    pub unsubscribe_url: String,
+    /// "daily" or "weekly"
+    pub frequency: &'static str,
+}
+
+/// The same message in plain text
+#[derive(Template)]
+#[template(path = "email/confirm.txt")]
+pub struct ConfirmEmailText<'a> {
+    pub email: &'a ConfirmEmail,
+}
+
+/// One day in a digest
+pub struct DigestWhispurr {
+    /// e.g. "Monday, June 2"
+    pub date_label: String,
+    pub text: String,
+    /// The day's permalink
+    pub url: String,
+}
+
+/// A daily or weekly digest, in HTML
+#[derive(Template)]
+#[template(path = "email/digest.html")]
+pub struct DigestEmail {
+    pub public_url: String,
+    /// Also the subject
+    pub title: String,
+    /// Heading above the whispurrs
+    pub intro: String,
+    /// Newest first
+    pub whispurrs: Vec<DigestWhispurr>,
+    /// Domain of the featured quantum seed, e.g. "2. Inner Path"
+    pub seed_domain: String,
+    pub seed: String,
+    pub field_url: String,
+    pub unsubscribe_url: String,
+}
+
+/// The same digest in plain text
+#[derive(Template)]
+#[template(path = "email/digest.txt")]
+pub struct DigestEmailText<'a> {
+    pub email: &'a DigestEmail,
+}
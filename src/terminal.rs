@@ -0,0 +1,172 @@
+//! # Terminal Rendering
+//!
+//! Plain-text rendering for people who meet the cat in a terminal: the
+//! `fortune` and `chat` commands (see `cli.rs` and `chat_client.rs`) and the
+//! text versions of the pages served to curl (see `negotiation.rs`).
+//!
+//! Everything here returns `String`s and counts characters rather than bytes,
+//! so the dashes and curly quotes the model likes don't break the layout.
+//! Colour is optional: with `Style::Ansi` the same layout is painted with ANSI
+//! escape codes, which are added only after the text has been laid out.
+
+use crate::quantum_field::WisdomNode;
+
+/// How wide text is wrapped unless told otherwise
+pub const DEFAULT_WIDTH: usize = 72;
+
+/// The cat, sitting below its speech bubble
+const CAT: &str = r"
+        \
+         \   /\_/\
+            ( -.- )
+             > ^ <   ~ purr ~";
+
+/// Width of the six-node field map
+const MAP_WIDTH: usize = 56;
+
+/// Whether output may contain ANSI colour codes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Style {
+    /// Just text, safe to pipe into files and other tools
+    #[default]
+    Plain,
+    /// Text painted with ANSI escape codes
+    Ansi,
+}
+
+impl Style {
+    /// `text` wrapped in the given SGR code (e.g. `"1;33"`), if colour is on
+    pub fn paint(self, code: &str, text: &str) -> String {
+        match self {
+            Style::Plain => text.to_string(),
+            Style::Ansi => format!("\x1b[{}m{}\x1b[0m", code, text),
+        }
+    }
+}
+
+/// Colour of each domain's node, in `DOMAINS` order
+const NODE_COLOURS: [&str; 6] = ["1;33", "1;35", "1;32", "1;36", "1;31", "1;34"];
+
+/// Splits `text` into lines of at most `width` characters, breaking at spaces
+///
+/// Words longer than `width` get a line of their own rather than being cut.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// The cat saying `text`, in a speech bubble wrapped at `width` characters
+pub fn cat_says(text: &str, width: usize, style: Style) -> String {
+    // Leave room for the bubble's borders
+    let lines = wrap(text, width.saturating_sub(4).max(1));
+    let inner = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+
+    let mut bubble = format!(" {}\n", style.paint("2", &"_".repeat(inner + 2)));
+    for (i, line) in lines.iter().enumerate() {
+        let (left, right) = match (i, lines.len()) {
+            (_, 1) => ('<', '>'),
+            (0, _) => ('/', '\\'),
+            (i, n) if i == n - 1 => ('\\', '/'),
+            _ => ('|', '|'),
+        };
+        let padding = " ".repeat(inner - line.chars().count());
+        bubble.push_str(&format!(
+            "{} {}{} {}\n",
+            style.paint("2", &left.to_string()),
+            style.paint("1", line),
+            padding,
+            style.paint("2", &right.to_string()),
+        ));
+    }
+    bubble.push_str(&format!(" {}", style.paint("2", &"-".repeat(inner + 2))));
+    bubble.push_str(&style.paint("33", CAT));
+    bubble
+}
+
+/// The cat speaking a Whispurr, signed with its date
+pub fn whispurr(text: &str, date_label: &str, width: usize, style: Style) -> String {
+    format!(
+        "{}\n\n    {}\n",
+        cat_says(text, width, style),
+        style.paint("2;3", &format!("— The Enlightened Cat, {}", date_label))
+    )
+}
+
+/// The six nodes of a quantum field as a numbered map, followed by each
+/// node's seed wrapped at `width` characters
+///
+/// ```text
+///                       (1) Essence
+///                   /                 \
+///    (6) Crystallization            (2) Inner Path
+///               |                           |
+///         (5) Friction               (3) Outer Path
+///                   \                 /
+///                       (4) Portal
+/// ```
+pub fn field_map(nodes: &[WisdomNode], width: usize, style: Style) -> String {
+    let label = |i: usize| nodes.get(i).map(|node| format!("({}) {}", i + 1, node.domain)).unwrap_or_default();
+    let half = MAP_WIDTH / 2;
+    // The side nodes sit in the middle of each half; the edges to the top
+    // and bottom nodes lean in from there
+    let (left, right) = (half / 2, half + half / 2);
+    let edges = |l: char, r: char, inset: usize| {
+        format!("{}{}{}{}", " ".repeat(left + inset), l, " ".repeat(right - left - 2 * inset - 1), r)
+    };
+
+    let plain = [
+        center(&label(0), MAP_WIDTH),
+        edges('/', '\\', 5),
+        format!("{:<half$}{}", center(&label(5), half), center(&label(1), half)),
+        edges('|', '|', 0),
+        format!("{:<half$}{}", center(&label(4), half), center(&label(2), half)),
+        edges('\\', '/', 5),
+        center(&label(3), MAP_WIDTH),
+    ];
+
+    // Paint after laying out, so escape codes don't upset the centering
+    let mut map = String::new();
+    for line in plain {
+        let mut line = line.trim_end().to_string();
+        for (i, colour) in NODE_COLOURS.iter().enumerate() {
+            let text = label(i);
+            if !text.is_empty() && line.contains(&text) {
+                line = line.replace(&text, &style.paint(colour, &text));
+            }
+        }
+        map.push_str(&line);
+        map.push('\n');
+    }
+
+    map.push('\n');
+    for (i, node) in nodes.iter().enumerate() {
+        let number = format!("  {}. ", i + 1);
+        let indent = " ".repeat(number.chars().count());
+        let seed = wrap(&node.seed, width.saturating_sub(indent.len()).max(1));
+        map.push_str(&style.paint(NODE_COLOURS[i % NODE_COLOURS.len()], &format!("{}{}", number, node.domain)));
+        map.push('\n');
+        for line in seed {
+            map.push_str(&format!("{}{}\n", indent, line));
+        }
+    }
+    map
+}
+
+/// `text` padded on the left to sit in the middle of `width` columns
+fn center(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.chars().count()) / 2;
+    format!("{}{}", " ".repeat(padding), text)
+}
@@ -0,0 +1,75 @@
+//! # Visitor Time Zones
+//!
+//! Daily content (the Whispurr and the Quantum Field) is keyed by calendar date.
+//! This module decides *whose* calendar that is: the site's configured time zone
+//! by default, or the visitor's own zone when they send one.
+//!
+//! Visitors can announce their zone in two ways:
+//! - An `X-Timezone` header, for API clients and embedded widgets
+//! - A `tz` cookie, which the front-end sets from the browser's `Intl` settings
+//!
+//! Both carry an IANA name such as `Europe/Berlin`. Unknown names are ignored.
+
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+
+use crate::cookies;
+use crate::state::AppState;
+
+/// Header API clients can use to pick their time zone
+pub const TIMEZONE_HEADER: &str = "x-timezone";
+
+/// Cookie the front-end sets with the browser's time zone
+pub const TIMEZONE_COOKIE: &str = "tz";
+
+/// The time zone a request should be served in
+///
+/// Use it as an extractor in any handler that serves daily content:
+/// `VisitorZone::today()` gives the date whose wisdom the visitor should see.
+#[derive(Debug, Clone, Copy)]
+pub struct VisitorZone(pub Tz);
+
+impl VisitorZone {
+    /// The current calendar date in this zone
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.0).date_naive()
+    }
+
+    /// The IANA name of this zone
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for VisitorZone {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let config = &state.config;
+
+        if config.visitor_timezones {
+            let requested = parts
+                .headers
+                .get(TIMEZONE_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+                .or_else(|| cookies::get(&parts.headers, TIMEZONE_COOKIE));
+
+            if let Some(zone) = requested.and_then(|name| parse_zone(&name)) {
+                return Ok(Self(zone));
+            }
+        }
+
+        Ok(Self(config.site_timezone))
+    }
+}
+
+/// Parses an IANA zone name, accepting the URL-encoded form browsers put in cookies
+fn parse_zone(name: &str) -> Option<Tz> {
+    name.trim().replace("%2F", "/").replace("%2f", "/").parse().ok()
+}
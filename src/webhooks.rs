@@ -0,0 +1,434 @@
+//! # Outgoing Webhooks
+//!
+//! Posts the Daily Whispurr to team chats every morning. Targets are listed in
+//! the JSON file named by `WEBHOOKS_PATH`:
+//!
+//! ```json
+//! [
+//!   {
+//!     "name": "team-slack",
+//!     "url": "https://hooks.slack.com/services/...",
+//!     "format": "slack",
+//!     "schedule": "09:00",
+//!     "timezone": "Europe/Berlin",
+//!     "days": ["mon", "tue", "wed", "thu", "fri"],
+//!     "secret": "shared-with-the-receiver"
+//!   }
+//! ]
+//! ```
+//!
+//! `format` is `slack`, `discord` or `generic` (plain JSON, the default);
+//! `timezone` defaults to `SITE_TIMEZONE` and `days` to every day. Once a
+//! target's local day has rolled over and its `schedule` time has passed, the
+//! scheduler posts that local date's wisdom, once per target and date. A server
+//! started after the scheduled time catches up on the same day.
+//!
+//! Every request carries `X-Cat-Event` and a unique `X-Cat-Delivery` id. With a
+//! `secret`, it is also signed: `X-Cat-Signature` is
+//! `sha256=` + hex HMAC-SHA256 of `"{X-Cat-Timestamp}.{body}"`, so receivers
+//! can check both the sender and the freshness. (Slack and Discord don't check
+//! signatures; their URLs are the secret.)
+//!
+//! Network errors, `429` and `5xx` answers are retried up to `MAX_ATTEMPTS`
+//! times, waiting `WEBHOOK_BACKOFF_MS` and doubling after each try, while
+//! the other targets are posted to meanwhile. The outcome
+//! of every delivery, successful or not, is kept in `DATA_DIR/deliveries.json`
+//! and shown by `the-enlightened-cat webhooks log`. A failed day is not retried
+//! by the scheduler; `webhooks send` posts it again by hand. While generation
+//! is failing, the day waits rather than going out with the corpus pick
+//! standing in for it (see `AppState::broadcast_wisdom`).
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use rand::RngCore;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::json_file::JsonFile;
+use crate::state::{AppState, DailyWisdom};
+
+/// Names the kind of event; only the daily wisdom for now
+pub const EVENT_HEADER: &str = "X-Cat-Event";
+/// Unique per delivery, and the same across its retries
+pub const DELIVERY_HEADER: &str = "X-Cat-Delivery";
+/// Unix time at which the request was signed
+pub const TIMESTAMP_HEADER: &str = "X-Cat-Timestamp";
+/// `sha256=` followed by the hex HMAC of the timestamp and body
+pub const SIGNATURE_HEADER: &str = "X-Cat-Signature";
+
+/// The event sent every morning
+const DAILY_WISDOM_EVENT: &str = "daily_wisdom";
+
+/// How many times a delivery is tried before it is logged as failed
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// How many deliveries the log keeps
+const MAX_LOGGED: usize = 500;
+
+/// How often the scheduler looks for due targets
+const TICK: Duration = Duration::from_secs(30);
+
+/// How long a receiver may take to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Name shown on Discord posts
+pub(crate) const DISPLAY_NAME: &str = "The Enlightened Cat";
+
+/// Colour of the cat's Discord embeds: the site's amber
+pub const DISCORD_COLOUR: u32 = 0xE0A030;
+
+/// The shape of the JSON body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// Slack incoming webhook (`text` plus blocks)
+    Slack,
+    /// Discord webhook (an embed)
+    Discord,
+    /// The event as plain JSON, for anything else
+    #[default]
+    Generic,
+}
+
+impl fmt::Display for PayloadFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PayloadFormat::Slack => "slack",
+            PayloadFormat::Discord => "discord",
+            PayloadFormat::Generic => "generic",
+        })
+    }
+}
+
+/// One place the wisdom is posted to
+#[derive(Clone, Deserialize)]
+pub struct WebhookTarget {
+    /// Unique name, used in the delivery log and on the command line
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub format: PayloadFormat,
+    /// Local time of day after which the day's wisdom is posted
+    #[serde(default = "default_schedule")]
+    pub schedule: NaiveTime,
+    /// Zone deciding the local date and time; `SITE_TIMEZONE` if unset
+    #[serde(default, deserialize_with = "zone_name")]
+    pub timezone: Option<Tz>,
+    /// Weekdays to post on; every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// Key for signing requests; unsigned if unset
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+fn default_schedule() -> NaiveTime {
+    NaiveTime::from_hms_opt(8, 0, 0).unwrap()
+}
+
+fn zone_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Tz>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| Tz::from_str(&name).map_err(|_| serde::de::Error::custom(format!("unknown time zone {}", name))))
+        .transpose()
+}
+
+impl WebhookTarget {
+    /// Reads the targets file, rejecting duplicate names and invalid URLs
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read webhook targets {}", path.display()))?;
+        let targets: Vec<WebhookTarget> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse webhook targets {}", path.display()))?;
+
+        for (i, target) in targets.iter().enumerate() {
+            reqwest::Url::parse(&target.url)
+                .with_context(|| format!("Webhook target {} has an invalid URL", target.name))?;
+            if targets[..i].iter().any(|other| other.name == target.name) {
+                anyhow::bail!("Webhook target {} is listed twice", target.name);
+            }
+        }
+        Ok(targets)
+    }
+
+    /// The zone this target lives in
+    pub fn zone(&self, site_timezone: Tz) -> Tz {
+        self.timezone.unwrap_or(site_timezone)
+    }
+
+    /// The local date whose wisdom is due at `now`, if the target's time has
+    /// come on one of its days
+    pub fn due_date(&self, now: DateTime<Utc>, site_timezone: Tz) -> Option<NaiveDate> {
+        let local = now.with_timezone(&self.zone(site_timezone));
+        let on_today = self.days.is_empty() || self.days.contains(&local.weekday());
+        (on_today && local.time() >= self.schedule).then(|| local.date_naive())
+    }
+}
+
+/// Whether a delivery reached its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Delivered,
+    Failed,
+}
+
+/// One delivery of a day's wisdom to one target, after any retries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    /// Sent as `X-Cat-Delivery`
+    pub id: String,
+    pub target: String,
+    /// The date whose wisdom was sent
+    pub date: NaiveDate,
+    /// When the last attempt finished
+    pub at: DateTime<Utc>,
+    pub attempts: u32,
+    pub outcome: Outcome,
+    /// HTTP status of the last answer, if there was one
+    pub status: Option<u16>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+}
+
+/// The configured targets and the log of what was sent to them
+#[derive(Clone)]
+pub struct Webhooks {
+    targets: Arc<Vec<WebhookTarget>>,
+    log: JsonFile<Vec<Delivery>>,
+    http: reqwest::Client,
+    backoff: Duration,
+}
+
+impl Webhooks {
+    /// Loads the targets named by the configuration and opens the delivery log at `log_path`
+    pub fn open(config: &Config, log_path: impl Into<PathBuf>) -> Result<Self> {
+        let targets = match &config.webhooks_path {
+            Some(path) => WebhookTarget::load(Path::new(path))?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            targets: Arc::new(targets),
+            log: JsonFile::open(log_path)?,
+            http: reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            backoff: Duration::from_millis(config.webhook_backoff_ms),
+        })
+    }
+
+    pub fn targets(&self) -> &[WebhookTarget] {
+        &self.targets
+    }
+
+    pub fn target(&self, name: &str) -> Option<&WebhookTarget> {
+        self.targets.iter().find(|target| target.name == name)
+    }
+
+    /// The most recent deliveries, newest first
+    pub async fn log(&self, limit: usize) -> Vec<Delivery> {
+        self.log.read(|log| log.iter().rev().take(limit).cloned().collect()).await
+    }
+
+    /// Every logged delivery, oldest first, for backups
+    pub async fn deliveries(&self) -> Vec<Delivery> {
+        self.log.read(Vec::clone).await
+    }
+
+    /// Adds `imported` deliveries to the log, replacing those with the same
+    /// ids; with `replace`, the log is cleared first
+    pub async fn import(&self, imported: Vec<Delivery>, replace: bool) {
+        self.log
+            .update(|log| {
+                if replace {
+                    log.clear();
+                }
+                log.retain(|delivery| !imported.iter().any(|i| i.id == delivery.id));
+                log.extend(imported);
+                log.sort_by_key(|delivery| delivery.at);
+                let excess = log.len().saturating_sub(MAX_LOGGED);
+                log.drain(..excess);
+            })
+            .await;
+    }
+
+    /// Whether `date` was already sent to `target`, successfully or not
+    async fn attempted(&self, target: &str, date: NaiveDate) -> bool {
+        self.log.read(|log| log.iter().any(|d| d.target == target && d.date == date)).await
+    }
+
+    /// Posts `wisdom` for `date` to `target`, retrying with backoff, and logs the outcome
+    pub async fn deliver(
+        &self,
+        target: &WebhookTarget,
+        date: NaiveDate,
+        wisdom: &DailyWisdom,
+        public_url: &str,
+    ) -> Delivery {
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        let id = hex::encode(id);
+        let body = payload(target.format, date, wisdom, public_url).to_string();
+
+        let mut attempts = 0;
+        let (outcome, status, error) = loop {
+            attempts += 1;
+            let (status, error) = match self.send(target, &id, &body).await {
+                Ok(response) if response.status().is_success() => break (Outcome::Delivered, Some(response.status().as_u16()), None),
+                Ok(response) => {
+                    let status = response.status();
+                    let retry = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                    if !retry {
+                        break (Outcome::Failed, Some(status.as_u16()), Some(format!("Receiver answered {}", status)));
+                    }
+                    (Some(status.as_u16()), format!("Receiver answered {}", status))
+                }
+                Err(err) => (None, format!("{:#}", err)),
+            };
+
+            if attempts == MAX_ATTEMPTS {
+                break (Outcome::Failed, status, Some(error));
+            }
+            warn!("Webhook {} attempt {} failed, retrying: {}", target.name, attempts, error);
+            tokio::time::sleep(self.backoff * 2u32.pow(attempts - 1)).await;
+        };
+
+        let delivery = Delivery { id, target: target.name.clone(), date, at: Utc::now(), attempts, outcome, status, error };
+        match outcome {
+            Outcome::Delivered => info!("Delivered {} wisdom to webhook {}", date, target.name),
+            Outcome::Failed => warn!("Gave up delivering {} wisdom to webhook {}: {:?}", date, target.name, delivery.error),
+        }
+
+        let logged = delivery.clone();
+        self.log
+            .update(move |log| {
+                log.push(logged);
+                let excess = log.len().saturating_sub(MAX_LOGGED);
+                log.drain(..excess);
+            })
+            .await;
+        delivery
+    }
+
+    /// One attempt, signed afresh so the timestamp is current
+    async fn send(&self, target: &WebhookTarget, id: &str, body: &str) -> Result<reqwest::Response> {
+        let mut request = self
+            .http
+            .post(&target.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, DAILY_WISDOM_EVENT)
+            .header(DELIVERY_HEADER, id);
+
+        if let Some(secret) = &target.secret {
+            let timestamp = Utc::now().timestamp();
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(SIGNATURE_HEADER, sign(secret, timestamp, body.as_bytes())?);
+        }
+
+        Ok(request.body(body.to_string()).send().await?)
+    }
+}
+
+/// The `X-Cat-Signature` value for a body sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> Result<String> {
+    let key = PKey::hmac(secret.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(timestamp.to_string().as_bytes())?;
+    signer.update(b".")?;
+    signer.update(body)?;
+    Ok(format!("sha256={}", hex::encode(signer.sign_to_vec()?)))
+}
+
+/// The JSON body for a day's wisdom in the target's format
+pub fn payload(format: PayloadFormat, date: NaiveDate, wisdom: &DailyWisdom, public_url: &str) -> Value {
+    let url = format!("{}/wisdom/{}", public_url, date);
+    let signed = format!("— {}, {}", DISPLAY_NAME, date.format("%B %-d, %Y"));
+
+    match format {
+        PayloadFormat::Slack => json!({
+            // Shown in notifications and by clients without blocks
+            "text": format!("{} {}", wisdom.text, signed),
+            "blocks": [
+                { "type": "section", "text": { "type": "mrkdwn", "text": format!("> {}\n{}", wisdom.text, signed) } },
+                { "type": "context", "elements": [{ "type": "mrkdwn", "text": format!("<{}|Read it on the site>", url) }] },
+            ],
+        }),
+        PayloadFormat::Discord => json!({
+            "username": DISPLAY_NAME,
+            "embeds": [{
+                "title": format!("Whispurr for {}", date.format("%B %-d, %Y")),
+                "description": wisdom.text,
+                "url": url,
+                "color": DISCORD_COLOUR,
+            }],
+        }),
+        PayloadFormat::Generic => json!({
+            "event": DAILY_WISDOM_EVENT,
+            "date": date,
+            "wisdom": wisdom.text,
+            "provenance": wisdom.provenance,
+            "url": url,
+        }),
+    }
+}
+
+/// Delivers the wisdom to every target that is due at `now` and hasn't had
+/// that date yet
+///
+/// Targets are posted to side by side, so one that is slow or being retried
+/// doesn't hold up the rest. The deliveries come back in the targets' order.
+pub async fn deliver_due(state: &AppState, now: DateTime<Utc>) -> Vec<Delivery> {
+    let mut tasks = Vec::new();
+    for target in state.webhooks.targets() {
+        let Some(date) = target.due_date(now, state.config.site_timezone) else {
+            continue;
+        };
+        if state.webhooks.attempted(&target.name, date).await {
+            continue;
+        }
+
+        let Some(wisdom) = state.broadcast_wisdom(date).await else {
+            info!("Holding the {} wisdom back from webhook {} until it is generated", date, target.name);
+            continue;
+        };
+        let (webhooks, target, public_url) = (state.webhooks.clone(), target.clone(), state.config.public_url.clone());
+        tasks.push(tokio::spawn(async move { webhooks.deliver(&target, date, &wisdom, &public_url).await }));
+    }
+
+    let mut deliveries = Vec::new();
+    for task in tasks {
+        match task.await {
+            Ok(delivery) => deliveries.push(delivery),
+            Err(err) => warn!("A webhook delivery didn't finish: {}", err),
+        }
+    }
+    deliveries
+}
+
+/// Starts the background task that delivers due wisdom, if any targets are configured
+pub fn spawn_scheduler(state: AppState) -> Option<JoinHandle<()>> {
+    if state.webhooks.targets().is_empty() {
+        return None;
+    }
+    info!("Posting the daily wisdom to {} webhook targets", state.webhooks.targets().len());
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        // A tick that runs long (retries) shouldn't be followed by a burst
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            deliver_due(&state, Utc::now()).await;
+        }
+    }))
+}
@@ -0,0 +1,540 @@
+//! # Web Push
+//!
+//! The morning Whispurr as a browser notification, sent through each
+//! browser's push service (see `routes/push.rs` for subscribing).
+//!
+//! Pushes are authenticated with VAPID (RFC 8292): a P-256 key pair,
+//! generated on first start in `DATA_DIR/vapid.pem`, signs a short-lived JWT
+//! naming the push service and `VAPID_SUBJECT`, the contact the services may
+//! reach about misbehaving pushes. Browsers are given the public key when they
+//! subscribe. Payloads are encrypted for the browser with `aes128gcm`
+//! (RFC 8291), so the push service never sees the wisdom.
+//!
+//! Subscriptions are kept in `DATA_DIR/push.json` with their local delivery
+//! time and zone, and are due like Telegram's daily push: once a day, after
+//! the subscriber's local time. A subscription the push service answers `404`
+//! or `410` for has expired (the browser dropped it) and is removed.
+//!
+//! Anyone can subscribe, so the endpoint is a URL chosen by a stranger. It
+//! must be `https` on a public address, is reached at the address that was
+//! checked and without following redirects, like ActivityPub deliveries
+//! (`PUSH_ALLOW_LOCAL` lifts this for tests and local development). Each
+//! address may subscribe `MAX_SUBSCRIBES_PER_HOUR` times an hour, and at most
+//! `MAX_PUSH_SUBSCRIPTIONS` subscriptions are kept.
+//!
+//! Everything is off without `VAPID_SUBJECT`. `mock_push::MockPushService`
+//! stands in for a browser and its push service in tests.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use openssl::{
+    bn::BigNumContext,
+    derive::Deriver,
+    ec::{EcGroup, EcKey, EcPoint, PointConversionForm},
+    ecdsa::EcdsaSig,
+    hash::{hash, MessageDigest},
+    nid::Nid,
+    pkey::{PKey, Private, Public},
+    sign::Signer,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::activitypub::{pinned_client, remote_address};
+use crate::config::Config;
+use crate::json_file::JsonFile;
+use crate::rate_limit::RateLimiter;
+use crate::state::{AppState, DailyWisdom};
+use crate::webhooks::DISPLAY_NAME;
+
+/// How often the scheduler looks for subscriptions that are due
+const TICK: Duration = Duration::from_secs(30);
+
+/// How long a push service keeps a push for an offline browser: a morning
+/// Whispurr delivered the next day is stale
+const TTL_SECS: u32 = 12 * 60 * 60;
+
+/// Default for the most subscriptions kept (`MAX_PUSH_SUBSCRIPTIONS`)
+pub const MAX_SUBSCRIPTIONS: usize = 10_000;
+
+/// How often one address may subscribe (or move its time) per hour
+pub const MAX_SUBSCRIBES_PER_HOUR: usize = 10;
+
+/// How long a VAPID token is valid (the RFC allows up to a day)
+const VAPID_VALIDITY_SECS: i64 = 12 * 60 * 60;
+
+/// Record size announced in the `aes128gcm` header; payloads are one record
+const RECORD_SIZE: u32 = 4096;
+
+/// Length of the browser's authentication secret
+const AUTH_SECRET_LEN: usize = 16;
+
+/// Length of an uncompressed P-256 public key
+const PUBLIC_KEY_LEN: usize = 65;
+
+/// A browser's opt-in to the morning Whispurr
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushSubscription {
+    /// The push service URL the browser was given; unique per subscription
+    pub endpoint: String,
+    /// The browser's P-256 public key, base64url
+    pub p256dh: String,
+    /// The browser's authentication secret, base64url
+    pub auth: String,
+    /// Local time of day after which the day's wisdom is pushed
+    pub time: NaiveTime,
+    pub timezone: Tz,
+    /// The last local date whose wisdom was pushed, or skipped on subscribing
+    pub last_sent: Option<NaiveDate>,
+}
+
+impl PushSubscription {
+    /// The local date whose wisdom is due at `now`, if the subscriber's time
+    /// has come and they haven't had that date yet
+    pub fn due_date(&self, now: DateTime<Utc>) -> Option<NaiveDate> {
+        let local = now.with_timezone(&self.timezone);
+        let date = local.date_naive();
+        (local.time() >= self.time && self.last_sent.is_none_or(|sent| sent < date)).then_some(date)
+    }
+}
+
+/// The VAPID key and the subscriptions, as kept in backups
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushBackup {
+    #[serde(default)]
+    pub subscriptions: Vec<PushSubscription>,
+    /// The contents of `vapid.pem`
+    pub private_key_pem: String,
+}
+
+impl PushBackup {
+    /// Fails if the key isn't a P-256 private key
+    pub fn validate(&self) -> Result<()> {
+        let key = EcKey::private_key_from_pem(self.private_key_pem.as_bytes()).context("The backup's VAPID key is invalid")?;
+        if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+            anyhow::bail!("The backup's VAPID key is not a P-256 key");
+        }
+        Ok(())
+    }
+}
+
+/// What became of a push
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The push service accepted it
+    Sent,
+    /// The subscription has expired and should be forgotten
+    Gone,
+}
+
+/// The VAPID key and the subscriptions
+#[derive(Clone)]
+pub struct WebPush {
+    subject: String,
+    key: Arc<EcKey<Private>>,
+    /// The uncompressed public key, base64url, as browsers want it
+    public_key: Arc<str>,
+    key_path: Arc<Path>,
+    subscriptions: JsonFile<Vec<PushSubscription>>,
+    /// Most subscriptions kept (`MAX_PUSH_SUBSCRIPTIONS`)
+    limit: usize,
+    /// Whether endpoints may be `http` and on local addresses (`PUSH_ALLOW_LOCAL`)
+    allow_local: bool,
+    /// Subscribe requests per client address
+    subscribes: RateLimiter,
+}
+
+impl WebPush {
+    /// Loads or generates the VAPID key in `data_dir` and opens the
+    /// subscriptions; `None` unless `VAPID_SUBJECT` is set
+    pub fn open(config: &Config, data_dir: &Path) -> Result<Option<Self>> {
+        let Some(subject) = config.vapid_subject.clone() else {
+            return Ok(None);
+        };
+
+        let key_path = data_dir.join("vapid.pem");
+        let key = if key_path.exists() {
+            let pem = std::fs::read(&key_path).with_context(|| format!("Failed to read {}", key_path.display()))?;
+            EcKey::private_key_from_pem(&pem).with_context(|| format!("Failed to parse {}", key_path.display()))?
+        } else {
+            info!("Generating the VAPID key pair in {}", key_path.display());
+            let group = p256()?;
+            let key = EcKey::generate(&group)?;
+            std::fs::create_dir_all(data_dir)?;
+            std::fs::write(&key_path, PKey::from_ec_key(key.clone())?.private_key_to_pem_pkcs8()?)
+                .with_context(|| format!("Failed to write {}", key_path.display()))?;
+            key
+        };
+        let public_key = URL_SAFE_NO_PAD.encode(public_key_bytes(&key)?);
+
+        Ok(Some(Self {
+            subject,
+            key: Arc::new(key),
+            public_key: public_key.into(),
+            key_path: key_path.into(),
+            subscriptions: JsonFile::open(data_dir.join("push.json"))?,
+            limit: config.max_push_subscriptions,
+            allow_local: config.push_allow_local,
+            subscribes: RateLimiter::new(MAX_SUBSCRIBES_PER_HOUR, Duration::from_secs(60 * 60)),
+        }))
+    }
+
+    /// The VAPID public key browsers subscribe with (`applicationServerKey`)
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// All subscriptions, in the order they were made
+    pub async fn subscriptions(&self) -> Vec<PushSubscription> {
+        self.subscriptions.read(Clone::clone).await
+    }
+
+    /// Whether `client` may subscribe, counting it if so; false once it did
+    /// `MAX_SUBSCRIBES_PER_HOUR` times in the last hour
+    pub fn allow_subscribe(&self, client: &str) -> bool {
+        self.subscribes.allow(client)
+    }
+
+    /// Subscribes a browser, or moves its time; a time that already passed
+    /// today takes effect tomorrow
+    ///
+    /// Fails if the endpoint or keys are unusable; `None` if the browser is
+    /// new and `MAX_PUSH_SUBSCRIPTIONS` are already kept.
+    pub async fn subscribe(
+        &self,
+        endpoint: &str,
+        p256dh: &str,
+        auth: &str,
+        time: NaiveTime,
+        timezone: Tz,
+        now: DateTime<Utc>,
+    ) -> Result<Option<PushSubscription>> {
+        let url = reqwest::Url::parse(endpoint).context("endpoint must be a URL")?;
+        remote_address(&url, self.allow_local).await.context("endpoint must be an https URL of a public host")?;
+        let public_key = URL_SAFE_NO_PAD.decode(p256dh.trim_end_matches('=')).context("p256dh must be base64url")?;
+        browser_key(&public_key).context("p256dh must be an uncompressed P-256 public key")?;
+        let secret = URL_SAFE_NO_PAD.decode(auth.trim_end_matches('=')).context("auth must be base64url")?;
+        if secret.len() != AUTH_SECRET_LEN {
+            anyhow::bail!("auth must be {} bytes", AUTH_SECRET_LEN);
+        }
+
+        let local = now.with_timezone(&timezone);
+        let passed = (local.time() >= time).then_some(local.date_naive());
+        let (endpoint, p256dh, auth) = (endpoint.to_string(), p256dh.to_string(), auth.to_string());
+        let limit = self.limit;
+        Ok(self
+            .subscriptions
+            .update(move |subscriptions| {
+                let previous = subscriptions.iter().position(|s| s.endpoint == endpoint).map(|i| subscriptions.remove(i));
+                if previous.is_none() && subscriptions.len() >= limit {
+                    return None;
+                }
+                let subscription = PushSubscription {
+                    endpoint,
+                    p256dh,
+                    auth,
+                    time,
+                    timezone,
+                    // Moving the time later on a day that was already sent mustn't send it twice
+                    last_sent: passed.max(previous.and_then(|previous| previous.last_sent)),
+                };
+                subscriptions.push(subscription.clone());
+                Some(subscription)
+            })
+            .await)
+    }
+
+    /// The key and subscriptions, for backups
+    pub async fn export(&self) -> Result<PushBackup> {
+        Ok(PushBackup {
+            subscriptions: self.subscriptions().await,
+            private_key_pem: String::from_utf8(PKey::from_ec_key((*self.key).clone())?.private_key_to_pem_pkcs8()?)?,
+        })
+    }
+
+    /// Adds the backup's subscriptions (replacing those with the same
+    /// endpoint) and writes its key to `vapid.pem`; with `replace`, other
+    /// subscriptions are dropped first
+    ///
+    /// The key is used from the next start on. Browsers subscribed with it,
+    /// so the restored subscriptions only work once it is.
+    pub async fn import(&self, backup: PushBackup, replace: bool) -> Result<()> {
+        backup.validate()?;
+        std::fs::write(&self.key_path, &backup.private_key_pem)
+            .with_context(|| format!("Failed to write {}", self.key_path.display()))?;
+
+        self.subscriptions
+            .update(|subscriptions| {
+                if replace {
+                    subscriptions.clear();
+                }
+                subscriptions.retain(|s| !backup.subscriptions.iter().any(|i| i.endpoint == s.endpoint));
+                subscriptions.extend(backup.subscriptions);
+            })
+            .await;
+        Ok(())
+    }
+
+    /// Drops a subscription; whether there was one
+    pub async fn unsubscribe(&self, endpoint: &str) -> bool {
+        self.subscriptions
+            .update(|subscriptions| {
+                let before = subscriptions.len();
+                subscriptions.retain(|s| s.endpoint != endpoint);
+                subscriptions.len() < before
+            })
+            .await
+    }
+
+    async fn mark_sent(&self, endpoint: &str, date: NaiveDate) {
+        self.subscriptions
+            .update(|subscriptions| {
+                if let Some(subscription) = subscriptions.iter_mut().find(|s| s.endpoint == endpoint) {
+                    subscription.last_sent = Some(date);
+                }
+            })
+            .await
+    }
+
+    /// Encrypts `payload` for a subscription and hands it to its push service
+    pub async fn send(&self, subscription: &PushSubscription, payload: &[u8]) -> Result<Delivery> {
+        let public_key = URL_SAFE_NO_PAD.decode(subscription.p256dh.trim_end_matches('='))?;
+        let auth = URL_SAFE_NO_PAD.decode(subscription.auth.trim_end_matches('='))?;
+        let body = encrypt(&public_key, &auth, payload)?;
+
+        let url = reqwest::Url::parse(&subscription.endpoint)?;
+        let response = pinned_client(&url, self.allow_local)
+            .await?
+            .post(url)
+            .header("authorization", self.authorization(&subscription.endpoint, Utc::now())?)
+            .header("content-encoding", "aes128gcm")
+            .header("content-type", "application/octet-stream")
+            .header("ttl", TTL_SECS.to_string())
+            // A newer Whispurr replaces one still waiting for the browser
+            .header("topic", "daily-wisdom")
+            .body(body)
+            .send()
+            .await?;
+        match response.status().as_u16() {
+            200..=299 => Ok(Delivery::Sent),
+            404 | 410 => Ok(Delivery::Gone),
+            status => anyhow::bail!("The push service answered {}", status),
+        }
+    }
+
+    /// The `Authorization` header for a push to `endpoint`: a VAPID token
+    /// for the endpoint's origin, and the key that signed it
+    pub fn authorization(&self, endpoint: &str, now: DateTime<Utc>) -> Result<String> {
+        let url = reqwest::Url::parse(endpoint)?;
+        let header = URL_SAFE_NO_PAD.encode(json!({ "typ": "JWT", "alg": "ES256" }).to_string());
+        let claims = URL_SAFE_NO_PAD.encode(
+            json!({
+                "aud": url.origin().ascii_serialization(),
+                "exp": now.timestamp() + VAPID_VALIDITY_SECS,
+                "sub": self.subject,
+            })
+            .to_string(),
+        );
+        let signing_input = format!("{}.{}", header, claims);
+
+        // JWTs want the raw r || s, not DER
+        let digest = hash(MessageDigest::sha256(), signing_input.as_bytes())?;
+        let signature = EcdsaSig::sign(&digest, &self.key)?;
+        let mut raw = signature.r().to_vec_padded(32)?;
+        raw.extend(signature.s().to_vec_padded(32)?);
+
+        Ok(format!("vapid t={}.{}, k={}", signing_input, URL_SAFE_NO_PAD.encode(raw), self.public_key))
+    }
+}
+
+/// The notification for a day's wisdom, as the service worker expects it
+pub fn wisdom_payload(date: NaiveDate, wisdom: &DailyWisdom, public_url: &str) -> Value {
+    json!({
+        "title": format!("{} · {}", DISPLAY_NAME, date.format("%B %-d")),
+        "body": wisdom.text,
+        "url": format!("{}/wisdom/{}", public_url, date),
+        "date": date,
+    })
+}
+
+/// Encrypts `payload` for a browser with `aes128gcm` (RFC 8291), given its
+/// `p256dh` public key and `auth` secret
+pub fn encrypt(browser_public_key: &[u8], auth: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let group = p256()?;
+    let browser = browser_key(browser_public_key)?;
+    let ephemeral = EcKey::generate(&group)?;
+    let ephemeral_public = public_key_bytes(&ephemeral)?;
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+
+    let shared = ecdh(&ephemeral, &browser)?;
+    let (key, nonce) = content_keys(&shared, auth, browser_public_key, &ephemeral_public, &salt)?;
+
+    // One record, ended by the last-record delimiter
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(Cipher::aes_128_gcm(), &key, Some(&nonce), &[], &plaintext, &mut tag)?;
+
+    let mut body = Vec::with_capacity(16 + 4 + 1 + PUBLIC_KEY_LEN + ciphertext.len() + tag.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(PUBLIC_KEY_LEN as u8);
+    body.extend_from_slice(&ephemeral_public);
+    body.extend_from_slice(&ciphertext);
+    body.extend_from_slice(&tag);
+    Ok(body)
+}
+
+/// Decrypts an `aes128gcm` push body as the browser holding `browser_key`
+/// and `auth` would; the inverse of `encrypt`, used by the mock push service
+pub fn decrypt(browser_key: &EcKey<Private>, auth: &[u8], body: &[u8]) -> Result<Vec<u8>> {
+    let header_len = 16 + 4 + 1;
+    let id_len = *body.get(header_len - 1).context("The push body is too short")? as usize;
+    if body.len() < header_len + id_len + 16 {
+        anyhow::bail!("The push body is too short");
+    }
+    let salt = &body[..16];
+    let sender_public = &body[header_len..header_len + id_len];
+    let (ciphertext, tag) = body[header_len + id_len..].split_at(body.len() - header_len - id_len - 16);
+
+    let shared = ecdh(browser_key, &browser_key_from(sender_public)?)?;
+    let (key, nonce) = content_keys(&shared, auth, &public_key_bytes(browser_key)?, sender_public, salt)?;
+    let mut plaintext = decrypt_aead(Cipher::aes_128_gcm(), &key, Some(&nonce), &[], ciphertext, tag)?;
+
+    // Padding, then the delimiter
+    while plaintext.last() == Some(&0) {
+        plaintext.pop();
+    }
+    if plaintext.pop() != Some(2) {
+        anyhow::bail!("The push body is not a single final record");
+    }
+    Ok(plaintext)
+}
+
+/// The content encryption key and nonce (RFC 8291 section 3.4, RFC 8188)
+fn content_keys(
+    shared: &[u8],
+    auth: &[u8],
+    browser_public: &[u8],
+    sender_public: &[u8],
+    salt: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(browser_public);
+    key_info.extend_from_slice(sender_public);
+    let ikm = hkdf(auth, shared, &key_info, 32)?;
+
+    let key = hkdf(salt, &ikm, b"Content-Encoding: aes128gcm\0", 16)?;
+    let nonce = hkdf(salt, &ikm, b"Content-Encoding: nonce\0", 12)?;
+    Ok((key, nonce))
+}
+
+/// HKDF-SHA256 for outputs of at most one block
+fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>> {
+    let prk = hmac(salt, ikm)?;
+    let mut block = info.to_vec();
+    block.push(1);
+    let mut okm = hmac(&prk, &block)?;
+    okm.truncate(len);
+    Ok(okm)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn ecdh(own: &EcKey<Private>, peer: &EcKey<Public>) -> Result<Vec<u8>> {
+    let own = PKey::from_ec_key(own.clone())?;
+    let peer = PKey::from_ec_key(peer.clone())?;
+    let mut deriver = Deriver::new(&own)?;
+    deriver.set_peer(&peer)?;
+    Ok(deriver.derive_to_vec()?)
+}
+
+fn p256() -> Result<EcGroup> {
+    Ok(EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?)
+}
+
+/// The uncompressed public point of a P-256 key
+pub fn public_key_bytes(key: &EcKey<Private>) -> Result<Vec<u8>> {
+    let mut ctx = BigNumContext::new()?;
+    Ok(key.public_key().to_bytes(key.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)?)
+}
+
+/// A browser's `p256dh` key, which must be an uncompressed point
+fn browser_key(bytes: &[u8]) -> Result<EcKey<Public>> {
+    if bytes.len() != PUBLIC_KEY_LEN || bytes[0] != 4 {
+        anyhow::bail!("Not an uncompressed P-256 public key");
+    }
+    browser_key_from(bytes)
+}
+
+fn browser_key_from(bytes: &[u8]) -> Result<EcKey<Public>> {
+    let group = p256()?;
+    let mut ctx = BigNumContext::new()?;
+    let point = EcPoint::from_bytes(&group, bytes, &mut ctx)?;
+    let key = EcKey::from_public_key(&group, &point)?;
+    key.check_key()?;
+    Ok(key)
+}
+
+/// Pushes the wisdom to every subscription that is due at `now`, forgetting
+/// expired ones, and returns the endpoints it was pushed to
+pub async fn push_due(state: &AppState, now: DateTime<Utc>) -> Vec<String> {
+    let mut pushed = Vec::new();
+    let Some(push) = &state.web_push else {
+        return pushed;
+    };
+    for subscription in push.subscriptions().await {
+        let Some(date) = subscription.due_date(now) else {
+            continue;
+        };
+
+        // Left unsent while only the corpus stands in, to be pushed on a later tick
+        let Some(wisdom) = state.broadcast_wisdom(date).await else {
+            continue;
+        };
+        // Marked first, so a failing push service isn't retried every tick
+        push.mark_sent(&subscription.endpoint, date).await;
+        let payload = wisdom_payload(date, &wisdom, &state.config.public_url).to_string();
+        match push.send(&subscription, payload.as_bytes()).await {
+            Ok(Delivery::Sent) => pushed.push(subscription.endpoint),
+            Ok(Delivery::Gone) => {
+                info!("Forgetting an expired push subscription");
+                push.unsubscribe(&subscription.endpoint).await;
+            }
+            Err(err) => warn!("Failed to push {} wisdom: {:#}", date, err),
+        }
+    }
+    pushed
+}
+
+/// Starts the background task that sends the morning pushes, if Web Push is enabled
+pub fn spawn_scheduler(state: AppState) -> Option<JoinHandle<()>> {
+    state.web_push.as_ref()?;
+    info!("Pushing the daily wisdom to subscribed browsers");
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            push_due(&state, Utc::now()).await;
+        }
+    }))
+}
@@ -0,0 +1,126 @@
+// The account page: signing up and logging in with a password or a passkey
+// (see src/routes/account.rs). Reloads the page whenever who is signed in changes.
+document.addEventListener('DOMContentLoaded', function() {
+    const status = document.getElementById('account-status');
+    if (!status) {
+        return;
+    }
+
+    // WebAuthn wants bytes, the server speaks base64url
+    function toBytes(base64url) {
+        const base64 = (base64url + '='.repeat((4 - base64url.length % 4) % 4)).replace(/-/g, '+').replace(/_/g, '/');
+        return Uint8Array.from(atob(base64), (c) => c.charCodeAt(0));
+    }
+
+    function toBase64url(buffer) {
+        const bytes = String.fromCharCode(...new Uint8Array(buffer));
+        return btoa(bytes).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+    }
+
+    // PublicKeyCredential.toJSON(), for browsers that don't have it yet
+    function credentialJson(credential) {
+        if (typeof credential.toJSON === 'function') {
+            return credential.toJSON();
+        }
+        const response = {};
+        for (const name of ['clientDataJSON', 'attestationObject', 'authenticatorData', 'signature', 'userHandle']) {
+            if (credential.response[name]) {
+                response[name] = toBase64url(credential.response[name]);
+            }
+        }
+        return { id: credential.id, rawId: toBase64url(credential.rawId), type: credential.type, response };
+    }
+
+    async function post(url, body) {
+        const response = await fetch(url, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body || {}),
+        });
+        if (!response.ok) {
+            throw new Error(await response.text() || 'The cat could not do that just now.');
+        }
+        return response.status === 204 ? null : response.json();
+    }
+
+    // Runs an action, reporting failures and reloading on success
+    function act(action) {
+        return async function(e) {
+            if (e) {
+                e.preventDefault();
+            }
+            status.textContent = '';
+            try {
+                await action();
+                window.location.reload();
+            } catch (error) {
+                status.textContent = error.name === 'NotAllowedError' ? 'The passkey was cancelled.' : error.message;
+            }
+        };
+    }
+
+    async function createPasskey(username, merge) {
+        const options = await post('/api/v1/account/passkeys/register-options', { username });
+        const credential = await navigator.credentials.create({ publicKey: {
+            ...options,
+            challenge: toBytes(options.challenge),
+            user: { ...options.user, id: toBytes(options.user.id) },
+            excludeCredentials: options.excludeCredentials.map((c) => ({ ...c, id: toBytes(c.id) })),
+        } });
+        await post('/api/v1/account/passkeys/register', { credential: credentialJson(credential), merge_session: merge });
+    }
+
+    async function usePasskey() {
+        const options = await post('/api/v1/account/passkeys/login-options');
+        const credential = await navigator.credentials.get({ publicKey: {
+            ...options,
+            challenge: toBytes(options.challenge),
+            allowCredentials: options.allowCredentials.map((c) => ({ ...c, id: toBytes(c.id) })),
+        } });
+        await post('/api/v1/account/passkeys/login', { credential: credentialJson(credential) });
+    }
+
+    const passkeys = 'PublicKeyCredential' in window;
+    document.querySelectorAll('#signup-passkey, #login-passkey, #add-passkey').forEach((button) => {
+        button.hidden = !passkeys;
+    });
+
+    const signupForm = document.getElementById('signup-form');
+    if (signupForm) {
+        const username = document.getElementById('signup-username');
+        const password = document.getElementById('signup-password');
+        const merge = document.getElementById('signup-merge');
+
+        signupForm.addEventListener('submit', act(async () => {
+            if (!password.value) {
+                throw new Error('Choose a password, or sign up with a passkey.');
+            }
+            await post('/api/v1/account/signup', { username: username.value, password: password.value, merge_session: merge.checked });
+        }));
+        document.getElementById('signup-passkey').addEventListener('click', act(async () => {
+            if (!username.reportValidity()) {
+                throw new Error('');
+            }
+            await createPasskey(username.value, merge.checked);
+        }));
+
+        document.getElementById('login-form').addEventListener('submit', act(() => post('/api/v1/account/login', {
+            username: document.getElementById('login-username').value,
+            password: document.getElementById('login-password').value,
+        })));
+        document.getElementById('login-passkey').addEventListener('click', act(usePasskey));
+        return;
+    }
+
+    document.getElementById('add-passkey').addEventListener('click', act(() => createPasskey(null, false)));
+    document.getElementById('logout').addEventListener('click', act(() => post('/api/v1/account/logout')));
+    document.getElementById('delete-account').addEventListener('click', act(async () => {
+        if (!window.confirm('Delete your account, your conversation and your readings? This cannot be undone.')) {
+            throw new Error('');
+        }
+        const response = await fetch('/api/v1/account', { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('The cat could not delete the account just now.');
+        }
+    }));
+});
@@ -1,4 +1,19 @@
 document.addEventListener('DOMContentLoaded', function() {
+    // Remember the visitor's time zone so "today's" wisdom rolls over at local midnight
+    try {
+        const timeZone = Intl.DateTimeFormat().resolvedOptions().timeZone;
+        if (timeZone) {
+            document.cookie = `tz=${encodeURIComponent(timeZone)}; path=/; max-age=31536000; SameSite=Lax`;
+        }
+    } catch (e) {
+        // Intl not available, the server falls back to the site time zone
+    }
+    
+    // Cache the site for offline use (see static/sw.js)
+    if ('serviceWorker' in navigator) {
+        navigator.serviceWorker.register('/sw.js').catch((error) => console.error('Service worker:', error));
+    }
+    
     // Mobile menu toggle
     const mobileMenuToggle = document.getElementById('mobile-menu-toggle');
     const mainNav = document.getElementById('main-nav');
@@ -40,10 +55,19 @@ document.addEventListener('DOMContentLoaded', function() {
         openChatBtn.classList.remove('hidden');
     });
     
+    // Say so while there is no connection; replies then come from the offline corpus
+    const offlineBadge = document.getElementById('offline-badge');
+    function updateOfflineBadge() {
+        offlineBadge.hidden = navigator.onLine;
+    }
+    window.addEventListener('online', updateOfflineBadge);
+    window.addEventListener('offline', updateOfflineBadge);
+    updateOfflineBadge();
+    
     // Track conversation state
     let conversationDepth = 0;
-    let currentTopic = null;
-    let suggestedTopics = [];
+    let currentTopic = null;  // Id of the suggested topic the visitor picked
+    let suggestedTopics = []; // [{ id, title, focus }] from the server
     
     // Send message function
     function sendMessage() {
@@ -71,15 +95,15 @@ document.addEventListener('DOMContentLoaded', function() {
         chatMessages.scrollTop = chatMessages.scrollHeight;
         
         // Send to API with conversation metadata
-        fetch('/api/chat', {
+        fetch('/api/v1/chat', {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json',
             },
             body: JSON.stringify({ 
                 message: message,
-                conversationDepth: conversationDepth,
-                currentTopic: currentTopic
+                conversation_depth: conversationDepth,
+                current_topic: currentTopic
             }),
         })
         .then(response => response.json())
@@ -87,11 +111,15 @@ document.addEventListener('DOMContentLoaded', function() {
             // Remove typing indicator
             chatMessages.removeChild(typingIndicator);
             
-            // Add cat response
-            addMessage('cat', data.message);
+            // Add cat response, marked when the service worker answered it offline
+            addMessage('cat', data.message, data.offline);
+            if (data.offline) {
+                offlineBadge.hidden = false;
+            }
             
-            // Update conversation depth
+            // Update conversation depth, and the topic the server is steering by
             conversationDepth++;
+            currentTopic = data.current_topic ? data.current_topic.id : null;
             
             // After a few exchanges, offer topic exploration
             if (conversationDepth === 3) {
@@ -101,8 +129,8 @@ document.addEventListener('DOMContentLoaded', function() {
             }
             
             // If the response includes suggested topics, store them
-            if (data.suggestedTopics && data.suggestedTopics.length > 0) {
-                suggestedTopics = data.suggestedTopics;
+            if (data.suggested_topics && data.suggested_topics.length > 0) {
+                suggestedTopics = data.suggested_topics;
                 if (suggestedTopics.length > 0) {
                     setTimeout(() => {
                         addTopicSuggestions(suggestedTopics);
@@ -192,12 +220,13 @@ document.addEventListener('DOMContentLoaded', function() {
         
         topics.forEach(topic => {
             const topicButton = document.createElement('button');
-            topicButton.textContent = topic;
+            topicButton.textContent = topic.title;
+            topicButton.title = topic.focus;
             topicButton.addEventListener('click', () => {
-                // Set current topic
-                currentTopic = topic;
+                // Ask the server to steer the conversation toward this topic
+                currentTopic = topic.id;
                 // Send a message to explore this topic
-                chatInput.value = `Let's talk about ${topic}`;
+                chatInput.value = `Let's talk about ${topic.title}`;
                 sendMessage();
                 // Remove the suggestions
                 chatMessages.removeChild(messageDiv);
@@ -217,7 +246,7 @@ document.addEventListener('DOMContentLoaded', function() {
     }
     
     // Add message to chat
-    function addMessage(role, content) {
+    function addMessage(role, content, offline) {
         const messageDiv = document.createElement('div');
         messageDiv.className = `message ${role}-message`;
         
@@ -232,6 +261,13 @@ document.addEventListener('DOMContentLoaded', function() {
         paragraph.textContent = content;
         
         messageContent.appendChild(paragraph);
+        if (offline) {
+            const badge = document.createElement('span');
+            badge.className = 'offline-badge';
+            badge.textContent = 'Offline';
+            badge.title = 'No connection: a Whispurr from the offline corpus';
+            messageContent.appendChild(badge);
+        }
         messageDiv.appendChild(avatar);
         messageDiv.appendChild(messageContent);
         
@@ -352,16 +388,4 @@ document.addEventListener('DOMContentLoaded', function() {
     // All social media sharing functionality has been removed
     // Using copy-to-clipboard functionality for sharing instead, which provides
     // a more consistent experience across platforms and gives users more control
-    
-    // Newsletter form
-    const newsletterForm = document.getElementById('newsletter-form');
-    if (newsletterForm) {
-        newsletterForm.addEventListener('submit', function(e) {
-            e.preventDefault();
-            const email = document.getElementById('email').value;
-            // Here you would normally send this to your backend
-            alert('Thank you for subscribing! The Enlightened Cat will be in touch soon.');
-            document.getElementById('email').value = '';
-        });
-    }
 });
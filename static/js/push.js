@@ -0,0 +1,79 @@
+// Morning Whispurr notifications: subscribes this browser to Web Push with
+// the time picked on the wisdom page (see src/routes/push.rs)
+document.addEventListener('DOMContentLoaded', function() {
+    const form = document.getElementById('push-form');
+    if (!form) {
+        return;
+    }
+    const status = document.getElementById('push-status');
+    const unsubscribeButton = document.getElementById('push-unsubscribe');
+
+    if (!('serviceWorker' in navigator) || !('PushManager' in window)) {
+        form.hidden = true;
+        status.textContent = 'This browser cannot receive notifications from the cat.';
+        return;
+    }
+
+    // applicationServerKey wants bytes, the server gives base64url
+    function keyBytes(base64url) {
+        const base64 = (base64url + '='.repeat((4 - base64url.length % 4) % 4)).replace(/-/g, '+').replace(/_/g, '/');
+        return Uint8Array.from(atob(base64), (c) => c.charCodeAt(0));
+    }
+
+    async function currentSubscription() {
+        const registration = await navigator.serviceWorker.register('/sw.js');
+        return { registration, subscription: await registration.pushManager.getSubscription() };
+    }
+
+    currentSubscription().then(({ subscription }) => {
+        unsubscribeButton.hidden = !subscription;
+    }).catch(() => {});
+
+    form.addEventListener('submit', async function(e) {
+        e.preventDefault();
+        try {
+            if (await Notification.requestPermission() !== 'granted') {
+                status.textContent = 'Notifications are blocked for this site.';
+                return;
+            }
+            const { registration } = await currentSubscription();
+            const key = await fetch('/api/v1/push/public-key').then((response) => response.json());
+            const subscription = await registration.pushManager.subscribe({
+                userVisibleOnly: true,
+                applicationServerKey: keyBytes(key.public_key),
+            });
+            const response = await fetch('/api/v1/push/subscriptions', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({
+                    ...subscription.toJSON(),
+                    time: document.getElementById('push-time').value,
+                    timezone: Intl.DateTimeFormat().resolvedOptions().timeZone,
+                }),
+            });
+            if (!response.ok) {
+                throw new Error(await response.text());
+            }
+            const saved = await response.json();
+            status.textContent = `The cat will whispurr at ${saved.time} every morning.`;
+            unsubscribeButton.hidden = false;
+        } catch (err) {
+            console.error('Push subscription failed:', err);
+            status.textContent = 'The cat could not set up notifications just now.';
+        }
+    });
+
+    unsubscribeButton.addEventListener('click', async function() {
+        const { subscription } = await currentSubscription();
+        if (subscription) {
+            await fetch('/api/v1/push/subscriptions', {
+                method: 'DELETE',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ endpoint: subscription.endpoint }),
+            });
+            await subscription.unsubscribe();
+        }
+        status.textContent = 'No more notifications from the cat.';
+        unsubscribeButton.hidden = true;
+    });
+});
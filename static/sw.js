@@ -0,0 +1,180 @@
+// The Enlightened Cat's service worker, served at /sw.js
+//
+// Keeps the site usable without a connection (see src/routes/offline.rs):
+// the shell, CSS and JS, the last seven days of wisdom and today's quantum
+// field are cached from /api/offline-bundle. Offline chat answers from the
+// bundle's corpus, flagged with `offline: true` so the page can say so.
+//
+// Also shows the morning Whispurr pushed by the server (see src/webpush.rs)
+// and opens its page when the notification is clicked.
+
+const CACHE = 'enlightened-cat-v1';
+const BUNDLE_URL = '/api/offline-bundle';
+
+// Caches the bundle, then every page and asset it names that isn't cached yet
+async function refreshBundle() {
+    const cache = await caches.open(CACHE);
+    const response = await fetch(BUNDLE_URL);
+    if (!response.ok) {
+        return;
+    }
+    await cache.put(BUNDLE_URL, response.clone());
+
+    const bundle = await response.json();
+    const urls = bundle.shell.concat(bundle.wisdom.map((day) => day.url));
+    await Promise.all(urls.map(async (url) => {
+        if (!(await cache.match(url))) {
+            await cache.add(url).catch(() => {});
+        }
+    }));
+}
+
+async function cachedBundle() {
+    const response = await caches.match(BUNDLE_URL);
+    return response ? response.json() : null;
+}
+
+function jsonResponse(body) {
+    return new Response(JSON.stringify(body), { headers: { 'Content-Type': 'application/json' } });
+}
+
+// The same message always gets the same corpus Whispurr
+function offlineReply(bundle, message) {
+    const corpus = bundle && bundle.corpus.length ? bundle.corpus : ['The connection is resting. So can you.'];
+    let hash = 0;
+    for (const char of message) {
+        hash = (hash * 31 + char.codePointAt(0)) >>> 0;
+    }
+    return corpus[hash % corpus.length];
+}
+
+// Network first; without one, what the bundle has instead
+async function offlineApi(request, url) {
+    try {
+        return await fetch(request.clone());
+    } catch (error) {
+        const bundle = await cachedBundle();
+        if (url.pathname === '/api/v1/chat') {
+            const { message = '' } = await request.json().catch(() => ({}));
+            return jsonResponse({
+                message: offlineReply(bundle, message),
+                suggested_topics: null,
+                current_topic: null,
+                provenance: 'corpus',
+                safety: null,
+                offline: true,
+            });
+        }
+        if (!bundle) {
+            throw error;
+        }
+        if (url.pathname === '/api/v1/quantum-field') {
+            return jsonResponse(bundle.quantum_field);
+        }
+        const today = bundle.wisdom[0];
+        if (!today) {
+            throw error;
+        }
+        return jsonResponse({
+            wisdom: today.wisdom,
+            date: today.date,
+            timezone: bundle.timezone,
+            timestamp: new Date().toISOString(),
+            provenance: today.provenance,
+        });
+    }
+}
+
+// Pages the bundle names: the shell and the days of wisdom. Only these are
+// kept, since others can be personal (an account, a private reading, a
+// newsletter link) or for admins only
+async function isBundled(url) {
+    const bundle = await cachedBundle();
+    const pages = bundle ? bundle.shell.concat(bundle.wisdom.map((day) => day.url)) : [];
+    return url.search === '' && pages.includes(url.pathname);
+}
+
+// Pages: network first, keeping a copy of bundled ones; offline, the copy or the home page
+async function page(request, url) {
+    const cache = await caches.open(CACHE);
+    try {
+        const response = await fetch(request);
+        if (response.ok && await isBundled(url)) {
+            await cache.put(request, response.clone());
+        }
+        return response;
+    } catch (error) {
+        return (await cache.match(request)) || (await cache.match('/')) || Response.error();
+    }
+}
+
+// Assets: from the cache, refreshed in the background
+async function asset(request) {
+    const cache = await caches.open(CACHE);
+    const cached = await cache.match(request) || await cache.match(request, { ignoreSearch: true });
+    const fresh = fetch(request).then((response) => {
+        if (response.ok) {
+            cache.put(request, response.clone());
+        }
+        return response;
+    });
+    return cached || fresh;
+}
+
+self.addEventListener('install', (event) => {
+    event.waitUntil(refreshBundle().then(() => self.skipWaiting()));
+});
+
+self.addEventListener('activate', (event) => {
+    event.waitUntil(caches.keys()
+        .then((names) => Promise.all(names.filter((name) => name !== CACHE).map((name) => caches.delete(name))))
+        .then(() => self.clients.claim()));
+});
+
+self.addEventListener('fetch', (event) => {
+    const request = event.request;
+    const url = new URL(request.url);
+    if (url.origin !== self.location.origin) {
+        return;
+    }
+
+    if (request.method === 'POST' && url.pathname === '/api/v1/chat') {
+        event.respondWith(offlineApi(request, url));
+    } else if (request.method !== 'GET') {
+        return;
+    } else if (['/api/v1/quantum-field', '/api/v1/daily-wisdom'].includes(url.pathname)) {
+        event.respondWith(offlineApi(request, url));
+    } else if (request.mode === 'navigate') {
+        event.respondWith(page(request, url));
+        // Each visit online keeps the bundle current; it answers 304 when nothing changed
+        event.waitUntil(refreshBundle().catch(() => {}));
+    } else if (url.pathname.startsWith('/static/') || url.pathname === '/manifest.webmanifest') {
+        event.respondWith(asset(request));
+    }
+});
+
+self.addEventListener('push', (event) => {
+    let whispurr = {};
+    try {
+        whispurr = event.data ? event.data.json() : {};
+    } catch (e) {
+        // Not ours to read; show something rather than nothing
+    }
+
+    event.waitUntil(self.registration.showNotification(whispurr.title || 'The Enlightened Cat', {
+        body: whispurr.body || 'A new Whispurr is waiting for you.',
+        icon: '/static/images/enlightened-cat.svg',
+        tag: 'daily-wisdom',
+        data: { url: whispurr.url || '/wisdom' },
+    }));
+});
+
+self.addEventListener('notificationclick', (event) => {
+    event.notification.close();
+    const url = event.notification.data && event.notification.data.url;
+
+    event.waitUntil(clients.matchAll({ type: 'window', includeUncontrolled: true }).then((windows) => {
+        const open = windows.find((client) => client.url === url);
+        return open ? open.focus() : clients.openWindow(url || '/wisdom');
+    }));
+});
@@ -0,0 +1,465 @@
+mod common;
+
+use reqwest::header::{COOKIE, SET_COOKIE};
+use serde_json::{json, Value};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_passkey::SoftAuthenticator;
+
+use common::{six_seeds, spawn_app_with, TestApp};
+
+/// Over plain http, so the client's cookie store sends the account cookies back
+const ORIGIN: &str = "http://cat.test";
+
+async fn spawn_account_app() -> TestApp {
+    spawn_app_with(|config| config.public_url = ORIGIN.to_string()).await
+}
+
+async fn sign_up(app: &TestApp, username: &str, password: &str, merge_session: bool) -> reqwest::Response {
+    let body = json!({ "username": username, "password": password, "merge_session": merge_session });
+    app.post_json("/api/v1/account/signup", body).await
+}
+
+async fn log_in(app: &TestApp, username: &str, password: &str) -> reqwest::Response {
+    app.post_json("/api/v1/account/login", json!({ "username": username, "password": password })).await
+}
+
+/// Runs a passkey registration with `authenticator`, for a new account named `username`
+/// or, with `None`, for the signed-in one
+async fn register_passkey(app: &TestApp, authenticator: &SoftAuthenticator, username: Option<&str>) -> reqwest::Response {
+    let options = app.post_json("/api/v1/account/passkeys/register-options", json!({ "username": username })).await;
+    assert_eq!(options.status(), 200);
+    let credential = authenticator.create(&options.json().await.unwrap()).unwrap();
+    app.post_json("/api/v1/account/passkeys/register", json!({ "credential": credential })).await
+}
+
+/// A signed passkey login from `authenticator`, not yet sent
+async fn passkey_assertion(app: &TestApp, authenticator: &SoftAuthenticator) -> Value {
+    let options: Value = app.post_json("/api/v1/account/passkeys/login-options", json!({})).await.json().await.unwrap();
+    assert_eq!(options["rpId"], "cat.test");
+    json!({ "credential": authenticator.get(&options).unwrap() })
+}
+
+fn set_cookies(response: &reqwest::Response) -> Vec<String> {
+    response.headers().get_all(SET_COOKIE).iter().map(|value| value.to_str().unwrap().to_string()).collect()
+}
+
+/// The value a response sets `name` to
+fn cookie_value(response: &reqwest::Response, name: &str) -> String {
+    set_cookies(response)
+        .iter()
+        .find_map(|cookie| cookie.strip_prefix(&format!("{}=", name)).map(|rest| rest.split(';').next().unwrap().to_string()))
+        .unwrap_or_else(|| panic!("no {} cookie set", name))
+}
+
+/// What the model was sent for the last chat reply, after the system prompt
+fn chat_messages(app: &TestApp) -> Vec<String> {
+    let requests = app.mock.requests();
+    let chat = requests.iter().rev().find(|request| request.messages[0].content.starts_with("You are The Enlightened Cat, a wise feline guide")).unwrap();
+    chat.messages[1..].iter().map(|message| message.content.clone()).collect()
+}
+
+#[tokio::test]
+async fn anonymous_visitors_keep_everything_working() {
+    let app = spawn_account_app().await;
+    app.mock.push(MockReply::content("No name needed."));
+
+    let me = app.get("/api/v1/account").await;
+    let chat = app.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+    let page = app.get("/account").await.text().await.unwrap();
+
+    assert_eq!(me.status(), 401);
+    assert_eq!(chat.status(), 200);
+    assert!(page.contains("signup-form"));
+    assert!(page.contains("minlength=\"8\""));
+    assert!(page.contains("noindex"));
+    assert!(app.get("/").await.text().await.unwrap().contains("href=\"/account\""));
+    assert!(app.get("/robots.txt").await.text().await.unwrap().contains("Disallow: /account"));
+    let openapi = app.get_json("/api/v1/openapi.json").await;
+    assert!(openapi["paths"]["/api/v1/account/signup"]["post"].is_object());
+    assert!(openapi["paths"]["/api/v1/account/passkeys/login"]["post"].is_object());
+}
+
+#[tokio::test]
+async fn password_accounts_sign_up_log_out_and_log_in() {
+    let app = spawn_account_app().await;
+
+    let signup = sign_up(&app, "Whiskers", "correct horse battery", false).await;
+    assert_eq!(signup.status(), 201);
+    let cookies = set_cookies(&signup);
+    assert!(cookies.iter().any(|cookie| cookie.starts_with("cat_account=")));
+    assert!(cookies.iter().all(|cookie| cookie.contains("HttpOnly") && cookie.contains("SameSite=Lax")));
+    assert!(cookies.iter().all(|cookie| !cookie.contains("Secure")), "PUBLIC_URL is http");
+    assert_eq!(signup.json::<Value>().await.unwrap()["username"], "whiskers");
+
+    let me = app.get_json("/api/v1/account").await;
+    assert_eq!(me["username"], "whiskers");
+    assert_eq!(me["has_password"], true);
+    assert!(app.get("/account").await.text().await.unwrap().contains("Welcome back, whiskers"));
+
+    let stored = std::fs::read_to_string(app.data_dir.path().join("accounts.json")).unwrap();
+    assert!(stored.contains("$argon2id$"));
+    assert!(!stored.contains("correct horse battery"));
+
+    assert_eq!(app.post_json("/api/v1/account/logout", json!({})).await.status(), 204);
+    assert_eq!(app.get("/api/v1/account").await.status(), 401);
+
+    assert_eq!(log_in(&app, "WHISKERS", "correct horse battery").await.status(), 200);
+    assert_eq!(app.get_json("/api/v1/account").await["username"], "whiskers");
+    assert!(app.state().accounts.find("whiskers").await.unwrap().last_login.is_some());
+}
+
+#[tokio::test]
+async fn wrong_passwords_and_unknown_names_are_turned_away_alike() {
+    let app = spawn_account_app().await;
+    sign_up(&app, "whiskers", "correct horse battery", false).await;
+    app.post_json("/api/v1/account/logout", json!({})).await;
+
+    let wrong = log_in(&app, "whiskers", "incorrect horse").await;
+    let unknown = log_in(&app, "mittens", "correct horse battery").await;
+
+    assert_eq!(wrong.status(), 401);
+    assert_eq!(unknown.status(), 401);
+    assert_eq!(wrong.text().await.unwrap(), unknown.text().await.unwrap());
+    assert_eq!(app.get("/api/v1/account").await.status(), 401);
+}
+
+#[tokio::test]
+async fn names_are_taken_in_any_case() {
+    let app = spawn_account_app().await;
+    sign_up(&app, "whiskers", "correct horse battery", false).await;
+
+    let again = sign_up(&app, "other", "correct horse battery", false).await;
+    let taken = app
+        .stranger()
+        .post(app.url("/api/v1/account/signup"))
+        .json(&json!({ "username": "Whiskers", "password": "another password" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(again.status(), 409, "signed in already");
+    assert_eq!(taken.status(), 409);
+    assert_eq!(app.state().accounts.len().await, 1);
+}
+
+#[tokio::test]
+async fn bad_names_and_passwords_are_rejected() {
+    let app = spawn_account_app().await;
+
+    for (username, password) in [("ab", "long enough password"), ("no spaces", "long enough password"), ("whiskers", "short")] {
+        let response = sign_up(&app, username, password, false).await;
+        assert_eq!(response.status(), 422, "{:?} / {:?}", username, password);
+    }
+    assert!(app.state().accounts.is_empty().await);
+}
+
+#[tokio::test]
+async fn cookies_are_secure_over_https() {
+    let app = common::spawn_app().await;
+
+    let signup = sign_up(&app, "whiskers", "correct horse battery", false).await;
+
+    assert_eq!(signup.status(), 201);
+    let cookies = set_cookies(&signup);
+    assert_eq!(cookies.len(), 2);
+    assert!(cookies.iter().all(|cookie| cookie.contains("; Secure")));
+}
+
+#[tokio::test]
+async fn tampered_cookies_are_anonymous() {
+    let app = spawn_account_app().await;
+    sign_up(&app, "mittens", "correct horse battery", false).await;
+    let signup = app
+        .stranger()
+        .post(app.url("/api/v1/account/signup"))
+        .json(&json!({ "username": "whiskers", "password": "correct horse battery" }))
+        .send()
+        .await
+        .unwrap();
+    let cookie = cookie_value(&signup, "cat_account");
+    let mittens = app.state().accounts.find("mittens").await.unwrap();
+    let (_, rest) = cookie.split_once('.').unwrap();
+    let forged = format!("{}.{}", mittens.id, rest);
+
+    let me = |value: String| {
+        let request = reqwest::Client::new().get(app.url("/api/v1/account")).header(COOKIE, format!("cat_account={}", value));
+        async move { request.send().await.unwrap() }
+    };
+
+    let genuine = me(cookie.clone()).await;
+    assert_eq!(genuine.status(), 200);
+    assert_eq!(genuine.json::<Value>().await.unwrap()["username"], "whiskers");
+    assert_eq!(me(forged).await.status(), 401, "another account's id under this signature");
+    assert_eq!(me(format!("{}x", cookie)).await.status(), 401);
+    assert_eq!(me(String::new()).await.status(), 401);
+
+    // The key lives in the data directory, so sign-ins survive a restart
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(COOKIE, format!("cat_account={}", cookie).parse().unwrap());
+    assert_eq!(app.state().accounts.from_headers(&headers).await.unwrap().username, "whiskers");
+}
+
+#[tokio::test]
+async fn signing_up_can_keep_the_anonymous_conversation_and_readings() {
+    let app = spawn_account_app().await;
+    app.mock.push(MockReply::content("Purr first."));
+    app.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+    app.mock.push(MockReply::content(six_seeds()));
+    let reading = app.get_json("/api/v1/quantum-field/collapse?index=3").await["reading_id"].as_str().unwrap().to_string();
+
+    assert_eq!(sign_up(&app, "whiskers", "correct horse battery", true).await.status(), 201);
+    app.post_json("/api/v1/account/logout", json!({})).await;
+
+    // Elsewhere, on a device that never saw the anonymous session
+    let elsewhere = app.stranger();
+    let login = elsewhere
+        .post(app.url("/api/v1/account/login"))
+        .json(&json!({ "username": "whiskers", "password": "correct horse battery" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(login.status(), 200);
+    app.mock.push(MockReply::content("Purr again."));
+    elsewhere.post(app.url("/api/v1/chat")).json(&json!({ "message": "It's me" })).send().await.unwrap();
+
+    let contents = chat_messages(&app);
+    assert_eq!(contents, ["Hello", "Purr first.", "It's me"]);
+    let html = elsewhere.get(app.url(&format!("/quantum-field/reading/{}", reading))).send().await.unwrap().text().await.unwrap();
+    assert!(html.contains("reading-settings"), "the account owns the reading");
+}
+
+#[tokio::test]
+async fn an_accounts_session_is_only_reached_with_its_signed_cookie() {
+    let app = spawn_account_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+    let anonymous = cookie_value(&app.post_json("/api/v1/chat", json!({ "message": "Hello" })).await, "cat_session");
+    let signup = sign_up(&app, "whiskers", "correct horse battery", true).await;
+    let account_session = cookie_value(&signup, "cat_session");
+    assert_ne!(account_session, anonymous, "the conversation moves to a fresh session");
+
+    // Whoever planted or copied a cat_session cookie starts a conversation of their own
+    for id in [&anonymous, &account_session] {
+        let request = reqwest::Client::new().post(app.url("/api/v1/chat")).header(COOKIE, format!("cat_session={}", id));
+        request.json(&json!({ "message": "Intruding" })).send().await.unwrap();
+        assert_eq!(chat_messages(&app), ["Intruding"]);
+    }
+
+    app.post_json("/api/v1/chat", json!({ "message": "Still me" })).await;
+    assert_eq!(chat_messages(&app), ["Hello", "Purr.", "Still me"]);
+}
+
+#[tokio::test]
+async fn logging_in_and_out_renews_the_accounts_session() {
+    let app = spawn_account_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+    sign_up(&app, "whiskers", "correct horse battery", false).await;
+    app.post_json("/api/v1/chat", json!({ "message": "Remember me" })).await;
+    app.mock.push(MockReply::content(six_seeds()));
+    let reading = app.get_json("/api/v1/quantum-field/collapse?index=3").await["reading_id"].as_str().unwrap().to_string();
+    let session_id = || async { app.state().accounts.find("whiskers").await.unwrap().session_id };
+    let signed_up = session_id().await;
+
+    app.post_json("/api/v1/account/logout", json!({})).await;
+    let signed_out = session_id().await;
+    let login = log_in(&app, "whiskers", "correct horse battery").await;
+
+    assert_ne!(signed_out, signed_up);
+    assert_ne!(session_id().await, signed_out);
+    assert_eq!(cookie_value(&login, "cat_session"), session_id().await);
+    assert_eq!(app.state().sessions.user_message_count(&signed_up).await, 0);
+    app.post_json("/api/v1/chat", json!({ "message": "Back again" })).await;
+    assert_eq!(chat_messages(&app), ["Remember me", "Purr.", "Back again"]);
+    let html = app.get(&format!("/quantum-field/reading/{}", reading)).await.text().await.unwrap();
+    assert!(html.contains("reading-settings"), "the account still owns the reading");
+}
+
+#[tokio::test]
+async fn accounts_keep_their_conversation_and_cookies_through_a_backup() {
+    let source = spawn_account_app().await;
+    source.mock.push(MockReply::content("Purr first."));
+    source.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+    let signed_up = sign_up(&source, "whiskers", "correct horse battery", true).await;
+    let account_cookie = cookie_value(&signed_up, "cat_account");
+    let backup = source.data_dir.path().join("backup.json");
+    let exported = source.run_cli(&["export", "--output", backup.to_str().unwrap()]).await.unwrap();
+    assert!(exported.contains("1 accounts"), "{}", exported);
+
+    let target = spawn_account_app().await;
+    let imported = target.run_cli(&["import", "--replace", backup.to_str().unwrap()]).await.unwrap();
+    assert!(imported.contains("1 accounts"), "{}", imported);
+
+    // As after a restart, the cookie handed out before the backup still signs in
+    let state = target.state();
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(COOKIE, format!("cat_account={}", account_cookie).parse().unwrap());
+    let account = state.accounts.from_headers(&headers).await.expect("the old cookie no longer verifies");
+    assert_eq!(account.username, "whiskers");
+    assert_eq!(state.sessions.user_message_count(&account.session_id).await, 1);
+
+    // And the password still logs in to the same conversation
+    assert_eq!(log_in(&target, "whiskers", "correct horse battery").await.status(), 200);
+    target.mock.push(MockReply::content("Purr again."));
+    target.post_json("/api/v1/chat", json!({ "message": "It's me" })).await;
+    assert_eq!(chat_messages(&target), ["Hello", "Purr first.", "It's me"]);
+}
+
+/// Makes every stored session look idle for `days`
+fn idle_all_sessions(app: &TestApp, days: i64) {
+    let path = app.data_dir.path().join("sessions.json");
+    let mut sessions: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let idle_since = chrono::Utc::now() - chrono::Duration::days(days);
+    for session in sessions.as_object_mut().unwrap().values_mut() {
+        session["last_active"] = json!(idle_since);
+    }
+    std::fs::write(&path, sessions.to_string()).unwrap();
+}
+
+#[tokio::test]
+async fn an_accounts_conversation_outlives_the_idle_sweep() {
+    let app = spawn_account_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+    assert_eq!(sign_up(&app, "whiskers", "correct horse battery", false).await.status(), 201);
+    app.post_json("/api/v1/chat", json!({ "message": "Remember me" })).await;
+    let anonymous = app.stranger();
+    anonymous.post(app.url("/api/v1/chat")).json(&json!({ "message": "Just passing" })).send().await.unwrap();
+    idle_all_sessions(&app, 10);
+
+    // A new visitor's first message sweeps idle sessions, as does the CLI
+    app.stranger().post(app.url("/api/v1/chat")).json(&json!({ "message": "Hi" })).send().await.unwrap();
+    let purged = app.run_cli(&["sessions", "purge"]).await.unwrap();
+
+    assert_eq!(purged, "Purged 0 sessions\n", "the anonymous session went with the sweep");
+    assert_eq!(app.state().sessions.len().await, 2);
+    app.post_json("/api/v1/account/logout", json!({})).await;
+    assert_eq!(log_in(&app, "whiskers", "correct horse battery").await.status(), 200);
+    app.post_json("/api/v1/chat", json!({ "message": "Back again" })).await;
+    assert_eq!(chat_messages(&app), ["Remember me", "Purr.", "Back again"]);
+}
+
+#[tokio::test]
+async fn signing_up_without_merging_starts_afresh() {
+    let app = spawn_account_app().await;
+    app.mock.push(MockReply::content("Purr first."));
+    app.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+
+    sign_up(&app, "whiskers", "correct horse battery", false).await;
+    app.mock.push(MockReply::content("Nice to meet you."));
+    app.post_json("/api/v1/chat", json!({ "message": "New me" })).await;
+
+    let contents = chat_messages(&app);
+    assert_eq!(contents, ["New me"]);
+}
+
+#[tokio::test]
+async fn passkeys_sign_up_and_log_in() {
+    let app = spawn_account_app().await;
+    let authenticator = SoftAuthenticator::new(ORIGIN);
+
+    let signup = register_passkey(&app, &authenticator, Some("Whiskers")).await;
+    assert_eq!(signup.status(), 201);
+    assert!(set_cookies(&signup).iter().any(|cookie| cookie.starts_with("cat_account=")));
+    let me = app.get_json("/api/v1/account").await;
+    assert_eq!(me["username"], "whiskers");
+    assert_eq!(me["has_password"], false);
+    assert_eq!(me["passkeys"].as_array().unwrap().len(), 1);
+
+    app.post_json("/api/v1/account/logout", json!({})).await;
+    assert_eq!(log_in(&app, "whiskers", "").await.status(), 401, "there is no password to log in with");
+
+    let assertion = passkey_assertion(&app, &authenticator).await;
+    let login = app.post_json("/api/v1/account/passkeys/login", assertion).await;
+    assert_eq!(login.status(), 200);
+    assert_eq!(app.get_json("/api/v1/account").await["username"], "whiskers");
+    assert_eq!(app.state().accounts.find("whiskers").await.unwrap().passkeys[0].sign_count, 1);
+}
+
+#[tokio::test]
+async fn signed_in_accounts_can_add_a_passkey() {
+    let app = spawn_account_app().await;
+    let authenticator = SoftAuthenticator::new(ORIGIN);
+    sign_up(&app, "whiskers", "correct horse battery", false).await;
+
+    let added = register_passkey(&app, &authenticator, None).await;
+
+    assert_eq!(added.status(), 201);
+    assert!(set_cookies(&added).is_empty(), "already signed in");
+    let me = app.get_json("/api/v1/account").await;
+    assert_eq!(me["has_password"], true);
+    assert_eq!(me["passkeys"].as_array().unwrap().len(), 1);
+    assert_eq!(app.state().accounts.len().await, 1);
+}
+
+#[tokio::test]
+async fn passkeys_from_another_origin_are_refused() {
+    let app = spawn_account_app().await;
+    let phishing = SoftAuthenticator::new("https://the-enlightened-cat.example");
+
+    let signup = register_passkey(&app, &phishing, Some("whiskers")).await;
+    assert_eq!(signup.status(), 422);
+    assert!(app.state().accounts.is_empty().await);
+
+    // A genuine passkey, relayed through a phishing page
+    let genuine = SoftAuthenticator::new(ORIGIN);
+    register_passkey(&app, &genuine, Some("whiskers")).await;
+    app.post_json("/api/v1/account/logout", json!({})).await;
+    let options: Value = app.post_json("/api/v1/account/passkeys/login-options", json!({})).await.json().await.unwrap();
+    let mut credential = genuine.get(&options).unwrap();
+    let relayed = phishing.create(&json!({ "rp": { "id": "cat.test" }, "challenge": options["challenge"], "user": { "id": "AA" } })).unwrap();
+    credential["response"]["clientDataJSON"] = relayed["response"]["clientDataJSON"].clone();
+
+    let login = app.post_json("/api/v1/account/passkeys/login", json!({ "credential": credential })).await;
+    assert_eq!(login.status(), 401);
+}
+
+#[tokio::test]
+async fn passkey_logins_cannot_be_replayed() {
+    let app = spawn_account_app().await;
+    let authenticator = SoftAuthenticator::new(ORIGIN);
+    register_passkey(&app, &authenticator, Some("whiskers")).await;
+    app.post_json("/api/v1/account/logout", json!({})).await;
+
+    let assertion = passkey_assertion(&app, &authenticator).await;
+    assert_eq!(app.post_json("/api/v1/account/passkeys/login", assertion.clone()).await.status(), 200);
+    app.post_json("/api/v1/account/logout", json!({})).await;
+
+    assert_eq!(app.post_json("/api/v1/account/passkeys/login", assertion).await.status(), 401);
+    assert_eq!(app.get("/api/v1/account").await.status(), 401);
+}
+
+#[tokio::test]
+async fn cloned_passkeys_are_caught_by_their_counter() {
+    let app = spawn_account_app().await;
+    let authenticator = SoftAuthenticator::new(ORIGIN);
+    register_passkey(&app, &authenticator, Some("whiskers")).await;
+    app.post_json("/api/v1/account/logout", json!({})).await;
+    let assertion = passkey_assertion(&app, &authenticator).await;
+    app.post_json("/api/v1/account/passkeys/login", assertion).await;
+    app.post_json("/api/v1/account/logout", json!({})).await;
+
+    authenticator.set_sign_count(0);
+    let assertion = passkey_assertion(&app, &authenticator).await;
+
+    assert_eq!(app.post_json("/api/v1/account/passkeys/login", assertion).await.status(), 401);
+}
+
+#[tokio::test]
+async fn deleting_an_account_deletes_its_conversation_and_readings() {
+    let app = spawn_account_app().await;
+    app.mock.push(MockReply::content("Purr first."));
+    app.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+    app.mock.push(MockReply::content(six_seeds()));
+    app.get_json("/api/v1/quantum-field/collapse?index=3").await;
+    sign_up(&app, "whiskers", "correct horse battery", true).await;
+    let session_id = app.state().accounts.find("whiskers").await.unwrap().session_id;
+
+    let response = app.client.delete(app.url("/api/v1/account")).send().await.unwrap();
+
+    assert_eq!(response.status(), 204);
+    assert!(set_cookies(&response).iter().all(|cookie| cookie.contains("Max-Age=0")));
+    assert_eq!(app.get("/api/v1/account").await.status(), 401);
+    let state = app.state();
+    assert!(state.accounts.is_empty().await);
+    assert_eq!(state.sessions.user_message_count(&session_id).await, 0);
+    assert!(app.readings_on_disk().is_empty());
+    assert_eq!(log_in(&app, "whiskers", "correct horse battery").await.status(), 401);
+}
@@ -0,0 +1,433 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use chrono::{NaiveTime, Utc};
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use serde_json::{json, Value};
+use the_enlightened_cat::activitypub::{self, SignatureHeader, PUBLIC};
+use the_enlightened_cat::backup::Backup;
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_webhook::{ReceivedWebhook, WebhookReceiver};
+
+use common::{spawn_app_with, TestApp};
+
+const ACTOR: &str = "https://the-enlightened-cat.com/ap/actor";
+
+async fn spawn_federated_app() -> TestApp {
+    spawn_app_with(|config| {
+        config.activitypub_username = Some("cat".to_string());
+        // The remote stand-ins are plain http on 127.0.0.1
+        config.activitypub_allow_local = true;
+    })
+    .await
+}
+
+/// A stand-in for a remote server with one user, alice
+struct Remote {
+    receiver: WebhookReceiver,
+    key: PKey<Private>,
+}
+
+impl Remote {
+    async fn start() -> Self {
+        let receiver = WebhookReceiver::start().await.unwrap();
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let id = receiver.url("/users/alice");
+        receiver.serve(
+            "/users/alice",
+            json!({
+                "id": id,
+                "type": "Person",
+                "preferredUsername": "alice",
+                "url": receiver.url("/@alice"),
+                "inbox": receiver.url("/users/alice/inbox"),
+                "endpoints": { "sharedInbox": receiver.url("/inbox") },
+                "publicKey": {
+                    "id": format!("{}#main-key", id),
+                    "owner": id,
+                    "publicKeyPem": String::from_utf8(key.public_key_to_pem().unwrap()).unwrap(),
+                },
+            }),
+        );
+        Self { receiver, key }
+    }
+
+    fn id(&self) -> String {
+        self.receiver.url("/users/alice")
+    }
+
+    /// POSTs an activity to the app's inbox, signed with `key`
+    async fn post(&self, app: &TestApp, activity: &Value, key: &PKey<Private>) -> reqwest::Response {
+        let body = activity.to_string();
+        let url = reqwest::Url::parse(&app.url("/ap/inbox")).unwrap();
+        let key_id = format!("{}#main-key", self.id());
+        let mut request = app.client.post(url.clone()).header("content-type", activitypub::ACTIVITY_JSON);
+        for (name, value) in activitypub::sign_request(key, &key_id, "POST", &url, Some(body.as_bytes()), Utc::now()).unwrap() {
+            request = request.header(name, value);
+        }
+        request.body(body).send().await.unwrap()
+    }
+
+    fn follow(&self) -> Value {
+        json!({ "id": format!("{}/follows/1", self.id()), "type": "Follow", "actor": self.id(), "object": ACTOR })
+    }
+
+    /// The activities the app POSTed, waiting for `count` of them
+    async fn delivered(&self, count: usize) -> Vec<ReceivedWebhook> {
+        for _ in 0..100 {
+            let posts: Vec<_> = self.receiver.received().into_iter().filter(|r| r.method == "POST").collect();
+            if posts.len() >= count {
+                return posts;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("expected {} deliveries", count);
+    }
+}
+
+async fn follower_count(app: &TestApp) -> u64 {
+    app.get_json("/ap/followers").await["totalItems"].as_u64().unwrap()
+}
+
+/// Whether the app's actor signed a delivery
+async fn signed_by_the_cat(app: &TestApp, delivery: &ReceivedWebhook) -> bool {
+    let pem = app.get_json("/ap/actor").await["publicKey"]["publicKeyPem"].as_str().unwrap().to_string();
+    let mut headers = HeaderMap::new();
+    for (name, value) in &delivery.headers {
+        headers.insert(HeaderName::from_bytes(name.as_bytes()).unwrap(), HeaderValue::from_str(value).unwrap());
+    }
+    let signature = SignatureHeader::parse(delivery.header("signature").unwrap()).unwrap();
+    let signing_string = signature.signing_string("POST", &delivery.path, &headers).unwrap();
+    signature.key_id == format!("{}#main-key", ACTOR)
+        && delivery.header("digest") == Some(activitypub::digest(delivery.body.as_bytes()).as_str())
+        && signature.verify(&pem, &signing_string)
+}
+
+#[tokio::test]
+async fn federation_is_off_without_a_username() {
+    let app = common::spawn_app().await;
+
+    let webfinger = app.get("/.well-known/webfinger?resource=acct:cat@the-enlightened-cat.com").await;
+    let actor = app.get("/ap/actor").await;
+
+    assert_eq!(webfinger.status(), 404);
+    assert_eq!(actor.status(), 404);
+    assert!(!app.data_dir.path().join("activitypub.pem").exists());
+}
+
+#[tokio::test]
+async fn webfinger_finds_the_actor_and_its_key() {
+    let app = spawn_federated_app().await;
+
+    let found = app.get("/.well-known/webfinger?resource=acct:cat@the-enlightened-cat.com").await;
+    let unknown = app.get("/.well-known/webfinger?resource=acct:dog@the-enlightened-cat.com").await;
+    let actor = app.get("/ap/actor").await;
+
+    assert_eq!(found.headers()["content-type"], "application/jrd+json");
+    let links = found.json::<Value>().await.unwrap()["links"].clone();
+    assert_eq!(links[0], json!({ "rel": "self", "type": "application/activity+json", "href": ACTOR }));
+    assert_eq!(unknown.status(), 404);
+    assert_eq!(actor.headers()["content-type"], "application/activity+json");
+    let actor: Value = actor.json().await.unwrap();
+    assert_eq!(actor["preferredUsername"], "cat");
+    assert_eq!(actor["inbox"], "https://the-enlightened-cat.com/ap/inbox");
+    let pem = actor["publicKey"]["publicKeyPem"].as_str().unwrap();
+    assert!(PKey::public_key_from_pem(pem.as_bytes()).is_ok());
+    // The key is kept, so a restart doesn't change the actor's identity
+    assert_eq!(app.state().federation.unwrap().public_key_pem(), pem);
+}
+
+#[tokio::test]
+async fn follows_are_accepted_and_undone() {
+    let app = spawn_federated_app().await;
+    let remote = Remote::start().await;
+
+    let follow = remote.post(&app, &remote.follow(), &remote.key).await;
+
+    assert_eq!(follow.status(), 202);
+    let accept = &remote.delivered(1).await[0];
+    assert_eq!(accept.path, "/users/alice/inbox");
+    assert_eq!(accept.json()["type"], "Accept");
+    assert_eq!(accept.json()["object"]["id"], remote.follow()["id"]);
+    assert!(signed_by_the_cat(&app, accept).await);
+    assert_eq!(follower_count(&app).await, 1);
+
+    let undo = json!({ "id": format!("{}/undo/1", remote.id()), "type": "Undo", "actor": remote.id(), "object": remote.follow() });
+    assert_eq!(remote.post(&app, &undo, &remote.key).await.status(), 202);
+    assert_eq!(follower_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn unsigned_forged_and_tampered_activities_are_rejected() {
+    let app = spawn_federated_app().await;
+    let remote = Remote::start().await;
+    let impostor = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let unsigned = app.client.post(app.url("/ap/inbox")).body(remote.follow().to_string()).send().await.unwrap();
+    let forged = remote.post(&app, &remote.follow(), &impostor).await;
+    let mut someone_else = remote.follow();
+    someone_else["actor"] = json!("https://elsewhere.example/users/bob");
+    let misattributed = remote.post(&app, &someone_else, &remote.key).await;
+
+    // Signed, then changed on the way
+    let url = reqwest::Url::parse(&app.url("/ap/inbox")).unwrap();
+    let body = remote.follow().to_string();
+    let key_id = format!("{}#main-key", remote.id());
+    let mut tampered = app.client.post(url.clone());
+    for (name, value) in activitypub::sign_request(&remote.key, &key_id, "POST", &url, Some(body.as_bytes()), Utc::now()).unwrap() {
+        tampered = tampered.header(name, value);
+    }
+    let tampered = tampered.body(body.replace("Follow", "Follow ")).send().await.unwrap();
+
+    assert_eq!(unsigned.status(), 401);
+    assert_eq!(forged.status(), 401);
+    assert_eq!(misattributed.status(), 401);
+    assert_eq!(tampered.status(), 401);
+    assert_eq!(follower_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn keys_are_only_fetched_from_the_actors_host() {
+    let app = spawn_federated_app().await;
+    let remote = Remote::start().await;
+    let elsewhere = Remote::start().await;
+
+    // Signed with elsewhere's key id, claiming to be remote's alice
+    let body = remote.follow().to_string();
+    let url = reqwest::Url::parse(&app.url("/ap/inbox")).unwrap();
+    let key_id = format!("{}#main-key", elsewhere.id());
+    let mut request = app.client.post(url.clone());
+    for (name, value) in activitypub::sign_request(&elsewhere.key, &key_id, "POST", &url, Some(body.as_bytes()), Utc::now()).unwrap() {
+        request = request.header(name, value);
+    }
+    let response = request.body(body).send().await.unwrap();
+
+    assert_eq!(response.status(), 401);
+    assert!(elsewhere.receiver.received().is_empty(), "the key was fetched from another host");
+    assert!(remote.receiver.received().is_empty());
+}
+
+#[tokio::test]
+async fn local_and_plain_http_actors_are_never_fetched() {
+    let app = spawn_app_with(|config| config.activitypub_username = Some("cat".to_string())).await;
+    let remote = Remote::start().await;
+
+    let response = remote.post(&app, &remote.follow(), &remote.key).await;
+
+    assert_eq!(response.status(), 401);
+    assert!(remote.receiver.received().is_empty(), "the app reached a local http server");
+}
+
+#[tokio::test]
+async fn remote_addresses_must_be_public_https() {
+    let refused = [
+        "http://93.184.215.14/users/alice",
+        "https://127.0.0.1/users/alice",
+        "https://localhost:8443/users/alice",
+        "https://169.254.169.254/latest/meta-data",
+        "https://10.1.2.3/users/alice",
+        "https://192.168.0.10/users/alice",
+        "https://100.64.0.1/users/alice",
+        "https://[::1]/users/alice",
+        "https://[fd00::1]/users/alice",
+        "https://[::ffff:127.0.0.1]/users/alice",
+        "https://[64:ff9b::a9fe:a9fe]/latest/meta-data",
+        "https://[64:ff9b:1::5db8:d70e]/users/alice",
+        "https://[2002:7f00:1::1]/users/alice",
+        "https://[2001:0:4136:e378::1]/users/alice",
+        "https://[2001:db8::1]/users/alice",
+        "https://[::7f00:1]/users/alice",
+        "ftp://93.184.215.14/users/alice",
+    ];
+    for url in refused {
+        let url = reqwest::Url::parse(url).unwrap();
+        assert!(activitypub::remote_address(&url, false).await.is_err(), "{} was allowed", url);
+    }
+
+    let public = reqwest::Url::parse("https://93.184.215.14/users/alice").unwrap();
+    assert_eq!(activitypub::remote_address(&public, false).await.unwrap(), "93.184.215.14:443".parse().unwrap());
+    // NAT64 and 6to4 addresses of a public IPv4 host are public too
+    for url in ["https://[64:ff9b::5db8:d70e]/users/alice", "https://[2002:5db8:d70e::1]/users/alice"] {
+        let url = reqwest::Url::parse(url).unwrap();
+        assert!(activitypub::remote_address(&url, false).await.is_ok(), "{} was refused", url);
+    }
+    let local = reqwest::Url::parse("http://127.0.0.1:8080/users/alice").unwrap();
+    assert_eq!(activitypub::remote_address(&local, true).await.unwrap(), "127.0.0.1:8080".parse().unwrap());
+}
+
+#[tokio::test]
+async fn the_daily_note_is_published_to_followers_once() {
+    let app = spawn_federated_app().await;
+    let remote = Remote::start().await;
+    remote.post(&app, &remote.follow(), &remote.key).await;
+    remote.delivered(1).await;
+    app.mock.push(MockReply::content("The inbox will still be there after the nap."));
+    let state = app.state();
+
+    assert_eq!(activitypub::publish_due(&state, "2020-01-01T07:59:00Z".parse().unwrap()).await, None);
+    assert_eq!(activitypub::publish_due(&state, "2020-01-01T08:01:00Z".parse().unwrap()).await, Some(1));
+    assert_eq!(activitypub::publish_due(&state, "2020-01-01T20:00:00Z".parse().unwrap()).await, None);
+
+    let create = &remote.delivered(2).await[1];
+    assert_eq!(create.path, "/inbox", "delivered to the shared inbox");
+    assert!(signed_by_the_cat(&app, create).await);
+    let note = &create.json()["object"];
+    assert_eq!(note["id"], "https://the-enlightened-cat.com/ap/notes/2020-01-01");
+    assert_eq!(note["to"], json!([PUBLIC]));
+    assert!(note["content"].as_str().unwrap().starts_with("<p>The inbox will still be there after the nap.</p>"));
+
+    assert_eq!(app.get_json("/ap/notes/2020-01-01").await["content"], note["content"]);
+    assert_eq!(app.get("/ap/notes/2020-01-02").await.status(), 404);
+    assert_eq!(app.get_json("/ap/outbox").await["orderedItems"][0]["object"]["id"], note["id"]);
+}
+
+#[tokio::test]
+async fn the_key_and_followers_move_with_a_backup() {
+    let source = spawn_federated_app().await;
+    let remote = Remote::start().await;
+    remote.post(&source, &remote.follow(), &remote.key).await;
+    remote.delivered(1).await;
+    let date = "2020-01-01".parse().unwrap();
+    source.state().set_daily_wisdom(date, "Curl up with the followers.".to_string()).await;
+    let target = spawn_federated_app().await;
+    let json = serde_json::to_string(&Backup::collect(&source.state()).await.unwrap()).unwrap();
+    let backup: Backup = serde_json::from_str(&json).unwrap();
+
+    let summary = backup.clone().restore(&target.state(), true).await.unwrap();
+
+    assert_eq!(summary.followers, 1);
+    assert_eq!(follower_count(&target).await, 1);
+    let (source, restarted) = (source.state().federation.unwrap(), target.state().federation.unwrap());
+    assert_eq!(restarted.public_key_pem(), source.public_key_pem(), "the key is used after a restart");
+    assert_eq!(restarted.followers().await, source.followers().await);
+
+    // Without an actor to give them to, nothing is imported
+    let unfederated = common::spawn_app().await;
+    let error = backup.restore(&unfederated.state(), false).await.unwrap_err();
+    assert!(error.to_string().contains("set ACTIVITYPUB_USERNAME"), "{}", error);
+    assert!(unfederated.state().wisdom_history.get(date).await.is_none());
+}
+
+#[tokio::test]
+async fn notes_are_held_back_until_they_are_published() {
+    let today = Utc::now().date_naive();
+    let tomorrow = today.succ_opt().unwrap();
+    let yesterday = today.pred_opt().unwrap();
+    let posted = spawn_app_with(|config| {
+        config.activitypub_username = Some("cat".to_string());
+        config.activitypub_post_time = NaiveTime::MIN;
+    })
+    .await;
+    let pending = spawn_app_with(|config| {
+        config.activitypub_username = Some("cat".to_string());
+        config.activitypub_post_time = NaiveTime::from_hms_opt(23, 59, 59).unwrap();
+    })
+    .await;
+    for app in [&posted, &pending] {
+        let state = app.state();
+        for date in [yesterday, today, tomorrow] {
+            state.set_daily_wisdom(date, format!("Wisdom for {}", date)).await;
+        }
+    }
+
+    let outbox_dates = |outbox: Value| -> Vec<String> {
+        outbox["orderedItems"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["object"]["id"].as_str().unwrap().rsplit('/').next().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(outbox_dates(posted.get_json("/ap/outbox").await), [today.to_string(), yesterday.to_string()]);
+    assert_eq!(posted.get(&format!("/ap/notes/{}", today)).await.status(), 200);
+    assert_eq!(posted.get(&format!("/ap/notes/{}", tomorrow)).await.status(), 404);
+
+    assert_eq!(outbox_dates(pending.get_json("/ap/outbox").await), [yesterday.to_string()]);
+    assert_eq!(pending.get(&format!("/ap/notes/{}", yesterday)).await.status(), 200);
+    assert_eq!(pending.get(&format!("/ap/notes/{}", today)).await.status(), 404);
+}
+
+#[tokio::test]
+async fn mentions_are_answered_with_a_reply() {
+    let app = spawn_federated_app().await;
+    let remote = Remote::start().await;
+    app.mock.push(MockReply::content("Rest is part of the work."));
+    app.mock.set_default(MockReply::content("Purr."));
+    let create = |id: &str, content: &str, tag: Value| {
+        json!({
+            "id": format!("{}/activity", id),
+            "type": "Create",
+            "actor": remote.id(),
+            "object": {
+                "id": id,
+                "type": "Note",
+                "attributedTo": remote.id(),
+                "to": [PUBLIC],
+                "cc": [],
+                "content": content,
+                "tag": tag,
+            },
+        })
+    };
+
+    let chatter = create(&format!("{}/notes/1", remote.id()), "<p>Lovely weather</p>", json!([]));
+    assert_eq!(remote.post(&app, &chatter, &remote.key).await.status(), 202);
+    let mention = create(
+        &format!("{}/notes/2", remote.id()),
+        "<p><span class=\"h-card\"><a href=\"https://the-enlightened-cat.com\" class=\"u-url mention\">@<span>cat</span></a></span> Should I skip lunch?</p>",
+        json!([{ "type": "Mention", "href": ACTOR, "name": "@cat@the-enlightened-cat.com" }]),
+    );
+    assert_eq!(remote.post(&app, &mention, &remote.key).await.status(), 202);
+
+    let delivered = remote.delivered(1).await;
+    assert_eq!(delivered.len(), 1, "only the mention is answered");
+    let reply = &delivered[0].json()["object"];
+    assert_eq!(delivered[0].path, "/users/alice/inbox");
+    assert_eq!(reply["inReplyTo"], mention["object"]["id"]);
+    assert_eq!(reply["cc"], json!([PUBLIC]));
+    assert_eq!(reply["tag"][0]["href"], remote.id());
+    let content = reply["content"].as_str().unwrap();
+    assert!(content.contains(">@alice@127.0.0.1</a>"), "{}", content);
+    assert!(content.ends_with(" Rest is part of the work.</p>"), "{}", content);
+    let asked = app.mock.requests().iter().any(|r| r.messages.last().is_some_and(|m| m.content == "Should I skip lunch?"));
+    assert!(asked, "the mention itself is stripped from the question");
+}
+
+#[tokio::test]
+async fn mentions_are_answered_within_limits() {
+    let app = spawn_federated_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+    let mention = |remote: &Remote, number: usize, question: &str| {
+        let id = format!("{}/notes/{}", remote.id(), number);
+        json!({
+            "id": format!("{}/activity", id),
+            "type": "Create",
+            "actor": remote.id(),
+            "object": {
+                "id": id,
+                "type": "Note",
+                "attributedTo": remote.id(),
+                "to": [ACTOR],
+                "content": format!("<p>@cat {}</p>", question),
+                "tag": [{ "type": "Mention", "href": ACTOR }],
+            },
+        })
+    };
+
+    let chatty = Remote::start().await;
+    for number in 0..=activitypub::MAX_MENTIONS_PER_HOUR {
+        assert_eq!(chatty.post(&app, &mention(&chatty, number, "Nap now?"), &chatty.key).await.status(), 202);
+    }
+    let verbose = Remote::start().await;
+    let essay = "Why? ".repeat(activitypub::MAX_MENTION_CHARS / 4);
+    assert_eq!(verbose.post(&app, &mention(&verbose, 0, &essay), &verbose.key).await.status(), 202);
+
+    assert_eq!(chatty.delivered(activitypub::MAX_MENTIONS_PER_HOUR).await.len(), activitypub::MAX_MENTIONS_PER_HOUR);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(chatty.delivered(0).await.len(), activitypub::MAX_MENTIONS_PER_HOUR, "one mention too many");
+    assert!(verbose.delivered(0).await.is_empty(), "one question too long");
+    assert!(!app.mock.requests().iter().any(|r| r.messages.iter().any(|m| m.content.contains("Why?"))));
+}
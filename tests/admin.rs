@@ -0,0 +1,187 @@
+mod common;
+
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{spawn_app_with, TestApp};
+
+const PASSWORD: &str = "correct horse";
+
+async fn spawn_admin_app() -> TestApp {
+    spawn_app_with(|config| config.admin_password = Some(PASSWORD.to_string())).await
+}
+
+/// A client that reports redirects instead of following them
+fn no_redirects() -> reqwest::Client {
+    reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build().unwrap()
+}
+
+/// Loads the console and pulls the CSRF token out of its forms
+async fn console(app: &TestApp, date: &str) -> (String, String) {
+    let html = app
+        .client
+        .get(app.url(&format!("/admin?date={}", date)))
+        .basic_auth("admin", Some(PASSWORD))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let marker = "name=\"csrf_token\" value=\"";
+    let start = html.find(marker).expect("console has no CSRF token") + marker.len();
+    let token = html[start..start + 64].to_string();
+    (html, token)
+}
+
+#[tokio::test]
+async fn admin_is_hidden_without_a_password() {
+    let app = common::spawn_app().await;
+
+    let response = app.client.get(app.url("/admin")).basic_auth("admin", Some("")).send().await.unwrap();
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn admin_challenges_missing_or_wrong_credentials() {
+    let app = spawn_admin_app().await;
+
+    let anonymous = app.get("/admin").await;
+    assert_eq!(anonymous.status(), 401);
+    assert!(anonymous.headers()["www-authenticate"].to_str().unwrap().starts_with("Basic"));
+
+    let wrong = app.client.get(app.url("/admin")).basic_auth("admin", Some("nope")).send().await.unwrap();
+    assert_eq!(wrong.status(), 401);
+}
+
+#[tokio::test]
+async fn admin_console_shows_the_days_content() {
+    let app = spawn_admin_app().await;
+    let state = app.state();
+    let date = "2025-06-01".parse().unwrap();
+    state.set_daily_wisdom(date, "Sit in the sunbeam.".to_string()).await;
+    state.set_quantum_field(date, serde_json::from_str(&common::six_seeds()).unwrap()).await;
+
+    let (html, _) = console(&app, "2025-06-01").await;
+
+    assert!(html.contains("Sit in the sunbeam."));
+    assert!(html.contains("Seed of the portal"));
+}
+
+#[tokio::test]
+async fn viewing_a_date_generates_nothing_until_asked() {
+    let app = spawn_admin_app().await;
+    app.mock.set_default(MockReply::content("Tomorrow's purr, today."));
+
+    let (html, token) = console(&app, "2030-01-01").await;
+
+    assert!(html.contains("Nothing has been generated for this date yet."));
+    assert!(html.contains("Generate wisdom"));
+    assert!(app.mock.requests().is_empty());
+    let date = "2030-01-01".parse().unwrap();
+    assert!(app.state().wisdom_history.get(date).await.is_none());
+    assert!(!app.get("/sitemap.xml").await.text().await.unwrap().contains("2030-01-01"));
+
+    let response = no_redirects()
+        .post(app.url("/admin/wisdom/regenerate"))
+        .basic_auth("admin", Some(PASSWORD))
+        .form(&[("csrf_token", token.as_str()), ("date", "2030-01-01")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 303);
+    assert_eq!(app.state().wisdom_history.get(date).await.unwrap().text, "Tomorrow's purr, today.");
+    assert!(console(&app, "2030-01-01").await.0.contains("Regenerate wisdom"));
+}
+
+#[tokio::test]
+async fn admin_rejects_forms_without_the_csrf_token() {
+    let app = spawn_admin_app().await;
+
+    let response = no_redirects()
+        .post(app.url("/admin/wisdom"))
+        .basic_auth("admin", Some(PASSWORD))
+        .form(&[("csrf_token", "forged"), ("date", "2025-06-01"), ("text", "Hacked")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+}
+
+#[tokio::test]
+async fn admin_edits_wisdom_and_records_it_in_the_audit_log() {
+    let app = spawn_admin_app().await;
+    app.mock.set_default(MockReply::content(common::six_seeds()));
+    let (_, token) = console(&app, "2025-06-01").await;
+
+    let response = no_redirects()
+        .post(app.url("/admin/wisdom"))
+        .basic_auth("admin", Some(PASSWORD))
+        .form(&[("csrf_token", token.as_str()), ("date", "2025-06-01"), ("text", "A curated purr.")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 303);
+    assert_eq!(response.headers()["location"], "/admin?date=2025-06-01");
+
+    let (html, _) = console(&app, "2025-06-01").await;
+    assert!(html.contains("A curated purr."));
+    assert!(html.contains("wisdom.edit"));
+
+    let log = std::fs::read_to_string(app.data_dir.path().join("audit.jsonl")).unwrap();
+    let entry: serde_json::Value = serde_json::from_str(log.lines().last().unwrap()).unwrap();
+    assert_eq!(entry["actor"], "admin");
+    assert_eq!(entry["action"], "wisdom.edit");
+    assert_eq!(entry["target"], "2025-06-01");
+}
+
+#[tokio::test]
+async fn admin_regenerates_the_quantum_field() {
+    let app = spawn_admin_app().await;
+    app.mock.set_default(MockReply::error(500, "down"));
+    let (_, token) = console(&app, "2025-06-01").await;
+    app.mock.set_default(MockReply::content(common::six_seeds()));
+
+    let response = no_redirects()
+        .post(app.url("/admin/field/regenerate"))
+        .basic_auth("admin", Some(PASSWORD))
+        .form(&[("csrf_token", token.as_str()), ("date", "2025-06-01")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 303);
+
+    let (html, _) = console(&app, "2025-06-01").await;
+    assert!(html.contains("Seed of crystallization"));
+    assert!(html.contains("field.regenerate"));
+}
+
+#[tokio::test]
+async fn admin_requires_one_seed_per_domain() {
+    let app = spawn_admin_app().await;
+    app.mock.set_default(MockReply::content(common::six_seeds()));
+    let (_, token) = console(&app, "2025-06-01").await;
+
+    let response = no_redirects()
+        .post(app.url("/admin/field"))
+        .basic_auth("admin", Some(PASSWORD))
+        .form(&[("csrf_token", token.as_str()), ("date", "2025-06-01"), ("seeds", "only\ntwo")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn admin_never_shows_chat_contents() {
+    let app = spawn_admin_app().await;
+    app.mock.set_default(MockReply::content(common::six_seeds()));
+    app.post_json("/api/v1/chat", serde_json::json!({ "message": "my secret worry" })).await;
+
+    let (html, _) = console(&app, "2025-06-01").await;
+
+    assert!(html.contains("1 active"));
+    assert!(!html.contains("my secret worry"));
+}
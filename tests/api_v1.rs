@@ -0,0 +1,102 @@
+mod common;
+
+use serde_json::json;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::spawn_app;
+
+#[tokio::test]
+async fn openapi_document_describes_every_v1_endpoint() {
+    let app = spawn_app().await;
+
+    let doc = app.get_json("/api/v1/openapi.json").await;
+
+    assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+    let paths = doc["paths"].as_object().unwrap();
+    for path in [
+        "/api/v1/chat",
+        "/api/v1/daily-wisdom",
+        "/api/v1/quantum-field",
+        "/api/v1/quantum-field/collapse",
+    ] {
+        assert!(paths.contains_key(path), "missing {}", path);
+    }
+    let request = &doc["components"]["schemas"]["ChatRequest"]["properties"];
+    assert!(request.get("conversation_depth").is_some());
+}
+
+#[tokio::test]
+async fn legacy_aliases_still_work_but_are_deprecated() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Old paths, same cat."));
+
+    let response = app.get("/api/daily-wisdom").await;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["deprecation"], "true");
+    assert!(response.headers().contains_key("sunset"));
+    assert_eq!(
+        response.headers()["link"],
+        "</api/v1/daily-wisdom>; rel=\"successor-version\""
+    );
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["wisdom"], "Old paths, same cat.");
+}
+
+#[tokio::test]
+async fn v1_responses_are_not_marked_deprecated() {
+    let app = spawn_app().await;
+
+    let response = app.get("/api/v1/daily-wisdom").await;
+
+    assert!(!response.headers().contains_key("deprecation"));
+}
+
+
+/// Has the app offer the `evening-shutdown-ritual` topic to the test client's session
+async fn offer_a_topic(app: &common::TestApp) {
+    app.mock.push_when(
+        "propose up to three topics",
+        MockReply::content(r#"[{"title": "Evening shutdown ritual", "focus": "Closing the laptop."}]"#),
+    );
+    app.mock.set_default(MockReply::content("Let us go deeper."));
+    app.post_json("/api/v1/chat", json!({ "message": "Work is endless" })).await;
+    app.post_json("/api/v1/chat", json!({ "message": "Tell me more about work" })).await;
+}
+
+#[tokio::test]
+async fn chat_accepts_camel_case_current_topic() {
+    let app = spawn_app().await;
+    offer_a_topic(&app).await;
+
+    let body: serde_json::Value = app
+        .post_json(
+            "/api/v1/chat",
+            json!({ "message": "Go on", "conversationDepth": 3, "currentTopic": "evening-shutdown-ritual" }),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // A dropped alias would leave the session without a topic
+    assert_eq!(body["current_topic"]["id"], "evening-shutdown-ritual", "topic was ignored: {}", body);
+}
+
+#[tokio::test]
+async fn chat_accepts_snake_case_current_topic() {
+    let app = spawn_app().await;
+    offer_a_topic(&app).await;
+
+    let body: serde_json::Value = app
+        .post_json(
+            "/api/v1/chat",
+            json!({ "message": "Go on", "conversation_depth": 3, "current_topic": "evening-shutdown-ritual" }),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["current_topic"]["id"], "evening-shutdown-ritual");
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use serde_json::json;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::spawn_app;
+
+#[tokio::test]
+async fn chat_returns_the_model_reply() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Purr. Breathe before the next meeting."));
+
+    let response = app.post_json("/api/v1/chat", json!({ "message": "I'm stressed" })).await;
+
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "Purr. Breathe before the next meeting.");
+    assert_eq!(body["provenance"], "generated");
+
+    let sent = app.mock.requests();
+    let last = sent[0].messages.last().unwrap();
+    assert_eq!(last.role, "user");
+    assert_eq!(last.content, "I'm stressed");
+}
+
+#[tokio::test]
+async fn chat_keeps_conversation_history() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("First reply"));
+    app.mock.push(MockReply::content("Second reply"));
+
+    app.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+    app.post_json("/api/v1/chat", json!({ "message": "Again" })).await;
+
+    let messages = &app.mock.requests()[1].messages;
+    let contents: Vec<_> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(&contents[1..], ["Hello", "First reply", "Again"]);
+}
+
+#[tokio::test]
+async fn chat_falls_back_to_corpus_on_upstream_error() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::error(429, "rate limited"));
+
+    let response = app.post_json("/api/v1/chat", json!({ "message": "Hello?" })).await;
+
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["provenance"], "corpus");
+    assert!(!body["message"].as_str().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn chat_falls_back_to_corpus_on_empty_choices() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::raw(
+        r#"{"id":"x","object":"chat.completion","created":0,"model":"mistral-small","choices":[]}"#,
+    ));
+
+    let body: serde_json::Value = app
+        .post_json("/api/v1/chat", json!({ "message": "Anyone there?" }))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn chat_rejects_a_body_without_message() {
+    let app = spawn_app().await;
+
+    let response = app.post_json("/api/v1/chat", json!({ "text": "wrong field" })).await;
+
+    assert_eq!(response.status(), 422);
+}
+
+#[tokio::test]
+async fn chat_sessions_are_kept_apart() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Hello, first visitor"));
+    app.mock.push(MockReply::content("Hello, second visitor"));
+
+    app.post_json("/api/v1/chat", json!({ "message": "I am the first" })).await;
+    // A client without the first visitor's cookie starts its own conversation
+    reqwest::Client::new()
+        .post(app.url("/api/v1/chat"))
+        .json(&json!({ "message": "I am the second" }))
+        .send()
+        .await
+        .unwrap();
+
+    let messages = &app.mock.requests()[1].messages;
+    let contents: Vec<_> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(&contents[1..], ["I am the second"]);
+}
+
+#[tokio::test]
+async fn chat_sets_a_session_cookie() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Welcome"));
+
+    let response = app.post_json("/api/v1/chat", json!({ "message": "Hi" })).await;
+
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    assert!(cookie.starts_with("cat_session="));
+    assert!(cookie.contains("HttpOnly"));
+}
+
+#[tokio::test]
+async fn chatting_renews_the_session_cookie() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Welcome"));
+    app.mock.push(MockReply::content("Welcome back"));
+    let first = app.post_json("/api/v1/chat", json!({ "message": "Hi" })).await;
+    let first = first.headers()["set-cookie"].to_str().unwrap().to_string();
+
+    let second = app.post_json("/api/v1/chat", json!({ "message": "Still here" })).await;
+
+    let renewed = second.headers()["set-cookie"].to_str().unwrap();
+    assert_eq!(renewed, first, "the same session, with its Max-Age starting over");
+    assert!(renewed.contains("Max-Age=604800"));
+}
+
+/// Text only the topic side-call's system prompt contains
+const TOPIC_PROMPT: &str = "propose up to three topics";
+
+fn topics_reply() -> MockReply {
+    MockReply::content(
+        r#"Here you go: [{"title": "Evening shutdown ritual", "focus": "Help them close the laptop without guilt."},
+            {"title": "Breathing between meetings", "focus": "Offer a one-minute breathing practice."}]"#,
+    )
+}
+
+#[tokio::test]
+async fn chat_suggests_topics_from_the_conversation() {
+    let app = spawn_app().await;
+    app.mock.push_when(TOPIC_PROMPT, topics_reply());
+    app.mock.set_default(MockReply::content("Purr."));
+
+    let first: serde_json::Value =
+        app.post_json("/api/v1/chat", json!({ "message": "Work is endless" })).await.json().await.unwrap();
+    let second: serde_json::Value =
+        app.post_json("/api/v1/chat", json!({ "message": "I answer email at midnight" })).await.json().await.unwrap();
+
+    assert_eq!(first["suggested_topics"], serde_json::Value::Null);
+    let topics = second["suggested_topics"].as_array().unwrap();
+    assert_eq!(topics[0]["id"], "evening-shutdown-ritual");
+    assert_eq!(topics[1]["title"], "Breathing between meetings");
+
+    // The side-call is shown the actual conversation
+    let side_call = app.mock.requests().into_iter().find(|r| r.messages[0].content.contains(TOPIC_PROMPT)).unwrap();
+    assert!(side_call.messages[1].content.contains("Visitor: I answer email at midnight"));
+}
+
+#[tokio::test]
+async fn chosen_topic_steers_the_following_turns() {
+    let app = spawn_app().await;
+    app.mock.push_when(TOPIC_PROMPT, topics_reply());
+    app.mock.set_default(MockReply::content("Purr."));
+    app.post_json("/api/v1/chat", json!({ "message": "Work is endless" })).await;
+    app.post_json("/api/v1/chat", json!({ "message": "I answer email at midnight" })).await;
+
+    let chosen: serde_json::Value = app
+        .post_json("/api/v1/chat", json!({ "message": "Let's do that", "current_topic": "evening-shutdown-ritual" }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    // The topic sticks without being sent again
+    app.post_json("/api/v1/chat", json!({ "message": "And then?" })).await;
+
+    assert_eq!(chosen["current_topic"]["id"], "evening-shutdown-ritual");
+    let turns: Vec<_> = app
+        .mock
+        .requests()
+        .into_iter()
+        .filter(|r| !r.messages[0].content.contains(TOPIC_PROMPT))
+        .collect();
+    for turn in &turns[2..] {
+        let steering = &turn.messages[turn.messages.len() - 2];
+        assert_eq!(steering.role, "system");
+        assert!(steering.content.contains("close the laptop without guilt"));
+    }
+}
+
+#[tokio::test]
+async fn unknown_topic_ids_are_ignored() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Purr."));
+
+    let body: serde_json::Value = app
+        .post_json("/api/v1/chat", json!({ "message": "Hi", "current_topic": "made-up" }))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["current_topic"], serde_json::Value::Null);
+    assert_eq!(app.mock.requests()[0].messages.iter().filter(|m| m.role == "system").count(), 1);
+}
+
+#[tokio::test]
+async fn failed_topic_suggestions_do_not_fail_the_reply() {
+    let app = spawn_app().await;
+    app.mock.push_when(TOPIC_PROMPT, MockReply::content("Sorry, no JSON today."));
+    app.mock.set_default(MockReply::content("Purr."));
+    app.post_json("/api/v1/chat", json!({ "message": "One" })).await;
+
+    let body: serde_json::Value =
+        app.post_json("/api/v1/chat", json!({ "message": "Two" })).await.json().await.unwrap();
+
+    assert_eq!(body["message"], "Purr.");
+    assert_eq!(body["provenance"], "generated");
+    assert_eq!(body["suggested_topics"], serde_json::Value::Null);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use the_enlightened_cat::chat_client::ChatClient;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{six_seeds, spawn_app, TestApp};
+
+/// Runs the client against `app` with the given lines typed in, returning what it printed
+async fn chat(app: &TestApp, input: &str) -> String {
+    let client = ChatClient::new(&app.base_url, None, 60).unwrap();
+    let mut out = Vec::new();
+    client.run(input.as_bytes(), &mut out).await.unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[tokio::test]
+async fn replies_come_from_the_ascii_cat() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Breathe first, answer the email second."));
+
+    let output = chat(&app, "I have 200 unread emails\n/quit\n").await;
+
+    assert!(output.contains("< Breathe first, answer the email second. >"), "{}", output);
+    assert!(output.contains("( -.- )"));
+    assert!(output.contains("/collapse N"), "help is shown on start");
+}
+
+#[tokio::test]
+async fn the_session_cookie_carries_the_conversation() {
+    let app = spawn_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+
+    chat(&app, "My name is Ada\nWhat is my name?\n").await;
+
+    let second = app
+        .mock
+        .requests()
+        .into_iter()
+        .find(|request| request.messages.iter().any(|m| m.content == "What is my name?"))
+        .unwrap();
+    assert!(second.messages.iter().any(|m| m.content == "My name is Ada"));
+}
+
+#[tokio::test]
+async fn wisdom_command_shows_todays_whispurr() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("The sunbeam moves; so can you."));
+
+    let output = chat(&app, "/wisdom\n").await;
+
+    assert!(output.contains("The sunbeam moves; so can you."), "{}", output);
+}
+
+#[tokio::test]
+async fn field_and_collapse_commands_use_the_field_api() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let output = chat(&app, "/field\n/collapse 4\n").await;
+
+    assert!(output.contains("(1) Essence"), "{}", output);
+    assert!(output.contains("  4. Portal\n     Seed of the portal"));
+    assert!(output.contains("Seed of the portal"), "the collapsed prompt is spoken by the cat");
+    assert_eq!(app.mock.requests().len(), 1);
+    assert!(output.contains(&format!("This reading is kept at {}/quantum-field/reading/", app.base_url)));
+
+    // The reading belongs to the client's session, like a browser's would
+    let reading = app.readings_on_disk();
+    assert_eq!(reading.len(), 1);
+    assert_eq!(reading[0]["index"], 3);
+}
+
+#[tokio::test]
+async fn bad_commands_get_help_instead_of_a_request() {
+    let app = spawn_app().await;
+
+    let output = chat(&app, "/collapse 9\n/collapse soon\n/dance\n").await;
+
+    assert!(output.contains("choose one from 1 to 6"));
+    assert!(output.contains("Usage: /collapse N"));
+    assert!(output.contains("Unknown command /dance"));
+    assert!(app.mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn an_unreachable_instance_is_reported_without_ending_the_chat() {
+    let client = ChatClient::new("http://127.0.0.1:9", None, 60).unwrap();
+    let mut out = Vec::new();
+
+    client.run("Hello?\n/help\n".as_bytes(), &mut out).await.unwrap();
+
+    let output = String::from_utf8(out).unwrap();
+    assert!(output.contains("The cat can't be reached right now"));
+    assert!(output.contains("May your naps be long"));
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use serde_json::json;
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::Config;
+
+use common::{six_seeds, spawn_app};
+
+#[tokio::test]
+async fn generated_wisdom_shows_up_on_the_running_site() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("The cron job purrs at midnight."));
+    let today = Utc::now().date_naive();
+
+    let output = app.run_cli(&["generate-wisdom"]).await.unwrap();
+
+    assert_eq!(output, format!("{} (generated)\nThe cron job purrs at midnight.\n", today));
+    let body = app.get_json("/api/v1/daily-wisdom").await;
+    assert_eq!(body["wisdom"], "The cron job purrs at midnight.");
+    assert_eq!(app.mock.requests().len(), 1, "the site reused the CLI's wisdom");
+}
+
+#[tokio::test]
+async fn generate_wisdom_keeps_a_settled_day_unless_forced() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("First thought."));
+    app.mock.push(MockReply::content("Second thought."));
+
+    let first = app.run_cli(&["generate-wisdom", "--date", "2026-01-05"]).await.unwrap();
+    let again = app.run_cli(&["generate-wisdom", "--date", "2026-01-05"]).await.unwrap();
+    let forced = app.run_cli(&["generate-wisdom", "--date", "2026-01-05", "--force"]).await.unwrap();
+
+    assert!(first.contains("First thought."));
+    assert!(again.contains("First thought."));
+    assert!(forced.contains("Second thought."));
+
+    let audit = std::fs::read_to_string(app.data_dir.path().join("audit.jsonl")).unwrap();
+    let actions: Vec<serde_json::Value> = audit.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(actions.len(), 2);
+    assert_eq!(actions[0]["action"], "wisdom.generate");
+    assert_eq!(actions[1]["action"], "wisdom.regenerate");
+    assert!(actions.iter().all(|entry| entry["actor"] == "cli"));
+}
+
+#[tokio::test]
+async fn generated_field_is_served_by_the_site() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let output = app.run_cli(&["generate-field"]).await.unwrap();
+
+    assert!(output.contains("(generated)"));
+    assert!(output.contains("1. Essence: Seed of essence"));
+    assert_eq!(output.lines().count(), 7);
+    let field = app.get_json("/api/v1/quantum-field").await;
+    assert_eq!(field["wisdom_field"][0]["seed"], "Seed of essence");
+    assert_eq!(app.mock.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn export_and_import_move_everything_to_another_instance() {
+    let source = spawn_app().await;
+    source.mock.push(MockReply::content("Carry the sunbeam with you."));
+    source.run_cli(&["generate-wisdom", "--date", "2026-02-01"]).await.unwrap();
+    source.mock.set_default(MockReply::content("Purr."));
+    source.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+    let backup = source.data_dir.path().join("backup.json");
+
+    let exported = source.run_cli(&["export", "--output", backup.to_str().unwrap()]).await.unwrap();
+    assert!(exported.contains("1 days of wisdom"), "{}", exported);
+    assert!(exported.contains("1 sessions"), "{}", exported);
+
+    let target = spawn_app().await;
+    let imported = target.run_cli(&["import", backup.to_str().unwrap()]).await.unwrap();
+    assert!(imported.starts_with("Imported 1 wisdom"), "{}", imported);
+
+    let page = target.get("/wisdom/2026-02-01").await;
+    assert_eq!(page.status(), 200);
+    assert!(page.text().await.unwrap().contains("Carry the sunbeam with you."));
+    let sessions = target.run_cli(&["sessions", "list"]).await.unwrap();
+    assert!(sessions.starts_with("Active sessions: 1"));
+
+    // Importing the same backup again doesn't duplicate the audit log
+    let again = target.run_cli(&["import", backup.to_str().unwrap()]).await.unwrap();
+    assert!(again.contains(", 0 audit entries"), "{}", again);
+}
+
+#[tokio::test]
+async fn export_to_stdout_is_a_json_backup() {
+    let app = spawn_app().await;
+
+    let output = app.run_cli(&["export"]).await.unwrap();
+
+    let backup: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(backup["format"], 1);
+    assert!(backup["wisdom"].as_object().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn import_rejects_an_unknown_format() {
+    let app = spawn_app().await;
+    let path = app.data_dir.path().join("future.json");
+    std::fs::write(&path, json!({ "format": 99, "exported_at": Utc::now() }).to_string()).unwrap();
+
+    let error = app.run_cli(&["import", path.to_str().unwrap()]).await.unwrap_err();
+
+    assert!(error.to_string().contains("Unsupported backup format 99"));
+}
+
+#[tokio::test]
+async fn sessions_list_never_shows_what_was_said() {
+    let app = spawn_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+    app.post_json("/api/v1/chat", json!({ "message": "my secret worry" })).await;
+
+    let output = app.run_cli(&["sessions", "list"]).await.unwrap();
+
+    assert!(output.starts_with("Active sessions: 1\n"));
+    assert!(output.contains("1 messages, 15 characters"));
+    assert!(!output.contains("secret"));
+}
+
+#[tokio::test]
+async fn sessions_purge_forgets_conversations() {
+    let app = spawn_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+    app.post_json("/api/v1/chat", json!({ "message": "Hello" })).await;
+
+    let idle = app.run_cli(&["sessions", "purge"]).await.unwrap();
+    assert_eq!(idle, "Purged 0 sessions\n");
+
+    let all = app.run_cli(&["sessions", "purge", "--all"]).await.unwrap();
+    assert_eq!(all, "Purged 1 sessions\n");
+
+    // The running site sees the purge: the next message starts a fresh conversation
+    app.post_json("/api/v1/chat", json!({ "message": "Again" })).await;
+    let last = app.mock.requests().pop().unwrap();
+    assert!(last.messages.iter().any(|m| m.content == "Again"));
+    assert!(!last.messages.iter().any(|m| m.content == "Hello"));
+}
+
+#[tokio::test]
+async fn purge_options_conflict() {
+    let app = spawn_app().await;
+
+    let error = app.run_cli(&["sessions", "purge", "--all", "--idle-days", "3"]).await.unwrap_err();
+
+    assert!(error.to_string().contains("cannot be used with"));
+}
+
+#[tokio::test]
+async fn fortune_reads_the_archive_without_the_model() {
+    let app = spawn_app().await;
+    let yesterday = Utc::now().date_naive() - Duration::days(1);
+    app.mock.push(MockReply::content("A cat who waits for the perfect moment naps through most of them."));
+    app.run_cli(&["generate-wisdom", "--date", &yesterday.to_string()]).await.unwrap();
+
+    let output = app.run_cli(&["fortune", "--width", "30"]).await.unwrap();
+
+    assert!(output.starts_with("A cat who waits for the\nperfect moment naps through\nmost of them.\n\n"), "{}", output);
+    assert!(output.ends_with(&format!("— The Enlightened Cat, {}\n", yesterday.format("%-d %B %Y"))));
+    assert_eq!(app.mock.requests().len(), 1, "only generate-wisdom called the model");
+}
+
+#[tokio::test]
+async fn fortune_falls_back_to_the_corpus() {
+    let app = spawn_app().await;
+
+    let output = app.run_cli(&["fortune", "--width", "1000"]).await.unwrap();
+
+    let corpus = the_enlightened_cat::corpus::Corpus::bundled();
+    let text = output.lines().next().unwrap();
+    assert!(corpus.whispurrs.iter().any(|whispurr| whispurr == text), "{}", text);
+    assert!(output.ends_with("— The Enlightened Cat\n"));
+    assert!(app.mock.requests().is_empty());
+}
+
+#[test]
+fn only_model_commands_need_an_api_key() {
+    let config = Config::default();
+    assert!(config.require_api_key().is_err());
+
+    let config = Config { mistral_api_key: "key".to_string(), ..Config::default() };
+    assert!(config.require_api_key().is_ok());
+
+    let config = Config { fixture_mode: the_enlightened_cat::fixtures::FixtureMode::Replay, ..Config::default() };
+    assert!(config.require_api_key().is_ok());
+}
@@ -0,0 +1,128 @@
+//! Shared helpers for the integration tests: spins up the full app on a random
+//! port, wired to a `MockMistral` instead of the real API.
+
+#![allow(dead_code)] // Not every test binary uses every helper
+
+use std::net::{SocketAddr, TcpListener};
+
+use tempfile::TempDir;
+
+use clap::Parser;
+
+use the_enlightened_cat::cli::{self, Cli};
+use the_enlightened_cat::mock_mistral::MockMistral;
+use the_enlightened_cat::state::AppState;
+use the_enlightened_cat::{App, Config};
+
+/// A running app plus the mock model behind it
+pub struct TestApp {
+    pub base_url: String,
+    pub mock: MockMistral,
+    pub client: reqwest::Client,
+    /// Holds the app's data directory; deleted when the test ends
+    pub data_dir: TempDir,
+    /// The configuration the app was built with
+    pub config: Config,
+}
+
+impl TestApp {
+    /// Absolute URL for a path on the app
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        self.client.get(self.url(path)).send().await.expect("request failed")
+    }
+
+    pub async fn get_json(&self, path: &str) -> serde_json::Value {
+        let response = self.get(path).await;
+        assert!(response.status().is_success(), "GET {} returned {}", path, response.status());
+        response.json().await.expect("response was not JSON")
+    }
+
+    pub async fn post_json(&self, path: &str, body: serde_json::Value) -> reqwest::Response {
+        self.client.post(self.url(path)).json(&body).send().await.expect("request failed")
+    }
+
+    pub async fn patch_json(&self, path: &str, body: serde_json::Value) -> reqwest::Response {
+        self.client.patch(self.url(path)).json(&body).send().await.expect("request failed")
+    }
+
+    /// Runs a command-line subcommand the way the binary would, as a separate
+    /// instance sharing this app's data directory, and returns what it printed
+    pub async fn run_cli(&self, args: &[&str]) -> anyhow::Result<String> {
+        let cli = Cli::try_parse_from(std::iter::once("the-enlightened-cat").chain(args.iter().copied()))?;
+        let state = App::builder(self.config.clone()).build_state()?;
+        let mut out = Vec::new();
+        cli::run(cli.command.expect("no subcommand given"), &state, &mut out).await?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// The state of a second instance sharing this app's data directory, as
+    /// `run_cli` builds it
+    pub fn state(&self) -> AppState {
+        App::builder(self.config.clone()).build_state().expect("failed to build state")
+    }
+
+    /// Every saved quantum field reading, straight from the data directory
+    pub fn readings_on_disk(&self) -> Vec<serde_json::Value> {
+        let path = self.data_dir.path().join("readings.json");
+        let readings: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        readings.into_values().collect()
+    }
+
+    /// A request from a different visitor: no cookies shared with `client`
+    pub fn stranger(&self) -> reqwest::Client {
+        reqwest::Client::builder().cookie_store(true).build().unwrap()
+    }
+}
+
+/// Starts the app with the default test configuration
+pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Starts the app after letting the caller adjust the configuration
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Config)) -> TestApp {
+    let mock = MockMistral::start().await.expect("failed to start mock Mistral");
+    let data_dir = TempDir::new().expect("failed to create data dir");
+
+    let mut config = Config {
+        mistral_api_key: "test-key".to_string(),
+        mistral_api_url: mock.url(),
+        mistral_timeout_secs: 2,
+        data_dir: data_dir.path().to_string_lossy().into_owned(),
+        ..Config::default()
+    };
+    configure(&mut config);
+
+    let router = App::builder(config.clone()).build().expect("failed to build app");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener).unwrap().serve(router.into_make_service_with_connect_info::<SocketAddr>());
+    tokio::spawn(server);
+
+    TestApp {
+        base_url: format!("http://{}", addr),
+        mock,
+        // Keep cookies like a browser would, so chat sessions carry over between requests
+        client: reqwest::Client::builder().cookie_store(true).build().unwrap(),
+        data_dir,
+        config,
+    }
+}
+
+/// A well-formed quantum field reply
+pub fn six_seeds() -> String {
+    serde_json::to_string(&[
+        "Seed of essence",
+        "Seed of the inner path",
+        "Seed of the outer path",
+        "Seed of the portal",
+        "Seed of friction",
+        "Seed of crystallization",
+    ])
+    .unwrap()
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use serde_json::{json, Value};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_webhook::{ReceivedWebhook, WebhookReceiver};
+
+use common::{six_seeds, spawn_app_with, TestApp};
+
+/// An app with a fresh Discord key pair, editing deferred replies on `api_url`
+async fn spawn_discord_app(api_url: Option<String>) -> (TestApp, PKey<Private>) {
+    let key = PKey::generate_ed25519().unwrap();
+    let public_key = hex::encode(key.raw_public_key().unwrap());
+    let app = spawn_app_with(|config| {
+        config.discord_public_key = Some(public_key);
+        if let Some(api_url) = api_url {
+            config.discord_api_url = api_url;
+        }
+    })
+    .await;
+    (app, key)
+}
+
+/// POSTs an interaction signed with `key` at `timestamp`
+async fn post_signed(app: &TestApp, key: &PKey<Private>, body: &Value, timestamp: i64) -> reqwest::Response {
+    let body = body.to_string();
+    let timestamp = timestamp.to_string();
+    let signature = Signer::new_without_digest(key)
+        .unwrap()
+        .sign_oneshot_to_vec(format!("{}{}", timestamp, body).as_bytes())
+        .unwrap();
+    app.client
+        .post(app.url("/integrations/discord/interactions"))
+        .header("content-type", "application/json")
+        .header("x-signature-ed25519", hex::encode(signature))
+        .header("x-signature-timestamp", timestamp)
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Sends an interaction as Discord would, from user `user` in a server
+async fn interact(app: &TestApp, key: &PKey<Private>, kind: u8, data: Value, user: &str) -> Value {
+    let body = json!({
+        "type": kind,
+        "application_id": "A1",
+        "token": format!("token-{}", user),
+        "member": { "user": { "id": user } },
+        "data": data,
+    });
+    let response = post_signed(app, key, &body, Utc::now().timestamp()).await;
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+/// Waits for the receiver to have `count` requests
+async fn wait_for(receiver: &WebhookReceiver, count: usize) -> Vec<ReceivedWebhook> {
+    for _ in 0..100 {
+        let received = receiver.received();
+        if received.len() >= count {
+            return received;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("expected {} deferred replies, got {}", count, receiver.received().len());
+}
+
+#[tokio::test]
+async fn the_endpoint_is_off_without_a_public_key() {
+    let app = common::spawn_app().await;
+    let key = PKey::generate_ed25519().unwrap();
+
+    let response = post_signed(&app, &key, &json!({ "type": 1 }), Utc::now().timestamp()).await;
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn unsigned_forged_and_stale_requests_are_rejected() {
+    let (app, key) = spawn_discord_app(None).await;
+    let other_key = PKey::generate_ed25519().unwrap();
+    let now = Utc::now().timestamp();
+    let ping = json!({ "type": 1, "application_id": "A1", "token": "t" });
+
+    let unsigned = app
+        .client
+        .post(app.url("/integrations/discord/interactions"))
+        .body(ping.to_string())
+        .send()
+        .await
+        .unwrap();
+    let forged = post_signed(&app, &other_key, &ping, now).await;
+    let stale = post_signed(&app, &key, &ping, now - 10 * 60).await;
+    let ancient = post_signed(&app, &key, &ping, i64::MIN).await;
+
+    assert_eq!(unsigned.status(), 401);
+    assert_eq!(forged.status(), 401);
+    assert_eq!(stale.status(), 401);
+    assert_eq!(ancient.status(), 401);
+}
+
+#[tokio::test]
+async fn pings_are_answered_with_a_pong() {
+    let (app, key) = spawn_discord_app(None).await;
+
+    let pong = interact(&app, &key, 1, Value::Null, "U1").await;
+
+    assert_eq!(pong, json!({ "type": 1 }));
+}
+
+#[tokio::test]
+async fn wisdom_is_an_embed_in_the_channel() {
+    let (app, key) = spawn_discord_app(None).await;
+    app.mock.push(MockReply::content("The inbox will still be there after the nap."));
+
+    let reply = interact(&app, &key, 2, json!({ "name": "wisdom" }), "U1").await;
+
+    assert_eq!(reply["type"], 4);
+    let embed = &reply["data"]["embeds"][0];
+    assert_eq!(embed["description"], "The inbox will still be there after the nap.");
+    assert!(embed["title"].as_str().unwrap().starts_with("Whispurr for "));
+    assert!(reply["data"]["flags"].is_null(), "everyone in the channel sees it");
+}
+
+#[tokio::test]
+async fn field_buttons_and_the_collapse_command_save_readings() {
+    let (app, key) = spawn_discord_app(None).await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let field = interact(&app, &key, 2, json!({ "name": "field" }), "U1").await;
+
+    let embed = &field["data"]["embeds"][0];
+    assert_eq!(embed["fields"].as_array().unwrap().len(), 6);
+    assert_eq!(embed["fields"][4]["value"], "Seed of friction");
+    let rows = field["data"]["components"].as_array().unwrap();
+    assert_eq!(rows.len(), 2);
+    let button = &rows[0]["components"][1];
+    assert_eq!(button["label"], "2. Inner Path");
+
+    let clicked = interact(&app, &key, 3, json!({ "custom_id": button["custom_id"] }), "U1").await;
+    let commanded = interact(&app, &key, 2, json!({ "name": "collapse", "options": [{ "name": "node", "value": 5 }] }), "U2").await;
+
+    let readings = app.readings_on_disk();
+    assert_eq!(readings.len(), 2);
+    let reading = |owner: &str| readings.iter().find(|r| r["owner"] == owner).unwrap().clone();
+    assert_eq!(reading("discord:U1")["index"], 1);
+    assert_eq!(reading("discord:U2")["index"], 4);
+
+    let embed = &clicked["data"]["embeds"][0];
+    assert!(embed["title"].as_str().unwrap().starts_with("2. Inner Path"));
+    assert_eq!(
+        embed["url"],
+        format!("https://the-enlightened-cat.com/quantum-field/reading/{}", reading("discord:U1")["id"].as_str().unwrap())
+    );
+    assert_eq!(commanded["data"]["embeds"][0]["fields"][0]["value"], "Seed of friction");
+}
+
+#[tokio::test]
+async fn ask_is_deferred_then_edited_in_for_each_user() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let (app, key) = spawn_discord_app(Some(receiver.url("/api"))).await;
+    app.mock.push(MockReply::content("Rest is part of the work."));
+    app.mock.set_default(MockReply::content("Purr."));
+    let ask = |question: &str| json!({ "name": "ask", "options": [{ "name": "question", "value": question }] });
+
+    let deferred = interact(&app, &key, 2, ask("Should I skip lunch?"), "U1").await;
+    assert_eq!(deferred, json!({ "type": 5, "data": { "flags": 64 } }));
+    let edit = &wait_for(&receiver, 1).await[0];
+    assert_eq!(edit.method, "PATCH");
+    assert_eq!(edit.path, "/api/webhooks/A1/token-U1/messages/@original");
+    assert_eq!(edit.json()["embeds"][0]["title"], "Should I skip lunch?");
+    assert_eq!(edit.json()["embeds"][0]["description"], "Rest is part of the work.");
+
+    interact(&app, &key, 2, ask("Really?"), "U1").await;
+    wait_for(&receiver, 2).await;
+    interact(&app, &key, 2, ask("Hi"), "U2").await;
+    wait_for(&receiver, 3).await;
+
+    // What the model was shown when asked `question`
+    let context = |question: &str| {
+        let requests = app.mock.requests();
+        let request = requests.iter().find(|r| r.messages.last().is_some_and(|m| m.content == question)).unwrap();
+        request.messages.iter().map(|m| m.content.clone()).collect::<Vec<_>>().join("\n")
+    };
+    assert!(context("Really?").contains("Should I skip lunch?"), "U1's second question carries the first");
+    assert!(!context("Hi").contains("Should I skip lunch?"), "U2 has a conversation of their own");
+}
+
+#[tokio::test]
+async fn discord_commands_prints_the_definitions_to_register() {
+    let app = common::spawn_app().await;
+
+    let output = app.run_cli(&["discord-commands"]).await.unwrap();
+
+    let commands: Vec<Value> = serde_json::from_str(&output).unwrap();
+    let names: Vec<&str> = commands.iter().map(|c| c["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["wisdom", "field", "collapse", "ask"]);
+    assert_eq!(commands[2]["options"][0]["max_value"], 6);
+}
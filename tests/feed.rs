@@ -0,0 +1,115 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use serde_json::json;
+use tempfile::TempDir;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{spawn_app_with, TestApp};
+
+/// Starts the app with a wisdom history already on disk
+async fn spawn_app_with_history() -> (TestApp, TempDir) {
+    let data_dir = TempDir::new().unwrap();
+    let history = json!({
+        "2025-06-01": { "text": "First <purr> & more", "provenance": "generated", "updated_at": "2025-06-01T06:00:00Z" },
+        "2025-06-02": { "text": "Second purr", "provenance": "corpus", "updated_at": "2025-06-02T06:00:00Z" },
+        // Settled by a visitor far ahead of the site's calendar
+        (Utc::now().date_naive() + Duration::days(3)).to_string():
+            { "text": "From the future", "provenance": "generated", "updated_at": "2025-06-02T06:00:00Z" },
+    });
+    std::fs::write(data_dir.path().join("wisdom.json"), history.to_string()).unwrap();
+
+    let path = data_dir.path().to_string_lossy().into_owned();
+    let app = spawn_app_with(|config| {
+        config.data_dir = path;
+        config.public_url = "https://cat.example".to_string();
+    })
+    .await;
+    app.mock.set_default(MockReply::content("Today's purr"));
+    (app, data_dir)
+}
+
+#[tokio::test]
+async fn atom_feed_lists_the_history_newest_first() {
+    let (app, _dir) = spawn_app_with_history().await;
+
+    let response = app.get("/feed.xml").await;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/atom+xml; charset=utf-8");
+    let xml = response.text().await.unwrap();
+    let today = xml.find("Today&apos;s purr").unwrap();
+    let second = xml.find("Second purr").unwrap();
+    let first = xml.find("First &lt;purr&gt; &amp; more").unwrap();
+    assert!(today < second && second < first);
+    assert!(xml.contains("<id>tag:cat.example,2025:whispurr/2025-06-01</id>"));
+    assert!(xml.contains("href=\"https://cat.example/wisdom/2025-06-02\""));
+    assert!(!xml.contains("From the future"));
+}
+
+#[tokio::test]
+async fn json_feed_follows_version_1_1() {
+    let (app, _dir) = spawn_app_with_history().await;
+
+    let response = app.get("/feed.json").await;
+
+    assert_eq!(response.headers()["content-type"], "application/feed+json; charset=utf-8");
+    let feed: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+    assert_eq!(feed["feed_url"], "https://cat.example/feed.json");
+    let items = feed["items"].as_array().unwrap();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[2]["id"], "tag:cat.example,2025:whispurr/2025-06-01");
+    assert_eq!(items[2]["url"], "https://cat.example/wisdom/2025-06-01");
+    assert_eq!(items[2]["content_text"], "First <purr> & more");
+}
+
+#[tokio::test]
+async fn feeds_answer_conditional_requests() {
+    let (app, _dir) = spawn_app_with_history().await;
+
+    let first = app.get("/feed.xml").await;
+    let etag = first.headers()["etag"].to_str().unwrap().to_string();
+    let last_modified = first.headers()["last-modified"].to_str().unwrap().to_string();
+
+    let by_etag = app.client.get(app.url("/feed.xml")).header("if-none-match", &etag).send().await.unwrap();
+    let by_date =
+        app.client.get(app.url("/feed.xml")).header("if-modified-since", &last_modified).send().await.unwrap();
+    let stale = app
+        .client
+        .get(app.url("/feed.xml"))
+        .header("if-none-match", "\"something-else\"")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(by_etag.status(), 304);
+    assert_eq!(by_etag.headers()["etag"], etag.as_str());
+    assert_eq!(by_date.status(), 304);
+    assert_eq!(stale.status(), 200);
+}
+
+#[tokio::test]
+async fn feed_entries_link_to_per_date_pages() {
+    let (app, _dir) = spawn_app_with_history().await;
+
+    let page = app.get("/wisdom/2025-06-02").await;
+    let missing = app.get("/wisdom/2024-01-01").await;
+    let malformed = app.get("/wisdom/yesterday").await;
+
+    assert_eq!(page.status(), 200);
+    assert!(page.text().await.unwrap().contains("Second purr"));
+    assert_eq!(missing.status(), 404);
+    assert_eq!(malformed.status(), 404);
+}
+
+#[tokio::test]
+async fn wisdom_history_is_written_to_the_data_dir() {
+    let app = common::spawn_app().await;
+    app.mock.push(MockReply::content("Remember me"));
+
+    app.get_json("/api/v1/daily-wisdom").await;
+
+    let saved = std::fs::read_to_string(app.data_dir.path().join("wisdom.json")).unwrap();
+    assert!(saved.contains("Remember me"));
+}
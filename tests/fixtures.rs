@@ -0,0 +1,113 @@
+mod common;
+
+use chrono::Utc;
+use the_enlightened_cat::corpus::Corpus;
+use the_enlightened_cat::fixtures::{FixtureMode, FixtureStore};
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{spawn_app, spawn_app_with};
+
+/// Checked-in recording of a model wrapping its JSON array in prose and a code fence
+const CHECKED_IN: &str = "tests/fixtures/llm";
+
+fn fixture_files(dir: &std::path::Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn record_mode_saves_exchanges_without_the_api_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture_dir = dir.path().to_str().unwrap().to_string();
+    let app = spawn_app_with(|config| {
+        config.mistral_api_key = "sk-very-secret".to_string();
+        config.fixture_mode = FixtureMode::Record;
+        config.fixture_dir = fixture_dir;
+    })
+    .await;
+    app.mock.push(MockReply::error(401, "invalid key sk-very-secret"));
+
+    app.get_json("/api/v1/daily-wisdom").await;
+
+    let files = fixture_files(dir.path());
+    assert_eq!(files.len(), 1);
+    assert!(!files[0].contains("sk-very-secret"));
+    assert!(files[0].contains("Bearer [REDACTED]"));
+    assert!(files[0].contains("invalid key [REDACTED]"));
+    let fixture: serde_json::Value = serde_json::from_str(&files[0]).unwrap();
+    assert_eq!(fixture["model"], "mistral-small");
+    assert_eq!(fixture["status"], 401);
+}
+
+#[tokio::test]
+async fn replay_mode_serves_recorded_exchanges_offline() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture_dir = dir.path().to_str().unwrap().to_string();
+
+    let recorder = spawn_app_with({
+        let fixture_dir = fixture_dir.clone();
+        |config| {
+            config.fixture_mode = FixtureMode::Record;
+            config.fixture_dir = fixture_dir;
+        }
+    })
+    .await;
+    recorder.mock.push(MockReply::content("Recorded whispurr"));
+    recorder.get_json("/api/v1/daily-wisdom").await;
+
+    let replayer = spawn_app_with(|config| {
+        config.fixture_mode = FixtureMode::Replay;
+        config.fixture_dir = fixture_dir;
+    })
+    .await;
+    let body = replayer.get_json("/api/v1/daily-wisdom").await;
+
+    assert_eq!(body["wisdom"], "Recorded whispurr");
+    assert_eq!(body["provenance"], "generated");
+    assert!(replayer.mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn replay_mode_without_a_fixture_falls_back_to_corpus() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture_dir = dir.path().to_str().unwrap().to_string();
+    let app = spawn_app_with(|config| {
+        config.fixture_mode = FixtureMode::Replay;
+        config.fixture_dir = fixture_dir;
+    })
+    .await;
+
+    let body = app.get_json("/api/v1/daily-wisdom").await;
+
+    assert_eq!(body["wisdom"], Corpus::bundled().whispurr_for(Utc::now().date_naive()));
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn prose_wrapped_quantum_field_is_parsed_on_replay() {
+    let app = spawn_app_with(|config| {
+        config.fixture_mode = FixtureMode::Replay;
+        config.fixture_dir = CHECKED_IN.to_string();
+    })
+    .await;
+
+    let body = app.get_json("/api/v1/quantum-field").await;
+
+    assert_eq!(body["provenance"], "generated");
+    assert_eq!(body["wisdom_field"][0]["seed"], "A whisker trembles at the edge of the known");
+    assert_eq!(body["wisdom_field"][5]["seed"], "Dew gathers the whole garden into one bead");
+}
+
+#[tokio::test]
+async fn mock_server_replays_fixtures() {
+    let app = spawn_app().await;
+    app.mock.replay_fixtures(FixtureStore::new(CHECKED_IN));
+
+    let body = app.get_json("/api/v1/quantum-field").await;
+
+    assert_eq!(body["provenance"], "generated");
+    assert_eq!(body["wisdom_field"][3]["seed"], "A door left ajar for the moon");
+    assert_eq!(app.mock.requests().len(), 1);
+}
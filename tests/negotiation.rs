@@ -0,0 +1,173 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{six_seeds, spawn_app, TestApp};
+
+const CURL: &str = "curl/8.5.0";
+
+/// GET `path` with the given request headers
+async fn get_with(app: &TestApp, path: &str, headers: &[(&str, &str)]) -> reqwest::Response {
+    let mut request = app.client.get(app.url(path));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    request.send().await.unwrap()
+}
+
+/// Whether the response tells caches it depends on `Accept` and `User-Agent`
+fn varies_by_client(response: &reqwest::Response) -> bool {
+    response.headers().get_all("vary").iter().any(|value| value == "Accept, User-Agent")
+}
+
+#[tokio::test]
+async fn curl_gets_the_cat_speaking_todays_wisdom() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Even the busiest mouse hole has a quiet corner."));
+    let today = Utc::now().date_naive();
+
+    let response = get_with(&app, "/", &[("user-agent", CURL), ("accept", "*/*")]).await;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/plain; charset=utf-8");
+    assert!(varies_by_client(&response));
+    let body = response.text().await.unwrap();
+    assert!(body.contains("< Even the busiest mouse hole has a quiet corner. >"), "{}", body);
+    assert!(body.contains("( -.- )"));
+    assert!(body.contains(&format!("— The Enlightened Cat, {}", today.format("%B %-d, %Y"))));
+    assert!(body.contains(&format!("curl https://the-enlightened-cat.com/wisdom/{}", today)));
+    assert!(!body.contains('\x1b'), "plain by default");
+    assert!(!body.contains("<html"));
+}
+
+#[tokio::test]
+async fn browsers_still_get_html() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Purr."));
+
+    let response = get_with(&app, "/", &[("accept", "text/html,application/xhtml+xml,*/*;q=0.8")]).await;
+
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+    assert!(varies_by_client(&response), "caches must not serve the HTML to curl");
+}
+
+#[tokio::test]
+async fn curl_asking_for_html_gets_html() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Purr."));
+
+    let response = get_with(&app, "/wisdom", &[("user-agent", CURL), ("accept", "text/html")]).await;
+
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+}
+
+#[tokio::test]
+async fn accept_decides_for_any_client() {
+    let app = spawn_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+
+    let cases = [
+        ("text/plain", true),
+        ("text/html;q=0.9, text/plain", true),
+        ("text/plain;q=0.5, text/html", false),
+        ("text/x-ansi", true),
+        ("*/*", false),
+    ];
+    for (accept, text) in cases {
+        let response = get_with(&app, "/wisdom", &[("accept", accept)]).await;
+        let content_type = response.headers()["content-type"].to_str().unwrap().to_string();
+        assert_eq!(content_type.starts_with("text/plain"), text, "Accept: {}", accept);
+    }
+}
+
+#[tokio::test]
+async fn color_is_available_on_request() {
+    let app = spawn_app().await;
+    app.mock.set_default(MockReply::content("Purr."));
+
+    let by_query = get_with(&app, "/?color", &[("user-agent", CURL)]).await.text().await.unwrap();
+    let by_accept = get_with(&app, "/", &[("accept", "text/x-ansi")]).await.text().await.unwrap();
+    let turned_off = get_with(&app, "/?color=0", &[("accept", "text/x-ansi")]).await.text().await.unwrap();
+
+    assert!(by_query.contains("\x1b[1mPurr.\x1b[0m"), "{}", by_query);
+    assert!(by_query.contains("/quantum-field?color"), "links keep the colour");
+    assert!(by_accept.contains('\x1b'));
+    assert!(!turned_off.contains('\x1b'));
+}
+
+#[tokio::test]
+async fn width_changes_the_wrap() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Stillness is not slowness; it is readiness for whatever the day brings."));
+
+    let body = get_with(&app, "/wisdom?width=30", &[("user-agent", CURL)]).await.text().await.unwrap();
+
+    let bubble: Vec<_> = body.lines().take_while(|line| !line.starts_with(" -")).collect();
+    assert!(bubble.len() > 3, "{}", body);
+    assert!(bubble.iter().all(|line| line.chars().count() <= 30), "{}", body);
+}
+
+#[tokio::test]
+async fn past_days_link_to_their_neighbours() {
+    let app = spawn_app().await;
+    let today = Utc::now().date_naive();
+    let yesterday = today - Duration::days(1);
+    app.mock.push(MockReply::content("Yesterday's purr."));
+    app.run_cli(&["generate-wisdom", "--date", &yesterday.to_string()]).await.unwrap();
+    app.mock.push(MockReply::content("Today's purr."));
+    app.get("/wisdom").await;
+
+    let response = get_with(&app, &format!("/wisdom/{}", yesterday), &[("user-agent", CURL)]).await;
+
+    let body = response.text().await.unwrap();
+    assert!(body.contains("Yesterday's purr."));
+    let next = body.lines().find(|line| line.trim_start().starts_with("Next day:")).unwrap();
+    assert!(next.ends_with(&format!("curl https://the-enlightened-cat.com/wisdom/{}", today)), "{}", body);
+    assert!(!body.contains("Previous day"));
+}
+
+#[tokio::test]
+async fn curl_gets_the_field_as_a_numbered_map() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let response = get_with(&app, "/quantum-field", &[("user-agent", CURL)]).await;
+
+    assert_eq!(response.headers()["content-type"], "text/plain; charset=utf-8");
+    let body = response.text().await.unwrap();
+    for label in ["(1) Essence", "(2) Inner Path", "(3) Outer Path", "(4) Portal", "(5) Friction", "(6) Crystallization"] {
+        assert!(body.contains(label), "{} missing from {}", label, body);
+    }
+    assert!(body.contains("  5. Friction\n     Seed of friction\n"));
+    assert!(body.contains("curl 'https://the-enlightened-cat.com/quantum-field?collapse=N'"));
+}
+
+#[tokio::test]
+async fn curl_collapses_a_node_into_a_reading() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let response = get_with(&app, "/quantum-field?collapse=2", &[("user-agent", CURL)]).await;
+
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("set-cookie"), "curl -c keeps the reading's owner");
+    let body = response.text().await.unwrap();
+    assert!(body.contains("Seed of the inner path"), "{}", body);
+    let readings = app.readings_on_disk();
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["index"], 1);
+    let id = readings[0]["id"].as_str().unwrap();
+    assert!(body.contains(&format!("https://the-enlightened-cat.com/quantum-field/reading/{}", id)));
+}
+
+#[tokio::test]
+async fn collapsing_outside_the_field_is_a_bad_request() {
+    let app = spawn_app().await;
+
+    let response = get_with(&app, "/quantum-field?collapse=7", &[("user-agent", CURL)]).await;
+
+    assert_eq!(response.status(), 400);
+    assert!(response.text().await.unwrap().contains("from 1 to 6"));
+    assert!(app.mock.requests().is_empty());
+}
@@ -0,0 +1,250 @@
+mod common;
+
+use chrono::NaiveDate;
+use the_enlightened_cat::mail::{Email, Mailer};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_smtp::{ReceivedMail, SmtpSink};
+use the_enlightened_cat::newsletter::{self, Status};
+
+use common::{six_seeds, spawn_app_with, TestApp};
+
+const PUBLIC_URL: &str = "https://the-enlightened-cat.com";
+
+/// An app whose newsletter is sent through `sink`
+async fn spawn_newsletter_app(sink: &SmtpSink) -> TestApp {
+    let smtp_url = sink.url();
+    spawn_app_with(|config| config.smtp_url = Some(smtp_url)).await
+}
+
+async fn subscribe(app: &TestApp, email: &str, frequency: &str) -> reqwest::Response {
+    app.client
+        .post(app.url("/newsletter/subscribe"))
+        .form(&[("email", email), ("frequency", frequency)])
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The first link in a message's text starting with `prefix`, on the app
+/// rather than the public site
+fn link(app: &TestApp, mail: &ReceivedMail, prefix: &str) -> String {
+    let text = mail.text().unwrap();
+    let start = text.find(&format!("{}{}", PUBLIC_URL, prefix)).expect("no such link");
+    let url = text[start..].split_whitespace().next().unwrap();
+    app.url(url.trim_start_matches(PUBLIC_URL))
+}
+
+/// Subscribes and follows the confirmation link
+async fn subscribe_confirmed(app: &TestApp, sink: &SmtpSink, email: &str, frequency: &str) {
+    subscribe(app, email, frequency).await;
+    let confirmation = sink.messages().pop().unwrap();
+    let confirmed = app.client.get(link(app, &confirmation, "/newsletter/confirm/")).send().await.unwrap();
+    assert_eq!(confirmed.status(), 200);
+}
+
+#[tokio::test]
+async fn the_newsletter_is_off_without_smtp() {
+    let app = common::spawn_app().await;
+
+    let signup = app.get("/newsletter").await;
+    let subscribed = subscribe(&app, "kit@example.com", "daily").await;
+    let about = app.get("/about").await.text().await.unwrap();
+
+    assert_eq!(signup.status(), 404);
+    assert_eq!(subscribed.status(), 404);
+    assert!(!about.contains("newsletter-form"), "no signup offered on the about page");
+}
+
+#[tokio::test]
+async fn a_subscription_starts_only_once_confirmed() {
+    let sink = SmtpSink::start().await.unwrap();
+    let app = spawn_newsletter_app(&sink).await;
+    assert!(app.get("/about").await.text().await.unwrap().contains("action=\"/newsletter/subscribe\""));
+
+    let response = subscribe(&app, "Kit@Example.com", "daily").await;
+
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("Check your inbox"));
+    let messages = sink.messages();
+    assert_eq!(messages.len(), 1);
+    let confirmation = &messages[0];
+    assert_eq!(confirmation.to, vec!["kit@example.com"]);
+    assert_eq!(confirmation.from, "whispurrs@the-enlightened-cat.com");
+    assert_eq!(confirmation.header("subject").unwrap(), "Confirm your Whispurrs subscription");
+    assert!(confirmation.html().unwrap().contains("/newsletter/confirm/"));
+    assert!(confirmation.header("list-unsubscribe").unwrap().starts_with("<https://the-enlightened-cat.com/newsletter/unsubscribe/"));
+
+    // Nothing is sent to an unconfirmed address
+    let state = app.state();
+    assert!(newsletter::send_due(&state, "2030-01-01T08:00:00Z".parse().unwrap()).await.is_empty());
+
+    let confirmed = app.client.get(link(&app, confirmation, "/newsletter/confirm/")).send().await.unwrap();
+    assert_eq!(confirmed.status(), 200);
+    assert!(confirmed.text().await.unwrap().contains("every morning"));
+    assert_eq!(state.newsletter.subscribers().await[0].status, Status::Confirmed);
+    assert_eq!(app.get("/newsletter/confirm/not-a-token").await.status(), 404);
+}
+
+#[tokio::test]
+async fn daily_digests_carry_the_whispurr_a_seed_and_a_one_click_unsubscribe() {
+    let sink = SmtpSink::start().await.unwrap();
+    let app = spawn_newsletter_app(&sink).await;
+    subscribe_confirmed(&app, &sink, "kit@example.com", "daily").await;
+    app.mock.push(MockReply::content("The inbox is not a to-do list."));
+    app.mock.push(MockReply::content(six_seeds()));
+    let state = app.state();
+
+    assert!(newsletter::send_due(&state, "2030-01-01T06:59:00Z".parse().unwrap()).await.is_empty());
+    assert_eq!(newsletter::send_due(&state, "2030-01-01T07:01:00Z".parse().unwrap()).await, vec!["kit@example.com"]);
+    assert!(newsletter::send_due(&state, "2030-01-01T20:00:00Z".parse().unwrap()).await.is_empty(), "once a day");
+
+    let messages = sink.messages();
+    assert_eq!(messages.len(), 2);
+    let digest = &messages[1];
+    assert_eq!(digest.header("subject").unwrap(), "Your Whispurr for January 1, 2030");
+    assert_eq!(digest.header("list-unsubscribe-post").unwrap(), "List-Unsubscribe=One-Click");
+    let text = digest.text().unwrap();
+    let html = digest.html().unwrap();
+    assert!(text.contains("\"The inbox is not a to-do list.\""), "{}", text);
+    assert!(text.contains("https://the-enlightened-cat.com/wisdom/2030-01-01"));
+    // January 1st features the first seed
+    assert!(text.contains("Seed of essence"), "{}", text);
+    assert!(html.contains("The inbox is not a to-do list."));
+    assert!(html.contains("Seed of essence"));
+    assert!(html.contains("Unsubscribe</a>"));
+}
+
+#[tokio::test]
+async fn weekly_digests_go_out_on_mondays_with_the_week() {
+    let sink = SmtpSink::start().await.unwrap();
+    let app = spawn_newsletter_app(&sink).await;
+    subscribe_confirmed(&app, &sink, "kit@example.com", "weekly").await;
+    let state = app.state();
+    let date = |day: &str| day.parse::<NaiveDate>().unwrap();
+    state.set_daily_wisdom(date("2029-12-31"), "Last year's nap.".to_string()).await;
+    state.set_daily_wisdom(date("2030-01-05"), "Saturday's stretch.".to_string()).await;
+    state.set_daily_wisdom(date("2030-01-06"), "Sunday's sunbeam.".to_string()).await;
+    app.mock.push(MockReply::content("Monday's purr."));
+    app.mock.push(MockReply::content(six_seeds()));
+
+    // 2030-01-01 is a Tuesday
+    assert!(newsletter::send_due(&state, "2030-01-01T09:00:00Z".parse().unwrap()).await.is_empty());
+    assert_eq!(newsletter::send_due(&state, "2030-01-07T09:00:00Z".parse().unwrap()).await.len(), 1);
+
+    let digest = sink.messages().pop().unwrap();
+    let text = digest.text().unwrap();
+    assert_eq!(digest.header("subject").unwrap(), "Your week of Whispurrs, January 7, 2030");
+    let monday = text.find("Monday's purr.").unwrap();
+    let sunday = text.find("Sunday's sunbeam.").unwrap();
+    let saturday = text.find("Saturday's stretch.").unwrap();
+    assert!(monday < sunday && sunday < saturday, "newest first: {}", text);
+    assert!(!text.contains("Last year's nap."), "only the last seven days");
+}
+
+#[tokio::test]
+async fn weekly_digests_wait_while_the_days_wisdom_cannot_be_generated() {
+    let sink = SmtpSink::start().await.unwrap();
+    let app = spawn_newsletter_app(&sink).await;
+    subscribe_confirmed(&app, &sink, "kit@example.com", "weekly").await;
+    app.mock.push(MockReply::error(503, "service unavailable"));
+    let monday = "2030-01-07T09:00:00Z".parse().unwrap();
+
+    assert!(newsletter::send_due(&app.state(), monday).await.is_empty());
+    assert_eq!(sink.messages().len(), 1, "only the confirmation");
+
+    // Later, once the API is back: a new instance has no pending retry
+    app.mock.push(MockReply::content("Monday's purr."));
+    app.mock.push(MockReply::content(six_seeds()));
+    assert_eq!(newsletter::send_due(&app.state(), monday).await.len(), 1);
+    let text = sink.messages().pop().unwrap().text().unwrap();
+    assert!(text.contains("Monday's purr."), "{}", text);
+}
+
+#[tokio::test]
+async fn unsubscribing_takes_one_click_but_not_a_link_scanner() {
+    let sink = SmtpSink::start().await.unwrap();
+    let app = spawn_newsletter_app(&sink).await;
+    subscribe_confirmed(&app, &sink, "kit@example.com", "weekly").await;
+    let header = sink.messages()[0].header("list-unsubscribe").unwrap();
+    let url = app.url(header.trim_matches(|c| c == '<' || c == '>').trim_start_matches(PUBLIC_URL));
+    let state = app.state();
+
+    let page = app.client.get(url.as_str()).send().await.unwrap();
+    assert_eq!(page.status(), 200);
+    assert!(page.text().await.unwrap().contains("Stop sending whispurrs to kit@example.com?"));
+    assert_eq!(state.newsletter.subscribers().await.len(), 1, "following the link alone doesn't unsubscribe");
+
+    // As a mail client does it (RFC 8058)
+    let one_click = app.client.post(url.as_str()).form(&[("List-Unsubscribe", "One-Click")]).send().await.unwrap();
+    assert_eq!(one_click.status(), 200);
+    assert!(state.newsletter.subscribers().await.is_empty());
+    // A retry still succeeds
+    assert_eq!(app.client.post(url.as_str()).send().await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_and_their_links_survive_a_backup() {
+    let sink = SmtpSink::start().await.unwrap();
+    let source = spawn_newsletter_app(&sink).await;
+    subscribe_confirmed(&source, &sink, "kit@example.com", "weekly").await;
+    subscribe(&source, "pending@example.com", "daily").await;
+    let backup = source.data_dir.path().join("backup.json");
+    let exported = source.run_cli(&["export", "--output", backup.to_str().unwrap()]).await.unwrap();
+    assert!(exported.contains("2 subscribers"), "{}", exported);
+
+    let target = spawn_newsletter_app(&sink).await;
+    let imported = target.run_cli(&["import", backup.to_str().unwrap()]).await.unwrap();
+
+    assert!(imported.contains("2 subscribers"), "{}", imported);
+    assert_eq!(target.state().newsletter.subscribers().await, source.state().newsletter.subscribers().await);
+    // The links already mailed keep working on the restored instance
+    let header = sink.messages()[0].header("list-unsubscribe").unwrap();
+    let url = target.url(header.trim_matches(|c| c == '<' || c == '>').trim_start_matches(PUBLIC_URL));
+    let one_click = target.client.post(url.as_str()).form(&[("List-Unsubscribe", "One-Click")]).send().await.unwrap();
+    assert_eq!(one_click.status(), 200);
+    assert_eq!(target.state().newsletter.subscribers().await.len(), 1);
+}
+
+#[tokio::test]
+async fn the_signup_neither_leaks_nor_floods() {
+    let sink = SmtpSink::start().await.unwrap();
+    let app = spawn_newsletter_app(&sink).await;
+
+    let invalid = subscribe(&app, "not an address", "daily").await;
+    assert_eq!(invalid.status(), 422);
+    assert!(invalid.text().await.unwrap().contains("look like an email address"));
+    assert!(sink.messages().is_empty());
+
+    let first = subscribe(&app, "kit@example.com", "daily").await.text().await.unwrap();
+    let again = subscribe(&app, "kit@example.com", "weekly").await.text().await.unwrap();
+    assert_eq!(first, again, "the same answer whether or not the address is known");
+    assert_eq!(sink.messages().len(), 1, "the confirmation isn't re-sent straight away");
+    assert_eq!(app.state().newsletter.subscribers().await[0].frequency, newsletter::Frequency::Weekly);
+}
+
+#[tokio::test]
+async fn mail_is_dot_stuffed_and_encoded() {
+    let sink = SmtpSink::start().await.unwrap();
+    let mailer = Mailer::new(&sink.url(), "Cat <cat@example.com>").unwrap();
+    let email = Email {
+        to: "kit@example.com".to_string(),
+        subject: "Purr — ☕".to_string(),
+        text: "Line one\n.\n.. a line of dots".to_string(),
+        html: "<p>Purr</p>".to_string(),
+        unsubscribe_url: None,
+    };
+
+    mailer.send(&email).await.unwrap();
+
+    let mail = &sink.messages()[0];
+    assert_eq!(mail.from, "cat@example.com");
+    assert_eq!(mail.header("from").unwrap(), "Cat <cat@example.com>");
+    let subject = mail.header("subject").unwrap();
+    assert!(subject.to_ascii_lowercase().contains("=?utf-8?") && subject.is_ascii(), "RFC 2047 encoded: {}", subject);
+    assert_eq!(mail.header("list-unsubscribe"), None);
+    // Text travels with CRLF line endings, as MIME has it
+    assert_eq!(mail.text().unwrap(), email.text.replace('\n', "\r\n"));
+    assert_eq!(mail.html().unwrap(), email.html);
+    assert!(Mailer::new("http://example.com", "cat@example.com").is_err());
+    assert!(Mailer::new(&sink.url(), "not an address").is_err());
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{six_seeds, spawn_app, TestApp};
+
+/// Fetches the offline bundle for a visitor in UTC
+async fn bundle(app: &TestApp) -> reqwest::Response {
+    app.client.get(app.url("/api/offline-bundle")).header("X-Timezone", "UTC").send().await.unwrap()
+}
+
+#[tokio::test]
+async fn every_page_links_the_manifest() {
+    let app = spawn_app().await;
+
+    let response = app.get("/manifest.webmanifest").await;
+    let home = app.get("/").await.text().await.unwrap();
+
+    assert_eq!(response.headers()["content-type"], "application/manifest+json");
+    let manifest: Value = response.json().await.unwrap();
+    assert_eq!(manifest["start_url"], "/");
+    assert_eq!(manifest["display"], "standalone");
+    for icon in manifest["icons"].as_array().unwrap() {
+        assert_eq!(app.get(icon["src"].as_str().unwrap()).await.status(), 200);
+    }
+    assert!(home.contains(r#"<link rel="manifest" href="/manifest.webmanifest">"#));
+    assert!(home.contains("offline-badge"));
+}
+
+#[tokio::test]
+async fn the_worker_caches_from_the_bundle_and_answers_chat_offline() {
+    let app = spawn_app().await;
+
+    let worker = app.get("/sw.js").await.text().await.unwrap();
+
+    assert!(worker.contains("'/api/offline-bundle'"));
+    assert!(worker.contains("offline: true"));
+    assert!(worker.contains("showNotification"), "still shows push notifications");
+    assert!(worker.contains("isBundled(url)"), "only pages the bundle names are cached");
+}
+
+#[tokio::test]
+async fn the_bundle_has_a_week_of_wisdom_and_the_field() {
+    let app = spawn_app().await;
+    let state = app.state();
+    let today = Utc::now().date_naive();
+    // A week and a day of history; the oldest is left out
+    for days_ago in 1..=7 {
+        state.set_daily_wisdom(today - Duration::days(days_ago), format!("Purr {}", days_ago)).await;
+    }
+    app.mock.push(MockReply::content("Today's purr"));
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let body: Value = bundle(&app).await.json().await.unwrap();
+
+    assert_eq!(body["date"], today.to_string());
+    assert_eq!(body["timezone"], "UTC");
+    let wisdom = body["wisdom"].as_array().unwrap();
+    assert_eq!(wisdom.len(), 7);
+    assert_eq!(wisdom[0]["wisdom"], "Today's purr");
+    assert_eq!(wisdom[0]["url"], format!("/wisdom/{}", today));
+    assert_eq!(wisdom[6]["wisdom"], "Purr 6");
+    assert_eq!(wisdom[6]["provenance"], "curated");
+    assert_eq!(body["quantum_field"]["wisdom_field"].as_array().unwrap().len(), 6);
+    assert_eq!(body["quantum_field"]["wisdom_field"][0]["seed"], "Seed of essence");
+    assert_eq!(body["corpus"].as_array().unwrap().len(), state.corpus.whispurrs.len());
+}
+
+#[tokio::test]
+async fn every_shell_url_in_the_bundle_is_served() {
+    let app = spawn_app().await;
+
+    let body: Value = bundle(&app).await.json().await.unwrap();
+
+    let shell = body["shell"].as_array().unwrap();
+    let home = app.get("/").await.text().await.unwrap();
+    let linked: Vec<_> = shell.iter().map(|url| url.as_str().unwrap()).filter(|url| url.contains("?v=")).collect();
+    assert!(linked.iter().any(|url| url.starts_with("/static/css/")));
+    assert!(linked.iter().any(|url| url.starts_with("/static/js/main.js")));
+    for url in linked {
+        assert!(home.contains(&format!("\"{}\"", url)), "pages link {} as the worker caches it", url);
+    }
+    for url in shell {
+        assert_eq!(app.get(url.as_str().unwrap()).await.status(), 200, "{}", url);
+    }
+    for day in body["wisdom"].as_array().unwrap() {
+        assert_eq!(app.get(day["url"].as_str().unwrap()).await.status(), 200);
+    }
+}
+
+#[tokio::test]
+async fn the_bundle_answers_revalidation_with_not_modified() {
+    let app = spawn_app().await;
+
+    let first = bundle(&app).await;
+    assert_eq!(first.headers()["cache-control"], "no-cache");
+    let etag = first.headers()["etag"].to_str().unwrap().to_string();
+    let again = app
+        .client
+        .get(app.url("/api/offline-bundle"))
+        .header("X-Timezone", "UTC")
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(again.status(), 304);
+}
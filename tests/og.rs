@@ -0,0 +1,122 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::og::{Artwork, Card, OgCards, CARD_HEIGHT, CARD_WIDTH};
+
+use common::{six_seeds, spawn_app};
+
+/// Width and height from a PNG's IHDR chunk
+fn png_size(bytes: &[u8]) -> (u32, u32) {
+    assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n", "not a PNG");
+    let width = u32::from_be_bytes(bytes[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(bytes[20..24].try_into().unwrap());
+    (width, height)
+}
+
+fn card(text: &str) -> Card {
+    Card {
+        heading: "DAILY WHISPURR".to_string(),
+        text: text.to_string(),
+        footer: "June 1, 2025".to_string(),
+        artwork: Artwork::Cat,
+    }
+}
+
+#[tokio::test]
+async fn wisdom_card_is_a_cached_png() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Sit in the sunbeam."));
+    let today = Utc::now().date_naive();
+
+    let response = app.get(&format!("/og/wisdom/{}.png", today)).await;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let body = response.bytes().await.unwrap();
+    assert_eq!(png_size(&body), (CARD_WIDTH, CARD_HEIGHT));
+
+    let cached: Vec<_> = std::fs::read_dir(app.data_dir.path().join("og")).unwrap().collect();
+    assert_eq!(cached.len(), 1);
+
+    let revalidated = app
+        .client
+        .get(app.url(&format!("/og/wisdom/{}.png", today)))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(revalidated.status(), 304);
+}
+
+#[tokio::test]
+async fn wisdom_card_for_a_future_or_malformed_date_is_not_found() {
+    let app = spawn_app().await;
+    let tomorrow = Utc::now().date_naive() + Duration::days(2);
+
+    assert_eq!(app.get(&format!("/og/wisdom/{}.png", tomorrow)).await.status(), 404);
+    assert_eq!(app.get("/og/wisdom/yesterday.png").await.status(), 404);
+    assert_eq!(app.get(&format!("/og/wisdom/{}.jpg", Utc::now().date_naive())).await.status(), 404);
+}
+
+#[tokio::test]
+async fn reading_card_respects_visibility() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+    let collapsed = app.get_json("/api/v1/quantum-field/collapse?index=0").await;
+    let id = collapsed["reading_id"].as_str().unwrap();
+    let path = format!("/og/reading/{}.png", id);
+
+    let public = app.stranger().get(app.url(&path)).send().await.unwrap();
+    assert_eq!(public.status(), 200);
+    assert_eq!(png_size(&public.bytes().await.unwrap()), (CARD_WIDTH, CARD_HEIGHT));
+
+    app.patch_json(&format!("/api/v1/quantum-field/readings/{}", id), serde_json::json!({ "visibility": "private" })).await;
+    assert_eq!(app.stranger().get(app.url(&path)).send().await.unwrap().status(), 404);
+    assert_eq!(app.get(&path).await.status(), 200);
+}
+
+#[tokio::test]
+async fn pages_point_their_previews_at_the_cards() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Sit in the sunbeam."));
+    let today = Utc::now().date_naive();
+
+    let html = app.get(&format!("/wisdom/{}", today)).await.text().await.unwrap();
+
+    let image = format!("https://the-enlightened-cat.com/og/wisdom/{}.png", today);
+    assert!(html.contains(&format!("<meta property=\"og:image\" content=\"{}\" />", image)));
+    assert!(html.contains(&format!("<meta name=\"twitter:image\" content=\"{}\" />", image)));
+}
+
+#[tokio::test]
+async fn changed_cards_replace_their_stale_render() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let cards = OgCards::new(dir.path());
+
+    let first = cards.png("wisdom-2025-06-01", card("Sit in the sunbeam.")).await.unwrap();
+    let again = cards.png("wisdom-2025-06-01", card("Sit in the sunbeam.")).await.unwrap();
+    let edited = cards.png("wisdom-2025-06-01", card("Nap, then decide.")).await.unwrap();
+    cards.png("wisdom-2025-06-02", card("Sit in the sunbeam.")).await.unwrap();
+
+    assert_eq!(first, again);
+    assert_ne!(first, edited);
+    let mut files: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), 2, "unexpected cache contents: {:?}", files);
+    assert!(files[0].starts_with("wisdom-2025-06-01-"));
+    assert!(files[1].starts_with("wisdom-2025-06-02-"));
+}
+
+#[test]
+fn long_text_still_renders_a_full_size_card() {
+    let cards = OgCards::new(std::env::temp_dir());
+
+    let png = cards.render(&card(&"purr ".repeat(400))).unwrap();
+
+    assert_eq!(png_size(&png), (CARD_WIDTH, CARD_HEIGHT));
+}
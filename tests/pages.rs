@@ -0,0 +1,159 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use tempfile::TempDir;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{spawn_app, spawn_app_with, TestApp};
+
+async fn html(app: &common::TestApp, path: &str) -> String {
+    let response = app.get(path).await;
+    assert_eq!(response.status(), 200, "GET {}", path);
+    let content_type = response.headers()["content-type"].to_str().unwrap().to_string();
+    assert!(content_type.starts_with("text/html"), "GET {} returned {}", path, content_type);
+    response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn index_shows_todays_wisdom() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("The windowsill remembers the sun."));
+
+    let page = html(&app, "/").await;
+
+    assert!(page.contains("The windowsill remembers the sun."));
+    assert!(page.contains(r#"data-provenance="generated""#));
+}
+
+#[tokio::test]
+async fn wisdom_page_escapes_model_output() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("<script>alert('meow')</script>"));
+
+    let page = html(&app, "/wisdom").await;
+
+    assert!(!page.contains("<script>alert('meow')</script>"));
+    assert!(page.contains("&lt;script&gt;"));
+}
+
+#[tokio::test]
+async fn wisdom_page_survives_upstream_errors() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::error(500, "boom"));
+
+    let page = html(&app, "/wisdom").await;
+
+    assert!(page.contains(r#"data-provenance="corpus""#));
+}
+
+#[tokio::test]
+async fn about_page_renders() {
+    let app = spawn_app().await;
+
+    let page = html(&app, "/about").await;
+
+    assert!(page.contains("The Enlightened Cat"));
+    assert!(app.mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn quantum_field_page_renders() {
+    let app = spawn_app().await;
+
+    let page = html(&app, "/quantum-field").await;
+
+    assert!(page.contains("/api/v1/quantum-field"));
+}
+
+#[tokio::test]
+async fn static_files_are_served() {
+    let app = spawn_app().await;
+
+    let response = app.get("/static/css/styles.css").await;
+
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn unknown_routes_are_not_found() {
+    let app = spawn_app().await;
+
+    let response = app.get("/no-such-page").await;
+
+    assert_eq!(response.status(), 404);
+    assert!(response.text().await.unwrap().contains("Read today's Whispurr"));
+}
+
+/// Starts the app with wisdom on disk for the given dates
+async fn spawn_app_with_days(days: &[&str]) -> (TestApp, TempDir) {
+    let data_dir = TempDir::new().unwrap();
+    let history: serde_json::Map<String, serde_json::Value> = days
+        .iter()
+        .map(|day| {
+            let entry = serde_json::json!({
+                "text": format!("Whispurr of {}", day),
+                "provenance": "generated",
+                "updated_at": "2025-06-01T06:00:00Z",
+            });
+            (day.to_string(), entry)
+        })
+        .collect();
+    std::fs::write(data_dir.path().join("wisdom.json"), serde_json::Value::Object(history).to_string()).unwrap();
+
+    let path = data_dir.path().to_string_lossy().into_owned();
+    let app = spawn_app_with(|config| config.data_dir = path).await;
+    (app, data_dir)
+}
+
+#[tokio::test]
+async fn wisdom_permalink_links_to_neighbouring_days() {
+    let (app, _dir) = spawn_app_with_days(&["2025-05-30", "2025-06-01", "2025-06-04"]).await;
+
+    let page = html(&app, "/wisdom/2025-06-01").await;
+
+    assert!(page.contains("Whispurr of 2025-06-01"));
+    assert!(page.contains("June 1, 2025"));
+    assert!(page.contains(r#"rel="prev" href="/wisdom/2025-05-30""#));
+    assert!(page.contains(r#"rel="next" href="/wisdom/2025-06-04""#));
+    assert!(page.contains(r#"og:url" content="https://the-enlightened-cat.com/wisdom/2025-06-01""#));
+    assert!(page.contains(r#"rel="canonical" href="https://the-enlightened-cat.com/wisdom/2025-06-01""#));
+}
+
+#[tokio::test]
+async fn todays_wisdom_page_shares_its_permalink() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Today only"));
+    let today = Utc::now().date_naive();
+
+    let page = html(&app, "/wisdom").await;
+    let permalink = html(&app, &format!("/wisdom/{}", today)).await;
+
+    assert!(page.contains(&format!(r#"og:url" content="https://the-enlightened-cat.com/wisdom/{}""#, today)));
+    assert!(permalink.contains("Today only"));
+    assert_eq!(app.mock.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn wisdom_permalinks_404_for_days_without_wisdom() {
+    let tomorrow = (Utc::now().date_naive() + Duration::days(1)).to_string();
+    let (app, _dir) = spawn_app_with_days(&["2025-06-01", &tomorrow]).await;
+
+    for path in ["/wisdom/2025-06-02".to_string(), format!("/wisdom/{}", tomorrow), "/wisdom/june".to_string()] {
+        let response = app.get(&path).await;
+        assert_eq!(response.status(), 404, "GET {}", path);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+        assert!(response.text().await.unwrap().contains("no Whispurr for that day"));
+    }
+}
+
+#[tokio::test]
+async fn wisdom_permalinks_do_not_link_into_the_future() {
+    let today = Utc::now().date_naive();
+    let yesterday = (today - Duration::days(1)).to_string();
+    let tomorrow = (today + Duration::days(1)).to_string();
+    let (app, _dir) = spawn_app_with_days(&[&yesterday, &tomorrow]).await;
+
+    let page = html(&app, &format!("/wisdom/{}", yesterday)).await;
+
+    assert!(!page.contains(r#"rel="next""#));
+}
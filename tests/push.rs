@@ -0,0 +1,206 @@
+mod common;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::{json, Value};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_push::MockPushService;
+use the_enlightened_cat::backup::Backup;
+use the_enlightened_cat::webpush;
+
+use common::{spawn_app_with, TestApp};
+
+async fn spawn_push_app() -> TestApp {
+    spawn_app_with(|config| {
+        config.vapid_subject = Some("mailto:cat@example.com".to_string());
+        // The mock push service is plain http on 127.0.0.1
+        config.push_allow_local = true;
+    })
+    .await
+}
+
+/// Subscribes a browser's subscription with a delivery time and zone
+async fn subscribe(app: &TestApp, subscription: &Value, time: &str, timezone: &str) -> reqwest::Response {
+    let mut body = subscription.clone();
+    body["time"] = json!(time);
+    body["timezone"] = json!(timezone);
+    app.post_json("/api/v1/push/subscriptions", body).await
+}
+
+#[tokio::test]
+async fn push_is_off_without_a_vapid_subject() {
+    let app = common::spawn_app().await;
+
+    let key = app.get("/api/v1/push/public-key").await;
+    let wisdom = app.get("/wisdom").await.text().await.unwrap();
+    let worker = app.get("/sw.js").await;
+
+    assert_eq!(key.status(), 404);
+    assert!(!wisdom.contains("push-form"));
+    assert!(!app.data_dir.path().join("vapid.pem").exists());
+    // The worker is harmless without subscriptions
+    assert_eq!(worker.status(), 200);
+    assert_eq!(worker.headers()["content-type"], "text/javascript; charset=utf-8");
+    assert!(worker.text().await.unwrap().contains("showNotification"));
+}
+
+#[tokio::test]
+async fn the_vapid_key_is_offered_and_kept() {
+    let app = spawn_push_app().await;
+
+    let key = app.get_json("/api/v1/push/public-key").await["public_key"].as_str().unwrap().to_string();
+    let bytes = URL_SAFE_NO_PAD.decode(&key).unwrap();
+
+    assert_eq!(bytes.len(), 65);
+    assert_eq!(bytes[0], 4, "uncompressed");
+    assert!(app.data_dir.path().join("vapid.pem").exists());
+    assert_eq!(app.state().web_push.unwrap().public_key(), key);
+    assert!(app.get("/wisdom").await.text().await.unwrap().contains("/static/js/push.js"));
+    let openapi = app.get_json("/api/v1/openapi.json").await;
+    assert!(openapi["paths"]["/api/v1/push/subscriptions"]["post"].is_object());
+}
+
+#[tokio::test]
+async fn subscribers_get_an_encrypted_signed_push_at_their_local_time() {
+    let app = spawn_push_app().await;
+    let service = MockPushService::start().await.unwrap();
+    app.mock.push(MockReply::content("Stretch before you answer that email."));
+
+    let response = subscribe(&app, &service.subscription("kit"), "07:30", "Europe/Berlin").await;
+    assert_eq!(response.status(), 201);
+    let saved: Value = response.json().await.unwrap();
+    assert_eq!(saved["time"], "07:30");
+    assert_eq!(saved["timezone"], "Europe/Berlin");
+    let state = app.state();
+
+    // 07:20 and 07:31 in Berlin
+    assert!(webpush::push_due(&state, "2030-01-01T06:20:00Z".parse().unwrap()).await.is_empty());
+    assert_eq!(webpush::push_due(&state, "2030-01-01T06:31:00Z".parse().unwrap()).await.len(), 1);
+    assert!(webpush::push_due(&state, "2030-01-01T12:00:00Z".parse().unwrap()).await.is_empty(), "once a day");
+
+    let messages = service.messages();
+    assert_eq!(messages.len(), 1);
+    let push = &messages[0];
+    assert!(push.vapid_valid, "signed with the VAPID key for this push service");
+    assert_eq!(push.vapid_subject.as_deref(), Some("mailto:cat@example.com"));
+    assert_eq!(push.header("content-encoding"), Some("aes128gcm"));
+    assert_eq!(push.header("ttl"), Some("43200"));
+    let payload = push.payload.as_ref().expect("the payload decrypts with the browser's keys");
+    assert_eq!(payload["body"], "Stretch before you answer that email.");
+    assert_eq!(payload["url"], "https://the-enlightened-cat.com/wisdom/2030-01-01");
+}
+
+#[tokio::test]
+async fn expired_subscriptions_are_pruned() {
+    let app = spawn_push_app().await;
+    let service = MockPushService::start().await.unwrap();
+    app.mock.set_default(MockReply::content("Purr."));
+    subscribe(&app, &service.subscription("kept"), "07:00", "UTC").await;
+    subscribe(&app, &service.subscription("dropped"), "07:00", "UTC").await;
+    service.expire("dropped");
+    let state = app.state();
+
+    let pushed = webpush::push_due(&state, "2030-01-01T08:00:00Z".parse().unwrap()).await;
+
+    assert_eq!(pushed, vec![service.subscription("kept")["endpoint"].as_str().unwrap()]);
+    let remaining = state.web_push.unwrap().subscriptions().await;
+    assert_eq!(remaining.len(), 1);
+    assert!(remaining[0].endpoint.ends_with("/push/kept"));
+}
+
+#[tokio::test]
+async fn endpoints_must_be_public_https_urls() {
+    let app = spawn_app_with(|config| config.vapid_subject = Some("mailto:cat@example.com".to_string())).await;
+    let service = MockPushService::start().await.unwrap();
+
+    for endpoint in [
+        service.subscription("local")["endpoint"].as_str().unwrap(),
+        "https://127.0.0.1/push/kit",
+        "https://169.254.169.254/latest/meta-data",
+        "https://[fd00::1]/push/kit",
+        "http://93.184.215.14/push/kit",
+    ] {
+        let mut subscription = service.subscription("kit");
+        subscription["endpoint"] = json!(endpoint);
+        let response = subscribe(&app, &subscription, "07:00", "UTC").await;
+        assert_eq!(response.status(), 422, "{} was taken", endpoint);
+    }
+    assert!(app.state().web_push.unwrap().subscriptions().await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribing_is_limited_per_address_and_in_total() {
+    let app = spawn_app_with(|config| {
+        config.vapid_subject = Some("mailto:cat@example.com".to_string());
+        config.push_allow_local = true;
+        config.max_push_subscriptions = 2;
+    })
+    .await;
+    let service = MockPushService::start().await.unwrap();
+    let subscribe_from = |address: &'static str, name: &str| {
+        let mut body = service.subscription(name);
+        body["time"] = json!("07:00");
+        body["timezone"] = json!("UTC");
+        app.client.post(app.url("/api/v1/push/subscriptions")).header("x-real-ip", address).json(&body).send()
+    };
+
+    for _ in 0..webpush::MAX_SUBSCRIBES_PER_HOUR {
+        assert_eq!(subscribe_from("203.0.113.7", "kit").await.unwrap().status(), 201);
+    }
+    assert_eq!(subscribe_from("203.0.113.7", "kit").await.unwrap().status(), 429);
+
+    assert_eq!(subscribe_from("203.0.113.8", "tom").await.unwrap().status(), 201);
+    assert_eq!(subscribe_from("203.0.113.8", "max").await.unwrap().status(), 503, "the store is full");
+    assert_eq!(subscribe_from("203.0.113.8", "tom").await.unwrap().status(), 201, "moving a time still works");
+    assert_eq!(app.state().web_push.unwrap().subscriptions().await.len(), 2);
+}
+
+#[tokio::test]
+async fn restored_subscriptions_are_pushed_with_the_restored_key() {
+    let source = spawn_push_app().await;
+    let service = MockPushService::start().await.unwrap();
+    subscribe(&source, &service.subscription("kit"), "07:00", "UTC").await;
+    let source_key = source.state().web_push.unwrap().public_key().to_string();
+    let json = serde_json::to_string(&Backup::collect(&source.state()).await.unwrap()).unwrap();
+    let backup: Backup = serde_json::from_str(&json).unwrap();
+
+    let target = spawn_push_app().await;
+    target.mock.set_default(MockReply::content("Purr."));
+    let summary = backup.clone().restore(&target.state(), true).await.unwrap();
+    assert_eq!(summary.push, 1);
+
+    // As after a restart
+    let state = target.state();
+    assert_eq!(state.web_push.as_ref().unwrap().public_key(), source_key);
+    assert_eq!(webpush::push_due(&state, "2030-01-01T08:00:00Z".parse().unwrap()).await.len(), 1);
+    let push = &service.messages()[0];
+    assert!(push.vapid_valid);
+    assert!(push.header("authorization").unwrap().ends_with(&format!("k={}", source_key)));
+    assert!(push.payload.is_some());
+
+    let unconfigured = common::spawn_app().await;
+    let error = backup.restore(&unconfigured.state(), false).await.unwrap_err();
+    assert!(error.to_string().contains("set VAPID_SUBJECT"), "{}", error);
+}
+
+#[tokio::test]
+async fn unusable_subscriptions_are_rejected_and_can_be_withdrawn() {
+    let app = spawn_push_app().await;
+    let service = MockPushService::start().await.unwrap();
+    let subscription = service.subscription("kit");
+    let mut bad_key = subscription.clone();
+    bad_key["keys"]["p256dh"] = json!(URL_SAFE_NO_PAD.encode([4u8; 65]));
+    let mut insecure = subscription.clone();
+    insecure["endpoint"] = json!("http://push.example.com/kit");
+
+    assert_eq!(subscribe(&app, &subscription, "7.30", "UTC").await.status(), 422);
+    assert_eq!(subscribe(&app, &subscription, "07:30", "Mars/Olympus").await.status(), 422);
+    assert_eq!(subscribe(&app, &bad_key, "07:30", "UTC").await.status(), 422);
+    assert_eq!(subscribe(&app, &insecure, "07:30", "UTC").await.status(), 422);
+    assert!(app.state().web_push.unwrap().subscriptions().await.is_empty());
+
+    assert_eq!(subscribe(&app, &subscription, "07:30", "UTC").await.status(), 201);
+    let withdraw = |endpoint: Value| app.client.delete(app.url("/api/v1/push/subscriptions")).json(&json!({ "endpoint": endpoint })).send();
+    assert_eq!(withdraw(subscription["endpoint"].clone()).await.unwrap().status(), 204);
+    assert_eq!(withdraw(subscription["endpoint"].clone()).await.unwrap().status(), 404);
+}
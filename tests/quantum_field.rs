@@ -0,0 +1,298 @@
+mod common;
+
+use chrono::Utc;
+use serde_json::json;
+use the_enlightened_cat::corpus::Corpus;
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::readings::{ReadingStore, Visibility};
+
+use common::{six_seeds, spawn_app, spawn_app_with, TestApp};
+
+#[tokio::test]
+async fn quantum_field_has_six_generated_nodes() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let body = app.get_json("/api/v1/quantum-field").await;
+
+    let nodes = body["wisdom_field"].as_array().unwrap();
+    assert_eq!(nodes.len(), 6);
+    assert_eq!(nodes[0]["domain"], "Essence");
+    assert_eq!(nodes[0]["seed"], "Seed of essence");
+    assert_eq!(nodes[5]["domain"], "Crystallization");
+    assert_eq!(body["provenance"], "generated");
+}
+
+#[tokio::test]
+async fn quantum_field_falls_back_to_corpus_on_prose_reply() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Here are your fragments: 1. A whisker..."));
+
+    let body = app.get_json("/api/v1/quantum-field").await;
+
+    let expected = Corpus::bundled().seeds_for(Utc::now().date_naive());
+    let seeds: Vec<_> = body["wisdom_field"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|node| node["seed"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(seeds, expected);
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn quantum_field_falls_back_to_corpus_on_too_few_seeds() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("[\"only one\"]"));
+
+    let body = app.get_json("/api/v1/quantum-field").await;
+
+    assert_eq!(body["wisdom_field"].as_array().unwrap().len(), 6);
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn quantum_field_falls_back_to_corpus_on_upstream_error() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::error(500, "internal error"));
+
+    let body = app.get_json("/api/v1/quantum-field").await;
+
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn collapse_builds_a_prompt_from_the_selected_seed() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let body = app.get_json("/api/v1/quantum-field/collapse?index=3").await;
+
+    assert_eq!(body["selected_index"], 3);
+    let prompt = body["collapsed_prompt"].as_str().unwrap();
+    assert!(prompt.contains("Seed of the portal"), "unexpected prompt: {}", prompt);
+    assert!(prompt.contains("doorway"));
+}
+
+#[tokio::test]
+async fn collapse_with_out_of_range_index_is_handled() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let body = app.get_json("/api/v1/quantum-field/collapse?index=42").await;
+
+    assert_eq!(body["collapsed_prompt"], "The quantum field collapsed in an unexpected way.");
+    assert!(body["reading_id"].is_null());
+}
+
+#[tokio::test]
+async fn collapse_without_index_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.get("/api/v1/quantum-field/collapse").await;
+
+    assert_eq!(response.status(), 400);
+}
+
+/// Collapses today's field on node 3 and returns the reading's id
+async fn collapse_reading(app: &TestApp) -> String {
+    app.mock.push(MockReply::content(six_seeds()));
+    let body = app.get_json("/api/v1/quantum-field/collapse?index=3").await;
+    let id = body["reading_id"].as_str().expect("collapse saved no reading").to_string();
+    assert_eq!(body["reading_url"], format!("/quantum-field/reading/{}", id));
+    id
+}
+
+#[tokio::test]
+async fn collapse_saves_a_shareable_reading() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+
+    let response = app.stranger().get(app.url(&format!("/quantum-field/reading/{}", id))).send().await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Portal"));
+    assert!(html.contains("Seed of the portal"));
+    assert!(html.contains("doorway"));
+    assert!(html.contains(&format!("https://the-enlightened-cat.com/quantum-field/reading/{}", id)));
+    assert!(html.contains("og:title\" content=\"A Portal reading from the Quantum Field"));
+    assert!(html.contains(&format!("https://the-enlightened-cat.com/og/reading/{}.png", id)));
+    assert!(html.contains("src=\"/static/images/quantum-cat.png\""));
+    assert!(!html.contains("noindex"));
+    assert!(!html.contains("reading-settings"), "strangers must not get the owner's form");
+}
+
+#[tokio::test]
+async fn collapsing_the_same_node_again_reuses_the_reading() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+
+    let again = app.get_json("/api/v1/quantum-field/collapse?index=3").await;
+    let other = app.get_json("/api/v1/quantum-field/collapse?index=1").await;
+    let stranger: serde_json::Value = app
+        .stranger()
+        .get(app.url("/api/v1/quantum-field/collapse?index=3"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(again["reading_id"], id.as_str());
+    assert_ne!(other["reading_id"], id.as_str());
+    assert_ne!(stranger["reading_id"], id.as_str());
+    assert_eq!(app.readings_on_disk().len(), 3);
+}
+
+/// Collapses node 0 as a new visitor and returns the reading's id
+async fn collapse_as_stranger(app: &TestApp) -> String {
+    let body: serde_json::Value = app
+        .stranger()
+        .get(app.url("/api/v1/quantum-field/collapse?index=0"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["reading_id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn oldest_unexplored_readings_are_dropped_past_the_limit() {
+    let app = spawn_app_with(|config| config.max_readings = 3).await;
+    let explored = collapse_reading(&app).await;
+    app.patch_json(&format!("/api/v1/quantum-field/readings/{}", explored), json!({ "exploration": "Doors open inward." }))
+        .await;
+    let oldest = collapse_as_stranger(&app).await;
+    assert_eq!(app.stranger().get(app.url(&format!("/og/reading/{}.png", oldest))).send().await.unwrap().status(), 200);
+    let middle = collapse_as_stranger(&app).await;
+
+    let newest = collapse_as_stranger(&app).await;
+
+    let mut kept: Vec<String> =
+        app.readings_on_disk().iter().map(|r| r["id"].as_str().unwrap().to_string()).collect();
+    kept.sort();
+    let mut expected = vec![explored, middle, newest];
+    expected.sort();
+    assert_eq!(kept, expected);
+    assert_eq!(app.get(&format!("/quantum-field/reading/{}", oldest)).await.status(), 404);
+    let cards: Vec<_> = std::fs::read_dir(app.data_dir.path().join("og"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert!(!cards.iter().any(|card| card.contains(&oldest)), "the dropped reading's card is still cached: {:?}", cards);
+}
+
+#[tokio::test]
+async fn owner_sees_the_settings_form() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+
+    let html = app.get(&format!("/quantum-field/reading/{}", id)).await.text().await.unwrap();
+
+    assert!(html.contains("reading-settings"));
+}
+
+#[tokio::test]
+async fn unknown_reading_is_not_found() {
+    let app = spawn_app().await;
+
+    let response = app.get("/quantum-field/reading/0123456789abcdef").await;
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn private_reading_is_hidden_from_everyone_but_the_owner() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+    let path = format!("/quantum-field/reading/{}", id);
+
+    let response = app.patch_json(&format!("/api/v1/quantum-field/readings/{}", id), json!({ "visibility": "private" })).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.json::<serde_json::Value>().await.unwrap()["visibility"], "private");
+
+    let stranger = app.stranger().get(app.url(&path)).send().await.unwrap();
+    assert_eq!(stranger.status(), 404);
+
+    let owner = app.get(&path).await;
+    assert_eq!(owner.status(), 200);
+    assert!(owner.text().await.unwrap().contains("noindex"));
+}
+
+#[tokio::test]
+async fn unlisted_reading_is_visible_but_not_indexed() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+
+    app.patch_json(&format!("/api/v1/quantum-field/readings/{}", id), json!({ "visibility": "unlisted" })).await;
+
+    let response = app.stranger().get(app.url(&format!("/quantum-field/reading/{}", id))).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("<meta name=\"robots\" content=\"noindex\">"));
+}
+
+#[tokio::test]
+async fn only_the_owner_can_change_a_reading() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+
+    let response = app
+        .stranger()
+        .patch(app.url(&format!("/api/v1/quantum-field/readings/{}", id)))
+        .json(&json!({ "visibility": "private" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+    let html = app.get(&format!("/quantum-field/reading/{}", id)).await.text().await.unwrap();
+    assert!(!html.contains("noindex"));
+}
+
+#[tokio::test]
+async fn exploration_summary_is_shown_on_the_reading() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+
+    let response = app
+        .patch_json(
+            &format!("/api/v1/quantum-field/readings/{}", id),
+            json!({ "exploration": "  Step through the door before the meeting starts.  " }),
+        )
+        .await;
+    assert_eq!(response.status(), 200);
+
+    let html = app.stranger().get(app.url(&format!("/quantum-field/reading/{}", id))).send().await.unwrap().text().await.unwrap();
+    assert!(html.contains("<p class=\"reading-exploration\">Step through the door before the meeting starts.</p>"));
+}
+
+#[tokio::test]
+async fn oversized_or_flagged_exploration_is_rejected() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+    let path = format!("/api/v1/quantum-field/readings/{}", id);
+
+    let too_long = app.patch_json(&path, json!({ "exploration": "purr ".repeat(500) })).await;
+    assert_eq!(too_long.status(), 422);
+
+    let flagged = app.patch_json(&path, json!({ "exploration": "I want to kill myself" })).await;
+    assert_eq!(flagged.status(), 422);
+}
+
+#[tokio::test]
+async fn readings_survive_a_restart() {
+    let app = spawn_app().await;
+    let id = collapse_reading(&app).await;
+    app.patch_json(&format!("/api/v1/quantum-field/readings/{}", id), json!({ "visibility": "unlisted" })).await;
+
+    let reopened = ReadingStore::open(app.data_dir.path().join("readings.json")).unwrap();
+
+    let reading = reopened.get(&id).await.expect("reading was not persisted");
+    assert_eq!(reading.domain, "Portal");
+    assert_eq!(reading.visibility, Visibility::Unlisted);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use chrono::Utc;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{spawn_app, spawn_app_with};
+
+/// The JSON-LD documents embedded in a page
+fn structured_data(html: &str) -> Vec<serde_json::Value> {
+    let marker = "<script type=\"application/ld+json\">";
+    html.match_indices(marker)
+        .map(|(start, _)| {
+            let rest = &html[start + marker.len()..];
+            let end = rest.find("</script>").expect("unterminated JSON-LD");
+            serde_json::from_str(&rest[..end]).expect("JSON-LD is not valid JSON")
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn sitemap_lists_fixed_pages_and_wisdom_days() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Sit in the sunbeam."));
+    let today = Utc::now().date_naive();
+    app.get("/wisdom").await;
+
+    let response = app.get("/sitemap.xml").await;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/xml; charset=utf-8");
+    let xml = response.text().await.unwrap();
+    for page in ["/", "/wisdom", "/quantum-field", "/about"] {
+        assert!(xml.contains(&format!("<loc>https://the-enlightened-cat.com{}</loc>", page)), "missing {}", page);
+    }
+    assert!(xml.contains(&format!("<loc>https://the-enlightened-cat.com/wisdom/{}</loc>\n    <lastmod>{}T", today, today)));
+    assert!(!xml.contains("/admin"));
+}
+
+#[tokio::test]
+async fn robots_txt_points_at_the_sitemap() {
+    let app = spawn_app_with(|config| config.public_url = "https://cat.example".to_string()).await;
+
+    let response = app.get("/robots.txt").await;
+
+    assert_eq!(response.headers()["content-type"], "text/plain; charset=utf-8");
+    let robots = response.text().await.unwrap();
+    assert!(robots.contains("Disallow: /admin"));
+    assert!(robots.contains("Sitemap: https://cat.example/sitemap.xml"));
+}
+
+#[tokio::test]
+async fn robots_txt_can_be_replaced() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "User-agent: *\nDisallow: /\n").unwrap();
+    let path = file.path().to_string_lossy().into_owned();
+    let app = spawn_app_with(|config| config.robots_txt_path = Some(path)).await;
+
+    let robots = app.get("/robots.txt").await.text().await.unwrap();
+
+    assert_eq!(robots, "User-agent: *\nDisallow: /\n");
+}
+
+#[tokio::test]
+async fn home_page_describes_the_site_with_a_search_action() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Sit in the sunbeam."));
+
+    let html = app.get("/").await.text().await.unwrap();
+
+    let documents = structured_data(&html);
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0]["@type"], "WebSite");
+    assert_eq!(
+        documents[0]["potentialAction"]["target"]["urlTemplate"],
+        "https://the-enlightened-cat.com/search?q={search_term_string}"
+    );
+}
+
+#[tokio::test]
+async fn wisdom_page_describes_the_whispurr() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Sit in the sunbeam. </script><b>"));
+    let today = Utc::now().date_naive();
+
+    let html = app.get(&format!("/wisdom/{}", today)).await.text().await.unwrap();
+
+    let documents = structured_data(&html);
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0]["@type"], "CreativeWork");
+    assert_eq!(documents[0]["text"], "Sit in the sunbeam. </script><b>");
+    assert_eq!(documents[0]["url"], format!("https://the-enlightened-cat.com/wisdom/{}", today));
+    assert_eq!(documents[0]["datePublished"], today.to_string());
+}
+
+#[tokio::test]
+async fn pages_use_the_configured_public_url() {
+    let app = spawn_app_with(|config| config.public_url = "https://cat.example".to_string()).await;
+    app.mock.push(MockReply::content("Sit in the sunbeam."));
+    let today = Utc::now().date_naive();
+
+    let html = app.get("/wisdom").await.text().await.unwrap();
+
+    assert!(html.contains(&format!("<link rel=\"canonical\" href=\"https://cat.example/wisdom/{}\">", today)));
+    assert!(html.contains(&format!("<meta property=\"og:url\" content=\"https://cat.example/wisdom/{}\" />", today)));
+    assert!(!html.contains("the-enlightened-cat.com/wisdom"));
+}
+
+#[tokio::test]
+async fn search_finds_past_whispurrs() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Sit in the sunbeam."));
+    let today = Utc::now().date_naive();
+    app.get("/wisdom").await;
+
+    let found = app.get("/search?q=SUNBEAM").await.text().await.unwrap();
+    let missing = app.get("/search?q=thunderstorm").await.text().await.unwrap();
+
+    assert!(found.contains(&format!("<a href=\"/wisdom/{}\">", today)));
+    assert!(found.contains("Sit in the sunbeam."));
+    assert!(missing.contains("has not whispurred about that yet"));
+}
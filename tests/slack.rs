@@ -0,0 +1,174 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_webhook::{ReceivedWebhook, WebhookReceiver};
+use the_enlightened_cat::routes::slack;
+
+use common::{six_seeds, spawn_app_with, TestApp};
+
+const SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+
+async fn spawn_slack_app() -> TestApp {
+    spawn_app_with(|config| config.slack_signing_secret = Some(SECRET.to_string())).await
+}
+
+/// POSTs a form body to the Slack endpoint, signed with `secret` at `timestamp`
+async fn post_signed(app: &TestApp, body: &str, secret: &str, timestamp: i64) -> reqwest::Response {
+    let timestamp = timestamp.to_string();
+    app.client
+        .post(app.url("/integrations/slack/command"))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .header("x-slack-request-timestamp", &timestamp)
+        .header("x-slack-signature", slack::sign(secret, &timestamp, body.as_bytes()).unwrap())
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Runs a slash command as Slack would, for user `user`
+async fn slash(app: &TestApp, command: &str, text: &str, user: &str, response_url: &str) -> Value {
+    let body = serde_urlencoded::to_string([
+        ("command", command),
+        ("text", text),
+        ("team_id", "T1"),
+        ("user_id", user),
+        ("response_url", response_url),
+    ])
+    .unwrap();
+    let response = post_signed(app, &body, SECRET, Utc::now().timestamp()).await;
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+/// Waits for the receiver to have `count` requests
+async fn wait_for(receiver: &WebhookReceiver, count: usize) -> Vec<ReceivedWebhook> {
+    for _ in 0..100 {
+        let received = receiver.received();
+        if received.len() >= count {
+            return received;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("expected {} deferred replies, got {}", count, receiver.received().len());
+}
+
+#[tokio::test]
+async fn the_endpoint_is_off_without_a_signing_secret() {
+    let app = common::spawn_app().await;
+
+    let response = post_signed(&app, "command=%2Fwhispurr", SECRET, Utc::now().timestamp()).await;
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn unsigned_forged_and_stale_requests_are_rejected() {
+    let app = spawn_slack_app().await;
+    let now = Utc::now().timestamp();
+
+    let unsigned = app.client.post(app.url("/integrations/slack/command")).body("command=%2Fwhispurr").send().await.unwrap();
+    let forged = post_signed(&app, "command=%2Fwhispurr", "not-the-secret", now).await;
+    let stale = post_signed(&app, "command=%2Fwhispurr", SECRET, now - 10 * 60).await;
+    let ancient = post_signed(&app, "command=%2Fwhispurr", SECRET, i64::MIN).await;
+
+    assert_eq!(unsigned.status(), 401);
+    assert_eq!(forged.status(), 401);
+    assert_eq!(stale.status(), 401);
+    assert_eq!(ancient.status(), 401);
+    assert!(app.mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn whispurr_posts_todays_wisdom_in_the_channel() {
+    let app = spawn_slack_app().await;
+    app.mock.push(MockReply::content("The inbox will still be there after the nap."));
+
+    let reply = slash(&app, "/whispurr", "", "U1", "http://unused").await;
+
+    assert_eq!(reply["response_type"], "in_channel");
+    assert!(reply["text"].as_str().unwrap().starts_with("The inbox will still be there after the nap."));
+    assert_eq!(reply["blocks"][0]["type"], "section");
+}
+
+#[tokio::test]
+async fn catfield_buttons_collapse_the_field_into_a_reading() {
+    let app = spawn_slack_app().await;
+    let receiver = WebhookReceiver::start().await.unwrap();
+    app.mock.push(MockReply::content(six_seeds()));
+
+    let field = slash(&app, "/catfield", "", "U1", "http://unused").await;
+
+    assert_eq!(field["response_type"], "ephemeral");
+    assert!(field["blocks"][1]["text"]["text"].as_str().unwrap().contains("*5. Friction*  Seed of friction"));
+    let buttons = field["blocks"][2]["elements"].as_array().unwrap();
+    assert_eq!(buttons.len(), 6);
+    assert_eq!(buttons[1]["text"]["text"], "2. Inner Path");
+    let value = buttons[1]["value"].as_str().unwrap().to_string();
+
+    let payload = json!({
+        "type": "block_actions",
+        "user": { "id": "U1" },
+        "team": { "id": "T1" },
+        "response_url": receiver.url("/response"),
+        "actions": [{ "action_id": "collapse_1", "value": value }],
+    });
+    let body = serde_urlencoded::to_string([("payload", payload.to_string())]).unwrap();
+    let click = post_signed(&app, &body, SECRET, Utc::now().timestamp()).await;
+    assert_eq!(click.status(), 200);
+
+    let posted = wait_for(&receiver, 1).await[0].json();
+    let text = posted["text"].as_str().unwrap();
+    assert!(text.contains("Seed of the inner path"), "{}", text);
+    let readings = app.readings_on_disk();
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["owner"], "slack:T1:U1");
+    assert_eq!(readings[0]["index"], 1);
+    let link = format!("https://the-enlightened-cat.com/quantum-field/reading/{}", readings[0]["id"].as_str().unwrap());
+    assert!(text.contains(&link), "{}", text);
+}
+
+#[tokio::test]
+async fn askcat_replies_later_and_remembers_each_user() {
+    let app = spawn_slack_app().await;
+    let receiver = WebhookReceiver::start().await.unwrap();
+    app.mock.push(MockReply::content("Rest is part of the work."));
+    app.mock.set_default(MockReply::content("Purr."));
+
+    let ack = slash(&app, "/askcat", "Should I skip lunch?", "U1", &receiver.url("/u1")).await;
+    assert_eq!(ack["response_type"], "ephemeral");
+    assert!(ack["text"].as_str().unwrap().starts_with("> Should I skip lunch?"));
+    let first = wait_for(&receiver, 1).await;
+    assert_eq!(first[0].path, "/u1");
+    assert_eq!(first[0].json()["text"], "> Should I skip lunch?\nRest is part of the work.");
+
+    slash(&app, "/askcat", "Really?", "U1", &receiver.url("/u1")).await;
+    wait_for(&receiver, 2).await;
+    slash(&app, "/askcat", "Hi", "U2", &receiver.url("/u2")).await;
+    wait_for(&receiver, 3).await;
+
+    // What the model was shown when asked `question`
+    let context = |question: &str| {
+        let requests = app.mock.requests();
+        let request = requests.iter().find(|r| r.messages.last().is_some_and(|m| m.content == question)).unwrap();
+        request.messages.iter().map(|m| m.content.clone()).collect::<Vec<_>>().join("\n")
+    };
+    assert!(context("Really?").contains("Should I skip lunch?"), "U1's second question carries the first");
+    assert!(!context("Hi").contains("Should I skip lunch?"), "U2 has a conversation of their own");
+}
+
+#[tokio::test]
+async fn unknown_commands_and_empty_questions_get_usage() {
+    let app = spawn_slack_app().await;
+
+    let empty = slash(&app, "/askcat", "  ", "U1", "http://unused").await;
+    let unknown = slash(&app, "/catnip", "", "U1", "http://unused").await;
+
+    assert!(empty["text"].as_str().unwrap().contains("/askcat <question>"));
+    assert_eq!(empty, unknown);
+    assert!(app.mock.requests().is_empty());
+}
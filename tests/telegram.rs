@@ -0,0 +1,256 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveTime, Utc};
+use serde_json::{json, Value};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_webhook::{ReceivedWebhook, WebhookReceiver};
+use the_enlightened_cat::backup::Backup;
+use the_enlightened_cat::telegram;
+
+use common::{six_seeds, spawn_app_with, TestApp};
+
+const TOKEN: &str = "123456:test-token";
+const SECRET: &str = "telegram-webhook-secret";
+
+/// An app whose bot calls the Bot API on `receiver`
+async fn spawn_telegram_app(receiver: &WebhookReceiver) -> TestApp {
+    let api_url = receiver.url("/api");
+    spawn_app_with(|config| {
+        config.telegram_bot_token = Some(TOKEN.to_string());
+        config.telegram_webhook_secret = Some(SECRET.to_string());
+        config.telegram_api_url = api_url;
+    })
+    .await
+}
+
+/// POSTs an update as Telegram would, with `secret`
+async fn post_update(app: &TestApp, update: &Value, secret: Option<&str>) -> reqwest::Response {
+    let mut request = app.client.post(app.url("/integrations/telegram/webhook")).json(update);
+    if let Some(secret) = secret {
+        request = request.header("x-telegram-bot-api-secret-token", secret);
+    }
+    request.send().await.unwrap()
+}
+
+/// Sends `text` from chat `chat_id`
+async fn say(app: &TestApp, chat_id: i64, text: &str) {
+    let update = json!({
+        "update_id": 1,
+        "message": {
+            "message_id": 1,
+            "chat": { "id": chat_id, "type": "private" },
+            "from": { "id": chat_id, "language_code": "en" },
+            "text": text,
+        },
+    });
+    assert_eq!(post_update(app, &update, Some(SECRET)).await.status(), 200);
+}
+
+/// Waits for the receiver to have `count` API calls
+async fn wait_for(receiver: &WebhookReceiver, count: usize) -> Vec<ReceivedWebhook> {
+    for _ in 0..100 {
+        let received = receiver.received();
+        if received.len() >= count {
+            return received;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("expected {} Bot API calls, got {}", count, receiver.received().len());
+}
+
+/// The Bot API path of `method`
+fn api(method: &str) -> String {
+    format!("/api/bot{}/{}", TOKEN, method)
+}
+
+fn at(time: &str) -> DateTime<Utc> {
+    time.parse().unwrap()
+}
+
+#[tokio::test]
+async fn the_webhook_is_off_without_a_token_and_secret() {
+    let app = spawn_app_with(|config| config.telegram_webhook_secret = Some(SECRET.to_string())).await;
+
+    let response = post_update(&app, &json!({ "update_id": 1 }), Some(SECRET)).await;
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn updates_without_the_secret_are_rejected() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_telegram_app(&receiver).await;
+    let update = json!({ "update_id": 1, "message": { "chat": { "id": 1 }, "text": "/wisdom" } });
+
+    let missing = post_update(&app, &update, None).await;
+    let wrong = post_update(&app, &update, Some("not-the-secret")).await;
+
+    assert_eq!(missing.status(), 401);
+    assert_eq!(wrong.status(), 401);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(receiver.received().is_empty());
+}
+
+#[tokio::test]
+async fn wisdom_sends_todays_whispurr() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_telegram_app(&receiver).await;
+    app.mock.push(MockReply::content("The inbox will still be there after the nap."));
+
+    say(&app, 42, "/wisdom@enlightened_cat_bot").await;
+
+    let sent = &wait_for(&receiver, 1).await[0];
+    assert_eq!(sent.path, api("sendMessage"));
+    assert_eq!(sent.json()["chat_id"], 42);
+    let text = sent.json()["text"].as_str().unwrap().to_string();
+    assert!(text.starts_with("The inbox will still be there after the nap.\n\n— The Enlightened Cat, "), "{}", text);
+}
+
+#[tokio::test]
+async fn field_buttons_collapse_the_field_into_a_reading() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_telegram_app(&receiver).await;
+    app.mock.push(MockReply::content(six_seeds()));
+
+    say(&app, 42, "/field").await;
+
+    let field = wait_for(&receiver, 1).await[0].json();
+    assert!(field["text"].as_str().unwrap().contains("5. Friction: Seed of friction"));
+    let keyboard = field["reply_markup"]["inline_keyboard"].as_array().unwrap();
+    assert_eq!(keyboard.len(), 3);
+    let button = &keyboard[0][1];
+    assert_eq!(button["text"], "2. Inner Path");
+
+    let press = json!({
+        "update_id": 2,
+        "callback_query": {
+            "id": "query-1",
+            "from": { "id": 7 },
+            "message": { "message_id": 5, "chat": { "id": 42 } },
+            "data": button["callback_data"],
+        },
+    });
+    assert_eq!(post_update(&app, &press, Some(SECRET)).await.status(), 200);
+
+    let calls = wait_for(&receiver, 3).await;
+    assert_eq!(calls[1].path, api("answerCallbackQuery"));
+    assert_eq!(calls[1].json()["callback_query_id"], "query-1");
+    let readings = app.readings_on_disk();
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0]["owner"], "telegram:42");
+    assert_eq!(readings[0]["index"], 1);
+    let text = calls[2].json()["text"].as_str().unwrap().to_string();
+    assert!(text.starts_with("2. Inner Path · "), "{}", text);
+    let link = format!("https://the-enlightened-cat.com/quantum-field/reading/{}", readings[0]["id"].as_str().unwrap());
+    assert!(text.ends_with(&link), "{}", text);
+}
+
+#[tokio::test]
+async fn messages_go_to_the_cat_with_a_session_per_chat() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_telegram_app(&receiver).await;
+    app.mock.push(MockReply::content("Rest is part of the work."));
+    app.mock.set_default(MockReply::content("Purr."));
+
+    say(&app, 42, "Should I skip lunch?").await;
+    let calls = wait_for(&receiver, 2).await;
+    assert_eq!(calls[0].path, api("sendChatAction"));
+    assert_eq!(calls[1].json(), json!({ "chat_id": 42, "text": "Rest is part of the work." }));
+
+    say(&app, 42, "Really?").await;
+    wait_for(&receiver, 4).await;
+    say(&app, 43, "Hi").await;
+    wait_for(&receiver, 6).await;
+
+    // What the model was shown when asked `question`
+    let context = |question: &str| {
+        let requests = app.mock.requests();
+        let request = requests.iter().find(|r| r.messages.last().is_some_and(|m| m.content == question)).unwrap();
+        request.messages.iter().map(|m| m.content.clone()).collect::<Vec<_>>().join("\n")
+    };
+    assert!(context("Really?").contains("Should I skip lunch?"), "chat 42's second message carries the first");
+    assert!(!context("Hi").contains("Should I skip lunch?"), "chat 43 has a conversation of its own");
+}
+
+#[tokio::test]
+async fn daily_push_is_sent_once_a_day_at_the_chosen_time() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_telegram_app(&receiver).await;
+    app.mock.set_default(MockReply::content("Stretch first."));
+
+    say(&app, 42, "/daily 07:30 Europe/Berlin").await;
+    let confirmation = wait_for(&receiver, 1).await[0].json();
+    assert!(confirmation["text"].as_str().unwrap().contains("after 07:30 (Europe/Berlin)"));
+    let state = app.state();
+
+    // 07:00 and 07:31 in Berlin
+    assert!(telegram::push_due(&state, at("2030-01-01T06:00:00Z")).await.is_empty());
+    assert_eq!(telegram::push_due(&state, at("2030-01-01T06:31:00Z")).await, [42]);
+    assert!(telegram::push_due(&state, at("2030-01-01T12:00:00Z")).await.is_empty());
+    assert_eq!(telegram::push_due(&state, at("2030-01-02T06:45:00Z")).await, [42]);
+
+    let pushed = &receiver.received()[1];
+    assert_eq!(pushed.json()["chat_id"], 42);
+    assert!(pushed.json()["text"].as_str().unwrap().contains("January 1, 2030"));
+
+    say(&app, 42, "/daily off").await;
+    wait_for(&receiver, 4).await;
+    assert!(telegram::push_due(&state, at("2030-01-03T06:45:00Z")).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribing_after_the_time_starts_tomorrow() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_telegram_app(&receiver).await;
+    let state = app.state();
+    let eight = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+
+    let late = state.telegram.subscribe(1, eight, chrono_tz::UTC, at("2030-01-01T09:00:00Z")).await;
+    let early = state.telegram.subscribe(2, eight, chrono_tz::UTC, at("2030-01-01T07:00:00Z")).await;
+
+    assert_eq!(late.due_date(at("2030-01-01T09:30:00Z")), None);
+    assert_eq!(late.due_date(at("2030-01-02T08:00:00Z")), Some("2030-01-02".parse().unwrap()));
+    assert_eq!(early.due_date(at("2030-01-01T08:00:00Z")), Some("2030-01-01".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn subscriptions_survive_a_backup() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let source = spawn_telegram_app(&receiver).await;
+    let target = spawn_telegram_app(&receiver).await;
+    let eight = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+    let nine = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+    source.state().telegram.subscribe(1, eight, chrono_tz::Europe::Berlin, at("2030-01-01T09:00:00Z")).await;
+    target.state().telegram.subscribe(1, nine, chrono_tz::UTC, at("2030-01-01T07:00:00Z")).await;
+    target.state().telegram.subscribe(2, nine, chrono_tz::UTC, at("2030-01-01T07:00:00Z")).await;
+
+    let json = serde_json::to_string(&Backup::collect(&source.state()).await.unwrap()).unwrap();
+    let backup: Backup = serde_json::from_str(&json).unwrap();
+    let summary = backup.clone().restore(&target.state(), false).await.unwrap();
+
+    assert_eq!(summary.telegram, 1);
+    let merged = target.state().telegram.subscriptions().await;
+    assert_eq!(merged.len(), 2);
+    assert_eq!(target.state().telegram.subscription(1).await, source.state().telegram.subscription(1).await);
+
+    backup.restore(&target.state(), true).await.unwrap();
+    assert_eq!(target.state().telegram.subscriptions().await, source.state().telegram.subscriptions().await);
+}
+
+#[tokio::test]
+async fn bad_daily_times_get_usage() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_telegram_app(&receiver).await;
+
+    say(&app, 42, "/daily 25:00").await;
+    wait_for(&receiver, 1).await;
+    say(&app, 42, "/daily 08:00 Mars/Olympus").await;
+
+    let calls = wait_for(&receiver, 2).await;
+    for call in calls {
+        assert!(call.json()["text"].as_str().unwrap().starts_with("Tell me when"), "{}", call.body);
+    }
+    assert!(app.state().telegram.subscriptions().await.is_empty());
+}
@@ -0,0 +1,83 @@
+use the_enlightened_cat::corpus::Provenance;
+use the_enlightened_cat::quantum_field::QuantumField;
+use the_enlightened_cat::terminal::{cat_says, field_map, whispurr, wrap, Style};
+
+#[test]
+fn wrap_breaks_at_spaces_and_keeps_long_words() {
+    assert_eq!(wrap("one two three", 7), ["one two", "three"]);
+    assert_eq!(wrap("supercalifragilistic cat", 5), ["supercalifragilistic", "cat"]);
+    assert_eq!(wrap("first\nsecond", 80), ["first", "second"]);
+}
+
+#[test]
+fn cat_says_one_line_in_angle_brackets() {
+    let art = cat_says("Purr.", 40, Style::Plain);
+
+    let lines: Vec<_> = art.lines().collect();
+    assert_eq!(lines[0], " _______");
+    assert_eq!(lines[1], "< Purr. >");
+    assert_eq!(lines[2], " -------");
+    assert!(art.contains("( -.- )"));
+}
+
+#[test]
+fn cat_says_longer_text_in_a_wrapped_bubble() {
+    let art = cat_says("Stillness is not slowness; it is readiness.", 24, Style::Plain);
+
+    let lines: Vec<_> = art.lines().collect();
+    assert_eq!(lines[1], "/ Stillness is not \\");
+    assert_eq!(lines[2], "| slowness; it is  |");
+    assert_eq!(lines[3], "\\ readiness.       /");
+    // Every bubble line is the same width, counting characters rather than bytes
+    let curly = cat_says("It’s “fine” — really, it’s fine, said the cat.", 20, Style::Plain);
+    let widths: Vec<_> = curly.lines().skip(1).take_while(|l| !l.starts_with(" -")).map(|l| l.chars().count()).collect();
+    assert!(widths.windows(2).all(|w| w[0] == w[1]), "{:?}", widths);
+}
+
+#[test]
+fn ansi_style_paints_without_changing_the_layout() {
+    let plain = cat_says("Purr.", 40, Style::Plain);
+    let ansi = cat_says("Purr.", 40, Style::Ansi);
+
+    assert!(!plain.contains('\x1b'));
+    assert!(ansi.contains("\x1b[1mPurr.\x1b[0m"));
+    assert_eq!(strip_ansi(&ansi), plain);
+}
+
+#[test]
+fn whispurr_is_signed_with_its_date() {
+    let text = whispurr("Purr.", "19 October 2026", 40, Style::Plain);
+
+    assert!(text.contains("< Purr. >"));
+    assert!(text.ends_with("    — The Enlightened Cat, 19 October 2026\n"));
+}
+
+#[test]
+fn field_map_numbers_all_six_nodes() {
+    let field = QuantumField::new((1..=6).map(|i| format!("seed number {}", i)).collect(), Provenance::Corpus);
+
+    let map = field_map(&field.wisdom_field, 60, Style::Plain);
+    println!("{}", map);
+
+    let lines: Vec<_> = map.lines().collect();
+    assert!(lines[0].trim() == "(1) Essence");
+    assert!(lines[2].contains("(6) Crystallization") && lines[2].contains("(2) Inner Path"));
+    assert!(lines[4].contains("(5) Friction") && lines[4].contains("(3) Outer Path"));
+    assert!(lines[6].trim() == "(4) Portal");
+    assert!(map.contains("  3. Outer Path\n     seed number 3\n"));
+    assert_eq!(strip_ansi(&field_map(&field.wisdom_field, 60, Style::Ansi)), map);
+}
+
+/// `text` without ANSI escape sequences
+fn strip_ansi(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
@@ -0,0 +1,312 @@
+mod common;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{json, Value};
+use the_enlightened_cat::mock_mistral::MockReply;
+use the_enlightened_cat::mock_webhook::WebhookReceiver;
+use the_enlightened_cat::webhooks::{self, Outcome, MAX_ATTEMPTS};
+
+use common::{spawn_app_with, TestApp};
+
+/// Starts the app with `targets` written to a webhook targets file in its data directory
+async fn spawn_with_targets(targets: Value) -> TestApp {
+    spawn_app_with(|config| {
+        let path = std::path::Path::new(&config.data_dir).join("webhooks.json");
+        std::fs::write(&path, targets.to_string()).unwrap();
+        config.webhooks_path = Some(path.to_string_lossy().into_owned());
+        config.webhook_backoff_ms = 5;
+    })
+    .await
+}
+
+fn at(timestamp: &str) -> DateTime<Utc> {
+    timestamp.parse().unwrap()
+}
+
+fn date(text: &str) -> NaiveDate {
+    text.parse().unwrap()
+}
+
+#[tokio::test]
+async fn due_wisdom_is_posted_once_and_signed() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([{
+        "name": "team",
+        "url": receiver.url("/hooks/team"),
+        "schedule": "09:00",
+        "secret": "s3cret",
+    }]))
+    .await;
+    app.mock.push(MockReply::content("Stretch before the standup."));
+    let state = app.state();
+
+    let deliveries = webhooks::deliver_due(&state, at("2026-03-02T09:30:00Z")).await;
+    let again = webhooks::deliver_due(&state, at("2026-03-02T17:00:00Z")).await;
+
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].outcome, Outcome::Delivered);
+    assert!(again.is_empty(), "one post per day");
+
+    let received = receiver.received();
+    assert_eq!(received.len(), 1);
+    let hook = &received[0];
+    assert_eq!(hook.path, "/hooks/team");
+    assert_eq!(hook.header("X-Cat-Event"), Some("daily_wisdom"));
+    assert_eq!(hook.header("X-Cat-Delivery"), Some(deliveries[0].id.as_str()));
+    assert_eq!(
+        hook.json(),
+        json!({
+            "event": "daily_wisdom",
+            "date": "2026-03-02",
+            "wisdom": "Stretch before the standup.",
+            "provenance": "generated",
+            "url": "https://the-enlightened-cat.com/wisdom/2026-03-02",
+        })
+    );
+
+    let timestamp: i64 = hook.header("X-Cat-Timestamp").unwrap().parse().unwrap();
+    let expected = webhooks::sign("s3cret", timestamp, hook.body.as_bytes()).unwrap();
+    assert_eq!(hook.header("X-Cat-Signature"), Some(expected.as_str()));
+    assert!(expected.starts_with("sha256=") && expected.len() == 7 + 64);
+}
+
+#[tokio::test]
+async fn nothing_is_posted_before_the_schedule_or_on_off_days() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([{
+        "name": "weekdays",
+        "url": receiver.url("/hook"),
+        "schedule": "09:00",
+        "days": ["mon", "tue", "wed", "thu", "fri"],
+    }]))
+    .await;
+    let state = app.state();
+
+    // Monday before nine, then Saturday after nine
+    assert!(webhooks::deliver_due(&state, at("2026-03-02T08:59:00Z")).await.is_empty());
+    assert!(webhooks::deliver_due(&state, at("2026-03-07T10:00:00Z")).await.is_empty());
+    assert!(receiver.received().is_empty());
+    assert!(app.mock.requests().is_empty(), "no wisdom generated for nobody");
+}
+
+#[tokio::test]
+async fn each_target_rolls_over_in_its_own_zone() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([
+        { "name": "auckland", "url": receiver.url("/nz"), "schedule": "08:00", "timezone": "Pacific/Auckland" },
+        { "name": "utc", "url": receiver.url("/utc"), "schedule": "08:00" },
+    ]))
+    .await;
+    app.mock.set_default(MockReply::content("Purr."));
+    let state = app.state();
+
+    // 21:00 UTC on Sunday is 10:00 on Monday in Auckland (NZDT)
+    let deliveries = webhooks::deliver_due(&state, at("2026-03-01T21:00:00Z")).await;
+
+    assert_eq!(deliveries.len(), 2);
+    let auckland = deliveries.iter().find(|d| d.target == "auckland").unwrap();
+    let utc = deliveries.iter().find(|d| d.target == "utc").unwrap();
+    assert_eq!(auckland.date, date("2026-03-02"));
+    assert_eq!(utc.date, date("2026-03-01"));
+}
+
+#[tokio::test]
+async fn slack_and_discord_get_their_own_payloads() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([
+        { "name": "slack", "url": receiver.url("/slack"), "format": "slack", "schedule": "00:00" },
+        { "name": "discord", "url": receiver.url("/discord"), "format": "discord", "schedule": "00:00" },
+    ]))
+    .await;
+    app.mock.push(MockReply::content("A closed laptop is a warm seat."));
+    let state = app.state();
+
+    webhooks::deliver_due(&state, at("2026-05-04T06:00:00Z")).await;
+
+    let received = receiver.received();
+    let slack = received.iter().find(|hook| hook.path == "/slack").unwrap().json();
+    assert_eq!(slack["text"], "A closed laptop is a warm seat. — The Enlightened Cat, May 4, 2026");
+    assert_eq!(slack["blocks"][0]["text"]["text"], "> A closed laptop is a warm seat.\n— The Enlightened Cat, May 4, 2026");
+    assert!(slack["blocks"][1]["elements"][0]["text"].as_str().unwrap().contains("/wisdom/2026-05-04|"));
+
+    let discord = received.iter().find(|hook| hook.path == "/discord").unwrap().json();
+    assert_eq!(discord["username"], "The Enlightened Cat");
+    assert_eq!(discord["embeds"][0]["title"], "Whispurr for May 4, 2026");
+    assert_eq!(discord["embeds"][0]["description"], "A closed laptop is a warm seat.");
+    assert!(received.iter().all(|hook| hook.header("X-Cat-Signature").is_none()), "no secret, no signature");
+}
+
+#[tokio::test]
+async fn server_errors_are_retried_with_the_same_delivery_id() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([{ "name": "flaky", "url": receiver.url("/hook"), "schedule": "00:00" }])).await;
+    receiver.push_status(500);
+    receiver.push_status(503);
+    let state = app.state();
+
+    let deliveries = webhooks::deliver_due(&state, at("2026-03-02T12:00:00Z")).await;
+
+    assert_eq!(deliveries[0].outcome, Outcome::Delivered);
+    assert_eq!(deliveries[0].attempts, 3);
+    let received = receiver.received();
+    assert_eq!(received.len(), 3);
+    assert!(received.iter().all(|hook| hook.header("X-Cat-Delivery") == Some(deliveries[0].id.as_str())));
+}
+
+#[tokio::test]
+async fn deliveries_give_up_and_are_logged() {
+    let (down, gone) = (WebhookReceiver::start().await.unwrap(), WebhookReceiver::start().await.unwrap());
+    let app = spawn_with_targets(json!([
+        { "name": "down", "url": down.url("/down"), "schedule": "00:00" },
+        { "name": "gone", "url": gone.url("/gone"), "schedule": "00:00" },
+    ]))
+    .await;
+    for _ in 0..MAX_ATTEMPTS {
+        down.push_status(502);
+    }
+    gone.push_status(404);
+    let state = app.state();
+
+    let deliveries = webhooks::deliver_due(&state, at("2026-03-02T12:00:00Z")).await;
+
+    assert!(deliveries.iter().all(|d| d.outcome == Outcome::Failed));
+    assert_eq!(deliveries[0].attempts, MAX_ATTEMPTS);
+    assert_eq!(deliveries[1].attempts, 1, "client errors aren't retried");
+    assert_eq!(deliveries[1].status, Some(404));
+
+    // A failed day isn't retried by the scheduler
+    assert!(webhooks::deliver_due(&state, at("2026-03-02T13:00:00Z")).await.is_empty());
+
+    let log = app.run_cli(&["webhooks", "log"]).await.unwrap();
+    assert_eq!(log.lines().count(), 2);
+    assert!(log.contains("  gone  2026-03-02  FAILED  1 attempts  Receiver answered 404 Not Found"), "{}", log);
+    assert!(log.contains("  down  2026-03-02  FAILED  5 attempts"), "{}", log);
+}
+
+#[tokio::test]
+async fn a_target_being_retried_does_not_hold_up_the_others() {
+    let (down, up) = (WebhookReceiver::start().await.unwrap(), WebhookReceiver::start().await.unwrap());
+    let targets = json!([
+        { "name": "down", "url": down.url("/down"), "schedule": "00:00" },
+        { "name": "up", "url": up.url("/up"), "schedule": "00:00" },
+    ]);
+    let app = spawn_app_with(|config| {
+        let path = std::path::Path::new(&config.data_dir).join("webhooks.json");
+        std::fs::write(&path, targets.to_string()).unwrap();
+        config.webhooks_path = Some(path.to_string_lossy().into_owned());
+        // Retrying "down" takes 1.5 seconds in all
+        config.webhook_backoff_ms = 100;
+    })
+    .await;
+    for _ in 0..MAX_ATTEMPTS {
+        down.push_status(502);
+    }
+    let state = app.state();
+
+    let delivering = tokio::spawn(async move { webhooks::deliver_due(&state, at("2026-03-02T12:00:00Z")).await });
+    for _ in 0..100 {
+        if !up.received().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(up.received().len(), 1);
+    assert!(!delivering.is_finished(), "\"up\" was posted to while \"down\" was being retried");
+    let deliveries = delivering.await.unwrap();
+    assert_eq!(deliveries[0].target, "down");
+    assert_eq!(deliveries[0].outcome, Outcome::Failed);
+    assert_eq!(deliveries[1].outcome, Outcome::Delivered);
+}
+
+#[tokio::test]
+async fn a_day_waits_while_its_wisdom_cannot_be_generated() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([{ "name": "team", "url": receiver.url("/hook"), "schedule": "00:00" }])).await;
+    app.mock.push(MockReply::error(503, "service unavailable"));
+    let now = at("2026-03-02T12:00:00Z");
+
+    assert!(webhooks::deliver_due(&app.state(), now).await.is_empty());
+    assert!(receiver.received().is_empty(), "the corpus pick isn't posted");
+    app.mock.push(MockReply::error(503, "service unavailable"));
+    let sent = app.run_cli(&["webhooks", "send", "--date", "2026-03-02"]).await;
+    assert!(sent.unwrap_err().to_string().contains("couldn't be generated"));
+
+    // Later, once the API is back: a new instance has no pending retry
+    app.mock.push(MockReply::content("Back from the vet."));
+    let state = app.state();
+    let deliveries = webhooks::deliver_due(&state, now).await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(receiver.received()[0].json()["wisdom"], "Back from the vet.");
+    assert_eq!(state.wisdom_history.get(date("2026-03-02")).await.unwrap().text, "Back from the vet.");
+}
+
+#[tokio::test]
+async fn webhooks_can_be_listed_and_sent_by_hand() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([
+        { "name": "team", "url": receiver.url("/team"), "format": "slack", "schedule": "09:30", "timezone": "Europe/Berlin", "days": ["mon", "fri"], "secret": "x" },
+        { "name": "other", "url": receiver.url("/other") },
+    ]))
+    .await;
+    app.mock.set_default(MockReply::content("Purr."));
+
+    let list = app.run_cli(&["webhooks", "list"]).await.unwrap();
+    assert!(list.contains(&format!("team  slack  09:30 Europe/Berlin  Mon,Fri  signed  {}", receiver.url("/team"))), "{}", list);
+    assert!(list.contains(&format!("other  generic  08:00 UTC  daily  {}", receiver.url("/other"))), "{}", list);
+
+    let sent = app.run_cli(&["webhooks", "send", "--target", "other", "--date", "2026-01-01"]).await.unwrap();
+    assert!(sent.contains("  other  2026-01-01  delivered  1 attempts"), "{}", sent);
+    // Sending by hand doesn't care whether the day went out already
+    app.run_cli(&["webhooks", "send", "--target", "other", "--date", "2026-01-01"]).await.unwrap();
+    let received = receiver.received();
+    assert_eq!(received.len(), 2);
+    assert!(received.iter().all(|hook| hook.path == "/other"));
+
+    let error = app.run_cli(&["webhooks", "send", "--target", "nope"]).await.unwrap_err();
+    assert!(error.to_string().contains("No webhook target named nope"));
+}
+
+#[tokio::test]
+async fn a_restored_delivery_log_keeps_a_day_from_being_posted_twice() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let targets = json!([{ "name": "team", "url": receiver.url("/team"), "schedule": "09:00" }]);
+    let source = spawn_with_targets(targets.clone()).await;
+    source.mock.push(MockReply::content("Stretch before the standup."));
+    let sent = webhooks::deliver_due(&source.state(), at("2026-03-02T09:30:00Z")).await;
+    let backup = source.data_dir.path().join("backup.json");
+    let exported = source.run_cli(&["export", "--output", backup.to_str().unwrap()]).await.unwrap();
+    assert!(exported.contains("1 deliveries"), "{}", exported);
+
+    let target = spawn_with_targets(targets).await;
+    let imported = target.run_cli(&["import", backup.to_str().unwrap()]).await.unwrap();
+
+    assert!(imported.contains("1 deliveries"), "{}", imported);
+    let log = target.run_cli(&["webhooks", "log"]).await.unwrap();
+    assert!(log.contains("team  2026-03-02  delivered"), "{}", log);
+    assert_eq!(target.state().webhooks.deliveries().await, sent);
+    assert!(webhooks::deliver_due(&target.state(), at("2026-03-02T10:00:00Z")).await.is_empty());
+    assert_eq!(receiver.received().len(), 1);
+}
+
+#[tokio::test]
+async fn a_failed_manual_send_is_an_error() {
+    let receiver = WebhookReceiver::start().await.unwrap();
+    let app = spawn_with_targets(json!([{ "name": "gone", "url": receiver.url("/gone") }])).await;
+    receiver.push_status(410);
+
+    let error = app.run_cli(&["webhooks", "send"]).await.unwrap_err();
+
+    assert!(error.to_string().contains("1 webhook deliveries failed"));
+}
+
+#[tokio::test]
+async fn duplicate_target_names_are_rejected() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("webhooks.json");
+    std::fs::write(&path, json!([{ "name": "a", "url": "https://example.com/1" }, { "name": "a", "url": "https://example.com/2" }]).to_string()).unwrap();
+
+    let error = the_enlightened_cat::webhooks::WebhookTarget::load(&path).err().unwrap();
+
+    assert!(error.to_string().contains("Webhook target a is listed twice"));
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use the_enlightened_cat::corpus::Corpus;
+use the_enlightened_cat::mock_mistral::MockReply;
+
+use common::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn daily_wisdom_is_generated_once_per_day() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("A cat naps where the sun will be."));
+
+    let first = app.get_json("/api/v1/daily-wisdom").await;
+    let second = app.get_json("/api/v1/daily-wisdom").await;
+
+    assert_eq!(first["wisdom"], "A cat naps where the sun will be.");
+    assert_eq!(first["provenance"], "generated");
+    assert_eq!(first["date"], Utc::now().date_naive().to_string());
+    assert_eq!(second["wisdom"], first["wisdom"]);
+    assert_eq!(app.mock.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn daily_wisdom_falls_back_to_corpus_on_upstream_error() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::error(503, "service unavailable"));
+
+    let body = app.get_json("/api/v1/daily-wisdom").await;
+
+    let expected = Corpus::bundled().whispurr_for(Utc::now().date_naive());
+    assert_eq!(body["wisdom"], expected);
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn daily_wisdom_falls_back_to_corpus_on_malformed_body() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::raw("{\"choices\": ["));
+
+    let body = app.get_json("/api/v1/daily-wisdom").await;
+
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn daily_wisdom_falls_back_to_corpus_when_upstream_is_too_slow() {
+    let app = spawn_app().await;
+    app.mock.push(MockReply::content("Too late").delayed(std::time::Duration::from_secs(5)));
+
+    let body = app.get_json("/api/v1/daily-wisdom").await;
+
+    assert_eq!(body["provenance"], "corpus");
+}
+
+#[tokio::test]
+async fn corpus_fallbacks_are_not_saved_and_generation_is_retried() {
+    let app = spawn_app().await;
+    let today = Utc::now().date_naive();
+    app.mock.push(MockReply::error(503, "service unavailable"));
+
+    let first = app.get_json("/api/v1/daily-wisdom").await;
+    let second = app.get_json("/api/v1/daily-wisdom").await;
+
+    assert_eq!(first["provenance"], "corpus");
+    assert_eq!(second["wisdom"], first["wisdom"]);
+    assert_eq!(app.mock.requests().len(), 1, "the outage is not asked about on every request");
+    let state = app.state();
+    assert!(state.wisdom_history.get(today).await.is_none());
+
+    // Later, once the API is back: a new instance has no pending retry
+    app.mock.push(MockReply::content("The sun came back, and so did the cat."));
+    let wisdom = state.get_daily_wisdom(today).await;
+
+    assert_eq!(wisdom.text, "The sun came back, and so did the cat.");
+    assert_eq!(state.wisdom_history.get(today).await.unwrap().text, wisdom.text);
+}
+
+#[tokio::test]
+async fn a_spent_token_budget_falls_back_to_the_corpus() {
+    let app = spawn_app_with(|config| config.daily_token_budget = Some(1)).await;
+    app.mock.push(MockReply::content("A cat naps where the sun will be."));
+    app.mock.push(MockReply::content(common::six_seeds()));
+
+    let wisdom = app.get_json("/api/v1/daily-wisdom").await;
+    let field = app.get_json("/api/v1/quantum-field").await;
+    let chat = app.post_json("/api/v1/chat", serde_json::json!({ "message": "Hello" })).await;
+
+    assert_eq!(wisdom["provenance"], "generated");
+    assert_eq!(field["provenance"], "corpus");
+    assert_eq!(chat.json::<serde_json::Value>().await.unwrap()["provenance"], "corpus");
+    assert_eq!(app.mock.requests().len(), 1, "nothing reaches the model once the budget is spent");
+}
+
+#[tokio::test]
+async fn daily_wisdom_uses_the_visitor_time_zone() {
+    let app = spawn_app().await;
+    let zone: Tz = "Pacific/Kiritimati".parse().unwrap();
+
+    let body: serde_json::Value = app
+        .client
+        .get(app.url("/api/v1/daily-wisdom"))
+        .header("X-Timezone", zone.name())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["timezone"], "Pacific/Kiritimati");
+    assert_eq!(body["date"], Utc::now().with_timezone(&zone).date_naive().to_string());
+}
+
+#[tokio::test]
+async fn unknown_time_zones_fall_back_to_the_site_zone() {
+    let app = spawn_app().await;
+
+    let body: serde_json::Value = app
+        .client
+        .get(app.url("/api/v1/daily-wisdom"))
+        .header("Cookie", "tz=Mars/Olympus_Mons")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["timezone"], "UTC");
+}